@@ -0,0 +1,49 @@
+// Compares `(&mut Transform, &Velocity)` iteration over 10k entities with Transform and
+// Velocity stored in SparseSets (the default) vs archetype tables.
+//
+// Run with `cargo bench --bench ecs_iteration`.
+
+use criterion::{ black_box, criterion_group, criterion_main, Criterion };
+use wasm_game_engine::engine::ecs::{
+    archetype::StorageType,
+    components::{ transform::Transform, velocity::Velocity },
+    world::World,
+};
+
+const ENTITY_COUNT: u32 = 10_000;
+
+fn populated_world(storage_type: StorageType) -> World {
+    let mut world = World::new();
+    world.register_component_with_storage::<Transform>(storage_type);
+    world.register_component_with_storage::<Velocity>(storage_type);
+    for i in 0..ENTITY_COUNT {
+        let builder = world.spawn().with(Transform::new().with_position(i as f32, 0.0, 0.0));
+        // Every other entity moves, so queries also have to skip non-matching entities
+        if i % 2 == 0 {
+            builder.with(Velocity { x: 1.0, y: 0.5, z: 0.0 }).build();
+        } else {
+            builder.build();
+        }
+    }
+    world
+}
+
+fn integrate(world: &mut World, dt: f32) {
+    for (transform, velocity) in world.query_iter::<(&mut Transform, &Velocity)>() {
+        transform.position.x += velocity.x * dt;
+        transform.position.y += velocity.y * dt;
+        transform.position.z += velocity.z * dt;
+    }
+}
+
+fn bench_iteration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transform_velocity_10k");
+    for (name, storage_type) in [("sparse_set", StorageType::SparseSet), ("table", StorageType::Table)] {
+        let mut world = populated_world(storage_type);
+        group.bench_function(name, |b| b.iter(|| integrate(&mut world, black_box(0.016))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_iteration);
+criterion_main!(benches);
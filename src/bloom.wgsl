@@ -0,0 +1,119 @@
+// Bloom as in Call of Duty: Advanced Warfare (Jimenez, SIGGRAPH 2014). The image so far is
+// downsampled through a chain of mips with a 13 tap filter, then upsampled back up the chain
+// with a 3x3 tent, each mip added onto the one above. There's no brightness threshold - every
+// pixel blooms a little, in proportion to its energy, and post_process.wgsl's `fs_bloom` mixes
+// it in.
+
+// The bloom chain entry's `EffectUniform` - the padding holds what `fs_bloom` reads
+struct Bloom {
+    // Of the tent filter, in UV units
+    filter_radius: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+}
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> bloom: Bloom;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the screen, no vertex buffer
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source_texture, source_sampler, uv, 0.0).rgb;
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Weighted by inverse brightness, so single very bright pixels don't flicker as they move
+fn karis_average(a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>) -> vec3<f32> {
+    let sum = a + b + c + d;
+    return sum * 0.25 / (1.0 + luma(sum * 0.25));
+}
+
+// The 13 taps around `uv`, in source texels:
+//   a . b . c
+//   . j . k .
+//   d . e . f
+//   . l . m .
+//   g . h . i
+fn downsample(uv: vec2<f32>, karis: bool) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source_texture));
+    let x = texel.x;
+    let y = texel.y;
+
+    let a = sample_source(uv + vec2<f32>(-2.0 * x, 2.0 * y));
+    let b = sample_source(uv + vec2<f32>(0.0, 2.0 * y));
+    let c = sample_source(uv + vec2<f32>(2.0 * x, 2.0 * y));
+    let d = sample_source(uv + vec2<f32>(-2.0 * x, 0.0));
+    let e = sample_source(uv);
+    let f = sample_source(uv + vec2<f32>(2.0 * x, 0.0));
+    let g = sample_source(uv + vec2<f32>(-2.0 * x, -2.0 * y));
+    let h = sample_source(uv + vec2<f32>(0.0, -2.0 * y));
+    let i = sample_source(uv + vec2<f32>(2.0 * x, -2.0 * y));
+    let j = sample_source(uv + vec2<f32>(-x, y));
+    let k = sample_source(uv + vec2<f32>(x, y));
+    let l = sample_source(uv + vec2<f32>(-x, -y));
+    let m = sample_source(uv + vec2<f32>(x, -y));
+
+    // Five overlapping 2x2 boxes - the centre one weighted 0.5, the corner ones 0.125 each
+    if (karis) {
+        return karis_average(j, k, l, m) * 0.5
+            + karis_average(a, b, d, e) * 0.125
+            + karis_average(b, c, e, f) * 0.125
+            + karis_average(d, e, g, h) * 0.125
+            + karis_average(e, f, h, i) * 0.125;
+    }
+    return e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+}
+
+// The first step, from the full resolution scene
+@fragment
+fn fs_downsample_first(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Keeps NaNs and infinities from the scene out of the chain
+    return vec4<f32>(max(downsample(in.uv, true), vec3<f32>(0.0)), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample(in.uv, false), 1.0);
+}
+
+// Blended additively onto the larger mip
+@fragment
+fn fs_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let r = bloom.filter_radius;
+    let a = sample_source(in.uv + vec2<f32>(-r, r));
+    let b = sample_source(in.uv + vec2<f32>(0.0, r));
+    let c = sample_source(in.uv + vec2<f32>(r, r));
+    let d = sample_source(in.uv + vec2<f32>(-r, 0.0));
+    let e = sample_source(in.uv);
+    let f = sample_source(in.uv + vec2<f32>(r, 0.0));
+    let g = sample_source(in.uv + vec2<f32>(-r, -r));
+    let h = sample_source(in.uv + vec2<f32>(0.0, -r));
+    let i = sample_source(in.uv + vec2<f32>(r, -r));
+
+    let tent = e * 4.0 + (b + d + f + h) * 2.0 + (a + c + g + i);
+    return vec4<f32>(tent / 16.0, 1.0);
+}
@@ -70,7 +70,7 @@ impl ApplicationHandler for App {
                         size.height
                     ).await.expect("Failed to create engine state");
 
-                    let render_state = RenderState::new();
+                    let render_state = RenderState::new(engine_state.scene_format);
 
                     let world: Box<CanyonRunnerWorld> = Box::new(CanyonRunnerWorld);
 
@@ -257,7 +257,9 @@ async fn initialize_gpu_for_wasm(app_state: Rc<RefCell<AppState>>, window: Windo
         ::new(&instance, surface, &window, width, height).await
         .expect("Failed to create engine state");
 
-    let render_state = crate::engine::state::render_state::RenderState::new();
+    let render_state = crate::engine::state::render_state::RenderState::new(
+        engine_state.scene_format
+    );
 
     let scene: Box<crate::game::canyon_runner_world::CanyonRunnerWorld> = Box::new(
         crate::game::canyon_runner_world::CanyonRunnerWorld
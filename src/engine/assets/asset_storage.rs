@@ -0,0 +1,230 @@
+use crate::engine::assets::handle::{ AssetId, DropQueue, Handle, LoadState, WeakHandle };
+
+enum Slot<T> {
+    Free,
+    Loading,
+    Loaded(T),
+    Failed(String),
+}
+
+struct Entry<T> {
+    generation: u32,
+    slot: Slot<T>,
+    // None while the slot is free
+    handle: Option<WeakHandle<T>>,
+}
+
+/// Assets of one type, by id. Every id is handed out with a strong `Handle`; once the last one
+/// drops, `unload_dropped` frees the asset and the slot is reused under the next generation.
+/// Ids can be handed out before their data arrives.
+pub struct Assets<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<usize>,
+    dropped: DropQueue,
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Assets<T> {
+    pub fn new() -> Self {
+        Self { entries: Vec::new(), free: Vec::new(), dropped: DropQueue::default() }
+    }
+
+    /// Adds an asset that's ready now.
+    pub fn register(&mut self, asset: T) -> Handle<T> {
+        self.allocate(Slot::Loaded(asset))
+    }
+
+    /// A handle for an asset that's still loading.
+    pub fn reserve(&mut self) -> Handle<T> {
+        self.allocate(Slot::Loading)
+    }
+
+    fn allocate(&mut self, slot: Slot<T>) -> Handle<T> {
+        let (index, generation) = match self.free.pop() {
+            Some(index) => (index, self.entries[index].generation),
+            None => {
+                self.entries.push(Entry { generation: 0, slot: Slot::Free, handle: None });
+                (self.entries.len() - 1, 0)
+            }
+        };
+        let handle = Handle::new(AssetId { index, generation }, &self.dropped);
+        let entry = &mut self.entries[index];
+        entry.slot = slot;
+        entry.handle = Some(handle.downgrade());
+        handle
+    }
+
+    fn entry(&self, id: AssetId) -> Option<&Entry<T>> {
+        self.entries.get(id.index).filter(|entry| entry.generation == id.generation)
+    }
+
+    fn entry_mut(&mut self, id: AssetId) -> Option<&mut Entry<T>> {
+        self.entries.get_mut(id.index).filter(|entry| entry.generation == id.generation)
+    }
+
+    /// Fills a reserved slot. False if the asset was unloaded in the meantime, in which case
+    /// `asset` is dropped.
+    pub fn insert(&mut self, id: AssetId, asset: T) -> bool {
+        match self.entry_mut(id) {
+            Some(entry) if !matches!(entry.slot, Slot::Free) => {
+                entry.slot = Slot::Loaded(asset);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn fail(&mut self, id: AssetId, error: String) {
+        if let Some(entry) = self.entry_mut(id) {
+            if !matches!(entry.slot, Slot::Free) {
+                entry.slot = Slot::Failed(error);
+            }
+        }
+    }
+
+    pub fn get(&self, id: AssetId) -> Option<&T> {
+        match &self.entry(id)?.slot {
+            Slot::Loaded(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: AssetId) -> Option<&mut T> {
+        match &mut self.entry_mut(id)?.slot {
+            Slot::Loaded(asset) => Some(asset),
+            _ => None,
+        }
+    }
+
+    /// Another strong handle to a live asset.
+    pub fn handle(&self, id: AssetId) -> Option<Handle<T>> {
+        self.entry(id)?.handle.as_ref()?.upgrade()
+    }
+
+    /// Strong handles keeping the asset loaded - 0 once they've all dropped.
+    pub fn strong_count(&self, id: AssetId) -> usize {
+        self.entry(id)
+            .and_then(|entry| entry.handle.as_ref())
+            .map_or(0, WeakHandle::strong_count)
+    }
+
+    /// None for an id that was never handed out, or whose asset has since been unloaded.
+    pub fn load_state(&self, id: AssetId) -> Option<LoadState> {
+        Some(match &self.entry(id)?.slot {
+            Slot::Free => {
+                return None;
+            }
+            Slot::Loading => LoadState::Loading,
+            Slot::Loaded(_) => LoadState::Loaded,
+            Slot::Failed(error) => LoadState::Failed(error.clone()),
+        })
+    }
+
+    /// Frees every asset whose last strong handle has dropped, returning their ids.
+    pub fn unload_dropped(&mut self) -> Vec<AssetId> {
+        // Taken before anything is freed - an asset holding handles to other assets drops them
+        // here, and those may be queued on this same storage
+        let dropped = std::mem::take(&mut *self.dropped.lock().unwrap());
+        for &id in &dropped {
+            let Some(entry) = self.entry_mut(id) else {
+                continue;
+            };
+            entry.slot = Slot::Free;
+            entry.handle = None;
+            entry.generation += 1;
+            self.free.push(id.index);
+        }
+        dropped
+    }
+
+    /// Every loaded asset, in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (AssetId, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                match &entry.slot {
+                    Slot::Loaded(asset) => Some((AssetId { index, generation: entry.generation }, asset)),
+                    _ => None,
+                }
+            })
+    }
+
+    /// Live assets, loaded or not.
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_ids_are_sequential_and_not_readable_until_inserted() {
+        let mut assets = Assets::new();
+        let ready = assets.register("ready");
+        let later = assets.reserve();
+        assert_eq!((ready.id().index(), later.id().index()), (0, 1));
+        assert_eq!(assets.get(later.id()), None);
+        assert_eq!(assets.load_state(later.id()), Some(LoadState::Loading));
+        assert_eq!(assets.iter().map(|(_, asset)| *asset).collect::<Vec<_>>(), vec!["ready"]);
+
+        assert!(assets.insert(later.id(), "later"));
+        assert_eq!(assets.get(later.id()), Some(&"later"));
+        assert_eq!(assets.load_state(later.id()), Some(LoadState::Loaded));
+    }
+
+    #[test]
+    fn failed_loads_keep_their_error() {
+        let mut assets = Assets::<u32>::new();
+        let handle = assets.reserve();
+        assets.fail(handle.id(), "404".to_string());
+        assert_eq!(assets.load_state(handle.id()), Some(LoadState::Failed("404".to_string())));
+        assert!(assets.get_mut(handle.id()).is_none());
+    }
+
+    #[test]
+    fn assets_unload_with_their_last_handle_and_stale_ids_miss() {
+        let mut assets = Assets::new();
+        let first = assets.register(1);
+        let kept = assets.register(2);
+        let stale = first.id();
+        assert_eq!(assets.handle(stale).as_ref(), Some(&first));
+
+        // Still referenced by a clone
+        let clone = first.clone();
+        drop(first);
+        assert!(assets.unload_dropped().is_empty());
+        drop(clone);
+        assert_eq!(assets.strong_count(stale), 0);
+        assert_eq!(assets.unload_dropped(), vec![stale]);
+        assert_eq!(assets.len(), 1);
+
+        let reused = assets.register(3);
+        assert_eq!(reused.id().index(), stale.index());
+        assert_eq!(reused.id().generation(), stale.generation() + 1);
+        assert_eq!(assets.get(stale), None);
+        assert_eq!(assets.load_state(stale), None);
+        assert_eq!(assets.get(reused.id()), Some(&3));
+        assert_eq!(assets.get(kept.id()), Some(&2));
+    }
+
+    #[test]
+    fn loads_finishing_after_their_handle_dropped_are_discarded() {
+        let mut assets = Assets::new();
+        let id = assets.reserve().id();
+        assets.unload_dropped();
+        assert!(!assets.insert(id, "late"));
+        assert!(assets.is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+// Problems found while validating a RON asset (world file, bindings), with where they are.
+//
+// Validators keep going after the first problem, so one report lists everything wrong with a
+// file. Positions are 1-based, like ron's own errors.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// A close match for a misspelt name, e.g. `Transform` for `Transfrom`
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean `{}`?)", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every problem in one file. Returned as the error when loading fails, so callers can
+/// `downcast_ref::<DiagnosticReport>()` for the details.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiagnosticReport {
+    pub source_name: String,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticReport {
+    pub fn new(source_name: &str) -> Self {
+        Self { source_name: source_name.to_string(), diagnostics: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} problem(s)", self.source_name, self.diagnostics.len())?;
+        for diagnostic in &self.diagnostics {
+            write!(f, "\n  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DiagnosticReport {}
+
+/// Collects diagnostics for one source string, turning byte offsets into lines and columns.
+pub struct DiagnosticSink<'s> {
+    source: &'s str,
+    report: DiagnosticReport,
+}
+
+impl<'s> DiagnosticSink<'s> {
+    pub fn new(source_name: &str, source: &'s str) -> Self {
+        Self { source, report: DiagnosticReport::new(source_name) }
+    }
+
+    pub fn source(&self) -> &'s str {
+        self.source
+    }
+
+    pub fn push(&mut self, offset: usize, message: impl Into<String>, suggestion: Option<String>) {
+        let (line, column) = line_column(self.source, offset);
+        self.report.diagnostics.push(Diagnostic { line, column, message: message.into(), suggestion });
+    }
+
+    /// Adds a ron error from parsing `source[start..]` on its own, moving its position into the
+    /// whole file. Unknown variants get a suggestion from the variants ron lists.
+    pub fn push_ron_error(&mut self, start: usize, position: ron::error::Position, message: String) {
+        let offset = start + byte_offset(&self.source[start..], position.line, position.col);
+        let suggestion = suggest_from_message(&message);
+        self.push(offset, message, suggestion);
+    }
+
+    pub fn finish(self) -> DiagnosticReport {
+        self.report
+    }
+}
+
+/// 1-based line and (char) column of a byte offset.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+// Inverse of line_column
+fn byte_offset(source: &str, line: usize, column: usize) -> usize {
+    let line_start = source
+        .match_indices('\n')
+        .nth(line.saturating_sub(2))
+        .map_or(0, |(i, _)| if line > 1 { i + 1 } else { 0 });
+    let column_offset: usize = source[line_start..]
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(char::len_utf8)
+        .sum();
+    line_start + column_offset
+}
+
+/// The candidate closest to `name`, if it's close enough to be a typo.
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+// ron words unknown variants as "... named `Sideways`, expected either `Up` or `Down` instead":
+// the first backticked name is what was written, the ones after "expected" are the options
+fn suggest_from_message(message: &str) -> Option<String> {
+    let (found_part, expected_part) = message.split_once(", expected")?;
+    let found = found_part.split('`').nth(1)?;
+    let candidates = expected_part.split('`').skip(1).step_by(2);
+    did_you_mean(found, candidates)
+}
+
+// Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_close_names_only() {
+        let names = ["Transform", "Velocity", "Collider"];
+        assert_eq!(did_you_mean("Transfrom", names).as_deref(), Some("Transform"));
+        assert_eq!(did_you_mean("Health", names), None);
+    }
+
+    #[test]
+    fn suggests_from_ron_variant_errors() {
+        let message = "Unexpected enum variant named `Dwn`, expected either `Up` or `Down` instead";
+        assert_eq!(suggest_from_message(message).as_deref(), Some("Down"));
+    }
+
+    #[test]
+    fn offsets_round_trip_through_line_and_column() {
+        let source = "(\n    a: 1,\n    b: \"é\", c: 2\n)";
+        let offset = source.find('c').unwrap();
+        let (line, column) = line_column(source, offset);
+        assert_eq!((line, column), (3, 13));
+        assert_eq!(byte_offset(source, line, column), offset);
+    }
+}
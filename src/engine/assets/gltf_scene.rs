@@ -0,0 +1,96 @@
+use crate::engine::{
+    assets::handle::Handle,
+    ecs::{ components::{ renderable::Renderable, transform::Transform }, entity::Entity, world::World },
+    model::{ gltf_loader::GltfDocument, model::Model },
+};
+
+/// A glTF file's node hierarchy, from `AssetServer::load_gltf`. Holds a handle to each of the
+/// file's meshes, so they stay loaded with it.
+pub struct GltfScene {
+    pub nodes: Vec<GltfSceneNode>,
+    pub roots: Vec<usize>,
+}
+
+pub struct GltfSceneNode {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub model: Option<Handle<Model>>,
+    pub children: Vec<usize>,
+}
+
+impl GltfScene {
+    // `models` has one entry per document mesh
+    pub(crate) fn new(document: &GltfDocument, models: &[Handle<Model>]) -> Self {
+        let nodes = document.nodes
+            .iter()
+            .map(|node| GltfSceneNode {
+                name: node.name.clone(),
+                transform: node.transform,
+                model: node.mesh.map(|mesh| models[mesh].clone()),
+                children: node.children.clone(),
+            })
+            .collect();
+        Self { nodes, roots: document.roots.clone() }
+    }
+
+    /// Spawns an entity per node, parented the way the file has them, under a new entity at
+    /// `transform`. Nodes with a mesh get a `Renderable`. Returns the new root.
+    pub fn spawn(&self, world: &mut World, transform: Transform) -> Entity {
+        let root = world.spawn().with(transform).build();
+        let mut stack: Vec<(usize, Entity)> = self.roots
+            .iter()
+            .map(|node| (*node, root))
+            .collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let mut builder = world.spawn().with(node.transform);
+            if let Some(model) = &node.model {
+                builder = builder.with(Renderable::new(model.clone()));
+            }
+            let entity = builder.build();
+            // A fresh entity can't be an ancestor of anything
+            world.set_parent(entity, parent).unwrap();
+            stack.extend(node.children.iter().map(|child| (*child, entity)));
+        }
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use cgmath::Vector3;
+
+    use super::*;
+    use crate::engine::{
+        assets::asset_storage::Assets,
+        ecs::components::hierarchy::{ Children, Parent },
+        model::gltf_loader::parse_gltf,
+    };
+
+    #[test]
+    fn nodes_spawn_as_child_entities() {
+        let document = parse_gltf(include_bytes!("../model/fixtures/two_nodes.gltf"), &HashMap::new()).unwrap();
+        let mut models = Assets::<Model>::new();
+        let handles = vec![models.reserve(), models.reserve()];
+        let scene = GltfScene::new(&document, &handles);
+
+        let mut world = World::new();
+        let root = scene.spawn(&mut world, Transform::new());
+        let Some(Children(bodies)) = world.get_component::<Children>(root).cloned() else {
+            panic!("root has no children");
+        };
+        assert_eq!(bodies.len(), 1);
+        let body = bodies[0];
+        let body_renderable = world.get_component::<Renderable>(body).unwrap();
+        assert_eq!(body_renderable.model, handles[0]);
+
+        let wing = world.get_component::<Children>(body).unwrap().0[0];
+        assert_eq!(world.get_component::<Parent>(wing), Some(&Parent(body)));
+        assert_eq!(world.get_component::<Renderable>(wing).unwrap().model, handles[1]);
+        let wing_transform = world.get_component::<Transform>(wing).unwrap();
+        assert_eq!(wing_transform.position, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(wing_transform.scale, Vector3::new(2.0, 2.0, 2.0));
+    }
+}
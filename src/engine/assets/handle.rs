@@ -0,0 +1,174 @@
+use std::{ fmt, hash::{ Hash, Hasher }, marker::PhantomData, sync::{ Arc, Mutex, Weak } };
+
+/// Where an asset lives in its `Assets` storage. Slots are reused once an asset unloads, with
+/// the generation bumped, so an id kept past its asset's lifetime reads as gone rather than
+/// silently pointing at whatever took the slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AssetId {
+    pub(crate) index: usize,
+    pub(crate) generation: u32,
+}
+
+impl AssetId {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for AssetId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+// Ids whose last strong handle has dropped, waiting for the storage to unload them
+pub(crate) type DropQueue = Arc<Mutex<Vec<AssetId>>>;
+
+// Shared by every clone of one strong handle, so it drops with the last of them
+struct StrongRef {
+    id: AssetId,
+    dropped: DropQueue,
+}
+
+impl Drop for StrongRef {
+    fn drop(&mut self) {
+        // A poisoned queue means we're already panicking - leaking the asset is fine then
+        if let Ok(mut dropped) = self.dropped.lock() {
+            dropped.push(self.id);
+        }
+    }
+}
+
+/// A reference-counted handle to an asset in the AssetServer. Handed out straight away, before
+/// the data has loaded - check `AssetServer::load_state` before relying on it. The asset stays
+/// loaded while any clone of its handle is alive and unloads (freeing its GPU buffers) on the
+/// frame after the last one drops.
+pub struct Handle<T> {
+    id: AssetId,
+    strong: Arc<StrongRef>,
+    // fn() -> T so the handle is Send/Sync whatever T is
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn new(id: AssetId, dropped: &DropQueue) -> Self {
+        Self { id, strong: Arc::new(StrongRef { id, dropped: dropped.clone() }), _marker: PhantomData }
+    }
+
+    pub fn id(&self) -> AssetId {
+        self.id
+    }
+
+    /// A handle that doesn't keep the asset loaded.
+    pub fn downgrade(&self) -> WeakHandle<T> {
+        WeakHandle { id: self.id, strong: Arc::downgrade(&self.strong), _marker: PhantomData }
+    }
+
+    /// How many strong handles to this asset exist, this one included.
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.strong)
+    }
+}
+
+/// Names an asset without keeping it loaded. `upgrade` gets a strong handle back while the
+/// asset is still alive.
+pub struct WeakHandle<T> {
+    id: AssetId,
+    strong: Weak<StrongRef>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> WeakHandle<T> {
+    pub fn id(&self) -> AssetId {
+        self.id
+    }
+
+    pub fn upgrade(&self) -> Option<Handle<T>> {
+        Some(Handle { id: self.id, strong: self.strong.upgrade()?, _marker: PhantomData })
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.strong_count() > 0
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.strong.strong_count()
+    }
+}
+
+// Derives would require T: Clone etc.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self { id: self.id, strong: self.strong.clone(), _marker: PhantomData }
+    }
+}
+
+impl<T> Clone for WeakHandle<T> {
+    fn clone(&self) -> Self {
+        Self { id: self.id, strong: self.strong.clone(), _marker: PhantomData }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+fn type_name<T>() -> &'static str {
+    std::any::type_name::<T>().rsplit("::").next().unwrap()
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle<{}>({})", type_name::<T>(), self.id)
+    }
+}
+
+impl<T> fmt::Debug for WeakHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WeakHandle<{}>({})", type_name::<T>(), self.id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_id_is_queued_when_the_last_strong_handle_drops() {
+        let dropped = DropQueue::default();
+        let handle = Handle::<u32>::new(AssetId { index: 2, generation: 1 }, &dropped);
+        let weak = handle.downgrade();
+        let clone = handle.clone();
+        assert_eq!(handle.strong_count(), 2);
+
+        drop(handle);
+        assert!(dropped.lock().unwrap().is_empty());
+        assert_eq!(weak.upgrade(), Some(clone.clone()));
+
+        drop(clone);
+        assert_eq!(*dropped.lock().unwrap(), vec![AssetId { index: 2, generation: 1 }]);
+        assert!(!weak.is_alive());
+        assert!(weak.upgrade().is_none());
+    }
+}
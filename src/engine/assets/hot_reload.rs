@@ -0,0 +1,146 @@
+// File watching for `--features hot-reload` (native only - the web build has no filesystem)
+//
+// Content normally comes from `include_str!`, so editing it means a rebuild. With the feature
+// on, the engine also watches the files those strings came from and hands back their new
+// contents when they change. The engine then patches the world scene, reapplies the bindings or
+// rebuilds the render pipelines. Anything that fails to load is logged and the last good version
+// stays in use.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{ Path, PathBuf },
+    sync::mpsc::{ channel, Receiver },
+};
+
+use anyhow::{ Context, Result };
+use notify::{ Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher };
+
+pub const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+pub const WIREFRAME_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/wireframe.wgsl");
+pub const POST_PROCESS_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/post_process.wgsl");
+pub const BLOOM_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/bloom.wgsl");
+pub const SHADOW_SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shadow.wgsl");
+
+/// What a watched file is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WatchedAsset {
+    World,
+    Bindings,
+    Shader(WatchedShader),
+}
+
+/// Which pipelines a watched shader is rebuilt into - see `EngineState::rebuild_pipeline`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WatchedShader {
+    Main,
+    Wireframe,
+    /// Every post-processing effect and the output pass
+    PostProcess,
+    /// The three bloom passes
+    Bloom,
+    Shadow,
+}
+
+pub struct AssetWatcher {
+    // Kept alive for as long as we want events
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<(PathBuf, WatchedAsset)>,
+}
+
+impl AssetWatcher {
+    /// Starts watching the given files. Their directories are watched rather than the files
+    /// themselves, since editors often save by replacing the file.
+    pub fn new(files: Vec<(PathBuf, WatchedAsset)>) -> Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+
+        let mut watched_files = Vec::new();
+        let mut directories = HashSet::new();
+        for (path, asset) in files {
+            let path = fs::canonicalize(&path).with_context(|| format!("can't watch {}", path.display()))?;
+            if let Some(directory) = path.parent() {
+                if directories.insert(directory.to_path_buf()) {
+                    watcher.watch(directory, RecursiveMode::NonRecursive)?;
+                }
+            }
+            log::info!("hot reload: watching {}", path.display());
+            watched_files.push((path, asset));
+        }
+        Ok(Self { _watcher: watcher, events, files: watched_files })
+    }
+
+    /// The watched files that changed since the last call, each once, with their new contents.
+    pub fn changed(&self) -> Vec<(WatchedAsset, String)> {
+        let mut changed: Vec<&(PathBuf, WatchedAsset)> = Vec::new();
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("hot reload: watch error: {}", e);
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in &event.paths {
+                if let Some(file) = self.file_for(path) {
+                    if !changed.contains(&file) {
+                        changed.push(file);
+                    }
+                }
+            }
+        }
+
+        changed
+            .into_iter()
+            .filter_map(|(path, asset)| {
+                match fs::read_to_string(path) {
+                    Ok(contents) => Some((*asset, contents)),
+                    Err(e) => {
+                        log::error!("hot reload: can't read {}: {}", path.display(), e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn file_for(&self, path: &Path) -> Option<&(PathBuf, WatchedAsset)> {
+        let path = fs::canonicalize(path).ok()?;
+        self.files.iter().find(|(file, _)| *file == path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ thread, time::{ Duration, Instant } };
+
+    use super::*;
+
+    #[test]
+    fn reports_changed_files_once_with_their_contents() {
+        let directory = std::env::temp_dir().join(format!("hot_reload_test_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let world = directory.join("world.ron");
+        let other = directory.join("other.ron");
+        fs::write(&world, "( entities: [] )").unwrap();
+        fs::write(&other, "").unwrap();
+
+        let watcher = AssetWatcher::new(vec![(world.clone(), WatchedAsset::World)]).unwrap();
+        fs::write(&other, "not watched").unwrap();
+        fs::write(&world, "( entities: [ {} ] )").unwrap();
+        fs::write(&world, "( entities: [ {}, {} ] )").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut changed = Vec::new();
+        while changed.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+            changed = watcher.changed();
+        }
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(changed, vec![(WatchedAsset::World, "( entities: [ {}, {} ] )".to_string())]);
+    }
+}
@@ -1,12 +1,12 @@
 use crate::engine::{
-    assets::server::AssetServer,
+    assets::{ handle::Handle, server::AssetServer },
     instance::Instance,
-    model::{ loader::load_model_from_obj_bytes },
+    model::{ loader::load_model_from_obj_bytes, model::Model },
     state::context::GpuContext,
 };
 
 /// Loads and registers the model asset in the
-/// worlds AssetServer
+/// worlds AssetServer. It stays loaded while the handle (or a clone) is alive
 pub fn load_obj(
     name: &str,
     obj_bytes: &[u8],
@@ -15,14 +15,13 @@ pub fn load_obj(
     initial_instances: Option<Vec<Instance>>,
     max_instances: usize,
     asset_server: &mut AssetServer
-) -> usize {
+) -> Handle<Model> {
     let model = load_model_from_obj_bytes(
         obj_bytes,
         material_bytes,
         gpu_context,
         initial_instances,
         max_instances
-    );
-    let model_id = asset_server.register_model(name, model);
-    model_id
+    ).expect("Failed to parse OBJ");
+    asset_server.register_model(name, model)
 }
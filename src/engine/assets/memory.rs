@@ -0,0 +1,80 @@
+use std::fmt;
+
+use crate::engine::assets::handle::AssetId;
+
+/// One loaded asset in a `MemoryReport`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssetMemory {
+    pub kind: &'static str,
+    pub id: AssetId,
+    // The name/path it was loaded under, if any
+    pub name: Option<String>,
+    pub bytes: u64,
+    // Strong handles keeping it loaded
+    pub handles: usize,
+}
+
+/// Live assets and what they hold on the GPU, from `AssetServer::memory_report`.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryReport {
+    pub assets: Vec<AssetMemory>,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> u64 {
+        self.assets.iter().map(|asset| asset.bytes).sum()
+    }
+}
+
+// One line per asset, largest first, e.g. for logging
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut assets = self.assets.iter().collect::<Vec<_>>();
+        assets.sort_by_key(|asset| std::cmp::Reverse(asset.bytes));
+        writeln!(f, "{} assets, {}", assets.len(), format_bytes(self.total_bytes()))?;
+        for asset in assets {
+            writeln!(
+                f,
+                "  {:<8} {:<6} {:>10}  x{}  {}",
+                asset.kind,
+                asset.id.to_string(),
+                format_bytes(asset.bytes),
+                asset.handles,
+                asset.name.as_deref().unwrap_or("-")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{} B", bytes),
+        1024..1_048_576 => format!("{:.1} KiB", (bytes as f64) / 1024.0),
+        _ => format!("{:.1} MiB", (bytes as f64) / 1_048_576.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_totals_and_lists_largest_first() {
+        let asset = |index, name: &str, bytes| AssetMemory {
+            kind: "model",
+            id: AssetId { index, generation: 0 },
+            name: Some(name.to_string()),
+            bytes,
+            handles: 1,
+        };
+        let report = MemoryReport { assets: vec![asset(0, "small", 100), asset(1, "big", 3 * 1024 * 1024)] };
+        assert_eq!(report.total_bytes(), 3 * 1024 * 1024 + 100);
+
+        let text = report.to_string();
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "2 assets, 3.0 MiB");
+        assert!(lines[1].contains("big") && lines[1].contains("3.0 MiB"));
+        assert!(lines[2].contains("small") && lines[2].contains("100 B"));
+    }
+}
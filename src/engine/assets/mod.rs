@@ -1,3 +1,11 @@
 pub mod ron_parser;
+pub mod diagnostics;
 pub mod server;
 pub mod loader;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+pub mod hot_reload;
+pub mod handle;
+pub mod asset_storage;
+mod pending;
+pub mod memory;
+pub mod gltf_scene;
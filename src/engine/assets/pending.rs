@@ -0,0 +1,203 @@
+// Background fetching for AssetServer::load_model/load_texture/load_gltf
+//
+// Only the I/O happens in the background - on a thread natively, as a browser future on wasm
+// (where `resources::load_binary` fetches from /pkg/res/). The bytes queue up here and
+// `AssetServer::update_loads` parses and uploads them on the main thread each frame, since
+// that's where the wgpu device lives.
+
+use std::{ collections::HashMap, future::Future, sync::{ Arc, Mutex } };
+
+use anyhow::Result;
+
+use crate::engine::{
+    assets::handle::AssetId,
+    model::{ gltf_loader::external_uris, loader::mtl_texture_maps, material::MapKind },
+    resources::load_binary,
+};
+
+pub(crate) enum Fetched {
+    Model {
+        obj: Vec<u8>,
+        // Empty if the OBJ has no mtllib
+        mtl: Vec<u8>,
+        // The maps the MTL names, by name. A map that failed to fetch is left out rather than
+        // failing the model
+        textures: HashMap<String, (Vec<u8>, MapKind)>,
+        max_instances: usize,
+    },
+    Texture {
+        bytes: Vec<u8>,
+    },
+    Gltf {
+        bytes: Vec<u8>,
+        // External buffers and images, by URI
+        resources: HashMap<String, Vec<u8>>,
+        max_instances: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AssetKind {
+    Model,
+    Texture,
+    GltfScene,
+}
+
+pub(crate) struct Arrival {
+    pub(crate) kind: AssetKind,
+    pub(crate) id: AssetId,
+    pub(crate) path: String,
+    pub(crate) result: Result<Fetched>,
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct PendingLoads {
+    arrived: Arc<Mutex<Vec<Arrival>>>,
+}
+
+impl PendingLoads {
+    pub(crate) fn fetch_model(&self, id: AssetId, path: &str, max_instances: usize) {
+        let path = path.to_string();
+        self.spawn(AssetKind::Model, id, path.clone(), async move {
+            let obj = load_binary(&path).await?;
+            let mtl = match mtllib(&obj) {
+                Some(mtl_name) => load_binary(&sibling(&path, mtl_name)).await?,
+                None => Vec::new(),
+            };
+            let mut textures = HashMap::new();
+            for (name, kind) in mtl_texture_maps(&mtl) {
+                match load_binary(&sibling(&path, &name)).await {
+                    Ok(bytes) => {
+                        textures.insert(name, (bytes, kind));
+                    }
+                    Err(e) => log::warn!("{}: couldn't load map {}: {:#}", path, name, e),
+                }
+            }
+            Ok(Fetched::Model { obj, mtl, textures, max_instances })
+        });
+    }
+
+    // A .gltf/.glb and the files it points at. `kind` says what it's loading as - a flattened
+    // `Model` or a `GltfScene`
+    pub(crate) fn fetch_gltf(&self, kind: AssetKind, id: AssetId, path: &str, max_instances: usize) {
+        let path = path.to_string();
+        self.spawn(kind, id, path.clone(), async move {
+            let bytes = load_binary(&path).await?;
+            let mut resources = HashMap::new();
+            for uri in external_uris(&bytes)? {
+                let resource = load_binary(&sibling(&path, &uri)).await?;
+                resources.insert(uri, resource);
+            }
+            Ok(Fetched::Gltf { bytes, resources, max_instances })
+        });
+    }
+
+    pub(crate) fn fetch_texture(&self, id: AssetId, path: &str) {
+        let path = path.to_string();
+        self.spawn(AssetKind::Texture, id, path.clone(), async move {
+            Ok(Fetched::Texture { bytes: load_binary(&path).await? })
+        });
+    }
+
+    /// Everything that finished fetching since the last call.
+    pub(crate) fn take(&self) -> Vec<Arrival> {
+        std::mem::take(&mut *self.arrived.lock().unwrap())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn(
+        &self,
+        kind: AssetKind,
+        id: AssetId,
+        path: String,
+        fetch: impl Future<Output = Result<Fetched>> + Send + 'static
+    ) {
+        let arrived = self.arrived.clone();
+        std::thread::spawn(move || {
+            let result = pollster::block_on(fetch);
+            arrived.lock().unwrap().push(Arrival { kind, id, path, result });
+        });
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn spawn(
+        &self,
+        kind: AssetKind,
+        id: AssetId,
+        path: String,
+        fetch: impl Future<Output = Result<Fetched>> + 'static
+    ) {
+        let arrived = self.arrived.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let result = fetch.await;
+            arrived.lock().unwrap().push(Arrival { kind, id, path, result });
+        });
+    }
+}
+
+// The first `mtllib` an OBJ refers to
+fn mtllib(obj: &[u8]) -> Option<&str> {
+    std::str
+        ::from_utf8(obj)
+        .ok()?
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("mtllib "))
+        .map(str::trim)
+}
+
+// `name` next to the file at `path`
+pub(crate) fn sibling(path: &str, name: &str) -> String {
+    match path.rsplit_once('/') {
+        Some((directory, _)) => format!("{}/{}", directory, name),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{ Duration, Instant };
+
+    use super::*;
+
+    fn wait_for(pending: &PendingLoads) -> Arrival {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            if let Some(arrival) = pending.take().pop() {
+                return arrival;
+            }
+            assert!(Instant::now() < deadline, "fetch never finished");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn models_are_fetched_with_their_material_library_and_maps() {
+        let pending = PendingLoads::default();
+        let id = AssetId { index: 3, generation: 0 };
+        pending.fetch_model(id, "cube.obj", 8);
+        let arrival = wait_for(&pending);
+        assert_eq!((arrival.kind, arrival.id), (AssetKind::Model, id));
+        let Ok(Fetched::Model { obj, mtl, textures, max_instances }) = arrival.result else {
+            panic!("cube.obj didn't load");
+        };
+        assert!(!obj.is_empty() && !mtl.is_empty());
+        assert_eq!(textures["cube-diffuse.jpg"].1, MapKind::Diffuse);
+        assert_eq!(textures["cube-normal.png"].1, MapKind::Normal);
+        assert_eq!(max_instances, 8);
+    }
+
+    #[test]
+    fn missing_files_arrive_as_errors() {
+        let pending = PendingLoads::default();
+        pending.fetch_texture(AssetId { index: 0, generation: 0 }, "no_such_texture.png");
+        assert!(wait_for(&pending).result.is_err());
+    }
+
+    #[test]
+    fn material_libraries_resolve_next_to_the_obj() {
+        assert_eq!(mtllib(b"# cube\nmtllib cube.mtl\nv 0 0 0"), Some("cube.mtl"));
+        assert_eq!(mtllib(b"v 0 0 0"), None);
+        assert_eq!(sibling("models/cube.obj", "cube.mtl"), "models/cube.mtl");
+        assert_eq!(sibling("cube.obj", "cube.mtl"), "cube.mtl");
+    }
+}
@@ -1,23 +1,128 @@
 use std::collections::HashMap;
 
 use crate::engine::{
+    assets::{
+        asset_storage::Assets,
+        gltf_scene::GltfScene,
+        handle::{ AssetId, Handle, LoadState, WeakHandle },
+        memory::{ AssetMemory, MemoryReport },
+        pending::{ sibling, AssetKind, Fetched, PendingLoads },
+    },
     ecs::components::collider::{ Collider, ColliderShape },
     instance::Instance,
-    model::{ loader::load_model_from_obj_bytes, model::Model, model_registry::{ ModelRegistry } },
+    model::{
+        gltf_loader::{ flatten_gltf, gltf_mesh_models, parse_gltf, GltfDocument },
+        loader::{ load_model_from_obj_bytes, parse_obj },
+        material::MapKind,
+        model::Model,
+        model_registry::{ ModelRegistry },
+    },
     state::context::GpuContext,
+    texture::Texture,
 };
 
+// Instance buffer size for models that don't say otherwise
+const DEFAULT_MAX_INSTANCES: usize = 1024;
+
+/// Asset types the AssetServer stores, so `load_state`/`get` work for any of them.
+pub trait Asset: Sized {
+    fn storage(asset_server: &AssetServer) -> &Assets<Self>;
+}
+
+impl Asset for Model {
+    fn storage(asset_server: &AssetServer) -> &Assets<Self> {
+        &asset_server.model_registry
+    }
+}
+
+impl Asset for Texture {
+    fn storage(asset_server: &AssetServer) -> &Assets<Self> {
+        &asset_server.textures
+    }
+}
+
+impl Asset for GltfScene {
+    fn storage(asset_server: &AssetServer) -> &Assets<Self> {
+        &asset_server.gltf_scenes
+    }
+}
+
+fn is_gltf(path: &str) -> bool {
+    path.ends_with(".gltf") || path.ends_with(".glb")
+}
+
+/// Owns every model and texture. Assets live as long as someone holds a strong `Handle` to
+/// them (a `Renderable`, a game resource...) - names only refer to them weakly, so looking a
+/// model up by name fails once nothing uses it any more.
 pub struct AssetServer {
-    models: HashMap<String, usize>,
+    models: HashMap<String, WeakHandle<Model>>,
     model_registry: ModelRegistry,
+    textures: Assets<Texture>,
+    texture_paths: HashMap<String, WeakHandle<Texture>>,
+    gltf_scenes: Assets<GltfScene>,
+    gltf_paths: HashMap<String, WeakHandle<GltfScene>>,
+    pending: PendingLoads,
 }
 
 impl AssetServer {
     pub fn new() -> Self {
-        Self { models: HashMap::new(), model_registry: ModelRegistry::new() }
+        Self {
+            models: HashMap::new(),
+            model_registry: ModelRegistry::new(),
+            textures: Assets::new(),
+            texture_paths: HashMap::new(),
+            gltf_scenes: Assets::new(),
+            gltf_paths: HashMap::new(),
+            pending: PendingLoads::default(),
+        }
+    }
+
+    /// Starts loading an OBJ (and the first material library it names), or a .gltf/.glb, from
+    /// `res/` - the build output natively, `/pkg/res/` over HTTP on the web. glTF scenes are
+    /// flattened into one model; use `load_gltf` to keep the nodes. The handle can be used,
+    /// e.g. in a `Renderable`, straight away; the model isn't drawn until it's
+    /// `LoadState::Loaded`. Loading a name that's still alive returns another handle to it.
+    pub fn load_model(&mut self, name: &str, path: &str) -> Handle<Model> {
+        if let Some(handle) = self.get_model_handle(name) {
+            return handle;
+        }
+        let handle = self.model_registry.reserve();
+        self.models.insert(name.to_string(), handle.downgrade());
+        if is_gltf(path) {
+            self.pending.fetch_gltf(AssetKind::Model, handle.id(), path, DEFAULT_MAX_INSTANCES);
+        } else {
+            self.pending.fetch_model(handle.id(), path, DEFAULT_MAX_INSTANCES);
+        }
+        handle
     }
 
-    pub fn load_model(
+    /// Starts loading a .gltf/.glb as a `GltfScene`, to spawn its node hierarchy as entities.
+    /// Each of its meshes becomes a model named `"<path>#<mesh name or index>"`.
+    pub fn load_gltf(&mut self, path: &str) -> Handle<GltfScene> {
+        if let Some(handle) = self.gltf_paths.get(path).and_then(WeakHandle::upgrade) {
+            return handle;
+        }
+        let handle = self.gltf_scenes.reserve();
+        self.gltf_paths.insert(path.to_string(), handle.downgrade());
+        self.pending.fetch_gltf(AssetKind::GltfScene, handle.id(), path, DEFAULT_MAX_INSTANCES);
+        handle
+    }
+
+    /// Starts loading an image from `res/`, like `load_model`. Loading a path that's still
+    /// alive returns another handle to it.
+    pub fn load_texture(&mut self, path: &str) -> Handle<Texture> {
+        if let Some(handle) = self.texture_paths.get(path).and_then(WeakHandle::upgrade) {
+            return handle;
+        }
+        let handle = self.textures.reserve();
+        self.texture_paths.insert(path.to_string(), handle.downgrade());
+        self.pending.fetch_texture(handle.id(), path);
+        handle
+    }
+
+    /// Builds a model from OBJ/MTL bytes that are already in memory (e.g. `include_bytes!`).
+    /// Panics if they don't parse.
+    pub fn load_model_from_bytes(
         &mut self,
         name: &str,
         obj_bytes: &[u8],
@@ -25,51 +130,364 @@ impl AssetServer {
         gpu_context: &GpuContext,
         initial_instances: Option<Vec<Instance>>,
         max_instances: Option<usize>
-    ) -> usize {
+    ) -> Handle<Model> {
         let model = load_model_from_obj_bytes(
             obj_bytes,
             material_bytes,
             gpu_context,
             initial_instances,
-            max_instances.unwrap_or(1024)
-        );
+            max_instances.unwrap_or(DEFAULT_MAX_INSTANCES)
+        ).unwrap_or_else(|e| panic!("Failed to parse OBJ for '{}': {}", name, e));
+        self.register_model(name, model)
+    }
+
+    /// Builds a flattened model from a self-contained .glb or .gltf (no external buffers or
+    /// images) that's already in memory. Panics if it doesn't parse.
+    pub fn load_model_from_gltf_bytes(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        gpu_context: &GpuContext,
+        initial_instances: Option<Vec<Instance>>,
+        max_instances: Option<usize>
+    ) -> Handle<Model> {
+        let model = parse_gltf(bytes, &HashMap::new())
+            .and_then(|document| {
+                let textures = self.upload_gltf_images(name, &document, gpu_context);
+                flatten_gltf(&document, &textures)
+            })
+            .map(|data| {
+                Model::upload(
+                    data,
+                    gpu_context,
+                    &self.textures,
+                    initial_instances,
+                    max_instances.unwrap_or(DEFAULT_MAX_INSTANCES)
+                )
+            })
+            .unwrap_or_else(|e| panic!("Failed to parse glTF for '{}': {}", name, e));
         self.register_model(name, model)
     }
 
-    pub fn register_model(&mut self, name: &str, model: Model) -> usize {
-        let model_id = self.model_registry.register(model);
-        self.models.insert(name.to_string(), model_id);
-        model_id
+    pub fn register_model(&mut self, name: &str, model: Model) -> Handle<Model> {
+        let handle = self.model_registry.register(model);
+        self.models.insert(name.to_string(), handle.downgrade());
+        handle
+    }
+
+    /// A model with no name, e.g. generated terrain. It can't be referenced from RON.
+    pub fn add_model(&mut self, model: Model) -> Handle<Model> {
+        self.model_registry.register(model)
+    }
+
+    /// Unloads assets whose last handle dropped, then parses and uploads whatever finished
+    /// downloading since last frame. Called by the engine before the systems run.
+    pub fn update_loads(&mut self, gpu_context: &GpuContext) {
+        self.unload_unused();
+
+        for arrival in self.pending.take() {
+            let result = arrival.result.and_then(|fetched| {
+                // Loads whose handles all dropped while downloading are thrown away by insert
+                match fetched {
+                    Fetched::Model { obj, mtl, textures, max_instances } => {
+                        // Maps are registered under their path, keyed by the name the MTL uses
+                        let maps = textures
+                            .into_iter()
+                            .filter_map(|(name, (bytes, kind))| {
+                                let path = sibling(&arrival.path, &name);
+                                Some((name, self.upload_texture(&path, &bytes, kind, gpu_context)?))
+                            })
+                            .collect();
+                        let data = parse_obj(&obj, &mtl, &maps)?;
+                        let model = Model::upload(data, gpu_context, &self.textures, None, max_instances);
+                        self.model_registry.insert(arrival.id, model);
+                    }
+                    Fetched::Texture { bytes } => {
+                        let texture = Texture::from_bytes(
+                            gpu_context.device,
+                            gpu_context.queue,
+                            &bytes,
+                            &arrival.path
+                        )?;
+                        self.textures.insert(arrival.id, texture);
+                    }
+                    Fetched::Gltf { bytes, resources, max_instances } => {
+                        let document = parse_gltf(&bytes, &resources)?;
+                        let textures = self.upload_gltf_images(&arrival.path, &document, gpu_context);
+                        match arrival.kind {
+                            AssetKind::GltfScene => {
+                                let uploaded = gltf_mesh_models(&document, &textures)
+                                    .into_iter()
+                                    .map(|data| Model::upload(data, gpu_context, &self.textures, None, max_instances))
+                                    .collect::<Vec<_>>();
+                                let models = uploaded
+                                    .into_iter()
+                                    .zip(&document.meshes)
+                                    .enumerate()
+                                    .map(|(index, (model, mesh))| {
+                                        let mesh_name = mesh.name.clone().unwrap_or_else(|| index.to_string());
+                                        self.register_model(&format!("{}#{}", arrival.path, mesh_name), model)
+                                    })
+                                    .collect::<Vec<_>>();
+                                self.gltf_scenes.insert(arrival.id, GltfScene::new(&document, &models));
+                            }
+                            _ => {
+                                let data = flatten_gltf(&document, &textures)?;
+                                let model = Model::upload(data, gpu_context, &self.textures, None, max_instances);
+                                self.model_registry.insert(arrival.id, model);
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            });
+            match result {
+                Ok(()) => log::info!("loaded {}", arrival.path),
+                Err(e) => {
+                    log::error!("Failed to load {}: {:#}", arrival.path, e);
+                    match arrival.kind {
+                        AssetKind::Model => self.model_registry.fail(arrival.id, format!("{:#}", e)),
+                        AssetKind::Texture => self.textures.fail(arrival.id, format!("{:#}", e)),
+                        AssetKind::GltfScene => self.gltf_scenes.fail(arrival.id, format!("{:#}", e)),
+                    }
+                }
+            }
+        }
+    }
+
+    // Uploads a glTF's images as textures named `"<path>#image<index>"`, in order
+    fn upload_gltf_images(
+        &mut self,
+        path: &str,
+        document: &GltfDocument,
+        gpu_context: &GpuContext
+    ) -> Vec<Option<Handle<Texture>>> {
+        document.images
+            .iter()
+            .enumerate()
+            .map(|(index, bytes)| {
+                let name = format!("{}#image{}", path, index);
+                self.upload_texture(&name, bytes, document.image_kind(index), gpu_context)
+            })
+            .collect()
     }
 
-    pub fn get_model_id(&self, name: &str) -> usize {
-        *self.models.get(name).unwrap_or_else(|| panic!("Asset '{}' not registered", name))
+    // Decodes and registers a material map under `name`. One that doesn't decode is logged and
+    // left as None, so its materials draw without it
+    fn upload_texture(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+        kind: MapKind,
+        gpu_context: &GpuContext
+    ) -> Option<Handle<Texture>> {
+        // Models sharing a map share the texture
+        if let Some(handle) = self.texture_paths.get(name).and_then(WeakHandle::upgrade) {
+            return Some(handle);
+        }
+        let texture = match kind {
+            MapKind::Diffuse => Texture::from_bytes(gpu_context.device, gpu_context.queue, bytes, name),
+            MapKind::Normal => Texture::normal_map_from_bytes(gpu_context.device, gpu_context.queue, bytes, name),
+        };
+        match texture {
+            Ok(texture) => {
+                let handle = self.textures.register(texture);
+                self.texture_paths.insert(name.to_string(), handle.downgrade());
+                Some(handle)
+            }
+            Err(e) => {
+                log::warn!("Failed to decode {}: {}", name, e);
+                None
+            }
+        }
     }
 
-    pub fn models(&self) -> &[Model] {
-        self.model_registry.models()
+    /// Frees every asset nothing holds a strong handle to any more, along with its GPU
+    /// buffers, and forgets its name. Their slots are reused with a new generation, so old
+    /// `AssetId`s stop resolving.
+    pub fn unload_unused(&mut self) {
+        // Scenes hold their models and models their textures, so this order frees a whole
+        // file in one call
+        for id in self.gltf_scenes.unload_dropped() {
+            log::debug!("unloaded glTF scene {}", id);
+        }
+        for id in self.model_registry.unload_dropped() {
+            log::debug!("unloaded model {}", id);
+        }
+        for id in self.textures.unload_dropped() {
+            log::debug!("unloaded texture {}", id);
+        }
+        self.models.retain(|_, handle| handle.is_alive());
+        self.texture_paths.retain(|_, handle| handle.is_alive());
+        self.gltf_paths.retain(|_, handle| handle.is_alive());
     }
 
-    pub fn get_model(&self, id: usize) -> &Model {
-        self.model_registry.get(id).unwrap_or_else(|| panic!("No model registered with id {}", id))
+    /// `Failed` for a handle this server never handed out.
+    pub fn load_state<T: Asset>(&self, handle: &Handle<T>) -> LoadState {
+        T::storage(self)
+            .load_state(handle.id())
+            .unwrap_or_else(|| LoadState::Failed(format!("unknown handle {:?}", handle)))
     }
 
-    pub fn get_model_mut(&mut self, id: usize) -> &mut Model {
-        self.model_registry
-            .get_mut(id)
-            .unwrap_or_else(|| panic!("No model registered with id {}", id))
+    /// The asset, once it's loaded.
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<&T> {
+        T::storage(self).get(handle.id())
+    }
+
+    /// A strong handle to the model loaded under `name`, if it's still alive.
+    pub fn get_model_handle(&self, name: &str) -> Option<Handle<Model>> {
+        self.models.get(name)?.upgrade()
+    }
+
+    /// The name a model was registered under. Linear in the number of models - fine for
+    /// saving, don't use it per frame.
+    pub fn get_model_name(&self, id: AssetId) -> Option<&str> {
+        self.models
+            .iter()
+            .find(|(_, handle)| handle.id() == id && handle.is_alive())
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Every model name, with a handle that doesn't keep the model loaded.
+    pub fn named_models(&self) -> &HashMap<String, WeakHandle<Model>> {
+        &self.models
+    }
+
+    /// Names of the models that are still alive.
+    pub fn model_names(&self) -> impl Iterator<Item = &str> {
+        self.models
+            .iter()
+            .filter(|(_, handle)| handle.is_alive())
+            .map(|(name, _)| name.as_str())
+    }
+
+    // A name for a model that never loads, for tests that only look at names. Keep the handle
+    // alive for as long as the name should resolve.
+    #[cfg(test)]
+    pub(crate) fn register_model_name(&mut self, name: &str) -> Handle<Model> {
+        let handle = self.model_registry.reserve();
+        self.models.insert(name.to_string(), handle.downgrade());
+        handle
+    }
+
+    /// Every loaded model. Ones still loading (or failed) are skipped.
+    pub fn models(&self) -> impl Iterator<Item = &Model> {
+        self.model_registry.iter().map(|(_, model)| model)
+    }
+
+    /// None until the model is loaded, and once it's been unloaded.
+    pub fn get_model(&self, id: AssetId) -> Option<&Model> {
+        self.model_registry.get(id)
+    }
+
+    pub fn get_model_mut(&mut self, id: AssetId) -> Option<&mut Model> {
+        self.model_registry.get_mut(id)
     }
 
     /// Builds an AABB collider from the model's vertex bounds, in model-local space.
     /// `collision_system` and `collider_debug_system` apply `Transform.scale` at runtime,
-    /// so do not pre-scale this before attaching to an entity.
-    pub fn get_collider_aabb(&self, name: &str) -> Collider {
-        let bounds = self.get_model(self.get_model_id(name)).bounds;
-        Collider {
+    /// so do not pre-scale this before attaching to an entity. None until the model is loaded.
+    pub fn get_collider_aabb(&self, handle: &Handle<Model>) -> Option<Collider> {
+        let bounds = self.get(handle)?.bounds;
+        Some(Collider {
             shape: ColliderShape::AABB {
                 offset: bounds.center(),
                 half_extents: bounds.half_extents(),
             },
+        })
+    }
+
+    /// Every loaded asset and the bytes it holds on the GPU.
+    pub fn memory_report(&self) -> MemoryReport {
+        let mut assets = Vec::new();
+        for (id, model) in self.model_registry.iter() {
+            assets.push(AssetMemory {
+                kind: "model",
+                id,
+                name: self.get_model_name(id).map(str::to_string),
+                bytes: model.gpu_bytes(),
+                handles: self.model_registry.strong_count(id),
+            });
+        }
+        for (id, texture) in self.textures.iter() {
+            assets.push(AssetMemory {
+                kind: "texture",
+                id,
+                name: self.texture_paths
+                    .iter()
+                    .find(|(_, handle)| handle.id() == id)
+                    .map(|(path, _)| path.clone()),
+                bytes: texture.gpu_bytes(),
+                handles: self.textures.strong_count(id),
+            });
+        }
+        MemoryReport { assets }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{ Duration, Instant };
+
+    use super::*;
+
+    #[test]
+    fn handles_are_returned_before_loading_and_reused_per_name() {
+        let mut asset_server = AssetServer::new();
+        let handle = asset_server.load_model("cube", "cube.obj");
+        assert_eq!(asset_server.load_state(&handle), LoadState::Loading);
+        assert!(asset_server.get(&handle).is_none());
+        assert_eq!(asset_server.load_model("cube", "cube.obj"), handle);
+        assert_eq!(asset_server.get_model_handle("cube"), Some(handle));
+        assert_eq!(asset_server.get_model_handle("nope"), None);
+        assert_eq!(asset_server.models().count(), 0);
+    }
+
+    #[test]
+    fn names_stop_resolving_once_the_last_handle_is_unloaded() {
+        let mut asset_server = AssetServer::new();
+        let handle = asset_server.register_model_name("ship");
+        let id = handle.id();
+        assert_eq!(asset_server.get_model_name(id), Some("ship"));
+
+        drop(handle);
+        asset_server.unload_unused();
+        assert_eq!(asset_server.get_model_handle("ship"), None);
+        assert_eq!(asset_server.get_model_name(id), None);
+        assert_eq!(asset_server.model_names().count(), 0);
+
+        // The slot is reused, but the old id doesn't see the new model
+        let again = asset_server.register_model_name("ship");
+        assert_eq!(again.id().index(), id.index());
+        assert_ne!(again.id(), id);
+        assert_eq!(asset_server.model_registry.load_state(id), None);
+    }
+
+    #[test]
+    fn failed_fetches_mark_the_right_asset_failed() {
+        let mut asset_server = AssetServer::new();
+        let model = asset_server.load_model("ghost", "no_such_model.obj");
+        let texture = asset_server.load_texture("no_such_texture.png");
+        // Same id, different asset types
+        assert_eq!(model.id(), texture.id());
+
+        // Failures never touch the GPU, but update_loads needs a GpuContext, so drain the queue
+        // the way it does
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut arrivals = Vec::new();
+        while arrivals.len() < 2 && Instant::now() < deadline {
+            arrivals.extend(asset_server.pending.take());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        for arrival in arrivals {
+            assert!(arrival.result.is_err());
+            match arrival.kind {
+                AssetKind::Model => asset_server.model_registry.fail(arrival.id, "missing".to_string()),
+                AssetKind::Texture => asset_server.textures.fail(arrival.id, "missing".to_string()),
+                AssetKind::GltfScene => asset_server.gltf_scenes.fail(arrival.id, "missing".to_string()),
+            }
         }
+        assert_eq!(asset_server.load_state(&model), LoadState::Failed("missing".to_string()));
+        assert_eq!(asset_server.load_state(&texture), LoadState::Failed("missing".to_string()));
     }
 }
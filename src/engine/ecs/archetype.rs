@@ -0,0 +1,447 @@
+// Table (archetype) storage
+//
+// Components registered with `StorageType::Table` live here instead of in a per-type SparseSet.
+// Every distinct *set* of table components gets its own `Archetype`: a table whose columns are
+// one `Vec<T>` per component type, with row `i` of every column belonging to `entities[i]`.
+// Iterating `(&mut Transform, &Velocity)` over a table is then a straight walk down two
+// contiguous Vecs - no per-entity sparse lookups.
+//
+// The price is paid on structural changes: adding or removing a table component moves the
+// entity's whole row into a different archetype. Components that are added/removed often
+// (markers like `DebugVisual`, short-lived state) are better left as sparse sets.
+//
+// Only table components decide an entity's archetype. An entity with no table components has
+// no location here at all.
+
+use std::{ any::{ Any, TypeId }, cell::UnsafeCell, collections::HashMap };
+
+use crate::engine::ecs::sparse_set::ComponentTicks;
+
+/// Where a component type's data is stored. Chosen per type at registration time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum StorageType {
+    /// Per-type SparseSet. Cheap add/remove, one lookup per extra component when iterating.
+    #[default]
+    SparseSet,
+    /// Archetype tables. Fast multi-component iteration, rows move on add/remove.
+    Table,
+}
+
+// Type-erased column so an archetype can move rows around without knowing T.
+pub(crate) trait Column {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    /// A new, empty column of the same component type
+    fn new_empty(&self) -> Box<dyn Column>;
+    /// Drops the value at `row`, filling the gap with the last row
+    fn swap_remove(&mut self, row: usize);
+    /// Moves the value at `row` onto the end of `dst` (which must hold the same type),
+    /// filling the gap with the last row
+    fn move_row(&mut self, row: usize, dst: &mut dyn Column);
+}
+
+// In `UnsafeCell`s like a SparseSet's values, so parallel systems can reach their own column
+// through a shared `&World`
+pub struct TypedColumn<T> {
+    data: Vec<UnsafeCell<T>>,
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+}
+
+impl<T> TypedColumn<T> {
+    fn new() -> Self {
+        Self { data: Vec::new(), ticks: Vec::new() }
+    }
+
+    fn push(&mut self, value: T, ticks: ComponentTicks) {
+        self.data.push(UnsafeCell::new(value));
+        self.ticks.push(UnsafeCell::new(ticks));
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get(&self, row: usize) -> &T {
+        // Safety: only `get_mut_unchecked` writes through a shared reference, and its callers
+        // guarantee nothing else is reading this row
+        unsafe { &*self.data[row].get() }
+    }
+
+    pub fn ticks(&self, row: usize) -> ComponentTicks {
+        // Safety: as in `get`
+        unsafe { *self.ticks[row].get() }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len()).map(|row| self.get(row))
+    }
+
+    fn set(&mut self, row: usize, value: T, tick: u32) {
+        *self.data[row].get_mut() = value;
+        self.ticks[row].get_mut().changed = tick;
+    }
+
+    fn get_mut_tracked(&mut self, row: usize, tick: u32) -> &mut T {
+        self.ticks[row].get_mut().changed = tick;
+        self.data[row].get_mut()
+    }
+
+    /// `row`'s value, marked as changed at `tick`, through a shared reference.
+    /// # Safety
+    /// Nothing else may access the row's value or ticks while the result is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get_mut_unchecked(&self, row: usize, tick: u32) -> &mut T {
+        (*self.ticks[row].get()).changed = tick;
+        &mut *self.data[row].get()
+    }
+}
+
+impl<T: 'static> Column for TypedColumn<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+    fn new_empty(&self) -> Box<dyn Column> {
+        Box::new(TypedColumn::<T>::new())
+    }
+    fn swap_remove(&mut self, row: usize) {
+        self.data.swap_remove(row);
+        self.ticks.swap_remove(row);
+    }
+    fn move_row(&mut self, row: usize, dst: &mut dyn Column) {
+        let dst = dst.as_any_mut().downcast_mut::<TypedColumn<T>>().unwrap();
+        dst.push(self.data.swap_remove(row).into_inner(), self.ticks.swap_remove(row).into_inner());
+    }
+}
+
+pub struct Archetype {
+    // Sorted, so the same component set always maps to the same archetype
+    types: Vec<TypeId>,
+    // columns[i] holds types[i]
+    columns: Vec<Box<dyn Column>>,
+    entities: Vec<u32>,
+}
+
+impl Archetype {
+    pub fn contains(&self, type_id: TypeId) -> bool {
+        self.types.binary_search(&type_id).is_ok()
+    }
+
+    pub fn entities(&self) -> &[u32] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn column<T: 'static>(&self) -> Option<&TypedColumn<T>> {
+        let index = self.types.binary_search(&TypeId::of::<T>()).ok()?;
+        self.columns[index].as_any().downcast_ref()
+    }
+
+    pub fn column_mut<T: 'static>(&mut self) -> Option<&mut TypedColumn<T>> {
+        let index = self.types.binary_search(&TypeId::of::<T>()).ok()?;
+        self.columns[index].as_any_mut().downcast_mut()
+    }
+
+    fn column_index(&self, type_id: TypeId) -> Option<usize> {
+        self.types.binary_search(&type_id).ok()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct EntityLocation {
+    archetype: usize,
+    row: usize,
+}
+
+#[derive(Default)]
+pub struct Tables {
+    archetypes: Vec<Archetype>,
+    by_types: HashMap<Vec<TypeId>, usize>,
+    // Indexed by entity id
+    locations: Vec<Option<EntityLocation>>,
+}
+
+impl Tables {
+    pub fn archetypes(&self) -> &[Archetype] {
+        &self.archetypes
+    }
+
+    pub fn archetypes_mut(&mut self) -> &mut [Archetype] {
+        &mut self.archetypes
+    }
+
+    fn location(&self, entity_id: u32) -> Option<EntityLocation> {
+        self.locations.get(entity_id as usize).copied().flatten()
+    }
+
+    fn set_location(&mut self, entity_id: u32, location: Option<EntityLocation>) {
+        let id = entity_id as usize;
+        if id >= self.locations.len() {
+            self.locations.resize(id + 1, None);
+        }
+        self.locations[id] = location;
+    }
+
+    pub fn contains(&self, entity_id: u32, type_id: TypeId) -> bool {
+        self.location(entity_id).is_some_and(|location| {
+            self.archetypes[location.archetype].contains(type_id)
+        })
+    }
+
+    /// The component types the entity has in tables, sorted.
+    pub fn types_of(&self, entity_id: u32) -> &[TypeId] {
+        self.location(entity_id).map_or(&[], |location| &self.archetypes[location.archetype].types)
+    }
+
+    pub fn get<T: 'static>(&self, entity_id: u32) -> Option<&T> {
+        let location = self.location(entity_id)?;
+        let column = self.archetypes[location.archetype].column::<T>()?;
+        Some(column.get(location.row))
+    }
+
+    pub fn get_mut_tracked<T: 'static>(&mut self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        let location = self.location(entity_id)?;
+        let column = self.archetypes[location.archetype].column_mut::<T>()?;
+        Some(column.get_mut_tracked(location.row, tick))
+    }
+
+    /// `get_mut_tracked` through a shared reference.
+    /// # Safety
+    /// See `TypedColumn::get_mut_unchecked`.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get_mut_unchecked<T: 'static>(&self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        let location = self.location(entity_id)?;
+        let column = self.archetypes[location.archetype].column::<T>()?;
+        Some(column.get_mut_unchecked(location.row, tick))
+    }
+
+    pub fn ticks<T: 'static>(&self, entity_id: u32) -> Option<ComponentTicks> {
+        let location = self.location(entity_id)?;
+        let column = self.archetypes[location.archetype].column::<T>()?;
+        Some(column.ticks(location.row))
+    }
+
+    /// Inserts or overwrites a T, moving the entity into the archetype that includes T if needed.
+    pub fn insert<T: 'static>(&mut self, entity_id: u32, value: T, tick: u32) {
+        let type_id = TypeId::of::<T>();
+        let location = self.location(entity_id);
+
+        if let Some(location) = location {
+            if let Some(column) = self.archetypes[location.archetype].column_mut::<T>() {
+                column.set(location.row, value, tick);
+                return;
+            }
+        }
+
+        let mut types: Vec<TypeId> = location
+            .map(|location| self.archetypes[location.archetype].types.clone())
+            .unwrap_or_default();
+        let insert_at = types.binary_search(&type_id).unwrap_err();
+        types.insert(insert_at, type_id);
+
+        let target = self.archetype_for(types, location, || Box::new(TypedColumn::<T>::new()));
+        let row = self.move_entity(entity_id, location, target);
+        self.archetypes[target]
+            .column_mut::<T>()
+            .unwrap()
+            .push(value, ComponentTicks { added: tick, changed: tick });
+        self.set_location(entity_id, Some(EntityLocation { archetype: target, row }));
+    }
+
+    /// Removes the entity's T, moving it to the archetype without T. No-op if it has none.
+    pub fn remove(&mut self, entity_id: u32, type_id: TypeId) {
+        let Some(location) = self.location(entity_id) else {
+            return;
+        };
+        let Some(column_index) = self.archetypes[location.archetype].column_index(type_id) else {
+            return;
+        };
+
+        let mut types = self.archetypes[location.archetype].types.clone();
+        types.remove(column_index);
+        if types.is_empty() {
+            self.remove_entity(entity_id);
+            return;
+        }
+
+        // Drop the removed component first; move_entity then carries the rest over
+        self.archetypes[location.archetype].columns[column_index].swap_remove(location.row);
+        let target = self.archetype_for(types, Some(location), || unreachable!());
+        let row = self.move_entity(entity_id, Some(location), target);
+        self.set_location(entity_id, Some(EntityLocation { archetype: target, row }));
+    }
+
+    /// Drops every table component of the entity (used by despawn).
+    pub fn remove_entity(&mut self, entity_id: u32) {
+        let Some(location) = self.location(entity_id) else {
+            return;
+        };
+        let archetype = &mut self.archetypes[location.archetype];
+        for column in archetype.columns.iter_mut() {
+            column.swap_remove(location.row);
+        }
+        self.remove_row(location);
+        self.set_location(entity_id, None);
+    }
+
+    // Finds or creates the archetype for `types`. Columns for the new archetype are cloned
+    // (empty) from the entity's current archetype, except for one new type built by `new_column`.
+    fn archetype_for(
+        &mut self,
+        types: Vec<TypeId>,
+        from: Option<EntityLocation>,
+        new_column: impl FnOnce() -> Box<dyn Column>
+    ) -> usize {
+        if let Some(index) = self.by_types.get(&types) {
+            return *index;
+        }
+
+        let mut new_column = Some(new_column);
+        let columns: Vec<Box<dyn Column>> = types
+            .iter()
+            .map(|type_id| {
+                let existing = from.and_then(|location| {
+                    let archetype = &self.archetypes[location.archetype];
+                    archetype.column_index(*type_id).map(|i| archetype.columns[i].new_empty())
+                });
+                existing.unwrap_or_else(|| (new_column.take().unwrap())())
+            })
+            .collect();
+
+        let index = self.archetypes.len();
+        self.archetypes.push(Archetype { types: types.clone(), columns, entities: Vec::new() });
+        self.by_types.insert(types, index);
+        index
+    }
+
+    // Moves every column value the target archetype shares with the source, then pushes the
+    // entity id. Columns of the source that the target lacks must already have been removed.
+    // Returns the entity's new row.
+    fn move_entity(&mut self, entity_id: u32, from: Option<EntityLocation>, target: usize) -> usize {
+        if let Some(location) = from {
+            debug_assert_ne!(location.archetype, target);
+            let (src, dst) = index_pair_mut(&mut self.archetypes, location.archetype, target);
+            for (i, type_id) in src.types.iter().enumerate() {
+                if let Some(j) = dst.column_index(*type_id) {
+                    src.columns[i].move_row(location.row, dst.columns[j].as_mut());
+                }
+            }
+            self.remove_row(location);
+        }
+        let archetype = &mut self.archetypes[target];
+        archetype.entities.push(entity_id);
+        archetype.entities.len() - 1
+    }
+
+    // Removes the entity id at `location` (its column values are already gone) and
+    // fixes up the location of whichever entity got swapped into the gap.
+    fn remove_row(&mut self, location: EntityLocation) {
+        let archetype = &mut self.archetypes[location.archetype];
+        archetype.entities.swap_remove(location.row);
+        if let Some(moved) = archetype.entities.get(location.row).copied() {
+            self.set_location(moved, Some(location));
+        }
+    }
+}
+
+fn index_pair_mut<T>(items: &mut [T], a: usize, b: usize) -> (&mut T, &mut T) {
+    if a < b {
+        let (left, right) = items.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = items.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(f32);
+    #[derive(Debug, PartialEq)]
+    struct Velocity(f32);
+
+    #[test]
+    fn entities_with_same_components_share_an_archetype() {
+        let mut tables = Tables::default();
+        for id in 0..3 {
+            tables.insert(id, Position(id as f32), 0);
+            tables.insert(id, Velocity(1.0), 0);
+        }
+        let full: Vec<&Archetype> = tables
+            .archetypes()
+            .iter()
+            .filter(|a| !a.is_empty())
+            .collect();
+        assert_eq!(full.len(), 1);
+        assert_eq!(full[0].entities(), &[0, 1, 2]);
+        assert_eq!(full[0].column::<Position>().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn adding_component_moves_row_and_keeps_values() {
+        let mut tables = Tables::default();
+        tables.insert(0, Position(1.0), 0);
+        tables.insert(1, Position(2.0), 0);
+        tables.insert(0, Velocity(5.0), 1);
+        assert_eq!(tables.get::<Position>(0), Some(&Position(1.0)));
+        assert_eq!(tables.get::<Velocity>(0), Some(&Velocity(5.0)));
+        // Entity 1 was swapped into entity 0's old row
+        assert_eq!(tables.get::<Position>(1), Some(&Position(2.0)));
+        assert!(tables.get::<Velocity>(1).is_none());
+    }
+
+    #[test]
+    fn removing_component_moves_row_back() {
+        let mut tables = Tables::default();
+        tables.insert(0, Position(1.0), 0);
+        tables.insert(0, Velocity(5.0), 0);
+        tables.remove(0, TypeId::of::<Velocity>());
+        assert_eq!(tables.get::<Position>(0), Some(&Position(1.0)));
+        assert!(!tables.contains(0, TypeId::of::<Velocity>()));
+    }
+
+    #[test]
+    fn removing_last_component_clears_location() {
+        let mut tables = Tables::default();
+        tables.insert(0, Position(1.0), 0);
+        tables.remove(0, TypeId::of::<Position>());
+        assert!(tables.get::<Position>(0).is_none());
+        assert!(tables.archetypes().iter().all(|a| a.is_empty()));
+    }
+
+    #[test]
+    fn remove_entity_fixes_swapped_location() {
+        let mut tables = Tables::default();
+        for id in 0..3 {
+            tables.insert(id, Position(id as f32), 0);
+        }
+        tables.remove_entity(0);
+        assert!(tables.get::<Position>(0).is_none());
+        assert_eq!(tables.get::<Position>(1), Some(&Position(1.0)));
+        assert_eq!(tables.get::<Position>(2), Some(&Position(2.0)));
+    }
+
+    #[test]
+    fn overwrite_updates_changed_tick_only() {
+        let mut tables = Tables::default();
+        tables.insert(0, Position(1.0), 1);
+        tables.insert(0, Position(2.0), 4);
+        assert_eq!(tables.ticks::<Position>(0), Some(ComponentTicks { added: 1, changed: 4 }));
+    }
+}
@@ -0,0 +1,253 @@
+// Deferred world mutations.
+//
+// Systems can't call `World::spawn`/`despawn` while a query holds the world, so they queue
+// the change on `SystemContext::commands` instead. The queue is applied, in the order the
+// commands were recorded, at the end of every stage `SystemSchedule` runs:
+// - the startup systems, on the first frame
+// - each state transition's OnExit and OnEnter systems (state.rs), before the game stage
+// - the game systems
+// - each fixed step, so the next step sees what the last one spawned (zero or more a frame)
+// - the engine systems
+// `AppState` also applies it once after the game's `setup`.
+//
+// `spawn` reserves the entity handle immediately, so the caller can store it (e.g. as a
+// `Parent` target) before the entity has any components. Until the queue is applied the
+// reserved entity is not alive and queries won't see it, even if the world spawns other
+// entities directly in the meantime.
+
+use crate::engine::ecs::{
+    entity::{ Entity, EntityReserver },
+    prefab::PrefabOverrides,
+    world::World,
+};
+
+// Send so systems running on worker threads can each record into their own queue
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+pub struct Commands {
+    queue: Vec<Command>,
+    reserver: EntityReserver,
+}
+
+impl Commands {
+    pub fn new(world: &World) -> Self {
+        Self {
+            queue: Vec::new(),
+            reserver: world.entities.reserver(),
+        }
+    }
+
+    /// Reserves an entity and returns a builder for queueing its components.
+    pub fn spawn(&mut self) -> EntityCommands<'_> {
+        let entity = self.reserver.reserve();
+        EntityCommands { commands: self, entity }
+    }
+
+    /// Builder for queueing components onto an existing entity.
+    pub fn entity(&mut self, entity: Entity) -> EntityCommands<'_> {
+        EntityCommands { commands: self, entity }
+    }
+
+    /// Reserves an entity and queues spawning the prefab `name` onto it. If that fails the
+    /// error is logged and the entity despawned.
+    pub fn spawn_prefab(&mut self, name: &str, overrides: PrefabOverrides) -> Entity {
+        let entity = self.reserver.reserve();
+        let name = name.to_string();
+        self.push(move |world| {
+            if let Err(e) = world.build_prefab(entity, &name, overrides) {
+                log::error!("failed to spawn prefab {}: {:?}", name, e);
+                world.despawn_recursive(entity);
+            }
+        });
+        entity
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.push(move |world| world.despawn(entity));
+    }
+
+    pub fn despawn_recursive(&mut self, entity: Entity) {
+        self.push(move |world| world.despawn_recursive(entity));
+    }
+
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) {
+        self.push(move |world| {
+            if world.is_alive(child) && world.is_alive(parent) {
+                if let Err(e) = world.set_parent(child, parent) {
+                    log::warn!("{}", e);
+                }
+            }
+        });
+    }
+
+    /// Adds (or overwrites) a component. Skipped if the entity is dead by the time it applies.
+    pub fn insert<T: Send + 'static>(&mut self, entity: Entity, component: T) {
+        self.push(move |world| {
+            if world.is_alive(entity) {
+                world.add_component(entity, component);
+            }
+        });
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) {
+        self.push(move |world| world.remove_component::<T>(entity));
+    }
+
+    pub fn add_resource<T: Send + 'static>(&mut self, value: T) {
+        self.push(move |world| world.add_resource(value));
+    }
+
+    /// Escape hatch for anything the typed commands don't cover.
+    pub fn push(&mut self, command: impl FnOnce(&mut World) + Send + 'static) {
+        self.queue.push(Box::new(command));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// An empty queue that reserves entities from the same world as this one.
+    pub(crate) fn fork(&self) -> Self {
+        Self { queue: Vec::new(), reserver: self.reserver.clone() }
+    }
+
+    /// Moves every command queued in `other` onto the end of this queue.
+    pub(crate) fn append(&mut self, other: &mut Commands) {
+        self.queue.append(&mut other.queue);
+    }
+
+    /// Runs every queued command against `world`, oldest first, leaving the queue empty.
+    pub fn apply(&mut self, world: &mut World) {
+        // Reserved entities become live before any command can touch them
+        world.entities.flush_reserved();
+        for command in self.queue.drain(..) {
+            command(world);
+        }
+    }
+}
+
+pub struct EntityCommands<'c> {
+    commands: &'c mut Commands,
+    entity: Entity,
+}
+
+impl<'c> EntityCommands<'c> {
+    pub fn with<T: Send + 'static>(self, component: T) -> Self {
+        self.commands.insert(self.entity, component);
+        self
+    }
+
+    pub fn build(self) -> Entity {
+        self.entity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    struct Score(u32);
+
+    #[test]
+    fn spawn_is_deferred_until_apply() {
+        let mut world = World::new();
+        let mut commands = Commands::new(&world);
+        let e = commands.spawn().with(Health(10)).build();
+        assert!(!world.is_alive(e));
+        assert!(world.get_component::<Health>(e).is_none());
+
+        commands.apply(&mut world);
+        assert!(world.is_alive(e));
+        assert_eq!(world.get_component::<Health>(e), Some(&Health(10)));
+        assert!(commands.is_empty());
+    }
+
+    #[test]
+    fn reserved_handles_do_not_collide_with_direct_spawns() {
+        let mut world = World::new();
+        let mut commands = Commands::new(&world);
+        let deferred = commands.spawn().with(Health(1)).build();
+        let direct = world.spawn().with(Health(2)).build();
+        commands.apply(&mut world);
+        assert_ne!(deferred, direct);
+        assert_eq!(world.get_component::<Health>(deferred), Some(&Health(1)));
+        assert_eq!(world.get_component::<Health>(direct), Some(&Health(2)));
+    }
+
+    #[test]
+    fn direct_spawn_does_not_make_reserved_entities_alive_early() {
+        let mut world = World::new();
+        let mut commands = Commands::new(&world);
+        let deferred = commands.spawn().with(Health(1)).build();
+        let direct = world.spawn().build();
+        assert!(!world.is_alive(deferred));
+        assert!(world.get_entity(deferred.id).is_none());
+        assert_eq!(world.alive_entity_ids(), vec![direct.id]);
+        assert_eq!(world.live_entity_count(), 1);
+
+        commands.apply(&mut world);
+        assert!(world.is_alive(deferred));
+        assert!(world.is_alive(direct));
+        assert_eq!(world.live_entity_count(), 2);
+        assert_eq!(world.get_component::<Health>(deferred), Some(&Health(1)));
+    }
+
+    #[test]
+    fn spawn_can_be_queued_while_iterating_a_query() {
+        let mut world = World::new();
+        world.spawn().with(Health(1)).build();
+        world.spawn().with(Health(2)).build();
+        let mut commands = Commands::new(&world);
+        for health in world.query_iter::<&Health>() {
+            commands.spawn().with(Health(health.0 * 10));
+        }
+        commands.apply(&mut world);
+        let mut values: Vec<u32> = world
+            .iter_component::<Health>()
+            .map(|(_, h)| h.0)
+            .collect();
+        values.sort();
+        assert_eq!(values, vec![1, 2, 10, 20]);
+    }
+
+    #[test]
+    fn despawn_and_remove_apply_in_order() {
+        let mut world = World::new();
+        let a = world.spawn().with(Health(1)).build();
+        let b = world.spawn().with(Health(2)).build();
+        let mut commands = Commands::new(&world);
+        commands.remove::<Health>(a);
+        commands.despawn(b);
+        commands.apply(&mut world);
+        assert!(world.is_alive(a));
+        assert!(world.get_component::<Health>(a).is_none());
+        assert!(!world.is_alive(b));
+    }
+
+    #[test]
+    fn insert_after_despawn_is_skipped() {
+        let mut world = World::new();
+        let e = world.spawn().build();
+        let mut commands = Commands::new(&world);
+        commands.despawn(e);
+        commands.insert(e, Health(5));
+        commands.apply(&mut world);
+        assert_eq!(world.iter_component::<Health>().count(), 0);
+    }
+
+    #[test]
+    fn add_resource_is_deferred() {
+        let mut world = World::new();
+        let mut commands = Commands::new(&world);
+        commands.add_resource(Score(3));
+        assert!(world.get_resource::<Score>().is_none());
+        commands.apply(&mut world);
+        assert_eq!(world.get_resource::<Score>().unwrap().0, 3);
+    }
+}
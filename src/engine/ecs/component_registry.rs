@@ -1,18 +1,43 @@
 use std::collections::HashMap;
 use anyhow::Result;
-use serde::de::DeserializeOwned;
+use serde::{ de::DeserializeOwned, Serialize };
 
 use crate::engine::ecs::{
-    components::{ collider::Collider, transform::Transform, velocity::Velocity },
+    components::{
+        collider::Collider,
+        light::{ DirectionalLight, PointLight, SpotLight },
+        shadows::{ CastsShadows, ReceivesShadows },
+        transform::Transform,
+        velocity::Velocity,
+    },
     entity::Entity,
+    entity_ref::{ EntityNames, MapEntities },
     world::World,
 };
 
+// Send + Sync so the registry can be shared with the Prefabs resource
+type DeserializeFn = Box<
+    dyn Fn(&mut World, Entity, &mut dyn erased_serde::Deserializer) -> Result<()> + Send + Sync
+>;
+// Borrows the entity's component as something serializable, if it has one
+type SerializeFn = Box<dyn (Fn(&World, u32) -> Option<&dyn erased_serde::Serialize>) + Send + Sync>;
+type MapEntitiesFn = fn(&mut World, Entity, &EntityNames);
+// Copies the component between entities through its serialized form
+type CopyFn = fn(&mut World, Entity, Entity) -> Result<()>;
+
+struct RegisteredComponent {
+    name: String,
+    deserialize: DeserializeFn,
+    serialize: SerializeFn,
+    copy: CopyFn,
+    // Only for types registered with `register_mapped`
+    map_entities: Option<MapEntitiesFn>,
+}
+
 pub struct ComponentRegistry {
-    deserializers: HashMap<
-        String,
-        Box<dyn Fn(&mut World, Entity, &mut dyn erased_serde::Deserializer) -> Result<()>>
-    >,
+    // In registration order, which is also the order components are written by `save_world`
+    components: Vec<RegisteredComponent>,
+    by_name: HashMap<String, usize>,
 }
 
 impl ComponentRegistry {
@@ -29,30 +54,120 @@ impl ComponentRegistry {
         registry.register::<Transform>("Transform");
         registry.register::<Velocity>("Velocity");
         registry.register::<Collider>("Collider");
+        registry.register::<DirectionalLight>("DirectionalLight");
+        registry.register::<PointLight>("PointLight");
+        registry.register::<SpotLight>("SpotLight");
+        registry.register::<CastsShadows>("CastsShadows");
+        registry.register::<ReceivesShadows>("ReceivesShadows");
         // New components here ^
 
         registry
     }
 
     fn new_empty() -> Self {
-        Self { deserializers: HashMap::new() }
+        Self { components: Vec::new(), by_name: HashMap::new() }
     }
 
-    pub fn register<T: DeserializeOwned + 'static>(&mut self, name: &str) {
-        self.deserializers.insert(
-            name.to_string(),
-            Box::new(|world, entity, d| {
+    /// Registering a name again replaces the earlier type.
+    pub fn register<T: Serialize + DeserializeOwned + 'static>(&mut self, name: &str) {
+        self.insert(name, Self::registered::<T>(name));
+    }
+
+    /// Like `register`, for components holding `EntityRef`s. `load_world` maps them once the
+    /// whole file is spawned.
+    pub fn register_mapped<T: Serialize + DeserializeOwned + MapEntities + 'static>(
+        &mut self,
+        name: &str
+    ) {
+        let mut component = Self::registered::<T>(name);
+        component.map_entities = Some(|world, entity, names| {
+            if let Some(component) = world.get_component_mut::<T>(entity) {
+                component.map_entities(names);
+            }
+        });
+        self.insert(name, component);
+    }
+
+    fn registered<T: Serialize + DeserializeOwned + 'static>(name: &str) -> RegisteredComponent {
+        RegisteredComponent {
+            name: name.to_string(),
+            deserialize: Box::new(|world, entity, d| {
                 let component: T = erased_serde::deserialize(d)?;
                 world.add_component(entity, component);
                 Ok(())
-            })
-        );
+            }),
+            serialize: Box::new(|world, entity_id| {
+                world
+                    .get_component_by_id::<T>(entity_id)
+                    .map(|component| component as &dyn erased_serde::Serialize)
+            }),
+            copy: |world, from, to| {
+                let Some(component) = world.get_component::<T>(from) else {
+                    world.remove_component::<T>(to);
+                    return Ok(());
+                };
+                let copy: T = ron::from_str(&ron::to_string(component)?)?;
+                world.add_component(to, copy);
+                Ok(())
+            },
+            map_entities: None,
+        }
+    }
+
+    fn insert(&mut self, name: &str, component: RegisteredComponent) {
+        match self.by_name.get(name) {
+            Some(&index) => {
+                self.components[index] = component;
+            }
+            None => {
+                self.by_name.insert(name.to_string(), self.components.len());
+                self.components.push(component);
+            }
+        }
     }
 
     pub fn get(
         &self,
         name: &str
     ) -> Option<&dyn Fn(&mut World, Entity, &mut dyn erased_serde::Deserializer) -> Result<()>> {
-        self.deserializers.get(name).map(|boxed| boxed.as_ref())
+        let index = *self.by_name.get(name)?;
+        Some(self.components[index].deserialize.as_ref())
+    }
+
+    /// Registered names, in registration order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.components.iter().map(|component| component.name.as_str())
+    }
+
+    /// Maps the `EntityRef`s in every mapped component of `entities`.
+    pub fn map_entities(&self, world: &mut World, entities: &[Entity], names: &EntityNames) {
+        for map_entities in self.components.iter().filter_map(|component| component.map_entities) {
+            for &entity in entities {
+                map_entities(world, entity, names);
+            }
+        }
+    }
+
+    /// Overwrites every registered component of `to` with `from`'s, removing the ones `from`
+    /// doesn't have. Unregistered components are left alone.
+    pub fn copy_components(&self, world: &mut World, from: Entity, to: Entity) -> Result<()> {
+        for component in &self.components {
+            (component.copy)(world, from, to)?;
+        }
+        Ok(())
+    }
+
+    /// Every registered component the entity has, as `(name, value)` in registration order.
+    pub fn serializable_components<'w>(
+        &'w self,
+        world: &'w World,
+        entity_id: u32
+    ) -> Vec<(&'w str, &'w dyn erased_serde::Serialize)> {
+        self.components
+            .iter()
+            .filter_map(|component| {
+                (component.serialize)(world, entity_id).map(|value| (component.name.as_str(), value))
+            })
+            .collect()
     }
 }
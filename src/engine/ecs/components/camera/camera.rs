@@ -96,15 +96,17 @@ impl Camera {
         );
     }
 
-    pub fn build_view_projection_matrix(&self, position: Vector3<f32>) -> cgmath::Matrix4<f32> {
-        // Create a vector from camera eye to the view direction,
-        // calculated from the pitch and yaw
+    /// The view direction, calculated from the pitch and yaw
+    pub fn forward(&self) -> Vector3<f32> {
         let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
         let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+        Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize()
+    }
 
+    pub fn build_view_projection_matrix(&self, position: Vector3<f32>) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_to_rh(
             cgmath::Point3::new(position.x, position.y, position.z),
-            Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize(),
+            self.forward(),
             Vector3::unit_y()
         );
         // Warp the scene with a projeciton matrix
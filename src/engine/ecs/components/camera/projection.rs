@@ -37,6 +37,14 @@ impl Projection {
         self.aspect = (width as f32) / (height as f32);
     }
 
+    pub fn fov_y(&self) -> Rad<f32> {
+        self.fov_y
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
     pub fn calculate_projection_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fov_y, self.aspect, self.z_near, self.z_far)
     }
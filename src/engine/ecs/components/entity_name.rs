@@ -0,0 +1,4 @@
+/// The `"id"` an entity was given in a world file. `EntityRef`s in the file point at it by this
+/// name, and `save_world` writes it back out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntityName(pub String);
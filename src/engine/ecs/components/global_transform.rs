@@ -0,0 +1,74 @@
+use cgmath::{ ElementWise, Vector3 };
+
+use crate::engine::{ ecs::components::transform::Transform, instance::InstanceRaw };
+
+/// World-space transform, written every frame by `transform_propagate_system`.
+/// For root entities it equals their `Transform`; for children it is the parent's
+/// `GlobalTransform` combined with the child's local `Transform`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlobalTransform(pub Transform);
+
+impl GlobalTransform {
+    pub fn position(&self) -> Vector3<f32> {
+        self.0.position
+    }
+
+    /// Applies a child's local transform on top of this one.
+    /// Scale is combined per-axis, so a non-uniformly scaled parent with a rotated child
+    /// only approximates the full matrix product (no shear) - fine for rigs and attachments.
+    pub fn mul_transform(&self, local: &Transform) -> GlobalTransform {
+        let parent = &self.0;
+        GlobalTransform(Transform {
+            position: parent.position +
+            parent.rotation * parent.scale.mul_element_wise(local.position),
+            rotation: parent.rotation * local.rotation,
+            scale: parent.scale.mul_element_wise(local.scale),
+        })
+    }
+
+    pub fn to_raw(&self) -> InstanceRaw {
+        self.0.to_raw()
+    }
+}
+
+impl From<Transform> for GlobalTransform {
+    fn from(transform: Transform) -> Self {
+        Self(transform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{ Deg, Quaternion, Rotation3 };
+
+    fn assert_vec_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).x.abs() < 1e-5 && (a - b).y.abs() < 1e-5 && (a - b).z.abs() < 1e-5);
+    }
+
+    #[test]
+    fn child_position_is_offset_from_parent() {
+        let parent = GlobalTransform(Transform::new().with_position(10.0, 0.0, 0.0));
+        let child = parent.mul_transform(&Transform::new().with_position(1.0, 2.0, 3.0));
+        assert_vec_close(child.position(), Vector3::new(11.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn parent_scale_scales_child_offset_and_size() {
+        let parent = GlobalTransform(Transform::new().with_scale(2.0, 2.0, 2.0));
+        let child = parent.mul_transform(
+            &Transform::new().with_position(1.0, 0.0, 0.0).with_scale(0.5, 0.5, 0.5)
+        );
+        assert_vec_close(child.position(), Vector3::new(2.0, 0.0, 0.0));
+        assert_vec_close(child.0.scale, Vector3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn parent_rotation_rotates_child_offset() {
+        let parent = GlobalTransform(
+            Transform::new().with_rotation(Quaternion::from_angle_y(Deg(90.0)))
+        );
+        let child = parent.mul_transform(&Transform::new().with_position(1.0, 0.0, 0.0));
+        assert_vec_close(child.position(), Vector3::new(0.0, 0.0, -1.0));
+    }
+}
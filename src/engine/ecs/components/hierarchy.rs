@@ -0,0 +1,11 @@
+use crate::engine::ecs::entity::Entity;
+
+/// Attaches an entity to a parent. Its `Transform` is then relative to the parent's.
+/// Maintained by `World::set_parent` / `World::remove_parent` - don't add it directly,
+/// or the parent's `Children` won't know about it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Parent(pub Entity);
+
+/// The direct children of an entity, in attach order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Children(pub Vec<Entity>);
@@ -0,0 +1,80 @@
+use serde::{ Deserialize, Serialize };
+
+// Light components. They take their position and direction from the entity's transform
+// (`GlobalTransform` if it has one) - lights point down their local -z, so rotate the entity to
+// aim them. `light_sync_system` uploads them each frame.
+
+fn white() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// Lights everything from one direction, e.g. the sun. Its position is ignored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    #[serde(default = "one")]
+    pub intensity: f32,
+    /// Casts cascaded shadows that follow the active camera. Only the first directional light
+    /// with this set gets them.
+    #[serde(default)]
+    pub shadows: bool,
+}
+
+/// Shines in every direction, fading out to nothing at `range`. Doesn't cast shadows.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PointLight {
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    #[serde(default = "one")]
+    pub intensity: f32,
+    pub range: f32,
+}
+
+/// A point light limited to a cone. Full brightness within `inner_angle` of its direction,
+/// fading to none at `outer_angle` (both half angles, in degrees).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpotLight {
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    #[serde(default = "one")]
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    /// Casts shadows, for the `MAX_SHADOWED_SPOT_LIGHTS` nearest the camera.
+    #[serde(default)]
+    pub shadows: bool,
+}
+
+impl DirectionalLight {
+    pub fn new(color: [f32; 3], intensity: f32) -> Self {
+        Self { color, intensity, shadows: false }
+    }
+
+    pub fn with_shadows(mut self) -> Self {
+        self.shadows = true;
+        self
+    }
+}
+
+impl PointLight {
+    pub fn new(color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Self { color, intensity, range }
+    }
+}
+
+impl SpotLight {
+    pub fn new(color: [f32; 3], intensity: f32, range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Self { color, intensity, range, inner_angle, outer_angle, shadows: false }
+    }
+
+    pub fn with_shadows(mut self) -> Self {
+        self.shadows = true;
+        self
+    }
+}
@@ -3,3 +3,9 @@ pub mod renderable;
 pub mod velocity;
 pub mod camera;
 pub mod collider;
+pub mod global_transform;
+pub mod hierarchy;
+pub mod previous_transform;
+pub mod entity_name;
+pub mod light;
+pub mod shadows;
@@ -0,0 +1,56 @@
+use cgmath::InnerSpace;
+
+use crate::engine::ecs::components::transform::Transform;
+
+/// World-space transform as it was before the latest fixed step, written by
+/// `previous_transform_system`. `render_sync_system` blends from this to the current transform
+/// by `FixedTime::alpha`, so movement that only happens in fixed steps still looks smooth at
+/// framerates above the tick rate.
+///
+/// Added automatically to anything with a `Velocity`; add it by hand to interpolate entities that
+/// other fixed systems move.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviousTransform(pub Transform);
+
+impl PreviousTransform {
+    /// Linear blend for position and scale, normalised lerp for rotation - steps are small
+    /// enough that nlerp and slerp are indistinguishable.
+    pub fn lerp(&self, current: &Transform, alpha: f32) -> Transform {
+        let previous = &self.0;
+        // Take the short way round
+        let rotation = if previous.rotation.dot(current.rotation) < 0.0 {
+            -current.rotation
+        } else {
+            current.rotation
+        };
+        Transform {
+            position: previous.position + (current.position - previous.position) * alpha,
+            rotation: (previous.rotation + (rotation - previous.rotation) * alpha).normalize(),
+            scale: previous.scale + (current.scale - previous.scale) * alpha,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{ Deg, Quaternion, Rotation3, Vector3 };
+
+    #[test]
+    fn lerp_blends_position_by_alpha() {
+        let previous = PreviousTransform(Transform::new().with_position(0.0, 0.0, 0.0));
+        let current = Transform::new().with_position(10.0, 0.0, 2.0);
+        assert_eq!(previous.lerp(&current, 0.0).position, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(previous.lerp(&current, 0.25).position, Vector3::new(2.5, 0.0, 0.5));
+        assert_eq!(previous.lerp(&current, 1.0).position, current.position);
+    }
+
+    #[test]
+    fn lerp_rotation_stays_normalised() {
+        let previous = PreviousTransform(Transform::new());
+        let current = Transform::new().with_rotation(Quaternion::from_angle_y(Deg(90.0)));
+        let halfway = previous.lerp(&current, 0.5).rotation;
+        assert!((halfway.magnitude() - 1.0).abs() < 1e-5);
+        assert!(halfway.dot(Quaternion::from_angle_y(Deg(45.0))) > 0.9999);
+    }
+}
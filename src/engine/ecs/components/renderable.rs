@@ -1,9 +1,12 @@
+use crate::engine::{ assets::handle::Handle, model::model::Model };
+
+// Holds a strong handle, so a model stays loaded while any entity renders it
 pub struct Renderable {
-    pub model_id: usize,
+    pub model: Handle<Model>,
 }
 
 impl Renderable {
-    pub fn new(model_id: usize) -> Self {
-        Self { model_id }
+    pub fn new(model: Handle<Model>) -> Self {
+        Self { model }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,11 @@
+use serde::{ Deserialize, Serialize };
+
+/// Drawn into the shadow maps, so it shadows whatever is behind it from a shadow-casting light
+/// (`DirectionalLight::shadows`, `SpotLight::shadows`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CastsShadows;
+
+/// Darkened where the shadow maps say a caster is between it and the light. Entities without
+/// it are lit as if nothing were in the way.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReceivesShadows;
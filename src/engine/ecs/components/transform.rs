@@ -3,7 +3,9 @@ use serde::{ Deserialize, Serialize };
 
 use crate::engine::instance::InstanceRaw;
 
-#[derive(PartialEq, Serialize, Deserialize)]
+/// Local transform. For entities with a `Parent` this is relative to the parent;
+/// the world-space result is written to `GlobalTransform` by `transform_propagate_system`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Transform {
     pub position: Vector3<f32>,
     pub rotation: Quaternion<f32>,
@@ -0,0 +1,79 @@
+// Run conditions
+//
+// A condition is checked right before its system (or every system in its set) would run, and the
+// system is skipped for the frame if it returns false:
+//
+//     schedule.add_game_system(player_system.run_if(in_state(GameState::Playing)));
+//     schedule.add_game_system(laser_log_system.run_if(on_event::<LaserFiredEvent>()));
+//
+// Conditions only get `&World` and are shared between the members of a set, so they are plain
+// `Fn`s. Keep state in a resource if a condition needs it.
+
+use std::sync::Arc;
+
+use crate::engine::{
+    ecs::{ state::{ State, States }, world::World },
+    events::events::Events,
+};
+
+pub type RunCondition = Arc<dyn Fn(&World) -> bool + Send + Sync>;
+
+pub fn resource_exists<T: 'static>() -> impl Fn(&World) -> bool + Send + Sync + Clone {
+    |world: &World| world.get_resource::<T>().is_some()
+}
+
+/// True while the resource exists and equals `value`.
+pub fn resource_equals<T: PartialEq + Send + Sync + 'static>(
+    value: T
+) -> impl Fn(&World) -> bool + Send + Sync {
+    move |world: &World| world.get_resource::<T>() == Some(&value)
+}
+
+/// True when `Events<T>` has anything to read - see `Events::read`.
+pub fn on_event<T: 'static>() -> impl Fn(&World) -> bool + Send + Sync + Clone {
+    |world: &World| {
+        world
+            .get_resource::<Events<T>>()
+            .is_some_and(|events| events.read().next().is_some())
+    }
+}
+
+/// True while `State<S>` is `state`. Use it on a set to scope a group of systems to a state.
+pub fn in_state<S: States>(state: S) -> impl Fn(&World) -> bool + Send + Sync + Clone {
+    move |world: &World| world.get_resource::<State<S>>().is_some_and(|current| current.get() == state)
+}
+
+pub fn not(condition: impl Fn(&World) -> bool + Send + Sync) -> impl Fn(&World) -> bool + Send + Sync {
+    move |world: &World| !condition(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(PartialEq)]
+    struct Paused(bool);
+    struct Ping;
+
+    #[test]
+    fn resource_conditions() {
+        let mut world = World::new();
+        assert!(!resource_exists::<Paused>()(&world));
+        assert!(!resource_equals(Paused(false))(&world));
+        world.add_resource(Paused(false));
+        assert!(resource_exists::<Paused>()(&world));
+        assert!(resource_equals(Paused(false))(&world));
+        assert!(not(resource_equals(Paused(true)))(&world));
+    }
+
+    #[test]
+    fn on_event_sees_readable_events_only() {
+        let mut world = World::new();
+        world.add_resource(Events::<Ping>::default());
+        world.get_resource_mut::<Events<Ping>>().unwrap().send(Ping);
+        // Sent this frame - readable after the swap
+        assert!(!on_event::<Ping>()(&world));
+        world.get_resource_mut::<Events<Ping>>().unwrap().swap();
+        assert!(on_event::<Ping>()(&world));
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Copy, Clone, Debug, PartialEq)]
+use std::sync::{ atomic::{ AtomicU32, Ordering }, Arc };
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Entity {
     pub id: u32,
     generation: u32,
@@ -7,7 +9,29 @@ pub struct Entity {
 pub struct EntityAllocator {
     generations: Vec<u32>,
     free_ids: Vec<u32>,
-    next_id: u32,
+    // Shared with any EntityReservers so ids can be handed out without &mut access.
+    // Slots between generations.len() and next_id are reserved but not yet flushed.
+    next_id: Arc<AtomicU32>,
+    // Reserved ids below generations.len() - a direct spawn grew past them - that still wait
+    // for `flush_reserved`. Not alive until then
+    unflushed: Vec<u32>,
+}
+
+/// Hands out fresh entity ids without borrowing the allocator, so `Commands` can
+/// give callers a usable `Entity` while a query still holds the World.
+/// Reserved ids never come from the free list - they become live on `flush_reserved`.
+#[derive(Clone)]
+pub struct EntityReserver {
+    next_id: Arc<AtomicU32>,
+}
+
+impl EntityReserver {
+    pub fn reserve(&self) -> Entity {
+        Entity {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            generation: 0,
+        }
+    }
 }
 
 impl EntityAllocator {
@@ -19,7 +43,21 @@ impl EntityAllocator {
         Self {
             generations: Vec::new(),
             free_ids: Vec::new(),
-            next_id: 0,
+            next_id: Arc::new(AtomicU32::new(0)),
+            unflushed: Vec::new(),
+        }
+    }
+
+    pub fn reserver(&self) -> EntityReserver {
+        EntityReserver { next_id: self.next_id.clone() }
+    }
+
+    /// Makes every reserved id a live slot.
+    pub fn flush_reserved(&mut self) {
+        self.unflushed.clear();
+        let next_id = self.next_id.load(Ordering::Relaxed) as usize;
+        if next_id > self.generations.len() {
+            self.generations.resize(next_id, 0);
         }
     }
 
@@ -30,9 +68,10 @@ impl EntityAllocator {
                 generation: self.generations[id as usize],
             }
         } else {
-            let id = self.next_id;
-            self.next_id += 1;
-            self.generations.push(0);
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            // Ids reserved before this one get slots too, but stay dead until they're flushed
+            self.unflushed.extend(self.generations.len() as u32..id);
+            self.generations.resize((id as usize) + 1, 0);
             Entity {
                 id,
                 generation: 0,
@@ -44,19 +83,28 @@ impl EntityAllocator {
     pub fn lookup(&self, id: u32) -> Option<Entity> {
         let generation = *self.generations.get(id as usize)?;
         // free_ids holds despawned slots — those aren't currently alive
-        if self.free_ids.contains(&id) {
+        if self.free_ids.contains(&id) || self.unflushed.contains(&id) {
             return None;
         }
         Some(Entity { id, generation })
     }
 
+    /// Ids of every currently alive slot, in id order.
+    pub fn alive_ids(&self) -> Vec<u32> {
+        let mut alive = vec![true; self.generations.len()];
+        for id in self.free_ids.iter().chain(&self.unflushed) {
+            alive[*id as usize] = false;
+        }
+        (0..self.generations.len() as u32).filter(|id| alive[*id as usize]).collect()
+    }
+
     pub fn despawn(&mut self, entity: &Entity) {
         self.free_ids.push(entity.id);
         self.generations[entity.id as usize] += 1;
     }
 
     pub fn is_alive(&self, entity: &Entity) -> bool {
-        if (entity.id as usize) >= self.generations.len() {
+        if (entity.id as usize) >= self.generations.len() || self.unflushed.contains(&entity.id) {
             return false;
         }
 
@@ -65,7 +113,7 @@ impl EntityAllocator {
 
     pub fn live_count(&self) -> usize {
         // n_registered_entities - n_freed_ids basically
-        (self.next_id as usize) - self.free_ids.len()
+        self.generations.len() - self.free_ids.len() - self.unflushed.len()
     }
 }
 
@@ -107,6 +155,41 @@ mod tests {
         assert!(alloc.is_alive(&b));
     }
 
+    #[test]
+    fn alive_ids_skips_despawned_slots() {
+        let mut alloc = EntityAllocator::new();
+        let a = alloc.spawn();
+        let b = alloc.spawn();
+        let c = alloc.spawn();
+        alloc.despawn(&b);
+        assert_eq!(alloc.alive_ids(), vec![a.id, c.id]);
+    }
+
+    #[test]
+    fn reserved_entity_is_alive_after_flush() {
+        let mut alloc = EntityAllocator::new();
+        let reserved = alloc.reserver().reserve();
+        assert!(!alloc.is_alive(&reserved));
+        alloc.flush_reserved();
+        assert!(alloc.is_alive(&reserved));
+    }
+
+    #[test]
+    fn spawn_after_reserve_does_not_reuse_reserved_id() {
+        let mut alloc = EntityAllocator::new();
+        let reserved = alloc.reserver().reserve();
+        let spawned = alloc.spawn();
+        assert_ne!(reserved.id, spawned.id);
+        // Spawning past a reservation doesn't make it alive - only a flush does
+        assert!(!alloc.is_alive(&reserved));
+        assert_eq!(alloc.lookup(reserved.id), None);
+        assert_eq!(alloc.alive_ids(), vec![spawned.id]);
+        assert_eq!(alloc.live_count(), 1);
+        alloc.flush_reserved();
+        assert!(alloc.is_alive(&reserved));
+        assert_eq!(alloc.live_count(), 2);
+    }
+
     #[test]
     fn is_alive_with_out_of_range_id_returns_false() {
         let alloc = EntityAllocator::new();
@@ -0,0 +1,64 @@
+// Entity references that survive a round trip through a world file.
+//
+// `Entity` ids only mean something in the running world, so a component that points at another
+// entity stores an `EntityRef` instead. In RON it's just the target's `"id"`:
+//
+//     { "id": "player", ... },
+//     { "Turret": (target: "player") },
+//
+// and `load_world` points it at the live entity once the whole file is spawned. The component
+// type implements `MapEntities` and is registered with `ComponentRegistry::register_mapped`.
+
+use std::collections::HashMap;
+
+use serde::{ Deserialize, Deserializer, Serialize, Serializer };
+
+use crate::engine::ecs::entity::Entity;
+
+/// The `"id" -> Entity` map returned by `load_world`.
+pub type EntityNames = HashMap<String, Entity>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntityRef {
+    name: String,
+    // None until mapped, or if no entity in the file has that id
+    entity: Option<Entity>,
+}
+
+impl EntityRef {
+    pub fn new(name: &str, entity: Entity) -> Self {
+        Self { name: name.to_string(), entity: Some(entity) }
+    }
+
+    pub fn get(&self) -> Option<Entity> {
+        self.entity
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Points the reference at the entity with its name. Returns false if there isn't one.
+    pub fn map(&mut self, names: &EntityNames) -> bool {
+        self.entity = names.get(&self.name).copied();
+        self.entity.is_some()
+    }
+}
+
+impl Serialize for EntityRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+impl<'de> Deserialize<'de> for EntityRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(Self { name, entity: None })
+    }
+}
+
+/// Implemented by components holding `EntityRef`s, so loading can map each of them.
+pub trait MapEntities {
+    fn map_entities(&mut self, names: &EntityNames);
+}
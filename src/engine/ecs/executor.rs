@@ -0,0 +1,339 @@
+// Stage executor
+//
+// A stage's systems are split into batches: taking systems in their sorted run order, each goes
+// in the earliest batch after the last batch holding a system it conflicts with (see
+// `SystemAccess::conflicts_with`) or is ordered after. So two systems that touch the same data in
+// a conflicting way always run in schedule order, while independent systems can share a batch.
+// Exclusive `&mut World` systems conflict with everything and always get a batch to themselves.
+//
+// Run conditions are checked when a system's batch comes up, so they see everything earlier
+// batches did. On native builds the systems of a batch run on rayon's thread pool. Each one
+// records into its own `Commands` queue, and those queues are appended to `SystemContext::commands` in schedule
+// order once the batch is done. On wasm32 there are no threads, so every system runs in order
+// on the calling thread.
+
+use crate::engine::ecs::{
+    system::{ ScheduledSystem, SystemContext },
+    world::World,
+};
+
+/// Groups systems into batches that can run together. `order` is a valid run order (system
+/// indices); `must_follow(later, earlier)` says whether `later` has to wait for `earlier`, either
+/// because their access conflicts or because of an ordering constraint.
+pub fn compute_batches(
+    order: &[usize],
+    must_follow: impl Fn(usize, usize) -> bool
+) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    for &i in order {
+        let earliest = batches
+            .iter()
+            .rposition(|batch| batch.iter().any(|&j| must_follow(i, j)))
+            .map_or(0, |last_conflict| last_conflict + 1);
+        match batches.get_mut(earliest) {
+            Some(batch) => batch.push(i),
+            None => batches.push(vec![i]),
+        }
+    }
+    batches
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn run_stage(
+    systems: &mut [ScheduledSystem],
+    batches: &[Vec<usize>],
+    world: &mut World,
+    system_context: &mut SystemContext
+) {
+    for &i in batches.iter().flatten() {
+        if systems[i].should_run(world) {
+            systems[i].run(world, system_context);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn run_stage(
+    systems: &mut [ScheduledSystem],
+    batches: &[Vec<usize>],
+    world: &mut World,
+    system_context: &mut SystemContext
+) {
+    for batch in batches {
+        // Conditions are checked on this thread before anything in the batch starts
+        let runnable: Vec<usize> = batch
+            .iter()
+            .copied()
+            .filter(|&i| systems[i].should_run(world))
+            .collect();
+        match runnable.as_slice() {
+            [] => {}
+            [single] => systems[*single].run(world, system_context),
+            _ => run_batch_parallel(systems, &runnable, world, system_context),
+        }
+    }
+}
+
+// Lets worker threads share the world pointer. Sound because systems in one batch never
+// conflict, shared/mutable params require Sync/Send data (see system_param.rs), and params only
+// ever make shared references to the World - mutable components and resources are reached
+// through their `UnsafeCell`s (`get_component_mut_unchecked`, `get_resource_mut_unchecked`).
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Copy, Clone)]
+struct WorldPtr(*mut World);
+
+#[cfg(not(target_arch = "wasm32"))]
+unsafe impl Send for WorldPtr {}
+#[cfg(not(target_arch = "wasm32"))]
+unsafe impl Sync for WorldPtr {}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run_batch_parallel(
+    systems: &mut [ScheduledSystem],
+    batch: &[usize],
+    world: &mut World,
+    system_context: &mut SystemContext
+) {
+    use crate::engine::ecs::commands::Commands;
+
+    let world = WorldPtr(world as *mut World);
+    let delta_time = system_context.delta_time;
+    // Batches never contain exclusive systems, so nothing here needs the GPU handles or the
+    // asset server - each job only gets delta time and its own command queue. Jobs are in the
+    // batch's order, which is run order, so their commands are appended as wasm32 applies them
+    let mut slots: Vec<Option<&mut ScheduledSystem>> = systems.iter_mut().map(Some).collect();
+    let mut jobs: Vec<(&mut ScheduledSystem, Commands)> = batch
+        .iter()
+        .map(|&i| {
+            let system = slots[i].take().expect("a system is listed twice in one batch");
+            (system, system_context.commands.fork())
+        })
+        .collect();
+
+    rayon::scope(|scope| {
+        for (system, commands) in jobs.iter_mut() {
+            scope.spawn(move |_| {
+                let mut job_context = SystemContext {
+                    delta_time,
+                    device: None,
+                    queue: None,
+                    asset_server: None,
+                    commands: std::mem::replace(commands, commands.fork()),
+                };
+                let world = world;
+                // Safety: see WorldPtr
+                unsafe { system.run_shared(world.0, &mut job_context) };
+                *commands = job_context.commands;
+            });
+        }
+    });
+
+    for (_, commands) in jobs.iter_mut() {
+        system_context.commands.append(commands);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ecs::{
+        commands::Commands,
+        query::{ Added, Changed, With, Without },
+        system::{ IntoSystem, System, SystemSchedule },
+        system_config::IntoSystemConfig,
+        system_param::{ Query, Res, ResMut, SystemAccess },
+    };
+
+    struct A(u32);
+    struct B(u32);
+    struct Log(Vec<&'static str>);
+
+    fn reads_a(_a: Res<A>) {}
+    fn writes_a(_a: ResMut<A>) {}
+    fn writes_b(_b: ResMut<B>) {}
+    fn exclusive(_world: &mut World, _ctx: &mut SystemContext) {}
+
+    fn batches_of(accesses: &[SystemAccess]) -> Vec<Vec<usize>> {
+        let order: Vec<usize> = (0..accesses.len()).collect();
+        compute_batches(&order, |later, earlier| accesses[later].conflicts_with(&accesses[earlier]))
+    }
+
+    fn access_of<M>(system: impl IntoSystem<M>) -> SystemAccess {
+        system.into_system().access().clone()
+    }
+
+    #[test]
+    fn readers_share_a_batch_and_writers_wait() {
+        let batches = batches_of(
+            &[access_of(reads_a), access_of(reads_a), access_of(writes_a), access_of(reads_a)]
+        );
+        assert_eq!(batches, vec![vec![0, 1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn independent_system_joins_earliest_batch() {
+        let batches = batches_of(&[access_of(writes_a), access_of(writes_a), access_of(writes_b)]);
+        assert_eq!(batches, vec![vec![0, 2], vec![1]]);
+    }
+
+    #[test]
+    fn exclusive_systems_run_alone_and_keep_order() {
+        let batches = batches_of(
+            &[access_of(writes_a), access_of(exclusive), access_of(writes_b)]
+        );
+        assert_eq!(batches, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn query_component_access_is_checked() {
+        struct Position;
+        let batches = batches_of(
+            &[
+                access_of(|_q: Query<&Position>| {}),
+                access_of(|_q: Query<&Position>| {}),
+                access_of(|_q: Query<&mut Position>| {}),
+            ]
+        );
+        assert_eq!(batches, vec![vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn change_filters_wait_for_writers_of_their_component() {
+        struct Position;
+        struct Velocity;
+        let batches = batches_of(
+            &[
+                access_of(|_q: Query<(&Position, Changed<Velocity>)>| {}),
+                access_of(|_q: Query<&mut Velocity>| {}),
+                access_of(|_q: Query<(&Position, Added<Velocity>)>| {}),
+            ]
+        );
+        assert_eq!(batches, vec![vec![0], vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn presence_filters_share_a_batch_with_writers_of_their_component() {
+        struct Position;
+        struct Velocity(f32);
+        let mut world = World::new();
+        world.register_component::<Position>();
+        world.register_component::<Velocity>();
+        let moving = world.spawn().with(Position).with(Velocity(1.0)).build();
+        world.spawn().with(Position).build();
+        world.add_resource(A(0));
+        world.add_resource(B(0));
+
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(|mut q: Query<(&Position, With<Velocity>)>, mut a: ResMut<A>| {
+            a.0 = q.iter().count() as u32;
+        });
+        schedule.add_game_system(|mut q: Query<(&Position, Without<Velocity>)>, mut b: ResMut<B>| {
+            b.0 = q.iter().count() as u32;
+        });
+        schedule.add_game_system(|mut q: Query<&mut Velocity>| {
+            for velocity in q.iter() {
+                velocity.0 *= 2.0;
+            }
+        });
+        schedule.build().unwrap();
+        // With/Without only look at which entities have a Velocity, never at the value
+        let game_batches = &schedule.batches()[1].1;
+        assert_eq!(game_batches.len(), 1);
+        assert_eq!(game_batches[0].len(), 3);
+
+        let mut ctx = SystemContext {
+            delta_time: 0.016,
+            device: None,
+            queue: None,
+            asset_server: None,
+            commands: Commands::new(&world),
+        };
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<A>().unwrap().0, 1);
+        assert_eq!(world.get_resource::<B>().unwrap().0, 1);
+        assert_eq!(world.get_component::<Velocity>(moving).unwrap().0, 2.0);
+    }
+
+    #[test]
+    fn change_filter_on_its_own_mutable_component_is_allowed() {
+        struct Velocity;
+        let access = access_of(|_q: Query<(&mut Velocity, Changed<Velocity>)>| {});
+        assert!(access.self_conflict().is_none());
+    }
+
+    #[test]
+    fn parallel_batch_results_and_commands_match_sequential_order() {
+        let mut world = World::new();
+        world.add_resource(A(0));
+        world.add_resource(B(0));
+        world.add_resource(Log(Vec::new()));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(|mut a: ResMut<A>, commands: &mut Commands| {
+            a.0 += 1;
+            commands.push(|world| world.get_resource_mut::<Log>().unwrap().0.push("a"));
+        });
+        schedule.add_game_system(|mut b: ResMut<B>, commands: &mut Commands| {
+            b.0 += 2;
+            commands.push(|world| world.get_resource_mut::<Log>().unwrap().0.push("b"));
+        });
+        schedule.build().unwrap();
+        let game_batches = &schedule.batches()[1].1;
+        assert_eq!(game_batches.len(), 1);
+        assert_eq!(game_batches[0].len(), 2);
+
+        let mut ctx = SystemContext {
+            delta_time: 0.016,
+            device: None,
+            queue: None,
+            asset_server: None,
+            commands: Commands::new(&world),
+        };
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<A>().unwrap().0, 1);
+        assert_eq!(world.get_resource::<B>().unwrap().0, 2);
+        assert_eq!(world.get_resource::<Log>().unwrap().0, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn parallel_commands_are_appended_in_run_order_not_insertion_order() {
+        fn log(commands: &mut Commands, entry: &'static str) {
+            commands.push(move |world| world.get_resource_mut::<Log>().unwrap().0.push(entry));
+        }
+        fn zero(commands: &mut Commands) { log(commands, "0") }
+        fn three(_b: ResMut<B>, commands: &mut Commands) { log(commands, "3") }
+        let mut world = World::new();
+        world.add_resource(A(0));
+        world.add_resource(B(0));
+        world.add_resource(Log(Vec::new()));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(zero.after("three"));
+        schedule.add_game_system(|_a: ResMut<A>, commands: &mut Commands| log(commands, "1"));
+        schedule.add_game_system(|_a: ResMut<A>, commands: &mut Commands| log(commands, "2"));
+        schedule.add_game_system(three);
+        schedule.build().unwrap();
+        // Run order is 1, 2, 3, 0: batches [1, 3] and [2, 0]
+        let game_batches = &schedule.batches()[1].1;
+        assert_eq!(game_batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2]);
+
+        let mut ctx = SystemContext {
+            delta_time: 0.016,
+            device: None,
+            queue: None,
+            asset_server: None,
+            commands: Commands::new(&world),
+        };
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Log>().unwrap().0, vec!["1", "3", "2", "0"]);
+    }
+
+    #[test]
+    fn batch_report_lists_short_names() {
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(writes_a);
+        schedule.add_game_system(writes_b);
+        schedule.build().unwrap();
+        let report = schedule.batch_report();
+        assert!(report.contains("game:\n  0: writes_a, writes_b\n"), "{}", report);
+        assert!(report.contains("engine:"), "{}", report);
+    }
+}
@@ -0,0 +1,146 @@
+// Parent/child relationships between entities.
+//
+// The links live in two components: `Parent` on the child and `Children` on the parent.
+// Always go through these World methods (or the matching `Commands`) so both sides stay in
+// sync - `World::despawn` relies on that to unhook a despawned entity from its relatives.
+
+use anyhow::{ bail, Result };
+
+use crate::engine::ecs::{
+    components::hierarchy::{ Children, Parent },
+    entity::Entity,
+    world::World,
+};
+
+impl World {
+    /// Attaches `child` to `parent`, detaching it from any previous parent first.
+    /// Fails if that would create a cycle (including parenting an entity to itself).
+    pub fn set_parent(&mut self, child: Entity, parent: Entity) -> Result<()> {
+        if child == parent || self.descendants(child).contains(&parent) {
+            bail!("cannot parent {:?} to {:?}: it would create a cycle", child, parent);
+        }
+        self.remove_parent(child);
+        self.add_component(child, Parent(parent));
+        match self.get_component_mut::<Children>(parent) {
+            Some(children) => children.0.push(child),
+            None => self.add_component(parent, Children(vec![child])),
+        }
+        Ok(())
+    }
+
+    /// Detaches `child` from its parent, making it a root. No-op if it has no parent.
+    pub fn remove_parent(&mut self, child: Entity) {
+        let Some(Parent(parent)) = self.get_component::<Parent>(child).copied() else {
+            return;
+        };
+        self.remove_component::<Parent>(child);
+        if let Some(children) = self.get_component_mut::<Children>(parent) {
+            children.0.retain(|entity| *entity != child);
+        }
+    }
+
+    /// Every entity below `entity` in the hierarchy, depth first. Excludes `entity` itself.
+    pub fn descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut out = Vec::new();
+        let mut stack: Vec<Entity> = self
+            .get_component::<Children>(entity)
+            .map(|children| children.0.clone())
+            .unwrap_or_default();
+        while let Some(next) = stack.pop() {
+            out.push(next);
+            if let Some(children) = self.get_component::<Children>(next) {
+                stack.extend(children.0.iter().copied());
+            }
+        }
+        out
+    }
+
+    /// Despawns `entity` and its whole subtree. Plain `despawn` only removes `entity`,
+    /// leaving its children in place as new roots.
+    pub fn despawn_recursive(&mut self, entity: Entity) {
+        for descendant in self.descendants(entity) {
+            self.despawn(descendant);
+        }
+        self.despawn(entity);
+    }
+
+    /// Unhooks `entity` from its parent's `Children`, and orphans its own children.
+    pub(crate) fn detach_from_hierarchy(&mut self, entity: Entity) {
+        self.remove_parent(entity);
+        if let Some(Children(children)) = self.get_component::<Children>(entity).cloned() {
+            for child in children {
+                self.remove_component::<Parent>(child);
+            }
+            self.remove_component::<Children>(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_parent_links_both_sides() {
+        let mut world = World::new();
+        let parent = world.spawn().build();
+        let child = world.spawn().build();
+        world.set_parent(child, parent).unwrap();
+        assert_eq!(world.get_component::<Parent>(child), Some(&Parent(parent)));
+        assert_eq!(world.get_component::<Children>(parent), Some(&Children(vec![child])));
+    }
+
+    #[test]
+    fn reparenting_removes_child_from_old_parent() {
+        let mut world = World::new();
+        let old = world.spawn().build();
+        let new = world.spawn().build();
+        let child = world.spawn().build();
+        world.set_parent(child, old).unwrap();
+        world.set_parent(child, new).unwrap();
+        assert_eq!(world.get_component::<Children>(old), Some(&Children(vec![])));
+        assert_eq!(world.get_component::<Children>(new), Some(&Children(vec![child])));
+    }
+
+    #[test]
+    fn set_parent_rejects_cycles() {
+        let mut world = World::new();
+        let a = world.spawn().build();
+        let b = world.spawn().build();
+        world.set_parent(b, a).unwrap();
+        assert!(world.set_parent(a, b).is_err());
+        assert!(world.set_parent(a, a).is_err());
+    }
+
+    #[test]
+    fn despawn_orphans_children_and_unhooks_from_parent() {
+        let mut world = World::new();
+        let grandparent = world.spawn().build();
+        let parent = world.spawn().build();
+        let child = world.spawn().build();
+        world.set_parent(parent, grandparent).unwrap();
+        world.set_parent(child, parent).unwrap();
+
+        world.despawn(parent);
+        assert!(world.is_alive(child));
+        assert!(world.get_component::<Parent>(child).is_none());
+        assert_eq!(world.get_component::<Children>(grandparent), Some(&Children(vec![])));
+    }
+
+    #[test]
+    fn despawn_recursive_removes_whole_subtree() {
+        let mut world = World::new();
+        let root = world.spawn().build();
+        let child = world.spawn().build();
+        let grandchild = world.spawn().build();
+        let unrelated = world.spawn().build();
+        world.set_parent(child, root).unwrap();
+        world.set_parent(grandchild, child).unwrap();
+
+        world.despawn_recursive(root);
+        assert!(!world.is_alive(root));
+        assert!(!world.is_alive(child));
+        assert!(!world.is_alive(grandchild));
+        assert!(world.is_alive(unrelated));
+    }
+}
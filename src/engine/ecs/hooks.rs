@@ -0,0 +1,24 @@
+// Component lifecycle hooks
+//
+//     world.on_add::<Enemy>(|world, entity| { ... });     // entity didn't have an Enemy before
+//     world.on_insert::<Enemy>(|world, entity| { ... });  // every add_component, overwrites too
+//     world.on_remove::<Enemy>(|world, entity| { ... });  // remove_component or despawn
+//
+// Hooks run synchronously inside `World::add_component`/`remove_component`/`despawn`, so they fire
+// for direct world calls and for `Commands` alike. on_add/on_insert run after the component is
+// stored; on_remove runs before it goes, so the hook can still read it (and, for despawn, every
+// other component of the entity).
+//
+// For systems that only need to know *that* something was removed, `RemovedComponents<T>` is
+// simpler: removals are logged per type and readable for a frame, like `Events<T>`.
+
+use crate::engine::ecs::{ entity::Entity, world::World };
+
+pub type ComponentHook = fn(&mut World, Entity);
+
+#[derive(Clone, Default)]
+pub(crate) struct ComponentHooks {
+    pub(crate) on_add: Vec<ComponentHook>,
+    pub(crate) on_insert: Vec<ComponentHook>,
+    pub(crate) on_remove: Vec<ComponentHook>,
+}
@@ -2,10 +2,24 @@ pub mod entity;
 pub mod world;
 pub mod query;
 pub mod system;
+pub mod system_param;
+pub mod executor;
+pub mod system_config;
+pub mod condition;
+pub mod state;
 pub mod sparse_set;
+pub mod archetype;
 pub mod components;
 pub mod resources;
 pub mod systems;
 pub mod component_registry;
 pub mod events;
 pub mod world_descriptor;
+pub mod world_validation;
+pub mod scene;
+pub mod ron_node;
+pub mod prefab;
+pub mod entity_ref;
+pub mod commands;
+pub mod hierarchy;
+pub mod hooks;
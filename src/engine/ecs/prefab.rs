@@ -0,0 +1,387 @@
+// Prefabs: named bundles of components, defined in RON and spawned from world files or code.
+//
+// A prefab file is one entity in the world file format. An optional "prefab" key names the
+// prefab it extends:
+//
+//     // enemy_fighter.ron
+//     {
+//         "prefab": "fighter",
+//         "Renderable": (model: "starfighter_enemy"),
+//         "HoverState": (direction: Down),
+//         "Enemy": (),
+//     }
+//
+// Components are merged field by field over the base prefab's, so `(direction: Down)` keeps
+// the limits from fighter's HoverState. "children" are added after the base prefab's children.
+// An entity in a world file instantiates a prefab the same way (there "prefab" has to be the
+// first key), and code uses `World::spawn_prefab` or `Commands::spawn_prefab`:
+//
+//     world.spawn_prefab("enemy_fighter", PrefabOverrides::new().patch("Transform", "(position: (x: 1.0, y: 0.0, z: 9.0))"))?;
+
+use std::{ collections::HashMap, sync::Arc };
+
+use anyhow::{ anyhow, bail, Context, Result };
+
+use crate::engine::{
+    assets::{ handle::{ Handle, WeakHandle }, server::AssetServer },
+    ecs::{
+        component_registry::ComponentRegistry,
+        components::renderable::Renderable,
+        entity::Entity,
+        ron_node::RonNode,
+        world::World,
+        world_descriptor::{ RenderableDescriptor, CHILDREN_KEY, ID_KEY, RENDERABLE_NAME },
+    },
+    model::model::Model,
+};
+
+/// Reserved entity key naming the prefab an entity (or another prefab) is based on
+pub const PREFAB_KEY: &str = "prefab";
+
+// An entity as written in RON, before it's spawned
+#[derive(Clone, Debug, Default)]
+pub(crate) struct EntityNode {
+    pub(crate) prefab: Option<String>,
+    pub(crate) components: Vec<(String, RonNode)>,
+    pub(crate) children: Vec<EntityNode>,
+}
+
+impl EntityNode {
+    pub(crate) fn from_ron(node: RonNode) -> Result<Self> {
+        let RonNode::Map(entries) = node else {
+            bail!("an entity must be a map of component name to component data");
+        };
+        let mut entity = EntityNode::default();
+        for (key, value) in entries {
+            let key = key.as_str().ok_or_else(|| anyhow!("entity keys must be strings, found {}", key))?;
+            entity.insert(key, value)?;
+        }
+        Ok(entity)
+    }
+
+    // A single `"key": value` entry of the entity map
+    pub(crate) fn insert(&mut self, key: String, value: RonNode) -> Result<()> {
+        match key.as_str() {
+            ID_KEY => bail!("\"id\" can't be used in a prefab - name the instance instead"),
+            PREFAB_KEY => {
+                let name = value.as_str().ok_or_else(|| anyhow!("\"prefab\" must be a string"))?;
+                self.prefab = Some(name);
+            }
+            CHILDREN_KEY => {
+                let RonNode::List(children) = value else {
+                    bail!("\"children\" must be a list of entities");
+                };
+                for child in children {
+                    self.children.push(EntityNode::from_ron(child)?);
+                }
+            }
+            _ => self.components.push((key, value)),
+        }
+        Ok(())
+    }
+
+    // Components merge field by field, children are appended
+    fn apply(&mut self, patch: EntityNode) {
+        for (name, value) in patch.components {
+            match self.components.iter_mut().find(|(existing, _)| *existing == name) {
+                Some((_, existing)) => existing.merge(value),
+                None => self.components.push((name, value)),
+            }
+        }
+        self.children.extend(patch.children);
+    }
+}
+
+// Send so overrides can be queued on Commands
+type InsertFn = Box<dyn FnOnce(&mut World, Entity) + Send>;
+
+/// Per-instance changes for `World::spawn_prefab`.
+pub struct PrefabOverrides {
+    patches: Vec<(String, String)>,
+    components: Vec<InsertFn>,
+}
+
+impl Default for PrefabOverrides {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrefabOverrides {
+    pub fn new() -> Self {
+        Self { patches: Vec::new(), components: Vec::new() }
+    }
+
+    /// Merges `ron` into the prefab's `component` field by field, e.g.
+    /// `.patch("HoverState", "(direction: Up)")`.
+    pub fn patch(mut self, component: &str, ron: impl Into<String>) -> Self {
+        self.patches.push((component.to_string(), ron.into()));
+        self
+    }
+
+    /// Adds (or replaces) a whole component once the prefab is spawned.
+    pub fn with<T: Send + 'static>(mut self, component: T) -> Self {
+        self.components.push(Box::new(move |world, entity| world.add_component(entity, component)));
+        self
+    }
+}
+
+/// Every prefab the game has loaded, as a resource. Cheap to clone.
+#[derive(Clone)]
+pub struct Prefabs {
+    definitions: Arc<HashMap<String, EntityNode>>,
+    registry: Arc<ComponentRegistry>,
+    // Model names, copied from the AssetServer so code can spawn prefabs without it. Weak, so
+    // prefabs don't keep models loaded
+    models: Arc<HashMap<String, WeakHandle<Model>>>,
+}
+
+impl Prefabs {
+    pub fn new(registry: Arc<ComponentRegistry>) -> Self {
+        Self {
+            definitions: Arc::new(HashMap::new()),
+            registry,
+            models: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// Adds (or replaces) the prefab `name`. Bases don't need to be added first - they're looked
+    /// up when the prefab is spawned.
+    pub fn add(&mut self, name: &str, ron_str: &str) -> Result<()> {
+        let node = EntityNode::from_ron(RonNode::parse(ron_str)?)?;
+        Arc::make_mut(&mut self.definitions).insert(name.to_string(), node);
+        Ok(())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.definitions.keys().map(String::as_str)
+    }
+
+    /// Picks up models registered since the last call. Prefabs spawned from code can only
+    /// name models the asset server had at that point.
+    pub fn sync_models(&mut self, asset_server: &AssetServer) {
+        self.models = Arc::new(asset_server.named_models().clone());
+    }
+
+    // Flattens the chain of "prefab" bases into one entity
+    pub(crate) fn resolve(&self, node: EntityNode) -> Result<EntityNode> {
+        self.resolve_from(node, &mut Vec::new())
+    }
+
+    fn resolve_from(&self, mut node: EntityNode, chain: &mut Vec<String>) -> Result<EntityNode> {
+        let Some(name) = node.prefab.take() else {
+            return Ok(node);
+        };
+        if chain.contains(&name) {
+            bail!("prefab cycle: {} -> {}", chain.join(" -> "), name);
+        }
+        let base = self.definitions
+            .get(&name)
+            .ok_or_else(|| anyhow!("unknown prefab: {}", name))?
+            .clone();
+        chain.push(name);
+        let mut resolved = self.resolve_from(base, chain)?;
+        chain.pop();
+        resolved.apply(node);
+        Ok(resolved)
+    }
+
+    /// Adds `node`'s components (after resolving its prefab) to `entity` and spawns its
+    /// children under it.
+    pub(crate) fn instantiate(
+        &self,
+        world: &mut World,
+        entity: Entity,
+        node: EntityNode,
+        registry: &ComponentRegistry,
+        model: &dyn Fn(&str) -> Option<Handle<Model>>
+    ) -> Result<()> {
+        let node = self.resolve(node)?;
+        for (name, value) in &node.components {
+            insert_component(world, entity, name, value, registry, model)?;
+        }
+        for child_node in node.children {
+            let child = world.spawn_entity_only();
+            world.set_parent(child, entity)?;
+            self.instantiate(world, child, child_node, registry, model)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn insert_component(
+    world: &mut World,
+    entity: Entity,
+    name: &str,
+    value: &RonNode,
+    registry: &ComponentRegistry,
+    model: &dyn Fn(&str) -> Option<Handle<Model>>
+) -> Result<()> {
+    let ron_str = value.to_string();
+    if name == RENDERABLE_NAME {
+        let descriptor: RenderableDescriptor = ron::from_str(&ron_str)?;
+        let handle = model(&descriptor.model).ok_or_else(|| anyhow!("unknown model: {}", descriptor.model))?;
+        world.add_component(entity, Renderable::new(handle));
+        return Ok(());
+    }
+
+    let deserialize_fn = registry.get(name).ok_or_else(|| anyhow!("unknown component: {}", name))?;
+    let mut deserializer = ron::de::Deserializer::from_str(&ron_str)?;
+    let mut erased = <dyn erased_serde::Deserializer>::erase(&mut deserializer);
+    deserialize_fn(world, entity, &mut erased).with_context(|| format!("in {}: {}", name, ron_str))
+}
+
+impl World {
+    /// Spawns the prefab `name` with `overrides` applied. Needs the `Prefabs` resource.
+    pub fn spawn_prefab(&mut self, name: &str, overrides: PrefabOverrides) -> Result<Entity> {
+        let entity = self.spawn_entity_only();
+        if let Err(e) = self.build_prefab(entity, name, overrides) {
+            self.despawn_recursive(entity);
+            return Err(e);
+        }
+        Ok(entity)
+    }
+
+    // Instantiates onto an existing entity, so Commands can hand out the entity up front
+    pub(crate) fn build_prefab(
+        &mut self,
+        entity: Entity,
+        name: &str,
+        overrides: PrefabOverrides
+    ) -> Result<()> {
+        let prefabs = self
+            .get_resource::<Prefabs>()
+            .cloned()
+            .ok_or_else(|| anyhow!("cannot spawn prefab {}: no Prefabs resource", name))?;
+
+        let mut node = EntityNode { prefab: Some(name.to_string()), ..Default::default() };
+        for (component, ron_str) in overrides.patches {
+            let patch = RonNode::parse(&ron_str).with_context(|| format!("override for {}", component))?;
+            node.components.push((component, patch));
+        }
+        let models = prefabs.models.clone();
+        prefabs.instantiate(self, entity, node, &prefabs.registry, &|model| {
+            models.get(model).and_then(WeakHandle::upgrade)
+        })?;
+
+        for insert in overrides.components {
+            insert(self, entity);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ecs::{
+        commands::Commands,
+        components::{ hierarchy::{ Children, Parent }, transform::Transform, velocity::Velocity },
+        world_descriptor::load_world,
+    };
+
+    const SHIP: &str = r#"{
+        "Transform": (
+            position: (x: 0.0, y: 0.0, z: 0.0),
+            rotation: (s: 1.0, v: (x: 0.0, y: 0.0, z: 0.0)),
+            scale: (x: 0.3, y: 0.3, z: 0.3),
+        ),
+        "Renderable": (model: "ship"),
+        "children": [ { "Velocity": (x: 0.0, y: 1.0, z: 0.0) } ],
+    }"#;
+
+    const FAST_SHIP: &str = r#"{
+        "prefab": "ship",
+        "Velocity": (x: 0.0, y: 0.0, z: 9.0),
+        "Transform": (scale: (x: 0.5)),
+    }"#;
+
+    // The handle keeps the "ship" name resolvable
+    fn world_with_prefabs() -> (World, Handle<Model>) {
+        let mut asset_server = AssetServer::new();
+        let ship = asset_server.register_model_name("ship");
+        let mut prefabs = Prefabs::new(Arc::new(ComponentRegistry::new()));
+        prefabs.add("ship", SHIP).unwrap();
+        prefabs.add("fast_ship", FAST_SHIP).unwrap();
+        prefabs.sync_models(&asset_server);
+        let mut world = World::new();
+        world.add_resource(prefabs);
+        (world, ship)
+    }
+
+    #[test]
+    fn extended_prefab_merges_fields_and_keeps_children() {
+        let (mut world, ship) = world_with_prefabs();
+        let entity = world.spawn_prefab("fast_ship", PrefabOverrides::new()).unwrap();
+
+        let transform = world.get_component::<Transform>(entity).unwrap();
+        assert_eq!(transform.scale.x, 0.5);
+        assert_eq!(transform.scale.y, 0.3);
+        assert_eq!(world.get_component::<Renderable>(entity).unwrap().model, ship);
+        assert_eq!(world.get_component::<Velocity>(entity).unwrap().z, 9.0);
+
+        let Children(children) = world.get_component::<Children>(entity).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_eq!(world.get_component::<Parent>(children[0]), Some(&Parent(entity)));
+    }
+
+    #[test]
+    fn overrides_patch_fields_and_replace_components() {
+        let (mut world, _ship) = world_with_prefabs();
+        let overrides = PrefabOverrides::new()
+            .patch("Transform", "(position: (y: 2.0))")
+            .with(Velocity { x: 1.0, y: 0.0, z: 0.0 });
+        let entity = world.spawn_prefab("ship", overrides).unwrap();
+
+        let transform = world.get_component::<Transform>(entity).unwrap();
+        assert_eq!((transform.position.x, transform.position.y), (0.0, 2.0));
+        assert_eq!(transform.scale.z, 0.3);
+        assert_eq!(world.get_component::<Velocity>(entity).unwrap().x, 1.0);
+    }
+
+    #[test]
+    fn unknown_prefab_and_cycles_are_errors() {
+        let (mut world, _ship) = world_with_prefabs();
+        assert!(world.spawn_prefab("nope", PrefabOverrides::new()).is_err());
+
+        let prefabs = world.get_resource_mut::<Prefabs>().unwrap();
+        prefabs.add("a", r#"{ "prefab": "b" }"#).unwrap();
+        prefabs.add("b", r#"{ "prefab": "a" }"#).unwrap();
+        let error = world.spawn_prefab("a", PrefabOverrides::new()).unwrap_err();
+        assert!(error.to_string().contains("a -> b -> a"));
+        // nothing is left behind by a failed spawn
+        assert_eq!(world.live_entity_count(), 0);
+    }
+
+    #[test]
+    fn world_files_instantiate_prefabs_with_overrides() {
+        let (mut world, _ship) = world_with_prefabs();
+        let mut asset_server = AssetServer::new();
+        let _ship = asset_server.register_model_name("ship");
+        load_world(
+            r#"( entities: [ { "prefab": "ship", "Transform": (position: (x: 4.0)) } ] )"#,
+            &mut world,
+            &ComponentRegistry::new(),
+            &asset_server
+        ).unwrap();
+
+        let (entity_id, transform) = world.iter_component::<Transform>().next().unwrap();
+        assert_eq!(transform.position.x, 4.0);
+        assert_eq!(transform.scale.x, 0.3);
+        assert!(world.has_component_by_id::<Children>(entity_id));
+    }
+
+    #[test]
+    fn commands_spawn_prefab_on_apply() {
+        let (mut world, _ship) = world_with_prefabs();
+        let mut commands = Commands::new(&world);
+        let entity = commands.spawn_prefab("ship", PrefabOverrides::new());
+        assert!(!world.is_alive(entity));
+        commands.apply(&mut world);
+        assert!(world.get_component::<Renderable>(entity).is_some());
+    }
+}
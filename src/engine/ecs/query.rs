@@ -4,55 +4,320 @@
 // The macro generates tuple implementations so you can fetch multiple components
 // in one call: `world.query::<(&mut Transform, &Velocity)>(id)`.
 //
-// `query_iter` iterates all entities that have the primary (first) component type
-// and yields tuples, skipping any entity missing the remaining types.
+// Filters narrow a query without borrowing component data - they yield `()`:
+// - `With<T>` / `Without<T>`: entity must / must not have a T
+// - `Changed<T>` / `Added<T>`: T was mutably accessed / added this frame or last frame
+// `Option<F>` fetches F when the entity matches it and `None` otherwise, so
+// `(&Transform, Option<&Velocity>)` yields every entity with a Transform.
 //
-// Safety invariant: never include the same type twice in a tuple — that would
-// produce two &mut references to the same data (UB). Different types always
-// live in different SparseSets, so there is no aliasing across distinct types.
-
-use std::any::TypeId;
+// `query_iter` iterates all entities that have the primary component type - the first
+// type in the tuple that the entity is required to have - and yields tuples, skipping any
+// entity that fails the rest of the tuple. A tuple with no required component (only
+// `Option`/`Without`) walks every live entity.
+//
+// If the primary type uses table storage the iterator walks each archetype that holds it
+// row by row. Each fetch caches its column for the current archetype (`Fetch::Chunk`), so
+// table components cost an index per row instead of a lookup per entity. Sparse primaries
+// walk the SparseSet's dense id array in place. Neither allocates.
+//
+// Safety invariant: never include the same type twice in a tuple where either is `&mut` —
+// that would produce a &mut reference aliasing another reference to the same data (UB).
+// Different types always live in different SparseSets / table columns, so there is no
+// aliasing across distinct types. `query`/`query_iter` check this via `Fetch::access` and panic on a
+// conflict. Filters don't borrow data, so `(&mut T, Changed<T>)` is fine. `Changed<T>`/`Added<T>`
+// still read T's change ticks, so they record a `filter` access: ignored within a query, but it
+// keeps them off other threads while a system with `&mut T` is stamping those ticks.
+
+use std::any::{ type_name, TypeId };
 use std::marker::PhantomData;
+use std::ptr::NonNull;
 
+use super::archetype::TypedColumn;
 use super::world::World;
 
+/// A single component borrow made by a `Fetch`, used to detect aliasing tuples.
+#[derive(Clone, Debug)]
+pub struct ComponentAccess {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    pub mutable: bool,
+    /// Only T's change ticks are read (`Changed<T>`/`Added<T>`). Never aliases a borrow in the
+    /// same system, but conflicts with `&mut T` in another.
+    pub filter: bool,
+}
+
+impl ComponentAccess {
+    fn of<T: 'static>(mutable: bool) -> Self {
+        Self { type_id: TypeId::of::<T>(), type_name: type_name::<T>(), mutable, filter: false }
+    }
+
+    fn ticks_of<T: 'static>() -> Self {
+        Self { filter: true, ..Self::of::<T>(false) }
+    }
+}
+
 pub trait Fetch<'w> {
     type Item;
-    fn primary_type_id() -> TypeId;
+    /// Per-archetype state cached while a table is being walked, e.g. a column pointer.
+    type Chunk: Copy + Default;
+    /// The component whose storage drives iteration, or None if this fetch doesn't
+    /// require the entity to have any particular component.
+    fn primary_type_id() -> Option<TypeId>;
+    /// Records the component data this fetch borrows. Filters borrow nothing, though
+    /// `Changed`/`Added` record the ticks they read.
+    fn access(_access: &mut Vec<ComponentAccess>) {}
+    /// Whether the entity passes this fetch. The whole tuple is checked before anything is
+    /// fetched, so a `&mut T` can't mark T as changed ahead of a `Changed<T>` check.
+    fn matches(world: &World, id: u32) -> bool;
     /// # Safety
-    /// Caller must ensure no aliasing occurs (no duplicate types in a tuple query).
+    /// Caller must ensure no aliasing occurs (no duplicate types in a tuple query),
+    /// and must only call this after `matches` returned true for the same entity.
     unsafe fn fetch(world: *mut World, id: u32) -> Option<Self::Item>;
+
+    /// Called once per archetype before its rows are walked.
+    /// # Safety
+    /// `archetype` must be a valid index into the world's tables.
+    unsafe fn chunk(_world: *mut World, _archetype: usize) -> Self::Chunk {
+        Self::Chunk::default()
+    }
+    /// `matches` for row `row` of the archetype `chunk` was built from.
+    /// # Safety
+    /// `chunk` must come from `chunk()` for the archetype `row` belongs to.
+    unsafe fn matches_row(_chunk: Self::Chunk, world: &World, _row: usize, id: u32) -> bool {
+        Self::matches(world, id)
+    }
+    /// `fetch` for row `row` of the archetype `chunk` was built from.
+    /// # Safety
+    /// Same as `fetch` and `matches_row`.
+    unsafe fn fetch_row(
+        _chunk: Self::Chunk,
+        world: *mut World,
+        _row: usize,
+        id: u32
+    ) -> Option<Self::Item> {
+        Self::fetch(world, id)
+    }
 }
 
+// Component chunks are None when T isn't in the archetype's columns (e.g. it's a sparse set
+// component), in which case the row falls back to the per-entity lookup.
 impl<'w, T: 'static> Fetch<'w> for &'w T {
     type Item = &'w T;
-    fn primary_type_id() -> TypeId { TypeId::of::<T>() }
+    type Chunk = Option<NonNull<TypedColumn<T>>>;
+    fn primary_type_id() -> Option<TypeId> { Some(TypeId::of::<T>()) }
+    fn access(access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess::of::<T>(false));
+    }
+    fn matches(world: &World, id: u32) -> bool {
+        world.has_component_by_id::<T>(id)
+    }
     unsafe fn fetch(world: *mut World, id: u32) -> Option<Self::Item> {
         (*world).get_component_by_id::<T>(id)
     }
+    unsafe fn chunk(world: *mut World, archetype: usize) -> Self::Chunk {
+        (*world).tables().archetypes()[archetype].column::<T>().map(NonNull::from)
+    }
+    unsafe fn matches_row(chunk: Self::Chunk, world: &World, _row: usize, id: u32) -> bool {
+        chunk.is_some() || Self::matches(world, id)
+    }
+    unsafe fn fetch_row(
+        chunk: Self::Chunk,
+        world: *mut World,
+        row: usize,
+        id: u32
+    ) -> Option<Self::Item> {
+        match chunk {
+            Some(column) => Some(column.as_ref().get(row)),
+            None => Self::fetch(world, id),
+        }
+    }
 }
 
 impl<'w, T: 'static> Fetch<'w> for &'w mut T {
     type Item = &'w mut T;
-    fn primary_type_id() -> TypeId { TypeId::of::<T>() }
+    type Chunk = Option<NonNull<TypedColumn<T>>>;
+    fn primary_type_id() -> Option<TypeId> { Some(TypeId::of::<T>()) }
+    fn access(access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess::of::<T>(true));
+    }
+    fn matches(world: &World, id: u32) -> bool {
+        world.has_component_by_id::<T>(id)
+    }
+    // Marks the component as changed - see SparseSet::get_mut_tracked. Only ever takes `&World`:
+    // other systems may be reading other components on other threads
+    unsafe fn fetch(world: *mut World, id: u32) -> Option<Self::Item> {
+        (*world).get_component_mut_unchecked::<T>(id)
+    }
+    unsafe fn chunk(world: *mut World, archetype: usize) -> Self::Chunk {
+        (*world).tables().archetypes()[archetype].column::<T>().map(NonNull::from)
+    }
+    unsafe fn matches_row(chunk: Self::Chunk, world: &World, _row: usize, id: u32) -> bool {
+        chunk.is_some() || Self::matches(world, id)
+    }
+    unsafe fn fetch_row(
+        chunk: Self::Chunk,
+        world: *mut World,
+        row: usize,
+        id: u32
+    ) -> Option<Self::Item> {
+        match chunk {
+            Some(column) => Some(column.as_ref().get_mut_unchecked(row, (*world).change_tick())),
+            None => Self::fetch(world, id),
+        }
+    }
+}
+
+impl<'w, F: Fetch<'w>> Fetch<'w> for Option<F> {
+    type Item = Option<F::Item>;
+    type Chunk = F::Chunk;
+    fn primary_type_id() -> Option<TypeId> { None }
+    fn access(access: &mut Vec<ComponentAccess>) {
+        F::access(access);
+    }
+    fn matches(_world: &World, _id: u32) -> bool {
+        true
+    }
     unsafe fn fetch(world: *mut World, id: u32) -> Option<Self::Item> {
-        (*world).get_component_mut_by_id::<T>(id)
+        if F::matches(&*world, id) { Some(F::fetch(world, id)) } else { Some(None) }
+    }
+    unsafe fn chunk(world: *mut World, archetype: usize) -> Self::Chunk {
+        F::chunk(world, archetype)
+    }
+    unsafe fn matches_row(_chunk: Self::Chunk, _world: &World, _row: usize, _id: u32) -> bool {
+        true
+    }
+    unsafe fn fetch_row(
+        chunk: Self::Chunk,
+        world: *mut World,
+        row: usize,
+        id: u32
+    ) -> Option<Self::Item> {
+        if F::matches_row(chunk, &*world, row, id) {
+            Some(F::fetch_row(chunk, world, row, id))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+// --- Filters ---
+
+/// Matches entities that have a T, without borrowing it.
+pub struct With<T>(PhantomData<T>);
+
+/// Matches entities that don't have a T.
+pub struct Without<T>(PhantomData<T>);
+
+/// Matches entities whose T was mutably accessed (or added) this frame or last frame.
+/// Any `&mut T` fetch or `get_component_mut` counts, whether or not it actually wrote.
+pub struct Changed<T>(PhantomData<T>);
+
+/// Matches entities whose T was added this frame or last frame.
+pub struct Added<T>(PhantomData<T>);
+
+impl<'w, T: 'static> Fetch<'w> for With<T> {
+    type Item = ();
+    type Chunk = ();
+    fn primary_type_id() -> Option<TypeId> { Some(TypeId::of::<T>()) }
+    fn matches(world: &World, id: u32) -> bool {
+        world.has_component_by_id::<T>(id)
+    }
+    unsafe fn fetch(_world: *mut World, _id: u32) -> Option<Self::Item> {
+        Some(())
+    }
+}
+
+impl<'w, T: 'static> Fetch<'w> for Without<T> {
+    type Item = ();
+    type Chunk = ();
+    fn primary_type_id() -> Option<TypeId> { None }
+    fn matches(world: &World, id: u32) -> bool {
+        !world.has_component_by_id::<T>(id)
+    }
+    unsafe fn fetch(_world: *mut World, _id: u32) -> Option<Self::Item> {
+        Some(())
     }
 }
 
-// The first type in the tuple drives iteration — put the rarest component first
+impl<'w, T: 'static> Fetch<'w> for Changed<T> {
+    type Item = ();
+    type Chunk = ();
+    fn primary_type_id() -> Option<TypeId> { Some(TypeId::of::<T>()) }
+    fn access(access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess::ticks_of::<T>());
+    }
+    fn matches(world: &World, id: u32) -> bool {
+        world
+            .component_ticks_by_id::<T>(id)
+            .is_some_and(|ticks| ticks.changed >= world.last_change_tick())
+    }
+    unsafe fn fetch(_world: *mut World, _id: u32) -> Option<Self::Item> {
+        Some(())
+    }
+}
+
+impl<'w, T: 'static> Fetch<'w> for Added<T> {
+    type Item = ();
+    type Chunk = ();
+    fn primary_type_id() -> Option<TypeId> { Some(TypeId::of::<T>()) }
+    fn access(access: &mut Vec<ComponentAccess>) {
+        access.push(ComponentAccess::ticks_of::<T>());
+    }
+    fn matches(world: &World, id: u32) -> bool {
+        world
+            .component_ticks_by_id::<T>(id)
+            .is_some_and(|ticks| ticks.added >= world.last_change_tick())
+    }
+    unsafe fn fetch(_world: *mut World, _id: u32) -> Option<Self::Item> {
+        Some(())
+    }
+}
+
+// The first required type in the tuple drives iteration — put the rarest component first
 // for best performance (fewer entities to test against remaining components).
 macro_rules! impl_fetch_tuple {
     ($first:ident $(, $rest:ident)*) => {
         impl<'w, $first: Fetch<'w> $(, $rest: Fetch<'w>)*> Fetch<'w> for ($first, $($rest,)*) {
             type Item = ($first::Item, $($rest::Item,)*);
-            fn primary_type_id() -> TypeId { $first::primary_type_id() }
+            type Chunk = ($first::Chunk, $($rest::Chunk,)*);
+            fn primary_type_id() -> Option<TypeId> {
+                $first::primary_type_id()$(.or_else($rest::primary_type_id))*
+            }
+            fn access(access: &mut Vec<ComponentAccess>) {
+                $first::access(access);
+                $($rest::access(access);)*
+            }
+            fn matches(world: &World, id: u32) -> bool {
+                $first::matches(world, id) $(&& $rest::matches(world, id))*
+            }
             unsafe fn fetch(world: *mut World, id: u32) -> Option<Self::Item> {
                 Some((
                     $first::fetch(world, id)?,
                     $($rest::fetch(world, id)?,)*
                 ))
             }
+            unsafe fn chunk(world: *mut World, archetype: usize) -> Self::Chunk {
+                ($first::chunk(world, archetype), $($rest::chunk(world, archetype),)*)
+            }
+            #[allow(non_snake_case)]
+            unsafe fn matches_row(chunk: Self::Chunk, world: &World, row: usize, id: u32) -> bool {
+                let ($first, $($rest,)*) = chunk;
+                $first::matches_row($first, world, row, id)
+                    $(&& $rest::matches_row($rest, world, row, id))*
+            }
+            #[allow(non_snake_case)]
+            unsafe fn fetch_row(
+                chunk: Self::Chunk,
+                world: *mut World,
+                row: usize,
+                id: u32
+            ) -> Option<Self::Item> {
+                let ($first, $($rest,)*) = chunk;
+                Some((
+                    $first::fetch_row($first, world, row, id)?,
+                    $($rest::fetch_row($rest, world, row, id)?,)*
+                ))
+            }
         }
     }
 }
@@ -63,23 +328,158 @@ impl_fetch_tuple!(A, B, C, D);
 impl_fetch_tuple!(A, B, C, D, E);
 impl_fetch_tuple!(A, B, C, D, E, F);
 
+// --- QueryData ---
+
+/// Maps a query type written with any lifetime (e.g. the `&Transform` in a system's
+/// `Query<&Transform>` argument) to the `Fetch` for a given world borrow. Lets `Query` hand out
+/// items that live as long as the borrow of the query, not as long as the system argument.
+pub trait QueryData {
+    type Fetch<'w>: Fetch<'w>;
+}
+
+impl<T: Sync + 'static> QueryData for &T {
+    type Fetch<'w> = &'w T;
+}
+
+impl<T: Send + 'static> QueryData for &mut T {
+    type Fetch<'w> = &'w mut T;
+}
+
+impl<Q: QueryData> QueryData for Option<Q> {
+    type Fetch<'w> = Option<Q::Fetch<'w>>;
+}
+
+impl<T: 'static> QueryData for With<T> {
+    type Fetch<'w> = With<T>;
+}
+
+impl<T: 'static> QueryData for Without<T> {
+    type Fetch<'w> = Without<T>;
+}
+
+impl<T: 'static> QueryData for Changed<T> {
+    type Fetch<'w> = Changed<T>;
+}
+
+impl<T: 'static> QueryData for Added<T> {
+    type Fetch<'w> = Added<T>;
+}
+
+macro_rules! impl_query_data_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: QueryData),+> QueryData for ($($name,)+) {
+            type Fetch<'w> = ($($name::Fetch<'w>,)+);
+        }
+    }
+}
+
+impl_query_data_tuple!(A, B);
+impl_query_data_tuple!(A, B, C);
+impl_query_data_tuple!(A, B, C, D);
+impl_query_data_tuple!(A, B, C, D, E);
+impl_query_data_tuple!(A, B, C, D, E, F);
+
+/// Panics if `F` would hand out a `&mut` aliasing another borrow of the same component.
+pub(crate) fn assert_no_aliasing<'w, F: Fetch<'w>>() {
+    let mut access = Vec::new();
+    F::access(&mut access);
+    for (i, a) in access.iter().enumerate() {
+        for b in &access[i + 1..] {
+            if a.type_id == b.type_id && (a.mutable || b.mutable) && !a.filter && !b.filter {
+                panic!(
+                    "query {} borrows {} more than once with at least one &mut",
+                    type_name::<F>(),
+                    a.type_name
+                );
+            }
+        }
+    }
+}
+
 // --- QueryIter ---
 
+enum Cursor {
+    // The primary SparseSet's dense id array, walked in place
+    Sparse { ids: *const [u32], index: usize },
+    // Rows of every archetype holding the primary table component
+    Table { primary: TypeId, archetype: usize, row: usize },
+    // No required component - every live entity
+    All(std::vec::IntoIter<u32>),
+}
+
 pub struct QueryIter<'w, F: Fetch<'w>> {
     world: *mut World,
-    ids: std::vec::IntoIter<u32>,
+    cursor: Cursor,
+    // Chunk for the archetype the Table cursor is in
+    chunk: F::Chunk,
     _phantom: PhantomData<fn() -> F::Item>,
 }
 
+impl<'w, F: Fetch<'w>> QueryIter<'w, F> {
+    // Index of the first non-empty archetype at or after `from` that holds `primary`,
+    // or archetypes().len() when there are none left.
+    fn seek_archetype(&self, primary: TypeId, from: usize) -> usize {
+        let archetypes = unsafe { (*self.world).tables().archetypes() };
+        archetypes[from.min(archetypes.len())..]
+            .iter()
+            .position(|a| !a.is_empty() && a.contains(primary))
+            .map_or(archetypes.len(), |i| from + i)
+    }
+
+    // Moves a Table cursor to the next matching archetype (starting at `from`)
+    fn enter_archetype(&mut self, from: usize) {
+        let Cursor::Table { primary, .. } = self.cursor else {
+            return;
+        };
+        let archetype = self.seek_archetype(primary, from);
+        if archetype < unsafe { (*self.world).tables().archetypes().len() } {
+            self.chunk = unsafe { F::chunk(self.world, archetype) };
+        }
+        self.cursor = Cursor::Table { primary, archetype, row: 0 };
+    }
+}
+
 impl<'w, F: Fetch<'w>> Iterator for QueryIter<'w, F> {
     type Item = F::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
-            let id = self.ids.next()?;
-            // Safety: same invariant as query() — no duplicate types in F.
-            // Entities missing any component in the tuple are skipped.
+            // Safety: same invariant as query() — no duplicate types in F, checked
+            // when the iterator was created. Entities failing any part of F are skipped.
+            // The iterator holds the world's &mut borrow (or, from a system's `Query`, runs
+            // while only non-conflicting systems do), so no storage can be resized (and no
+            // archetype created) while it is alive.
+            let id = match &mut self.cursor {
+                Cursor::Sparse { ids, index } => {
+                    let id = unsafe { *(&**ids).get(*index)? };
+                    *index += 1;
+                    id
+                }
+                Cursor::All(ids) => ids.next()?,
+                Cursor::Table { archetype, row, .. } => {
+                    let archetypes = unsafe { (*self.world).tables().archetypes() };
+                    let current = archetypes.get(*archetype)?;
+                    if *row >= current.len() {
+                        let next = *archetype + 1;
+                        self.enter_archetype(next);
+                        continue;
+                    }
+                    let (r, id) = (*row, current.entities()[*row]);
+                    *row += 1;
+                    unsafe {
+                        if F::matches_row(self.chunk, &*self.world, r, id) {
+                            if let Some(item) = F::fetch_row(self.chunk, self.world, r, id) {
+                                return Some(item);
+                            }
+                        }
+                    }
+                    continue;
+                }
+            };
             unsafe {
+                if !F::matches(&*self.world, id) {
+                    continue;
+                }
                 if let Some(item) = F::fetch(self.world, id) {
                     return Some(item);
                 }
@@ -92,24 +492,59 @@ impl<'w, F: Fetch<'w>> Iterator for QueryIter<'w, F> {
 
 impl World {
     pub fn query<'w, F: Fetch<'w>>(&'w mut self, id: u32) -> Option<F::Item> {
-        unsafe { F::fetch(self as *mut World, id) }
+        unsafe { World::query_unchecked::<F>(self, id) }
     }
 
-    /// Iterates all entities that have the primary (first) component in `F`,
-    /// yielding only those that also have every other component in the tuple.
+    /// Iterates all entities that have the primary (first required) component in `F`,
+    /// yielding only those that also pass every other element of the tuple.
     pub fn query_iter<'w, F: Fetch<'w>>(&'w mut self) -> QueryIter<'w, F> {
-        let ids = self.entity_ids_for(F::primary_type_id());
-        QueryIter {
-            world: self as *mut World,
-            ids: ids.into_iter(),
-            _phantom: PhantomData,
+        unsafe { World::query_iter_unchecked::<F>(self) }
+    }
+
+    /// `query` without a `&mut World`, for a system's `Query` while other systems run on other
+    /// threads. Only shared references to the world are made.
+    /// # Safety
+    /// `world` must be valid for `'w`, and nothing may access the components `F` borrows
+    /// mutably (or borrow mutably the ones it reads) until the item is dropped.
+    pub(crate) unsafe fn query_unchecked<'w, F: Fetch<'w>>(world: *mut World, id: u32) -> Option<F::Item> {
+        assert_no_aliasing::<F>();
+        if !F::matches(&*world, id) {
+            return None;
         }
+        F::fetch(world, id)
+    }
+
+    /// `query_iter` without a `&mut World` - see `query_unchecked`.
+    /// # Safety
+    /// As `query_unchecked`, for as long as the iterator or any item is alive. No entity or
+    /// component may be added or removed meanwhile either.
+    pub(crate) unsafe fn query_iter_unchecked<'w, F: Fetch<'w>>(world: *mut World) -> QueryIter<'w, F> {
+        assert_no_aliasing::<F>();
+        let shared = &*world;
+        let cursor = match F::primary_type_id() {
+            Some(primary) if shared.is_table_type(primary) => {
+                Cursor::Table { primary, archetype: 0, row: 0 }
+            }
+            Some(primary) => {
+                Cursor::Sparse { ids: shared.sparse_entity_ids(primary) as *const [u32], index: 0 }
+            }
+            None => Cursor::All(shared.alive_entity_ids().into_iter()),
+        };
+        let mut iter = QueryIter {
+            world,
+            cursor,
+            chunk: F::Chunk::default(),
+            _phantom: PhantomData,
+        };
+        iter.enter_archetype(0);
+        iter
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::ecs::archetype::StorageType;
 
     #[derive(Debug, PartialEq)]
     struct Position { x: f32, y: f32 }
@@ -254,4 +689,203 @@ mod tests {
         let mut world = World::new();
         assert_eq!(world.query_iter::<&Position>().count(), 0);
     }
+
+    // --- aliasing ---
+
+    #[test]
+    #[should_panic(expected = "more than once")]
+    fn duplicate_mutable_type_in_tuple_panics() {
+        let mut world = World::new();
+        world.query_iter::<(&mut Position, &Position)>();
+    }
+
+    #[test]
+    fn duplicate_immutable_type_in_tuple_is_allowed() {
+        let mut world = World::new();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).build();
+        assert_eq!(world.query_iter::<(&Position, &Position)>().count(), 1);
+    }
+
+    // --- filters ---
+
+    #[test]
+    fn with_filter_requires_component_without_yielding_it() {
+        let mut world = World::new();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).with(Health(1)).build();
+        world.spawn().with(Position { x: 2.0, y: 0.0 }).build();
+        let xs: Vec<f32> = world
+            .query_iter::<(&Position, With<Health>)>()
+            .map(|(p, _)| p.x)
+            .collect();
+        assert_eq!(xs, vec![1.0]);
+    }
+
+    #[test]
+    fn with_filter_can_drive_iteration() {
+        let mut world = World::new();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).build();
+        world.spawn().with(Position { x: 2.0, y: 0.0 }).with(Health(1)).build();
+        let xs: Vec<f32> = world
+            .query_iter::<(With<Health>, &Position)>()
+            .map(|(_, p)| p.x)
+            .collect();
+        assert_eq!(xs, vec![2.0]);
+    }
+
+    #[test]
+    fn without_filter_excludes_entities_with_component() {
+        let mut world = World::new();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).with(Health(1)).build();
+        world.spawn().with(Position { x: 2.0, y: 0.0 }).build();
+        let xs: Vec<f32> = world
+            .query_iter::<(&Position, Without<Health>)>()
+            .map(|(p, _)| p.x)
+            .collect();
+        assert_eq!(xs, vec![2.0]);
+    }
+
+    #[test]
+    fn without_only_query_walks_all_live_entities() {
+        let mut world = World::new();
+        world.spawn().with(Health(1)).build();
+        world.spawn().with(Position { x: 0.0, y: 0.0 }).build();
+        let dead = world.spawn().build();
+        world.despawn(dead);
+        assert_eq!(world.query_iter::<Without<Health>>().count(), 1);
+    }
+
+    #[test]
+    fn option_fetch_yields_some_or_none() {
+        let mut world = World::new();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).with(Health(7)).build();
+        world.spawn().with(Position { x: 2.0, y: 0.0 }).build();
+        let mut pairs: Vec<(f32, Option<u32>)> = world
+            .query_iter::<(&Position, Option<&Health>)>()
+            .map(|(p, h)| (p.x, h.map(|h| h.0)))
+            .collect();
+        pairs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        assert_eq!(pairs, vec![(1.0, Some(7)), (2.0, None)]);
+    }
+
+    #[test]
+    fn option_mut_fetch_allows_mutation_when_present() {
+        let mut world = World::new();
+        let e = world.spawn().with(Position { x: 0.0, y: 0.0 }).with(Health(1)).build();
+        for (_, health) in world.query_iter::<(&Position, Option<&mut Health>)>() {
+            if let Some(health) = health {
+                health.0 = 50;
+            }
+        }
+        assert_eq!(world.query::<&Health>(e.id).unwrap().0, 50);
+    }
+
+    #[test]
+    fn changed_filter_matches_mutated_components_until_two_frames_pass() {
+        let mut world = World::new();
+        let a = world.spawn().with(Position { x: 0.0, y: 0.0 }).build();
+        let b = world.spawn().with(Position { x: 0.0, y: 0.0 }).build();
+        world.advance_change_tick();
+        world.advance_change_tick();
+        assert_eq!(world.query_iter::<Changed<Position>>().count(), 0);
+
+        world.get_component_mut::<Position>(a).unwrap().x = 1.0;
+        let xs: Vec<f32> = world
+            .query_iter::<(&Position, Changed<Position>)>()
+            .map(|(p, _)| p.x)
+            .collect();
+        assert_eq!(xs, vec![1.0]);
+
+        // Still visible next frame, gone the frame after
+        world.advance_change_tick();
+        assert_eq!(world.query_iter::<Changed<Position>>().count(), 1);
+        world.advance_change_tick();
+        assert_eq!(world.query_iter::<Changed<Position>>().count(), 0);
+        assert!(world.query::<Changed<Position>>(b.id).is_none());
+    }
+
+    #[test]
+    fn mutable_fetch_in_same_tuple_does_not_trip_changed_filter() {
+        let mut world = World::new();
+        world.spawn().with(Position { x: 0.0, y: 0.0 }).build();
+        world.advance_change_tick();
+        world.advance_change_tick();
+        assert_eq!(world.query_iter::<(&mut Position, Changed<Position>)>().count(), 0);
+    }
+
+    #[test]
+    fn added_filter_ignores_later_mutation() {
+        let mut world = World::new();
+        let old = world.spawn().with(Position { x: 0.0, y: 0.0 }).build();
+        world.advance_change_tick();
+        world.advance_change_tick();
+        world.spawn().with(Position { x: 5.0, y: 0.0 }).build();
+        world.get_component_mut::<Position>(old).unwrap().x = 1.0;
+        let xs: Vec<f32> = world
+            .query_iter::<(&Position, Added<Position>)>()
+            .map(|(p, _)| p.x)
+            .collect();
+        assert_eq!(xs, vec![5.0]);
+    }
+
+    // --- table storage ---
+
+    fn table_world() -> World {
+        let mut world = World::new();
+        world.register_component_with_storage::<Position>(StorageType::Table);
+        world.register_component_with_storage::<Velocity>(StorageType::Table);
+        world
+    }
+
+    #[test]
+    fn table_query_iter_walks_every_matching_archetype() {
+        let mut world = table_world();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).with(Velocity { x: 1.0, y: 0.0 }).build();
+        world.spawn().with(Position { x: 2.0, y: 0.0 }).build();
+        world.spawn().with(Velocity { x: 9.0, y: 0.0 }).build();
+        world.spawn().with(Position { x: 3.0, y: 0.0 }).with(Velocity { x: 1.0, y: 0.0 }).build();
+
+        for (pos, vel) in world.query_iter::<(&mut Position, &Velocity)>() {
+            pos.x += vel.x;
+        }
+        let mut xs: Vec<f32> = world.query_iter::<&Position>().map(|p| p.x).collect();
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(xs, vec![2.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn table_query_mixes_with_sparse_components_and_filters() {
+        let mut world = table_world();
+        world.spawn().with(Position { x: 1.0, y: 0.0 }).with(Health(5)).build();
+        world.spawn().with(Position { x: 2.0, y: 0.0 }).build();
+        let with_health: Vec<(f32, u32)> = world
+            .query_iter::<(&Position, &Health)>()
+            .map(|(p, h)| (p.x, h.0))
+            .collect();
+        assert_eq!(with_health, vec![(1.0, 5)]);
+        let without: Vec<f32> = world
+            .query_iter::<(&Position, Without<Health>)>()
+            .map(|(p, _)| p.x)
+            .collect();
+        assert_eq!(without, vec![2.0]);
+        // Sparse primary, table secondary
+        assert_eq!(world.query_iter::<(&Health, Option<&Velocity>)>().count(), 1);
+    }
+
+    #[test]
+    fn table_mutable_fetch_marks_changed() {
+        let mut world = table_world();
+        let a = world.spawn().with(Position { x: 0.0, y: 0.0 }).build();
+        world.spawn().with(Position { x: 0.0, y: 0.0 }).with(Velocity { x: 0.0, y: 0.0 }).build();
+        world.advance_change_tick();
+        world.advance_change_tick();
+        for (pos, _) in world.query_iter::<(&mut Position, Without<Velocity>)>() {
+            pos.x = 1.0;
+        }
+        let changed: Vec<u32> = world
+            .query_iter::<(&Position, Changed<Position>)>()
+            .map(|(p, _)| p.x as u32)
+            .collect();
+        assert_eq!(changed, vec![1]);
+        assert!(world.query::<Changed<Position>>(a.id).is_some());
+    }
 }
@@ -2,3 +2,10 @@ pub struct ShowDebugPanel(pub bool);
 pub struct ShowColliderDebug(pub bool);
 
 pub struct DebugVisual;
+
+/// The render graph in run order, as `RenderGraph::report` text and as Graphviz DOT (e.g.
+/// `dot -Tsvg`). Added by the engine once the graph is set up.
+pub struct RenderGraphDump {
+    pub text: String,
+    pub dot: String,
+}
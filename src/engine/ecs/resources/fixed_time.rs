@@ -0,0 +1,86 @@
+/// Drives the schedule's fixed stage. Each frame's delta time goes into an accumulator, and
+/// the fixed stage runs once per whole `timestep` in it, so simulation results don't depend on
+/// the framerate. Replace the resource to change the tick rate.
+pub struct FixedTime {
+    pub timestep: f32,
+    /// Catch-up cap. After a long stall we drop the backlog rather than running dozens of
+    /// steps, which would make the next frame slower still.
+    pub max_steps_per_frame: u32,
+    accumulator: f32,
+    alpha: f32,
+}
+
+pub const DEFAULT_FIXED_HZ: f32 = 60.0;
+const DEFAULT_MAX_STEPS_PER_FRAME: u32 = 5;
+
+impl FixedTime {
+    pub fn from_hz(hz: f32) -> Self {
+        Self {
+            timestep: 1.0 / hz,
+            max_steps_per_frame: DEFAULT_MAX_STEPS_PER_FRAME,
+            accumulator: 0.0,
+            alpha: 0.0,
+        }
+    }
+
+    /// Adds a frame's delta time and returns how many fixed steps to run this frame.
+    pub fn accumulate(&mut self, delta_time: f32) -> u32 {
+        self.accumulator += delta_time;
+        let mut steps = 0;
+        while self.accumulator >= self.timestep && steps < self.max_steps_per_frame {
+            self.accumulator -= self.timestep;
+            steps += 1;
+        }
+        if self.accumulator >= self.timestep {
+            log::debug!("fixed stage fell behind, dropping {:.3}s", self.accumulator);
+            self.accumulator %= self.timestep;
+        }
+        self.alpha = self.accumulator / self.timestep;
+        steps
+    }
+
+    /// How far between the last fixed step and the next one this frame is, from 0 to 1.
+    /// `render_sync_system` uses it to blend `PreviousTransform` into the current transform.
+    pub fn alpha(&self) -> f32 {
+        self.alpha
+    }
+}
+
+impl Default for FixedTime {
+    fn default() -> Self {
+        Self::from_hz(DEFAULT_FIXED_HZ)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_once_per_whole_timestep() {
+        let mut fixed = FixedTime::from_hz(10.0);
+        assert_eq!(fixed.accumulate(0.05), 0);
+        assert!((fixed.alpha() - 0.5).abs() < 1e-5);
+        assert_eq!(fixed.accumulate(0.05), 1);
+        assert_eq!(fixed.accumulate(0.25), 2);
+        assert!((fixed.alpha() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn catch_up_is_capped_and_backlog_dropped() {
+        let mut fixed = FixedTime::from_hz(10.0);
+        fixed.max_steps_per_frame = 3;
+        assert_eq!(fixed.accumulate(1.05), 3);
+        assert!(fixed.alpha() < 1.0);
+        assert_eq!(fixed.accumulate(0.0), 0);
+    }
+
+    #[test]
+    fn same_total_time_gives_same_step_count_at_any_framerate() {
+        let mut slow = FixedTime::from_hz(60.0);
+        let mut fast = FixedTime::from_hz(60.0);
+        let slow_steps: u32 = (0..30).map(|_| slow.accumulate(1.0 / 30.0)).sum();
+        let fast_steps: u32 = (0..144).map(|_| fast.accumulate(1.0 / 144.0)).sum();
+        assert!(slow_steps.abs_diff(60) <= 1 && fast_steps.abs_diff(60) <= 1);
+    }
+}
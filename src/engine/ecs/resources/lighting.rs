@@ -0,0 +1,39 @@
+use crate::engine::{ light::MAX_LIGHTS, shadow::SHADOW_CASCADES };
+
+/// Scene-wide light settings, read by `light_sync_system`. Replace the resource to change them.
+pub struct Lighting {
+    /// Added to every lit surface, so the sides facing away from every light aren't black
+    pub ambient: [f32; 3],
+    /// Lights past this many are dropped, furthest from the camera first (directional lights
+    /// are always kept). Capped at `MAX_LIGHTS`, the size of the shader's array.
+    pub max_lights: usize,
+    /// How far from the camera each directional shadow cascade reaches. Nearer cascades cover
+    /// less, so they're sharper. Nothing past the last one is shadowed.
+    pub shadow_cascades: [f32; SHADOW_CASCADES],
+    /// Shadow map depth bias, against shadow acne. Raise it if lit surfaces get striped, lower
+    /// it if shadows detach from their casters.
+    pub shadow_bias: f32,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: [0.1, 0.1, 0.1],
+            max_lights: MAX_LIGHTS,
+            shadow_cascades: [12.0, 35.0, 100.0],
+            shadow_bias: 0.002,
+        }
+    }
+}
+
+/// The GPU buffer behind the shader's lights (bind group 1). Added by the engine at startup.
+pub struct LightBuffer(pub wgpu::Buffer);
+
+/// The GPU side of the shadow maps, written by `light_sync_system`: the view-projections the
+/// main pass samples with, one buffer per shadow map layer to render it with, and which layers
+/// are in use this frame. Added by the engine at startup.
+pub struct ShadowBuffers {
+    pub uniform: wgpu::Buffer,
+    pub layers: Vec<wgpu::Buffer>,
+    pub active_maps: Vec<usize>,
+}
@@ -1,2 +1,5 @@
 pub mod camera;
 pub mod debug;
+pub mod fixed_time;
+pub mod lighting;
+pub mod post_processing;
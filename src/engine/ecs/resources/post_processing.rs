@@ -0,0 +1,107 @@
+/// How the HDR scene is turned into the final image: a chain of fullscreen effects, read by
+/// the post-processing node every frame - change it at any time. The defaults leave the image
+/// as it was drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostProcessing {
+    /// Applied in this order, each one a pass over the result of the last. Disabled entries are
+    /// skipped, and only the first `post_process::MAX_POST_EFFECTS` enabled ones run
+    pub effects: Vec<PostEffect>,
+}
+
+/// One step of the chain.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostEffect {
+    pub enabled: bool,
+    pub effect: Effect,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    /// How far apart red and blue are pulled at the screen's edge, in UV units. 0.005 is subtle
+    ChromaticAberration(f32),
+    Bloom(Bloom),
+    /// Multiplies the image, e.g. before tonemapping
+    Exposure(f32),
+    Tonemapping(Tonemapping),
+    Vignette(Vignette),
+    /// Strength of the noise added to the image. 0.02-0.05 is subtle
+    FilmGrain(f32),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bloom {
+    /// How much of the blurred image is mixed in. There's no threshold - brighter pixels bloom
+    /// more - so keep it low, around 0.05
+    pub intensity: f32,
+    /// Of each upsampling step, in UV units. Larger is softer and wider
+    pub filter_radius: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tonemapping {
+    Reinhard,
+    /// Filmic, with more contrast and saturation than Reinhard
+    Aces,
+}
+
+/// Darkens the screen towards its corners.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vignette {
+    /// How dark the corners get, 0.0-1.0
+    pub intensity: f32,
+    /// Where the darkening starts, from 0.0 at the centre to 1.0 at the corners
+    pub radius: f32,
+    /// How far past `radius` it takes to reach full darkness
+    pub smoothness: f32,
+}
+
+impl PostEffect {
+    pub fn on(effect: Effect) -> Self {
+        Self { enabled: true, effect }
+    }
+
+    pub fn off(effect: Effect) -> Self {
+        Self { enabled: false, effect }
+    }
+}
+
+impl Effect {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Effect::ChromaticAberration(_) => "Chromatic aberration",
+            Effect::Bloom(_) => "Bloom",
+            Effect::Exposure(_) => "Exposure",
+            Effect::Tonemapping(_) => "Tonemapping",
+            Effect::Vignette(_) => "Vignette",
+            Effect::FilmGrain(_) => "Film grain",
+        }
+    }
+}
+
+// Every effect, in the order that reads best, all turned off
+impl Default for PostProcessing {
+    fn default() -> Self {
+        Self {
+            effects: vec![
+                PostEffect::off(Effect::ChromaticAberration(0.005)),
+                PostEffect::off(Effect::Bloom(Bloom::default())),
+                PostEffect::off(Effect::Exposure(1.0)),
+                PostEffect::off(Effect::Tonemapping(Tonemapping::Aces)),
+                PostEffect::off(Effect::Vignette(Vignette::default())),
+                PostEffect::off(Effect::FilmGrain(0.03))
+            ],
+        }
+    }
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self { intensity: 0.05, filter_radius: 0.005 }
+    }
+}
+
+impl Default for Vignette {
+    fn default() -> Self {
+        Self { intensity: 0.35, radius: 0.5, smoothness: 0.5 }
+    }
+}
@@ -0,0 +1,401 @@
+// A minimal RON syntax tree, used to merge prefab overrides field by field.
+//
+// `ron::Value` can't be used for this: it drops struct and enum variant names, so
+// `direction: Up` comes back as `Value::Unit` (see docs/SCENE_SERIALISATION.md). `RonNode` keeps
+// everything as written - numbers, strings and unit variants are stored as their source text -
+// and prints back to RON that the real `ron` deserializer reads.
+
+use std::{ fmt, ops::Range };
+
+use anyhow::{ anyhow, bail, Result };
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RonNode {
+    /// `(x: 1.0)` or `Name(x: 1.0)`
+    Struct(Option<String>, Vec<(String, RonNode)>),
+    /// `()`, `(1.0, 2.0)` or `Name(1.0)`
+    Tuple(Option<String>, Vec<RonNode>),
+    /// `[a, b]`
+    List(Vec<RonNode>),
+    /// `{ "key": value }`
+    Map(Vec<(RonNode, RonNode)>),
+    /// Numbers, strings, chars, bools and bare identifiers (unit variants), as written
+    Atom(String),
+}
+
+impl RonNode {
+    pub fn parse(source: &str) -> Result<RonNode> {
+        let mut parser = Parser { source, pos: 0 };
+        let node = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < source.len() {
+            bail!("unexpected trailing input at byte {}", parser.pos);
+        }
+        Ok(node)
+    }
+
+    /// The contents of a string atom, e.g. a map key like `"Transform"`.
+    pub fn as_str(&self) -> Option<String> {
+        match self {
+            RonNode::Atom(text) if text.starts_with('"') => ron::from_str(text).ok(),
+            _ => None,
+        }
+    }
+
+    /// Overlays `patch` onto this node. Structs (with the same or no name) and maps are merged
+    /// key by key, recursively; anything else - including a different enum variant - is
+    /// replaced outright.
+    pub fn merge(&mut self, patch: RonNode) {
+        match (self, patch) {
+            (RonNode::Struct(name, fields), RonNode::Struct(patch_name, patch_fields)) if
+                patch_name.is_none() || *name == patch_name
+            => {
+                for (key, value) in patch_fields {
+                    match fields.iter_mut().find(|(existing, _)| *existing == key) {
+                        Some((_, existing)) => existing.merge(value),
+                        None => fields.push((key, value)),
+                    }
+                }
+            }
+            (RonNode::Map(entries), RonNode::Map(patch_entries)) => {
+                for (key, value) in patch_entries {
+                    match entries.iter_mut().find(|(existing, _)| *existing == key) {
+                        Some((_, existing)) => existing.merge(value),
+                        None => entries.push((key, value)),
+                    }
+                }
+            }
+            (this, patch) => {
+                *this = patch;
+            }
+        }
+    }
+}
+
+/// Byte ranges of each `key: value` in the map or struct that starts `source`. Used by the
+/// validators to point diagnostics at the right place.
+pub fn entry_spans(source: &str) -> Result<Vec<(Range<usize>, Range<usize>)>> {
+    let mut parser = Parser { source, pos: 0 };
+    parser.skip_whitespace();
+    parser.take_while(|c| c.is_alphanumeric() || c == '_');
+    parser.skip_whitespace();
+    let is_map = match parser.peek() {
+        Some('{') => true,
+        Some('(') => false,
+        _ => bail!("expected a map or struct"),
+    };
+    parser.pos += 1;
+    let close = if is_map { '}' } else { ')' };
+
+    let mut spans = Vec::new();
+    while !parser.eat(close) {
+        parser.skip_whitespace();
+        let key_start = parser.pos;
+        if is_map {
+            parser.value()?;
+        } else {
+            parser.take_while(|c| c.is_alphanumeric() || c == '_');
+        }
+        let key = key_start..parser.pos;
+        parser.expect(':')?;
+        spans.push((key, parser.spanned_value()?));
+        if !parser.eat(',') {
+            parser.expect(close)?;
+            break;
+        }
+    }
+    Ok(spans)
+}
+
+/// Byte ranges of each item in the list or tuple that starts `source`.
+pub fn item_spans(source: &str) -> Result<Vec<Range<usize>>> {
+    let mut parser = Parser { source, pos: 0 };
+    parser.skip_whitespace();
+    let close = match parser.peek() {
+        Some('[') => ']',
+        Some('(') => ')',
+        _ => bail!("expected a list"),
+    };
+    parser.pos += 1;
+
+    let mut spans = Vec::new();
+    while !parser.eat(close) {
+        spans.push(parser.spanned_value()?);
+        if !parser.eat(',') {
+            parser.expect(close)?;
+            break;
+        }
+    }
+    Ok(spans)
+}
+
+fn write_list<T>(
+    f: &mut fmt::Formatter,
+    items: &[T],
+    write_item: impl Fn(&mut fmt::Formatter, &T) -> fmt::Result
+) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_item(f, item)?;
+    }
+    Ok(())
+}
+
+// Compact RON, e.g. `Transform(position: (x: 1.0, y: 0.0, z: 0.0))`
+impl fmt::Display for RonNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RonNode::Struct(name, fields) => {
+                write!(f, "{}(", name.as_deref().unwrap_or(""))?;
+                write_list(f, fields, |f, (key, value)| write!(f, "{}: {}", key, value))?;
+                write!(f, ")")
+            }
+            RonNode::Tuple(name, items) => {
+                write!(f, "{}(", name.as_deref().unwrap_or(""))?;
+                write_list(f, items, |f, item| write!(f, "{}", item))?;
+                write!(f, ")")
+            }
+            RonNode::List(items) => {
+                write!(f, "[")?;
+                write_list(f, items, |f, item| write!(f, "{}", item))?;
+                write!(f, "]")
+            }
+            RonNode::Map(entries) => {
+                write!(f, "{{")?;
+                write_list(f, entries, |f, (key, value)| write!(f, "{}: {}", key, value))?;
+                write!(f, "}}")
+            }
+            RonNode::Atom(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+struct Parser<'s> {
+    source: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn rest(&self) -> &'s str {
+        &self.source[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.pos += trimmed.find("*/").map_or(trimmed.len(), |end| end + 2);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        self.skip_whitespace();
+        if self.peek() != Some(c) {
+            bail!("expected '{}' at byte {}", c, self.pos);
+        }
+        self.pos += c.len_utf8();
+        Ok(())
+    }
+
+    // Consumes `c` if it's next
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    // Takes chars while `keep` holds, returning them
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> &'s str {
+        let rest = self.rest();
+        let end = rest.find(|c: char| !keep(c)).unwrap_or(rest.len());
+        self.pos += end;
+        &rest[..end]
+    }
+
+    fn value(&mut self) -> Result<RonNode> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => self.parenthesized(None),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                while !self.eat(']') {
+                    items.push(self.value()?);
+                    if !self.eat(',') {
+                        self.expect(']')?;
+                        break;
+                    }
+                }
+                Ok(RonNode::List(items))
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut entries = Vec::new();
+                while !self.eat('}') {
+                    let key = self.value()?;
+                    self.expect(':')?;
+                    entries.push((key, self.value()?));
+                    if !self.eat(',') {
+                        self.expect('}')?;
+                        break;
+                    }
+                }
+                Ok(RonNode::Map(entries))
+            }
+            Some(quote @ ('"' | '\'')) => self.quoted(quote),
+            Some(c) if c.is_alphabetic() || c == '_' => {
+                let ident = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                self.skip_whitespace();
+                if self.peek() == Some('(') {
+                    self.parenthesized(Some(ident.to_string()))
+                } else {
+                    Ok(RonNode::Atom(ident.to_string()))
+                }
+            }
+            Some(c) if c.is_ascii_digit() || "+-.".contains(c) => {
+                let number = self.take_while(|c| c.is_alphanumeric() || "+-._".contains(c));
+                Ok(RonNode::Atom(number.to_string()))
+            }
+            Some(c) => Err(anyhow!("unexpected '{}' at byte {}", c, self.pos)),
+            None => Err(anyhow!("unexpected end of input")),
+        }
+    }
+
+    fn spanned_value(&mut self) -> Result<Range<usize>> {
+        self.skip_whitespace();
+        let start = self.pos;
+        self.value()?;
+        Ok(start..self.pos)
+    }
+
+    fn quoted(&mut self, quote: char) -> Result<RonNode> {
+        let start = self.pos;
+        let mut chars = self.rest().char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            if c == '\\' {
+                chars.next();
+            } else if c == quote {
+                self.pos += i + 1;
+                return Ok(RonNode::Atom(self.source[start..self.pos].to_string()));
+            }
+        }
+        bail!("unterminated {} at byte {}", quote, start)
+    }
+
+    // `( ... )`, as a struct if the first entry is `ident:`, otherwise as a tuple
+    fn parenthesized(&mut self, name: Option<String>) -> Result<RonNode> {
+        self.expect('(')?;
+        self.skip_whitespace();
+        let checkpoint = self.pos;
+        let first_ident = self.take_while(|c| c.is_alphanumeric() || c == '_');
+        let is_struct = !first_ident.is_empty() && {
+            self.skip_whitespace();
+            self.rest().starts_with(':')
+        };
+        self.pos = checkpoint;
+
+        if is_struct {
+            let mut fields = Vec::new();
+            while !self.eat(')') {
+                self.skip_whitespace();
+                let key = self.take_while(|c| c.is_alphanumeric() || c == '_').to_string();
+                if key.is_empty() {
+                    bail!("expected a field name at byte {}", self.pos);
+                }
+                self.expect(':')?;
+                fields.push((key, self.value()?));
+                if !self.eat(',') {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+            Ok(RonNode::Struct(name, fields))
+        } else {
+            let mut items = Vec::new();
+            while !self.eat(')') {
+                items.push(self.value()?);
+                if !self.eat(',') {
+                    self.expect(')')?;
+                    break;
+                }
+            }
+            Ok(RonNode::Tuple(name, items))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_enum_variants_and_prints_parseable_ron() {
+        let node = RonNode::parse(
+            r#"(shape: AABB(offset: (x: 0.0, y: 0.0, z: -0.3), half_extents: (x: 1.0, y: 0.5, z: 1.5)), direction: Up) // comment"#
+        ).unwrap();
+        assert_eq!(
+            node.to_string(),
+            "(shape: AABB(offset: (x: 0.0, y: 0.0, z: -0.3), half_extents: (x: 1.0, y: 0.5, z: 1.5)), direction: Up)"
+        );
+        assert_eq!(RonNode::parse(&node.to_string()).unwrap(), node);
+    }
+
+    #[test]
+    fn merge_overrides_only_the_given_fields() {
+        let mut base = RonNode::parse(
+            "(position: (x: 0.0, y: 0.0, z: 0.0), scale: (x: 0.3, y: 0.3, z: 0.3))"
+        ).unwrap();
+        base.merge(RonNode::parse("(position: (z: 5.0))").unwrap());
+        assert_eq!(
+            base.to_string(),
+            "(position: (x: 0.0, y: 0.0, z: 5.0), scale: (x: 0.3, y: 0.3, z: 0.3))"
+        );
+    }
+
+    #[test]
+    fn merge_replaces_a_different_variant() {
+        let mut base = RonNode::parse("(shape: Sphere(radius: 1.0), direction: Up)").unwrap();
+        base.merge(RonNode::parse("(shape: Box(size: 2.0), direction: Down)").unwrap());
+        assert_eq!(base.to_string(), "(shape: Box(size: 2.0), direction: Down)");
+    }
+
+    #[test]
+    fn spans_cover_keys_and_values() {
+        let source = r#"{ "Transform": (x: 1), "Player": () }"#;
+        let spans = entry_spans(source).unwrap();
+        let text: Vec<(&str, &str)> = spans
+            .iter()
+            .map(|(key, value)| (&source[key.clone()], &source[value.clone()]))
+            .collect();
+        assert_eq!(text, vec![(r#""Transform""#, "(x: 1)"), (r#""Player""#, "()")]);
+
+        let source = "[ a, B(1) ]";
+        let items: Vec<&str> = item_spans(source).unwrap().into_iter().map(|span| &source[span]).collect();
+        assert_eq!(items, vec!["a", "B(1)"]);
+    }
+
+    #[test]
+    fn string_keys_unquote() {
+        let node = RonNode::parse(r#"{ "Transform": (), "a\"b": 1 }"#).unwrap();
+        let RonNode::Map(entries) = node else { panic!("expected a map") };
+        assert_eq!(entries[0].0.as_str().as_deref(), Some("Transform"));
+        assert_eq!(entries[1].0.as_str().as_deref(), Some("a\"b"));
+    }
+}
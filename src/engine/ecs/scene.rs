@@ -0,0 +1,421 @@
+// Scenes: world files loaded into the running World, additively
+//
+//     // from any system
+//     fn stream_system(mut scenes: ResMut<Scenes>) {
+//         let chunk = scenes.load("canyon_chunk_2", include_str!("chunk_2.ron"));
+//         ...
+//         scenes.unload(chunk);
+//     }
+//
+// Every entity a scene spawns - roots, children and prefab children - gets the scene's
+// `SceneId`, so unloading despawns exactly what it contributed. Requests are queued and applied
+// by the engine after the frame's systems (model names need the AssetServer). A reload spawns
+// the new content before despawning the old, so a file that fails to load leaves the scene as
+// it was.
+
+use std::{ collections::{ HashMap, HashSet }, sync::Arc };
+
+use anyhow::{ anyhow, Context, Result };
+
+use crate::engine::{
+    assets::server::AssetServer,
+    ecs::{
+        component_registry::ComponentRegistry,
+        components::{ hierarchy::Parent, renderable::Renderable },
+        entity::Entity,
+        entity_ref::EntityNames,
+        world::World,
+        world_descriptor::spawn_world,
+    },
+};
+
+/// Which scene an entity was loaded from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SceneId(u32);
+
+struct LoadedScene {
+    name: String,
+    source: String,
+    entities: EntityNames,
+}
+
+enum SceneRequest {
+    Load(SceneId),
+    Unload(SceneId),
+    Reload(SceneId, Option<String>),
+}
+
+/// Every loaded scene, as a resource. Added by the engine before the world file is loaded,
+/// which becomes the first scene.
+pub struct Scenes {
+    registry: Arc<ComponentRegistry>,
+    scenes: HashMap<SceneId, LoadedScene>,
+    // Queued loads wait here until applied
+    pending: HashMap<SceneId, LoadedScene>,
+    requests: Vec<SceneRequest>,
+    next_id: u32,
+}
+
+impl Scenes {
+    pub fn new(registry: Arc<ComponentRegistry>) -> Self {
+        Self {
+            registry,
+            scenes: HashMap::new(),
+            pending: HashMap::new(),
+            requests: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Queues a world file to be loaded at the end of the frame. The id is usable straight away,
+    /// e.g. to unload it again.
+    pub fn load(&mut self, name: &str, ron_str: impl Into<String>) -> SceneId {
+        let id = self.reserve(name, ron_str.into());
+        self.requests.push(SceneRequest::Load(id));
+        id
+    }
+
+    /// Queues despawning everything the scene spawned.
+    pub fn unload(&mut self, id: SceneId) {
+        self.requests.push(SceneRequest::Unload(id));
+    }
+
+    /// Queues reloading the scene from the source it was loaded from.
+    pub fn reload(&mut self, id: SceneId) {
+        self.requests.push(SceneRequest::Reload(id, None));
+    }
+
+    /// Queues reloading the scene from new source, e.g. after the file changed.
+    pub fn reload_from(&mut self, id: SceneId, ron_str: impl Into<String>) {
+        self.requests.push(SceneRequest::Reload(id, Some(ron_str.into())));
+    }
+
+    pub fn is_loaded(&self, id: SceneId) -> bool {
+        self.scenes.contains_key(&id)
+    }
+
+    /// The first loaded scene with this name.
+    pub fn find(&self, name: &str) -> Option<SceneId> {
+        self.scenes
+            .iter()
+            .filter(|(_, scene)| scene.name == name)
+            .map(|(id, _)| *id)
+            .min_by_key(|id| id.0)
+    }
+
+    pub fn name(&self, id: SceneId) -> Option<&str> {
+        self.scenes.get(&id).map(|scene| scene.name.as_str())
+    }
+
+    /// The scene's entities that were given an `"id"`, by id.
+    pub fn entities(&self, id: SceneId) -> Option<&EntityNames> {
+        self.scenes.get(&id).map(|scene| &scene.entities)
+    }
+
+    pub fn loaded(&self) -> impl Iterator<Item = SceneId> + '_ {
+        self.scenes.keys().copied()
+    }
+
+    fn reserve(&mut self, name: &str, source: String) -> SceneId {
+        let id = SceneId(self.next_id);
+        self.next_id += 1;
+        let scene = LoadedScene { name: name.to_string(), source, entities: EntityNames::new() };
+        self.pending.insert(id, scene);
+        id
+    }
+}
+
+/// Loads a world file as a new scene right away. Needs the `Scenes` resource.
+pub fn load_scene(
+    world: &mut World,
+    name: &str,
+    ron_str: &str,
+    asset_server: &AssetServer
+) -> Result<SceneId> {
+    let id = scenes_mut(world)?.reserve(name, ron_str.to_string());
+    spawn_scene(world, id, asset_server)?;
+    Ok(id)
+}
+
+/// Despawns everything the scene spawned (and anything since parented under it).
+pub fn unload_scene(world: &mut World, id: SceneId) -> Result<()> {
+    let scenes = scenes_mut(world)?;
+    if scenes.pending.remove(&id).is_some() {
+        // Never got loaded - nothing to despawn
+        return Ok(());
+    }
+    let scene = scenes.scenes.remove(&id).ok_or_else(|| anyhow!("scene {:?} is not loaded", id))?;
+    despawn_scene_entities(world, id);
+    log::info!("unloaded scene {}", scene.name);
+    Ok(())
+}
+
+/// Replaces the scene's entities with a fresh load of `ron_str`, or of its current source if
+/// `None`. On error the old entities are left alone.
+pub fn reload_scene(
+    world: &mut World,
+    id: SceneId,
+    ron_str: Option<&str>,
+    asset_server: &AssetServer
+) -> Result<()> {
+    let previous: Vec<Entity> = scene_entities(world, id);
+    let scenes = scenes_mut(world)?;
+    let mut scene = scenes.scenes.remove(&id).ok_or_else(|| anyhow!("scene {:?} is not loaded", id))?;
+    let old_source = ron_str.map(|source| std::mem::replace(&mut scene.source, source.to_string()));
+    scenes.pending.insert(id, scene);
+
+    if let Err(e) = spawn_scene(world, id, asset_server) {
+        // Put the scene back as it was
+        let scenes = scenes_mut(world)?;
+        let mut scene = scenes.pending.remove(&id).unwrap();
+        if let Some(source) = old_source {
+            scene.source = source;
+        }
+        scenes.scenes.insert(id, scene);
+        return Err(e);
+    }
+    for entity in previous {
+        world.despawn_recursive(entity);
+    }
+    Ok(())
+}
+
+/// Applies new source to a loaded scene, keeping the entities whose `"id"` is in both versions.
+/// Those keep their `Entity` - and anything code added to them - but get the new file's
+/// components, parent and children. Everything else the scene spawned is replaced. On error the
+/// scene is left as it was. Used by hot reload.
+pub fn patch_scene(
+    world: &mut World,
+    id: SceneId,
+    ron_str: &str,
+    asset_server: &AssetServer
+) -> Result<()> {
+    let scenes = scenes_mut(world)?;
+    let registry = scenes.registry.clone();
+    let scene = scenes.scenes.get(&id).ok_or_else(|| anyhow!("scene {:?} is not loaded", id))?;
+    let old_names = scene.entities.clone();
+    let name = scene.name.clone();
+    let previous = scene_entities(world, id);
+    let (new_names, spawned) = spawn_world(ron_str, world, &registry, asset_server).with_context(||
+        format!("failed to reload scene {}", name)
+    )?;
+
+    // Freshly spawned entity -> the old one standing in for it
+    let kept: HashMap<Entity, Entity> = new_names
+        .iter()
+        .filter_map(|(id_name, new)| {
+            let old = *old_names.get(id_name)?;
+            world.is_alive(old).then_some((*new, old))
+        })
+        .collect();
+    let resolve = |entity: Entity| kept.get(&entity).copied().unwrap_or(entity);
+
+    for (&new, &old) in &kept {
+        registry.copy_components(world, new, old)?;
+        match world.get_component::<Renderable>(new).map(|renderable| renderable.model.clone()) {
+            Some(model) => world.add_component(old, Renderable::new(model)),
+            None => world.remove_component::<Renderable>(old),
+        }
+    }
+    // Rebuild the new hierarchy with the kept entities in place
+    for &entity in &spawned {
+        let parent = world.get_component::<Parent>(entity).map(|parent| parent.0);
+        let (target, target_parent) = (resolve(entity), parent.map(resolve));
+        if target == entity && target_parent == parent {
+            continue;
+        }
+        match target_parent {
+            Some(target_parent) => world.set_parent(target, target_parent)?,
+            None => world.remove_parent(target),
+        }
+    }
+
+    for &new in kept.keys() {
+        world.despawn(new);
+    }
+    let kept_old: HashSet<Entity> = kept.values().copied().collect();
+    for entity in previous.into_iter().filter(|entity| !kept_old.contains(entity)) {
+        world.despawn_recursive(entity);
+    }
+    let mut entities = Vec::new();
+    for entity in spawned.into_iter().map(resolve) {
+        if !kept_old.contains(&entity) {
+            world.add_component(entity, id);
+        }
+        entities.push(entity);
+    }
+
+    // Refs were mapped to the spawned entities, some of which are gone now
+    let names: EntityNames = new_names
+        .into_iter()
+        .map(|(id_name, entity)| (id_name, resolve(entity)))
+        .collect();
+    registry.map_entities(world, &entities, &names);
+
+    let scene = scenes_mut(world)?.scenes.get_mut(&id).unwrap();
+    scene.source = ron_str.to_string();
+    scene.entities = names;
+    log::info!("reloaded scene {}, kept {} entities", scene.name, kept_old.len());
+    Ok(())
+}
+
+/// Applies the requests queued on `Scenes` this frame, in order. Failures are logged.
+pub fn apply_scene_requests(world: &mut World, asset_server: &AssetServer) {
+    let Some(scenes) = world.get_resource_mut::<Scenes>() else {
+        return;
+    };
+    let requests = std::mem::take(&mut scenes.requests);
+    for request in requests {
+        let result = match request {
+            SceneRequest::Load(id) => spawn_scene(world, id, asset_server),
+            SceneRequest::Unload(id) => unload_scene(world, id),
+            SceneRequest::Reload(id, source) => reload_scene(world, id, source.as_deref(), asset_server),
+        };
+        if let Err(e) = result {
+            log::error!("scene request failed: {:#}", e);
+        }
+    }
+}
+
+// Spawns a pending scene and moves it to the loaded ones
+fn spawn_scene(world: &mut World, id: SceneId, asset_server: &AssetServer) -> Result<()> {
+    let scenes = scenes_mut(world)?;
+    let registry = scenes.registry.clone();
+    let Some(mut scene) = scenes.pending.remove(&id) else {
+        // Unloaded before it was applied
+        return Ok(());
+    };
+
+    match spawn_world(&scene.source, world, &registry, asset_server) {
+        Ok((entities, spawned)) => {
+            for entity in spawned {
+                world.add_component(entity, id);
+            }
+            log::info!("loaded scene {}", scene.name);
+            scene.entities = entities;
+            scenes_mut(world)?.scenes.insert(id, scene);
+            Ok(())
+        }
+        Err(e) => {
+            let error = e.context(format!("failed to load scene {}", scene.name));
+            // Leave it pending so a reload can restore it
+            scenes_mut(world)?.pending.insert(id, scene);
+            Err(error)
+        }
+    }
+}
+
+fn scene_entities(world: &World, id: SceneId) -> Vec<Entity> {
+    world
+        .iter_component::<SceneId>()
+        .filter(|(_, scene)| **scene == id)
+        .filter_map(|(entity_id, _)| world.get_entity(entity_id))
+        .collect()
+}
+
+fn despawn_scene_entities(world: &mut World, id: SceneId) {
+    for entity in scene_entities(world, id) {
+        world.despawn_recursive(entity);
+    }
+}
+
+fn scenes_mut(world: &mut World) -> Result<&mut Scenes> {
+    world.get_resource_mut::<Scenes>().ok_or_else(|| anyhow!("there is no Scenes resource"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ecs::components::velocity::Velocity;
+
+    const A: &str = r#"( entities: [ { "id": "a", "Velocity": (x: 1.0, y: 0.0, z: 0.0), "children": [ { "Velocity": (x: 2.0, y: 0.0, z: 0.0) } ] } ] )"#;
+    const B: &str = r#"( entities: [ { "id": "a", "Velocity": (x: 5.0, y: 0.0, z: 0.0) } ] )"#;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.add_resource(Scenes::new(Arc::new(ComponentRegistry::new())));
+        world
+    }
+
+    fn xs(world: &mut World) -> Vec<f32> {
+        let mut xs: Vec<f32> = world.query_iter::<&Velocity>().map(|v| v.x).collect();
+        xs.sort_by(f32::total_cmp);
+        xs
+    }
+
+    #[test]
+    fn scenes_load_additively_and_unload_only_their_entities() {
+        let mut world = world();
+        let asset_server = AssetServer::new();
+        let a = load_scene(&mut world, "a", A, &asset_server).unwrap();
+        let b = load_scene(&mut world, "b", B, &asset_server).unwrap();
+        assert_eq!(xs(&mut world), vec![1.0, 2.0, 5.0]);
+
+        let child = world.get_resource::<Scenes>().unwrap().entities(a).unwrap()["a"];
+        assert_eq!(world.get_component::<SceneId>(child), Some(&a));
+
+        unload_scene(&mut world, a).unwrap();
+        assert_eq!(xs(&mut world), vec![5.0]);
+        assert!(!world.get_resource::<Scenes>().unwrap().is_loaded(a));
+        assert!(world.get_resource::<Scenes>().unwrap().is_loaded(b));
+    }
+
+    #[test]
+    fn reload_replaces_in_place_and_keeps_old_content_on_error() {
+        let mut world = world();
+        let asset_server = AssetServer::new();
+        let id = load_scene(&mut world, "level", A, &asset_server).unwrap();
+
+        reload_scene(&mut world, id, Some(B), &asset_server).unwrap();
+        assert_eq!(xs(&mut world), vec![5.0]);
+
+        let broken = r#"( entities: [ { "Velocity": (x: "fast") } ] )"#;
+        assert!(reload_scene(&mut world, id, Some(broken), &asset_server).is_err());
+        assert_eq!(xs(&mut world), vec![5.0]);
+
+        // Reloading without new source uses the last one that loaded
+        reload_scene(&mut world, id, None, &asset_server).unwrap();
+        assert_eq!(xs(&mut world), vec![5.0]);
+    }
+
+    #[test]
+    fn patch_keeps_entities_with_matching_ids() {
+        let mut world = world();
+        let asset_server = AssetServer::new();
+        let id = load_scene(&mut world, "level", A, &asset_server).unwrap();
+        let player = world.get_resource::<Scenes>().unwrap().entities(id).unwrap()["a"];
+        // Runtime state the file knows nothing about survives
+        world.add_component(player, 7u32);
+
+        patch_scene(&mut world, id, B, &asset_server).unwrap();
+        assert!(world.is_alive(player));
+        assert_eq!(world.get_component::<Velocity>(player).unwrap().x, 5.0);
+        assert_eq!(world.get_component::<u32>(player), Some(&7));
+        // The child only the old version had is gone
+        assert_eq!(xs(&mut world), vec![5.0]);
+        assert!(world.descendants(player).is_empty());
+
+        patch_scene(&mut world, id, A, &asset_server).unwrap();
+        assert_eq!(world.descendants(player).len(), 1);
+        assert_eq!(xs(&mut world), vec![1.0, 2.0]);
+        assert_eq!(world.get_resource::<Scenes>().unwrap().entities(id).unwrap()["a"], player);
+    }
+
+    #[test]
+    fn queued_requests_apply_in_order() {
+        let mut world = world();
+        let asset_server = AssetServer::new();
+        let scenes = world.get_resource_mut::<Scenes>().unwrap();
+        let a = scenes.load("a", A);
+        let skipped = scenes.load("b", B);
+        scenes.unload(skipped);
+        apply_scene_requests(&mut world, &asset_server);
+        assert_eq!(xs(&mut world), vec![1.0, 2.0]);
+
+        let scenes = world.get_resource_mut::<Scenes>().unwrap();
+        assert_eq!(scenes.find("a"), Some(a));
+        scenes.unload(a);
+        apply_scene_requests(&mut world, &asset_server);
+        assert!(xs(&mut world).is_empty());
+    }
+}
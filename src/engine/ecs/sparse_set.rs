@@ -1,7 +1,22 @@
+use std::cell::UnsafeCell;
+
+/// The world tick at which a component was added and last mutably accessed.
+/// Compared against `World::last_change_tick` by the `Added<T>` / `Changed<T>` filters.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ComponentTicks {
+    pub added: u32,
+    pub changed: u32,
+}
+
+// Values and ticks are in `UnsafeCell`s so systems running on other threads can each get at
+// their own components through a shared `&World` (see `get_mut_unchecked`). Everything else
+// goes through `&self`/`&mut self` as usual.
 pub struct SparseSet<T> {
     sparse: Vec<Option<usize>>,
     dense: Vec<u32>,
-    data: Vec<T>,
+    data: Vec<UnsafeCell<T>>,
+    // Parallel to `data` - swapped/removed alongside it
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
 }
 
 impl<T> SparseSet<T> {
@@ -14,10 +29,17 @@ impl<T> SparseSet<T> {
             sparse: Vec::new(),
             dense: Vec::new(),
             data: Vec::new(),
+            ticks: Vec::new(),
         }
     }
 
     pub fn insert(&mut self, entity_id: u32, value: T) {
+        self.insert_at_tick(entity_id, value, 0);
+    }
+
+    /// Inserts (or overwrites) a component, stamping it with the given world tick.
+    /// Overwriting counts as a change but not as an add.
+    pub fn insert_at_tick(&mut self, entity_id: u32, value: T, tick: u32) {
         let id = entity_id as usize;
 
         // Make sure our sparse array is big enough
@@ -29,7 +51,8 @@ impl<T> SparseSet<T> {
         match self.sparse[id] {
             Some(dense_index) => {
                 // entity already has the component, so just overwrite
-                self.data[dense_index] = value;
+                *self.data[dense_index].get_mut() = value;
+                self.ticks[dense_index].get_mut().changed = tick;
             }
             None => {
                 // Create new component
@@ -38,7 +61,8 @@ impl<T> SparseSet<T> {
 
                 // Push entity id and data to the end of the arrays
                 self.dense.push(entity_id);
-                self.data.push(value);
+                self.data.push(UnsafeCell::new(value));
+                self.ticks.push(UnsafeCell::new(ComponentTicks { added: tick, changed: tick }));
             }
         }
     }
@@ -58,6 +82,7 @@ impl<T> SparseSet<T> {
         // Move last element to the gap vacated by the removed entity
         self.dense.swap_remove(index);
         self.data.swap_remove(index);
+        self.ticks.swap_remove(index);
 
         // Clear removed entities sparse entry
         self.sparse[id] = None;
@@ -74,25 +99,59 @@ impl<T> SparseSet<T> {
         // giving Option<Option<usize>>, flatten() collapses to Option<usize>
         // None meaning either out of bounds or removed.
         let dense_idx = self.sparse.get(id).copied().flatten()?;
-        Some(&self.data[dense_idx])
+        // Safety: only `get_mut_unchecked` writes through a shared reference, and its callers
+        // guarantee nothing else is reading this component
+        Some(unsafe { &*self.data[dense_idx].get() })
     }
 
     pub fn get_mut(&mut self, entity_id: u32) -> Option<&mut T> {
         let id = entity_id as usize;
         let dense_idx = self.sparse.get(id).copied().flatten()?;
-        Some(&mut self.data[dense_idx])
+        Some(self.data[dense_idx].get_mut())
+    }
+
+    /// Like `get_mut`, but records the access as a change at `tick`.
+    /// Any mutable access counts as a change - we can't see whether the caller actually wrote.
+    pub fn get_mut_tracked(&mut self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        let id = entity_id as usize;
+        let dense_idx = self.sparse.get(id).copied().flatten()?;
+        self.ticks[dense_idx].get_mut().changed = tick;
+        Some(self.data[dense_idx].get_mut())
+    }
+
+    /// `get_mut_tracked` through a shared reference, for systems running in parallel.
+    /// # Safety
+    /// Nothing else may access this entity's T, or its ticks, while the result is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get_mut_unchecked(&self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        let dense_idx = self.sparse.get(entity_id as usize).copied().flatten()?;
+        (*self.ticks[dense_idx].get()).changed = tick;
+        Some(&mut *self.data[dense_idx].get())
+    }
+
+    pub fn ticks(&self, entity_id: u32) -> Option<ComponentTicks> {
+        let dense_idx = self.sparse.get(entity_id as usize).copied().flatten()?;
+        // Safety: as in `get`
+        Some(unsafe { *self.ticks[dense_idx].get() })
     }
 
+    // Never touches `data`, so `With`/`Without` can run while another thread holds a `&mut T`
     pub fn contains(&self, entity_id: u32) -> bool {
-        self.get(entity_id).is_some()
+        self.sparse.get(entity_id as usize).copied().flatten().is_some()
+    }
+
+    /// Entity ids in storage order - `entity_ids()[i]` owns the i-th value yielded by `iter`.
+    pub fn entity_ids(&self) -> &[u32] {
+        &self.dense
     }
 
     pub fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
-        self.dense.iter().copied().zip(self.data.iter())
+        // Safety: as in `get`
+        self.dense.iter().copied().zip(self.data.iter().map(|value| unsafe { &*value.get() }))
     }
 
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (u32, &mut T)> {
-        self.dense.iter().copied().zip(self.data.iter_mut())
+        self.dense.iter().copied().zip(self.data.iter_mut().map(UnsafeCell::get_mut))
     }
 
     pub fn len(&self) -> usize {
@@ -181,6 +240,39 @@ mod tests {
         assert_eq!(pairs, vec![(3, 30), (7, 70)]);
     }
 
+    #[test]
+    fn insert_at_tick_records_added_and_changed() {
+        let mut set: SparseSet<i32> = SparseSet::new();
+        set.insert_at_tick(0, 1, 5);
+        assert_eq!(set.ticks(0), Some(ComponentTicks { added: 5, changed: 5 }));
+    }
+
+    #[test]
+    fn overwrite_updates_changed_but_not_added() {
+        let mut set: SparseSet<i32> = SparseSet::new();
+        set.insert_at_tick(0, 1, 1);
+        set.insert_at_tick(0, 2, 3);
+        assert_eq!(set.ticks(0), Some(ComponentTicks { added: 1, changed: 3 }));
+    }
+
+    #[test]
+    fn get_mut_tracked_marks_changed() {
+        let mut set: SparseSet<i32> = SparseSet::new();
+        set.insert_at_tick(0, 1, 1);
+        *set.get_mut_tracked(0, 4).unwrap() = 2;
+        assert_eq!(set.ticks(0).unwrap().changed, 4);
+        assert_eq!(set.ticks(0).unwrap().added, 1);
+    }
+
+    #[test]
+    fn ticks_follow_swapped_entity_on_remove() {
+        let mut set: SparseSet<i32> = SparseSet::new();
+        set.insert_at_tick(0, 10, 1);
+        set.insert_at_tick(1, 20, 2);
+        set.remove(0);
+        assert_eq!(set.ticks(1), Some(ComponentTicks { added: 2, changed: 2 }));
+    }
+
     #[test]
     fn reinsertion_after_removal_works() {
         let mut set: SparseSet<i32> = SparseSet::new();
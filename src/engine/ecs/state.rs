@@ -0,0 +1,268 @@
+// Game states
+//
+//     schedule.init_state(GameState::Menu);
+//     schedule.add_on_enter(GameState::GameOver, show_score_system);
+//     schedule.add_game_system(player_system.run_if(in_state(GameState::Playing)));
+//
+//     // from any system
+//     fn game_over_system(mut state: ResMut<State<GameState>>) { state.set(GameState::GameOver); }
+//
+// `State::set` only queues the change. Queued transitions are applied at the start of the next
+// frame, before the game stage: the state is switched, OnExit(old) runs, entities with
+// `StateScoped(old)` are despawned, then OnEnter(new) runs. Each of those applies its commands
+// before the next starts. The initial state's OnEnter runs on the first frame, after startup.
+
+use std::{ any::Any, fmt::Debug, hash::Hash };
+
+use crate::engine::ecs::{
+    entity::Entity,
+    system::{ ScheduledSystem, SystemContext, SystemStage },
+    system_config::IntoSystemConfig,
+    world::World,
+};
+
+/// Anything usable as a state - normally a fieldless enum.
+pub trait States: Copy + Eq + Hash + Debug + Send + Sync + 'static {}
+
+impl<T: Copy + Eq + Hash + Debug + Send + Sync + 'static> States for T {}
+
+/// The current state, as a resource. Added by the schedule on the first frame with the value
+/// given to `SystemSchedule::init_state`.
+pub struct State<S: States> {
+    current: S,
+    queued: Option<S>,
+}
+
+impl<S: States> State<S> {
+    pub fn new(initial: S) -> Self {
+        Self { current: initial, queued: None }
+    }
+
+    pub fn get(&self) -> S {
+        self.current
+    }
+
+    /// Queues a transition for the start of next frame. If several are queued in one frame the
+    /// last one wins, and setting the current state again does nothing.
+    pub fn set(&mut self, next: S) {
+        self.queued = Some(next);
+    }
+
+    pub fn queued(&self) -> Option<S> {
+        self.queued
+    }
+}
+
+/// Despawns the entity (and its children) when the state is left.
+#[derive(Clone, Copy, Debug)]
+pub struct StateScoped<S: States>(pub S);
+
+// Lets SystemSchedule hold the OnEnter/OnExit stages of several state types
+pub(crate) trait StateDriver {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn stages(&self) -> Vec<&SystemStage>;
+    fn stages_mut(&mut self) -> Vec<&mut SystemStage>;
+    fn apply_transitions(&mut self, world: &mut World, system_context: &mut SystemContext);
+}
+
+pub(crate) struct StateStages<S: States> {
+    initial: S,
+    entered: bool,
+    on_enter: Vec<(S, SystemStage)>,
+    on_exit: Vec<(S, SystemStage)>,
+}
+
+impl<S: States> StateStages<S> {
+    pub(crate) fn new(initial: S) -> Self {
+        Self { initial, entered: false, on_enter: Vec::new(), on_exit: Vec::new() }
+    }
+
+    pub(crate) fn add_on_enter<M>(&mut self, state: S, system: impl IntoSystemConfig<M>) {
+        stage_for(&mut self.on_enter, state, "OnEnter").add(ScheduledSystem::new(system));
+    }
+
+    pub(crate) fn add_on_exit<M>(&mut self, state: S, system: impl IntoSystemConfig<M>) {
+        stage_for(&mut self.on_exit, state, "OnExit").add(ScheduledSystem::new(system));
+    }
+}
+
+fn stage_for<'a, S: States>(
+    stages: &'a mut Vec<(S, SystemStage)>,
+    state: S,
+    kind: &str
+) -> &'a mut SystemStage {
+    let index = match stages.iter().position(|(s, _)| *s == state) {
+        Some(index) => index,
+        None => {
+            stages.push((state, SystemStage::new(format!("{}({:?})", kind, state))));
+            stages.len() - 1
+        }
+    };
+    &mut stages[index].1
+}
+
+fn run_stage_for<S: States>(
+    stages: &mut [(S, SystemStage)],
+    state: S,
+    world: &mut World,
+    system_context: &mut SystemContext
+) {
+    if let Some((_, stage)) = stages.iter_mut().find(|(s, _)| *s == state) {
+        stage.run(world, system_context);
+    }
+}
+
+impl<S: States> StateDriver for StateStages<S> {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn stages(&self) -> Vec<&SystemStage> {
+        self.on_enter.iter().chain(&self.on_exit).map(|(_, stage)| stage).collect()
+    }
+
+    fn stages_mut(&mut self) -> Vec<&mut SystemStage> {
+        self.on_enter.iter_mut().chain(&mut self.on_exit).map(|(_, stage)| stage).collect()
+    }
+
+    fn apply_transitions(&mut self, world: &mut World, system_context: &mut SystemContext) {
+        if world.get_resource::<State<S>>().is_none() {
+            world.add_resource(State::new(self.initial));
+        }
+        if !self.entered {
+            self.entered = true;
+            let current = world.get_resource::<State<S>>().unwrap().current;
+            run_stage_for(&mut self.on_enter, current, world, system_context);
+        }
+
+        let state = world.get_resource_mut::<State<S>>().unwrap();
+        let Some(next) = state.queued.take() else {
+            return;
+        };
+        if next == state.current {
+            return;
+        }
+        let previous = state.current;
+        state.current = next;
+        log::info!("state {:?} -> {:?}", previous, next);
+
+        run_stage_for(&mut self.on_exit, previous, world, system_context);
+        despawn_scoped(world, previous);
+        run_stage_for(&mut self.on_enter, next, world, system_context);
+    }
+}
+
+fn despawn_scoped<S: States>(world: &mut World, left: S) {
+    let scoped: Vec<Entity> = world
+        .iter_component::<StateScoped<S>>()
+        .filter(|(_, scoped)| scoped.0 == left)
+        .filter_map(|(entity_id, _)| world.get_entity(entity_id))
+        .collect();
+    for entity in scoped {
+        world.despawn_recursive(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::ecs::{
+        commands::Commands,
+        condition::in_state,
+        system::SystemSchedule,
+        system_param::ResMut,
+    };
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    enum Phase {
+        Menu,
+        Playing,
+    }
+
+    struct Log(Vec<&'static str>);
+
+    fn make_ctx(world: &World) -> SystemContext<'static> {
+        SystemContext {
+            delta_time: 0.016,
+            device: None,
+            queue: None,
+            asset_server: None,
+            commands: Commands::new(world),
+        }
+    }
+
+    fn schedule_with_log() -> SystemSchedule {
+        let mut schedule = SystemSchedule::new();
+        schedule.init_state(Phase::Menu);
+        schedule.add_on_enter(Phase::Menu, |mut log: ResMut<Log>| log.0.push("enter menu"));
+        schedule.add_on_exit(Phase::Menu, |mut log: ResMut<Log>| log.0.push("exit menu"));
+        schedule.add_on_enter(Phase::Playing, |mut log: ResMut<Log>| log.0.push("enter playing"));
+        schedule.add_game_system(
+            (|mut log: ResMut<Log>| log.0.push("playing")).run_if(in_state(Phase::Playing))
+        );
+        schedule
+    }
+
+    #[test]
+    fn initial_state_is_entered_on_first_frame() {
+        let mut world = World::new();
+        world.add_resource(Log(Vec::new()));
+        let mut schedule = schedule_with_log();
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<State<Phase>>().unwrap().get(), Phase::Menu);
+        assert_eq!(world.get_resource::<Log>().unwrap().0, vec!["enter menu"]);
+    }
+
+    #[test]
+    fn queued_transition_runs_exit_then_enter_next_frame() {
+        let mut world = World::new();
+        world.add_resource(Log(Vec::new()));
+        let mut schedule = schedule_with_log();
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+
+        world.get_resource_mut::<State<Phase>>().unwrap().set(Phase::Playing);
+        assert_eq!(world.get_resource::<State<Phase>>().unwrap().get(), Phase::Menu);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(
+            world.get_resource::<Log>().unwrap().0,
+            vec!["enter menu", "exit menu", "enter playing", "playing"]
+        );
+    }
+
+    #[test]
+    fn setting_the_current_state_is_not_a_transition() {
+        let mut world = World::new();
+        world.add_resource(Log(Vec::new()));
+        let mut schedule = schedule_with_log();
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        world.get_resource_mut::<State<Phase>>().unwrap().set(Phase::Menu);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Log>().unwrap().0, vec!["enter menu"]);
+    }
+
+    #[test]
+    fn scoped_entities_despawn_when_their_state_is_left() {
+        let mut world = World::new();
+        world.add_resource(Log(Vec::new()));
+        let mut schedule = schedule_with_log();
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        let menu_only = world.spawn().with(StateScoped(Phase::Menu)).build();
+        let playing_only = world.spawn().with(StateScoped(Phase::Playing)).build();
+
+        world.get_resource_mut::<State<Phase>>().unwrap().set(Phase::Playing);
+        schedule.run_all(&mut world, &mut ctx);
+        assert!(!world.is_alive(menu_only));
+        assert!(world.is_alive(playing_only));
+    }
+
+    #[test]
+    #[should_panic(expected = "init_state")]
+    fn on_enter_without_init_state_panics() {
+        let mut schedule = SystemSchedule::new();
+        schedule.add_on_enter(Phase::Menu, |_log: ResMut<Log>| {});
+    }
+}
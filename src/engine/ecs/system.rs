@@ -1,13 +1,32 @@
+use std::{
+    any::type_name,
+    cmp::Reverse,
+    collections::{ BinaryHeap, HashMap, HashSet },
+    marker::PhantomData,
+};
+
+use anyhow::anyhow;
+
 use crate::engine::{
     assets::server::AssetServer,
     ecs::{
+        commands::Commands,
+        condition::RunCondition,
+        executor,
+        system_config::{ IntoSystemConfig, SystemSet },
+        system_param::{ SystemAccess, SystemParam },
         systems::{
             camera_update_system::camera_update_system,
             collision_system::collision_system,
             event_swap_system::event_swap_system,
+            light_sync_system::light_sync_system,
+            previous_transform_system::previous_transform_system,
             render_sync_system::render_sync_system,
-            velocity_system::velocity_system,
+            transform_propagate_system::transform_propagate_system,
+            velocity_system::{ velocity_reset_system, velocity_system },
         },
+        resources::fixed_time::FixedTime,
+        state::{ StateDriver, StateStages, States },
         world::World,
     },
 };
@@ -18,6 +37,8 @@ pub struct SystemContext<'a> {
     pub device: Option<&'a wgpu::Device>,
     pub queue: Option<&'a wgpu::Queue>,
     pub asset_server: Option<&'a mut AssetServer>,
+    // Deferred spawns/despawns etc, applied by the schedule between stages
+    pub commands: Commands,
 }
 
 impl<'a> SystemContext<'a> {
@@ -25,80 +46,613 @@ impl<'a> SystemContext<'a> {
         delta_time: f32,
         device: &'a wgpu::Device,
         queue: &'a wgpu::Queue,
-        asset_server: &'a mut AssetServer
+        asset_server: &'a mut AssetServer,
+        commands: Commands
     ) -> Self {
         Self {
             delta_time,
             device: Some(device),
             queue: Some(queue),
             asset_server: Some(asset_server),
+            commands,
         }
     }
 }
 
-pub type System = fn(&mut World, &mut SystemContext);
+/// Anything the schedule can run. Usually made with `IntoSystem` from a function or closure:
+/// - `fn(&mut World, &mut SystemContext)` - exclusive, may touch anything in the world
+/// - `fn(Res<A>, Query<(&mut B, &C)>, ...)` - fetches its params first, see `system_param.rs`
+pub trait System: Send + 'static {
+    /// The function's path, used in error messages
+    fn name(&self) -> &'static str;
+    fn access(&self) -> &SystemAccess;
+    /// Errors if a param couldn't be fetched - the system body didn't run.
+    fn run(&mut self, world: &mut World, system_context: &mut SystemContext) -> anyhow::Result<()>;
+    /// `run` for the parallel executor, which never hands out `&mut World` - other systems with
+    /// non-conflicting access may be running on other threads.
+    /// # Safety
+    /// `world` must be valid, and no running system may conflict with `self.access()`.
+    unsafe fn run_shared(
+        &mut self,
+        world: *mut World,
+        system_context: &mut SystemContext
+    ) -> anyhow::Result<()> {
+        self.run(&mut *world, system_context)
+    }
+}
+
+pub type BoxedSystem = Box<dyn System>;
+
+/// Conversion into a `System`. `Marker` only exists to keep the blanket impls for different
+/// function shapes from overlapping - it's always inferred.
+pub trait IntoSystem<Marker> {
+    type System: System;
+    fn into_system(self) -> Self::System;
+}
+
+impl<S: System> IntoSystem<()> for S {
+    type System = S;
+    fn into_system(self) -> S {
+        self
+    }
+}
+
+pub struct ExclusiveSystem<F> {
+    func: F,
+    name: &'static str,
+    access: SystemAccess,
+}
+
+#[doc(hidden)]
+pub struct ExclusiveMarker;
+
+impl<F> IntoSystem<ExclusiveMarker> for F
+    where F: FnMut(&mut World, &mut SystemContext) + Send + 'static
+{
+    type System = ExclusiveSystem<F>;
+    fn into_system(self) -> Self::System {
+        ExclusiveSystem {
+            func: self,
+            name: type_name::<F>(),
+            access: SystemAccess { exclusive: true, ..Default::default() },
+        }
+    }
+}
+
+impl<F> System for ExclusiveSystem<F> where F: FnMut(&mut World, &mut SystemContext) + Send + 'static {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+    fn access(&self) -> &SystemAccess {
+        &self.access
+    }
+    fn run(&mut self, world: &mut World, system_context: &mut SystemContext) -> anyhow::Result<()> {
+        (self.func)(world, system_context);
+        Ok(())
+    }
+}
+
+/// A function or closure whose arguments are all `SystemParam`s.
+pub struct FunctionSystem<F, P: SystemParam> {
+    func: F,
+    name: &'static str,
+    access: SystemAccess,
+    // Created on first run so Locals start fresh per system instance
+    state: Option<P::State>,
+    _phantom: PhantomData<fn() -> P>,
+}
+
+#[doc(hidden)]
+pub struct FunctionMarker;
+
+// `Func: FnMut($param)` ties the marker types down; `for<'w, 's> FnMut($param::Item<'w, 's>)`
+// is what actually gets called. Plain `fn` items and closures with annotated args satisfy both.
+macro_rules! impl_function_system {
+    ($($param:ident),*) => {
+        impl<Func, $($param: SystemParam + 'static),*> IntoSystem<(FunctionMarker, fn($($param,)*))>
+            for Func
+            where Func: FnMut($($param),*) + for<'w, 's> FnMut($($param::Item<'w, 's>),*) + Send + 'static
+        {
+            type System = FunctionSystem<Func, ($($param,)*)>;
+            fn into_system(self) -> Self::System {
+                let name = type_name::<Func>();
+                let mut access = SystemAccess::default();
+                <($($param,)*) as SystemParam>::access(&mut access);
+                if let Some(conflict) = access.self_conflict() {
+                    panic!("system {} has conflicting params: {}", name, conflict);
+                }
+                FunctionSystem { func: self, name, access, state: None, _phantom: PhantomData }
+            }
+        }
+
+        impl<Func, $($param: SystemParam + 'static),*> System for FunctionSystem<Func, ($($param,)*)>
+            where Func: FnMut($($param),*) + for<'w, 's> FnMut($($param::Item<'w, 's>),*) + Send + 'static
+        {
+            fn name(&self) -> &'static str {
+                self.name
+            }
+            fn access(&self) -> &SystemAccess {
+                &self.access
+            }
+            fn run(
+                &mut self,
+                world: &mut World,
+                system_context: &mut SystemContext
+            ) -> anyhow::Result<()> {
+                // Safety: we hold the only reference to world
+                unsafe { self.run_shared(world, system_context) }
+            }
+            #[allow(non_snake_case)]
+            unsafe fn run_shared(
+                &mut self,
+                world: *mut World,
+                system_context: &mut SystemContext
+            ) -> anyhow::Result<()> {
+                let state = self.state.get_or_insert_with(<($($param,)*) as SystemParam>::init_state);
+                // Safety: param aliasing was ruled out in into_system, the caller rules out
+                // conflicts with other systems, and world/context outlive the call below
+                let params = <($($param,)*) as SystemParam>::get_param(state, world, system_context);
+                let ($($param,)*) = params.map_err(|e| anyhow!("system {} could not run: {}", self.name, e))?;
+                (self.func)($($param),*);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl_function_system!();
+impl_function_system!(A);
+impl_function_system!(A, B);
+impl_function_system!(A, B, C);
+impl_function_system!(A, B, C, D);
+impl_function_system!(A, B, C, D, E);
+impl_function_system!(A, B, C, D, E, F);
+impl_function_system!(A, B, C, D, E, F, G);
+impl_function_system!(A, B, C, D, E, F, G, H);
+
+// A system with its config, plus the last error it reported so a missing resource is logged
+// once rather than every frame
+pub(crate) struct ScheduledSystem {
+    pub(crate) system: BoxedSystem,
+    labels: Vec<&'static str>,
+    before: Vec<&'static str>,
+    after: Vec<&'static str>,
+    conditions: Vec<RunCondition>,
+    // Conditions of the sets this system is in, filled in by SystemSchedule::build
+    set_conditions: Vec<RunCondition>,
+    last_error: Option<String>,
+}
+
+impl ScheduledSystem {
+    pub(crate) fn new<M>(system: impl IntoSystemConfig<M>) -> Self {
+        let config = system.into_config();
+        let mut labels = vec![short_system_name(config.system.name())];
+        labels.extend(config.labels);
+        Self {
+            system: config.system,
+            labels,
+            before: config.before,
+            after: config.after,
+            conditions: config.conditions,
+            set_conditions: Vec::new(),
+            last_error: None,
+        }
+    }
+
+    pub(crate) fn should_run(&self, world: &World) -> bool {
+        self.conditions
+            .iter()
+            .chain(&self.set_conditions)
+            .all(|condition| condition(world))
+    }
+
+    pub(crate) fn run(&mut self, world: &mut World, system_context: &mut SystemContext) {
+        let result = self.system.run(world, system_context);
+        self.record(result);
+    }
+
+    /// # Safety
+    /// See `System::run_shared`.
+    pub(crate) unsafe fn run_shared(&mut self, world: *mut World, system_context: &mut SystemContext) {
+        let result = self.system.run_shared(world, system_context);
+        self.record(result);
+    }
+
+    fn record(&mut self, result: anyhow::Result<()>) {
+        match result {
+            Ok(()) => {
+                self.last_error = None;
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if self.last_error.as_ref() != Some(&message) {
+                    log::error!("{}", message);
+                }
+                self.last_error = Some(message);
+            }
+        }
+    }
+}
+
+// One of the schedule's stages, with its systems grouped into batches that can run in parallel
+pub(crate) struct SystemStage {
+    name: String,
+    systems: Vec<ScheduledSystem>,
+    // Indices into `systems`, in a valid run order. Rebuilt by SystemSchedule::build
+    batches: Vec<Vec<usize>>,
+}
+
+impl SystemStage {
+    pub(crate) fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into(), systems: Vec::new(), batches: Vec::new() }
+    }
+
+    pub(crate) fn add(&mut self, system: ScheduledSystem) {
+        self.systems.push(system);
+    }
+
+    // Sorts the systems by their before/after constraints (and those of their sets), then
+    // batches them. Ties keep the order systems were added in.
+    fn build(&mut self, sets: &HashMap<&'static str, SystemSet>) -> anyhow::Result<()> {
+        let count = self.systems.len();
+        let with_label = |label: &str| -> Vec<usize> {
+            (0..count).filter(|&i| self.systems[i].labels.contains(&label)).collect()
+        };
+
+        // successors[a] holds every b that must run after a
+        let mut successors: Vec<Vec<usize>> = vec![Vec::new(); count];
+        for (i, system) in self.systems.iter().enumerate() {
+            let member_sets: Vec<&SystemSet> = system.labels
+                .iter()
+                .filter_map(|label| sets.get(label))
+                .collect();
+            let before = system.before.iter().chain(member_sets.iter().flat_map(|set| &set.before));
+            for label in before {
+                successors[i].extend(with_label(label));
+            }
+            let after = system.after.iter().chain(member_sets.iter().flat_map(|set| &set.after));
+            for label in after {
+                for j in with_label(label) {
+                    successors[j].push(i);
+                }
+            }
+        }
+        for (i, next) in successors.iter_mut().enumerate() {
+            next.retain(|&j| j != i);
+            next.sort_unstable();
+            next.dedup();
+        }
+
+        // Kahn's algorithm, always taking the earliest-added ready system
+        let mut in_degree = vec![0; count];
+        for &j in successors.iter().flatten() {
+            in_degree[j] += 1;
+        }
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..count)
+            .filter(|&i| in_degree[i] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(count);
+        while let Some(Reverse(i)) = ready.pop() {
+            order.push(i);
+            for &j in &successors[i] {
+                in_degree[j] -= 1;
+                if in_degree[j] == 0 {
+                    ready.push(Reverse(j));
+                }
+            }
+        }
+        if order.len() < count {
+            return Err(anyhow!(
+                "system ordering cycle in the {} stage: {}",
+                self.name,
+                self.describe_cycle(&successors, &in_degree)
+            ));
+        }
+
+        for system in &mut self.systems {
+            system.set_conditions = system.labels
+                .iter()
+                .filter_map(|label| sets.get(label))
+                .flat_map(|set| set.conditions.iter().cloned())
+                .collect();
+        }
+        self.batches = executor::compute_batches(&order, |later, earlier| {
+            successors[earlier].contains(&later) ||
+                self.systems[later].system.access().conflicts_with(self.systems[earlier].system.access())
+        });
+        Ok(())
+    }
+
+    // Every system Kahn's algorithm couldn't place has an unplaced predecessor, so walking
+    // predecessors from any of them must loop
+    fn describe_cycle(&self, successors: &[Vec<usize>], in_degree: &[usize]) -> String {
+        let predecessor = |i: usize| {
+            (0..successors.len()).find(|&j| in_degree[j] > 0 && successors[j].contains(&i)).unwrap()
+        };
+        let mut path = vec![(0..in_degree.len()).find(|&i| in_degree[i] > 0).unwrap()];
+        loop {
+            let previous = predecessor(*path.last().unwrap());
+            if let Some(start) = path.iter().position(|&i| i == previous) {
+                path.drain(..start);
+                path.reverse();
+                path.push(path[0]);
+                break;
+            }
+            path.push(previous);
+        }
+        path.iter()
+            .map(|&i| short_system_name(self.systems[i].system.name()))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    // Runs the stage then applies its commands
+    pub(crate) fn run(&mut self, world: &mut World, system_context: &mut SystemContext) {
+        executor::run_stage(&mut self.systems, &self.batches, world, system_context);
+        system_context.commands.apply(world);
+    }
+}
 
 // Systems execute in order:
 // 1. On Load ONLY - startup_systems (loading models/scene etc),
 // 2. game_systems - systems that handle game specific logic e.g. `[input, ai, pathfinding, movement]`
-// 3. engine_systems - systems that deal directly with the engine e.g. `[velocity, camera_update, render_sync]`
+// 3. fixed_systems - simulation at a fixed tick rate (see FixedTime), zero or more times per frame
+//    with `DeltaTime` set to the timestep e.g. `[velocity, collision]`
+// 4. engine_systems - systems that deal directly with the engine e.g. `[camera_update, light_sync, render_sync]`
+// `SystemContext::commands` is applied after each of these stages, so anything a game system
+// spawns through it is in the world before render_sync runs.
+//
+// Queued state transitions (state.rs) are applied between the startup and game stages, running
+// their OnExit/OnEnter systems.
+//
+// Within a stage, systems run in the order they were added unless `before`/`after` constraints
+// say otherwise (see system_config.rs). Systems whose access doesn't conflict may run at the same
+// time on native builds (see executor.rs), but never against a declared ordering.
 pub struct SystemSchedule {
-    startup_systems: Vec<System>,
-    game_systems: Vec<System>,
-    engine_systems: Vec<System>,
+    startup: SystemStage,
+    game: SystemStage,
+    fixed: SystemStage,
+    engine: SystemStage,
+    sets: HashMap<&'static str, SystemSet>,
+    // OnEnter/OnExit stages, one entry per state type (see state.rs)
+    states: Vec<Box<dyn StateDriver>>,
+    // Set whenever a system or set is added, cleared by build()
+    dirty: bool,
     started: bool,
 }
 
 impl SystemSchedule {
     pub fn new() -> Self {
-        Self {
-            startup_systems: Vec::new(),
-            game_systems: Vec::new(),
-            engine_systems: vec![
-                velocity_system,
-                collision_system,
-                camera_update_system,
-                render_sync_system,
-                event_swap_system
-            ],
+        let mut schedule = Self {
+            startup: SystemStage::new("startup"),
+            game: SystemStage::new("game"),
+            fixed: SystemStage::new("fixed"),
+            engine: SystemStage::new("engine"),
+            sets: HashMap::new(),
+            states: Vec::new(),
+            dirty: true,
             started: false,
+        };
+        for system in [
+            ScheduledSystem::new(previous_transform_system),
+            ScheduledSystem::new(velocity_system),
+            ScheduledSystem::new(transform_propagate_system),
+            ScheduledSystem::new(collision_system),
+        ] {
+            schedule.fixed.add(system);
+        }
+        // Propagating again picks up whatever the game stage moved since the last fixed step
+        for system in [
+            ScheduledSystem::new(transform_propagate_system),
+            ScheduledSystem::new(velocity_reset_system),
+            ScheduledSystem::new(camera_update_system),
+            ScheduledSystem::new(light_sync_system),
+            ScheduledSystem::new(render_sync_system),
+            ScheduledSystem::new(event_swap_system),
+        ] {
+            schedule.engine.add(system);
         }
+        schedule
     }
 
-    pub fn add_startup(&mut self, system: System) {
-        self.startup_systems.push(system);
+    pub fn add_startup<M>(&mut self, system: impl IntoSystemConfig<M>) {
+        self.startup.add(ScheduledSystem::new(system));
+        self.dirty = true;
     }
 
-    pub fn add_game_system(&mut self, system: System) {
-        self.game_systems.push(system);
+    pub fn add_game_system<M>(&mut self, system: impl IntoSystemConfig<M>) {
+        self.game.add(ScheduledSystem::new(system));
+        self.dirty = true;
     }
 
-    // run_all takes &mut self and &mut World. When you call each system with world,
-    // you're passing the same &mut World repeatedly through the loop. Rust will let you
-    // do this because each call completes before the next one starts - the borrow is
-    // released between iterations.
-    // We'll need to reconsider this if we want to run systems async
-    pub fn run_all(&mut self, world: &mut World, system_context: &mut SystemContext) {
-        if !self.started {
-            for system in &self.startup_systems {
-                system(world, system_context);
+    /// Adds a system to the fixed stage, after the built-in velocity and collision systems
+    /// unless ordered otherwise.
+    pub fn add_fixed_system<M>(&mut self, system: impl IntoSystemConfig<M>) {
+        self.fixed.add(ScheduledSystem::new(system));
+        self.dirty = true;
+    }
+
+    /// Adds the `State<S>` resource on the first frame, starting at `initial`, and lets
+    /// `add_on_enter`/`add_on_exit` be used for `S`.
+    pub fn init_state<S: States>(&mut self, initial: S) {
+        if self.state_stages::<S>().is_none() {
+            self.states.push(Box::new(StateStages::new(initial)));
+        }
+    }
+
+    /// # Panics
+    /// If `init_state::<S>` hasn't been called.
+    pub fn add_on_enter<S: States, M>(&mut self, state: S, system: impl IntoSystemConfig<M>) {
+        self.expect_state_stages::<S>().add_on_enter(state, system);
+        self.dirty = true;
+    }
+
+    /// # Panics
+    /// If `init_state::<S>` hasn't been called.
+    pub fn add_on_exit<S: States, M>(&mut self, state: S, system: impl IntoSystemConfig<M>) {
+        self.expect_state_stages::<S>().add_on_exit(state, system);
+        self.dirty = true;
+    }
+
+    fn state_stages<S: States>(&mut self) -> Option<&mut StateStages<S>> {
+        self.states.iter_mut().find_map(|driver| driver.as_any_mut().downcast_mut::<StateStages<S>>())
+    }
+
+    fn expect_state_stages<S: States>(&mut self) -> &mut StateStages<S> {
+        match self.state_stages::<S>() {
+            Some(stages) => stages,
+            None => panic!("call init_state::<{}>() before adding its systems", type_name::<S>()),
+        }
+    }
+
+    /// Adds ordering/run conditions to every system labelled `set.name`. Configuring the same
+    /// set again replaces the earlier config.
+    pub fn configure_set(&mut self, set: SystemSet) {
+        self.sets.insert(set.name, set);
+        self.dirty = true;
+    }
+
+    /// Orders and batches every stage. Called by `run_all` when systems changed, but worth calling
+    /// once after setup so a bad schedule fails at startup.
+    pub fn build(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.warn_unknown_labels();
+        let state_stages = self.states.iter_mut().flat_map(|driver| driver.stages_mut());
+        for stage in [&mut self.startup, &mut self.game, &mut self.fixed, &mut self.engine]
+            .into_iter()
+            .chain(state_stages) {
+            stage.build(&self.sets)?;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    // A before/after naming a label nothing has is almost always a typo
+    fn warn_unknown_labels(&self) {
+        let known: HashSet<&str> = self
+            .stages()
+            .flat_map(|stage| &stage.systems)
+            .flat_map(|system| system.labels.iter().copied())
+            .chain(self.sets.keys().copied())
+            .collect();
+        let systems = self
+            .stages()
+            .flat_map(|stage| &stage.systems)
+            .map(|s| (short_system_name(s.system.name()), s.before.iter().chain(&s.after)));
+        let sets = self.sets.values().map(|set| (set.name, set.before.iter().chain(&set.after)));
+        for (owner, labels) in systems.chain(sets) {
+            for label in labels.filter(|label| !known.contains(*label)) {
+                log::warn!("{} is ordered against unknown label \"{}\"", owner, label);
             }
-            self.started = true;
         }
+    }
+
+    /// The error each failing system last reported, as `(system name, message)`.
+    pub fn errors(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.stages()
+            .flat_map(|stage| &stage.systems)
+            .filter_map(|s| s.last_error.as_deref().map(|e| (s.system.name(), e)))
+    }
+
+    /// The batches each stage runs, as system names. Systems in the same batch may run in
+    /// parallel; batches run one after another. Only up to date after `build`.
+    pub fn batches(&self) -> Vec<(&str, Vec<Vec<&'static str>>)> {
+        self.stages()
+            .map(|stage| {
+                let batches = stage.batches
+                    .iter()
+                    .map(|batch| batch.iter().map(|&i| stage.systems[i].system.name()).collect())
+                    .collect();
+                (stage.name.as_str(), batches)
+            })
+            .collect()
+    }
+
+    /// `batches()` formatted one batch per line, for logging.
+    pub fn batch_report(&self) -> String {
+        let mut report = String::new();
+        for (stage, batches) in self.batches() {
+            report.push_str(&format!("{}:\n", stage));
+            for (i, batch) in batches.iter().enumerate() {
+                let names: Vec<&str> = batch.iter().map(|name| short_system_name(name)).collect();
+                report.push_str(&format!("  {}: {}\n", i, names.join(", ")));
+            }
+        }
+        report
+    }
+
+    fn stages(&self) -> impl Iterator<Item = &SystemStage> {
+        [&self.startup, &self.game, &self.fixed, &self.engine]
+            .into_iter()
+            .chain(self.states.iter().flat_map(|driver| driver.stages()))
+    }
+
+    // Runs the fixed stage once per whole timestep in the accumulator. Adds a default 60Hz
+    // FixedTime if the game didn't add its own.
+    fn run_fixed(&mut self, world: &mut World, system_context: &mut SystemContext) {
+        if world.get_resource::<FixedTime>().is_none() {
+            world.add_resource(FixedTime::default());
+        }
+        let fixed_time = world.get_resource_mut::<FixedTime>().unwrap();
+        let steps = fixed_time.accumulate(system_context.delta_time);
+        let timestep = fixed_time.timestep;
 
-        for game_system in &self.game_systems {
-            game_system(world, system_context);
+        let frame_delta_time = system_context.delta_time;
+        system_context.delta_time = timestep;
+        for _ in 0..steps {
+            self.fixed.run(world, system_context);
         }
+        system_context.delta_time = frame_delta_time;
+    }
 
-        for engine_system in &self.engine_systems {
-            engine_system(world, system_context);
+    /// # Panics
+    /// If the schedule needs rebuilding and has an ordering cycle - see `build`.
+    pub fn run_all(&mut self, world: &mut World, system_context: &mut SystemContext) {
+        if let Err(e) = self.build() {
+            panic!("{}", e);
+        }
+        if !self.started {
+            self.startup.run(world, system_context);
+            self.started = true;
         }
+        for driver in &mut self.states {
+            driver.apply_transitions(world, system_context);
+        }
+        self.game.run(world, system_context);
+        self.run_fixed(world, system_context);
+        self.engine.run(world, system_context);
+
+        world.advance_change_tick();
+        world.swap_removed_components();
     }
 }
 
+// `a::b::velocity_system` -> `velocity_system`, `a::b::setup::{{closure}}` -> `setup::{{closure}}`
+pub(crate) fn short_system_name(name: &str) -> &str {
+    let mut segments = name.rmatch_indices("::");
+    let cut = if name.ends_with("}}") { segments.nth(1) } else { segments.next() };
+    cut.map_or(name, |(i, _)| &name[i + 2..])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::engine::{
+        ecs::system_param::{
+            DeltaTime,
+            EventReader,
+            EventWriter,
+            Local,
+            Query,
+            RemovedComponents,
+            Res,
+            ResMut,
+        },
+        events::{ event_registry::EventRegistry, events::Events },
+    };
 
     struct Counter(u32);
 
@@ -114,12 +668,13 @@ mod tests {
         world.add_resource(system_context.delta_time);
     }
 
-    fn make_ctx() -> SystemContext<'static> {
+    fn make_ctx(world: &World) -> SystemContext<'static> {
         SystemContext {
             delta_time: 0.016,
             device: None,
             queue: None,
             asset_server: None,
+            commands: Commands::new(world),
         }
     }
 
@@ -129,7 +684,8 @@ mod tests {
         world.add_resource(Counter(0));
         let mut schedule = SystemSchedule::new();
         schedule.add_game_system(increment_system);
-        schedule.run_all(&mut world, &mut make_ctx());
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
         assert_eq!(world.get_resource::<Counter>().unwrap().0, 1);
     }
 
@@ -142,7 +698,8 @@ mod tests {
         let mut schedule = SystemSchedule::new();
         schedule.add_game_system(increment_system);
         schedule.add_game_system(double_system);
-        schedule.run_all(&mut world, &mut make_ctx());
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
         assert_eq!(world.get_resource::<Counter>().unwrap().0, 2);
     }
 
@@ -152,9 +709,12 @@ mod tests {
         world.add_resource(Counter(0));
         let mut schedule = SystemSchedule::new();
         schedule.add_game_system(increment_system);
-        schedule.run_all(&mut world, &mut make_ctx());
-        schedule.run_all(&mut world, &mut make_ctx());
-        schedule.run_all(&mut world, &mut make_ctx());
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
         assert_eq!(world.get_resource::<Counter>().unwrap().0, 3);
     }
 
@@ -162,7 +722,8 @@ mod tests {
     fn empty_schedule_does_not_panic() {
         let mut world = World::new();
         let mut schedule = SystemSchedule::new();
-        schedule.run_all(&mut world, &mut make_ctx());
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
     }
 
     #[test]
@@ -175,9 +736,239 @@ mod tests {
             device: None,
             queue: None,
             asset_server: None,
+            commands: Commands::new(&world),
         };
         schedule.run_all(&mut world, &mut ctx);
         let stored = world.get_resource::<f32>().unwrap();
         assert!((stored - 1.0 / 60.0).abs() < f32::EPSILON);
     }
+
+    // --- function systems ---
+
+    fn add_one(mut counter: ResMut<Counter>) {
+        counter.0 += 1;
+    }
+
+    fn copy_counter_to_f32(counter: Res<Counter>, commands: &mut Commands) {
+        commands.add_resource(counter.0 as f32);
+    }
+
+    fn run_once<M>(world: &mut World, system: impl IntoSystem<M>) -> anyhow::Result<()> {
+        let mut system = system.into_system();
+        let mut ctx = make_ctx(world);
+        let result = system.run(world, &mut ctx);
+        ctx.commands.apply(world);
+        result
+    }
+
+    #[test]
+    fn res_params_are_fetched_for_function_systems() {
+        let mut world = World::new();
+        world.add_resource(Counter(4));
+        run_once(&mut world, add_one).unwrap();
+        run_once(&mut world, copy_counter_to_f32).unwrap();
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 5);
+        assert_eq!(*world.get_resource::<f32>().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn missing_resource_errors_with_system_name_instead_of_running() {
+        let mut world = World::new();
+        let error = run_once(&mut world, add_one).unwrap_err().to_string();
+        assert!(error.contains("add_one"), "{}", error);
+        assert!(error.contains("Counter"), "{}", error);
+    }
+
+    #[test]
+    fn schedule_keeps_running_other_systems_when_one_fails() {
+        let mut world = World::new();
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(add_one);
+        schedule.add_game_system(capture_dt_system);
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        assert!(world.get_resource::<f32>().is_some());
+        let errors: Vec<_> = schedule.errors().collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].0.ends_with("add_one"));
+    }
+
+    #[test]
+    fn optional_res_is_none_when_missing() {
+        let mut world = World::new();
+        run_once(&mut world, |counter: Option<Res<Counter>>, commands: &mut Commands| {
+            commands.add_resource(counter.is_none());
+        }).unwrap();
+        assert!(*world.get_resource::<bool>().unwrap());
+    }
+
+    #[test]
+    fn local_state_persists_between_runs() {
+        let mut world = World::new();
+        world.add_resource(Counter(0));
+        let mut system = (|mut runs: Local<u32>, mut counter: ResMut<Counter>| {
+            *runs += 1;
+            counter.0 = *runs;
+        }).into_system();
+        for _ in 0..3 {
+            let mut ctx = make_ctx(&world);
+            system.run(&mut world, &mut ctx).unwrap();
+        }
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 3);
+    }
+
+    #[test]
+    fn query_param_iterates_and_mutates_components() {
+        struct Speed(u32);
+        let mut world = World::new();
+        world.spawn().with(Speed(1)).build();
+        world.spawn().with(Speed(2)).build();
+        run_once(&mut world, |mut query: Query<&mut Speed>| {
+            for speed in query.iter() {
+                speed.0 *= 10;
+            }
+        }).unwrap();
+        let mut speeds: Vec<u32> = world.iter_component::<Speed>().map(|(_, s)| s.0).collect();
+        speeds.sort();
+        assert_eq!(speeds, vec![10, 20]);
+    }
+
+    #[test]
+    fn event_writer_and_reader_params() {
+        struct Ping(u32);
+        let mut world = World::new();
+        world.add_resource(EventRegistry::new());
+        world.register_event::<Ping>();
+        run_once(&mut world, |mut pings: EventWriter<Ping>| pings.send(Ping(7))).unwrap();
+        world.get_resource_mut::<Events<Ping>>().unwrap().swap();
+        run_once(&mut world, |pings: EventReader<Ping>, commands: &mut Commands| {
+            let total: u32 = pings.read().map(|p| p.0).sum();
+            commands.add_resource(Counter(total));
+        }).unwrap();
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "conflicting params")]
+    fn aliasing_params_are_rejected() {
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(|_a: ResMut<Counter>, _b: Res<Counter>| {});
+    }
+
+    #[test]
+    fn after_constraint_overrides_insertion_order() {
+        let mut world = World::new();
+        world.add_resource(Counter(1));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(double_system.after("increment_system"));
+        schedule.add_game_system(increment_system);
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        // (1 + 1) * 2, not 1 * 2 + 1
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 4);
+    }
+
+    #[test]
+    fn before_constraint_uses_custom_labels() {
+        let mut world = World::new();
+        world.add_resource(Counter(1));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(double_system.label("scoring"));
+        schedule.add_game_system(increment_system.before("scoring"));
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 4);
+    }
+
+    #[test]
+    fn ordering_cycle_is_reported_with_system_names() {
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(increment_system.after("double_system"));
+        schedule.add_game_system(double_system.after("increment_system"));
+        let error = schedule.build().unwrap_err().to_string();
+        assert!(error.contains("cycle in the game stage"), "{}", error);
+        assert!(
+            error.ends_with("increment_system -> double_system -> increment_system") ||
+                error.ends_with("double_system -> increment_system -> double_system"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn run_condition_skips_system() {
+        use crate::engine::ecs::condition::resource_exists;
+        struct Enabled;
+
+        let mut world = World::new();
+        world.add_resource(Counter(0));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(increment_system.run_if(resource_exists::<Enabled>()));
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 0);
+
+        world.add_resource(Enabled);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 1);
+    }
+
+    #[test]
+    fn set_ordering_and_conditions_apply_to_members() {
+        use crate::engine::ecs::condition::resource_exists;
+        struct Enabled;
+
+        let mut world = World::new();
+        world.add_resource(Counter(1));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(double_system.label("late"));
+        schedule.add_game_system(increment_system);
+        schedule.configure_set(SystemSet::new("late").after("increment_system"));
+        let mut ctx = make_ctx(&world);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 4);
+
+        schedule.configure_set(SystemSet::new("late").run_if(resource_exists::<Enabled>()));
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Counter>().unwrap().0, 5);
+    }
+
+    #[test]
+    fn fixed_systems_run_once_per_timestep_with_fixed_delta() {
+        struct Steps(Vec<f32>);
+
+        let mut world = World::new();
+        world.add_resource(FixedTime::from_hz(10.0));
+        world.add_resource(Steps(Vec::new()));
+        let mut schedule = SystemSchedule::new();
+        schedule.add_fixed_system(|mut steps: ResMut<Steps>, dt: DeltaTime| steps.0.push(dt.0));
+        let mut ctx = make_ctx(&world);
+        ctx.delta_time = 0.25;
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Steps>().unwrap().0, vec![0.1, 0.1]);
+        assert_eq!(ctx.delta_time, 0.25);
+
+        ctx.delta_time = 0.04;
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Steps>().unwrap().0.len(), 2);
+    }
+
+    #[test]
+    fn removed_components_param_reads_last_frames_removals() {
+        struct Seen(usize);
+
+        let mut world = World::new();
+        world.add_resource(Seen(0));
+        let e = world.spawn().with(Counter(1)).build();
+        let mut schedule = SystemSchedule::new();
+        schedule.add_game_system(|removed: RemovedComponents<Counter>, mut seen: ResMut<Seen>| {
+            seen.0 += removed.read().count();
+        });
+        let mut ctx = make_ctx(&world);
+        ctx.commands.despawn(e);
+        schedule.run_all(&mut world, &mut ctx);
+        schedule.run_all(&mut world, &mut ctx);
+        schedule.run_all(&mut world, &mut ctx);
+        assert_eq!(world.get_resource::<Seen>().unwrap().0, 1);
+    }
 }
@@ -0,0 +1,118 @@
+// Labels, ordering and run conditions for systems
+//
+//     schedule.add_game_system(laser_system);
+//     schedule.add_game_system(laser_log_system.after("laser_system"));
+//     schedule.add_game_system(player_system.label("gameplay").run_if(in_state(GameState::Playing)));
+//     schedule.configure_set(SystemSet::new("gameplay").after("input"));
+//
+// A label names a set of systems. Every system is automatically labelled with its own function
+// name (`laser_system`), so `.after("laser_system")` works without an explicit `.label()`.
+// `before`/`after` take any label and order against every system carrying it, within the same
+// stage. Systems with no constraint between them keep the order they were added in.
+
+use crate::engine::ecs::{
+    condition::RunCondition,
+    system::{ BoxedSystem, IntoSystem },
+    world::World,
+};
+
+pub struct SystemConfig {
+    pub(crate) system: BoxedSystem,
+    pub(crate) labels: Vec<&'static str>,
+    pub(crate) before: Vec<&'static str>,
+    pub(crate) after: Vec<&'static str>,
+    pub(crate) conditions: Vec<RunCondition>,
+}
+
+impl SystemConfig {
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Runs before every system labelled `label`.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    /// Runs after every system labelled `label`.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+
+    /// Skips the system on frames where `condition` is false. Multiple conditions must all pass.
+    pub fn run_if(mut self, condition: impl Fn(&World) -> bool + Send + Sync + 'static) -> Self {
+        self.conditions.push(std::sync::Arc::new(condition));
+        self
+    }
+}
+
+/// Anything that can be added to a schedule: a system, or a system with config attached.
+pub trait IntoSystemConfig<Marker>: Sized {
+    fn into_config(self) -> SystemConfig;
+
+    fn label(self, label: &'static str) -> SystemConfig {
+        self.into_config().label(label)
+    }
+    fn before(self, label: &'static str) -> SystemConfig {
+        self.into_config().before(label)
+    }
+    fn after(self, label: &'static str) -> SystemConfig {
+        self.into_config().after(label)
+    }
+    fn run_if(self, condition: impl Fn(&World) -> bool + Send + Sync + 'static) -> SystemConfig {
+        self.into_config().run_if(condition)
+    }
+}
+
+impl<M, S: IntoSystem<M>> IntoSystemConfig<M> for S {
+    fn into_config(self) -> SystemConfig {
+        SystemConfig {
+            system: Box::new(self.into_system()),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            conditions: Vec::new(),
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct ConfiguredMarker;
+
+impl IntoSystemConfig<ConfiguredMarker> for SystemConfig {
+    fn into_config(self) -> SystemConfig {
+        self
+    }
+}
+
+/// Ordering and run conditions shared by every system with the label `name`.
+pub struct SystemSet {
+    pub(crate) name: &'static str,
+    pub(crate) before: Vec<&'static str>,
+    pub(crate) after: Vec<&'static str>,
+    pub(crate) conditions: Vec<RunCondition>,
+}
+
+impl SystemSet {
+    pub fn new(name: &'static str) -> Self {
+        Self { name, before: Vec::new(), after: Vec::new(), conditions: Vec::new() }
+    }
+
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
+
+    pub fn run_if(mut self, condition: impl Fn(&World) -> bool + Send + Sync + 'static) -> Self {
+        self.conditions.push(std::sync::Arc::new(condition));
+        self
+    }
+}
@@ -0,0 +1,470 @@
+// System parameters
+//
+// A function system declares what it needs as arguments instead of pulling it out of the world:
+//
+//     fn laser_log_system(lasers: EventReader<LaserFiredEvent>) { ... }
+//     fn velocity_system(mut query: Query<(&mut Transform, &mut Velocity)>, dt: DeltaTime) { ... }
+//
+// `SystemSchedule` fetches each argument before calling the system. If a `Res`/`ResMut`/event
+// param can't be fetched (the resource was never added) the system doesn't run and the schedule
+// logs an error naming the system and the missing type. Wrap the param in `Option` to make it
+// optional: `Option<Res<ShowColliderDebug>>`.
+//
+// Every param also records what it borrows in a `SystemAccess`. A system whose params would
+// alias each other (`ResMut<A>` + `Res<A>`, `Query<&mut T>` + `Query<&T>`) is rejected when it is
+// added to the schedule. The executor uses the same access sets to run systems that don't
+// conflict on different threads, which is why shared params need `T: Sync` and mutable ones
+// `T: Send`.
+
+use std::{ any::{ type_name, TypeId }, marker::PhantomData, ops::{ Deref, DerefMut } };
+
+use anyhow::anyhow;
+
+use crate::engine::{
+    ecs::{
+        commands::Commands,
+        entity::Entity,
+        query::{ ComponentAccess, Fetch, QueryData, QueryIter },
+        system::SystemContext,
+        world::World,
+    },
+    events::events::Events,
+};
+
+/// A single resource borrow made by a system param.
+#[derive(Clone, Debug)]
+pub struct ResourceAccess {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    pub mutable: bool,
+}
+
+impl ResourceAccess {
+    fn of<T: 'static>(mutable: bool) -> Self {
+        Self { type_id: TypeId::of::<T>(), type_name: type_name::<T>(), mutable }
+    }
+}
+
+/// Everything a system reads and writes. Function systems build this from their params;
+/// systems taking `&mut World` are `exclusive` and may touch anything.
+#[derive(Clone, Debug, Default)]
+pub struct SystemAccess {
+    pub components: Vec<ComponentAccess>,
+    pub resources: Vec<ResourceAccess>,
+    pub exclusive: bool,
+}
+
+impl SystemAccess {
+    /// Whether two systems can't safely run at the same time.
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        fn overlap(
+            a: impl Iterator<Item = (TypeId, bool)>,
+            b: impl Iterator<Item = (TypeId, bool)> + Clone
+        ) -> bool {
+            a.into_iter().any(|(type_id, mutable)| {
+                b.clone().any(|(other_id, other_mutable)| {
+                    type_id == other_id && (mutable || other_mutable)
+                })
+            })
+        }
+        self.exclusive ||
+            other.exclusive ||
+            overlap(
+                self.components.iter().map(|a| (a.type_id, a.mutable)),
+                other.components.iter().map(|a| (a.type_id, a.mutable))
+            ) ||
+            overlap(
+                self.resources.iter().map(|a| (a.type_id, a.mutable)),
+                other.resources.iter().map(|a| (a.type_id, a.mutable))
+            )
+    }
+
+    /// Describes the first pair of borrows in this access set that alias each other, if any.
+    pub fn self_conflict(&self) -> Option<String> {
+        // Change filters only matter across threads - a system's own params run one at a time
+        let components: Vec<&ComponentAccess> = self.components.iter().filter(|a| !a.filter).collect();
+        for (i, a) in components.iter().enumerate() {
+            for b in &components[i + 1..] {
+                if a.type_id == b.type_id && (a.mutable || b.mutable) {
+                    return Some(format!("component {} is borrowed more than once with at least one mutable borrow", a.type_name));
+                }
+            }
+        }
+        for (i, a) in self.resources.iter().enumerate() {
+            for b in &self.resources[i + 1..] {
+                if a.type_id == b.type_id && (a.mutable || b.mutable) {
+                    return Some(format!("resource {} is borrowed more than once with at least one mutable borrow", a.type_name));
+                }
+            }
+        }
+        None
+    }
+}
+
+pub trait SystemParam: Sized {
+    /// Per-system storage that lives as long as the system, e.g. the value behind a `Local`.
+    type State: Send + 'static;
+    /// The param handed to the system for one run.
+    type Item<'w, 's>;
+
+    fn init_state() -> Self::State;
+    fn access(_access: &mut SystemAccess) {}
+    /// # Safety
+    /// `world` and `ctx` must be valid for `'w`, and the params of one system must not alias -
+    /// `FunctionSystem` checks that with `access` before the first run.
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        world: *mut World,
+        ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>>;
+}
+
+fn missing_resource<T>() -> anyhow::Error {
+    anyhow!("missing resource {}", type_name::<T>())
+}
+
+fn missing_event<T>() -> anyhow::Error {
+    anyhow!(
+        "missing resource {} - register it with World::register_event::<{}>()",
+        type_name::<Events<T>>(),
+        type_name::<T>()
+    )
+}
+
+// --- Resources ---
+
+/// Shared access to a resource. The system doesn't run if the resource is missing.
+pub struct Res<'w, T: 'static> {
+    value: &'w T,
+}
+
+impl<T: 'static> Deref for Res<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: Sync + 'static> SystemParam for Res<'_, T> {
+    type State = ();
+    type Item<'w, 's> = Res<'w, T>;
+
+    fn init_state() -> Self::State {}
+    fn access(access: &mut SystemAccess) {
+        access.resources.push(ResourceAccess::of::<T>(false));
+    }
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        let value = (*world).get_resource::<T>().ok_or_else(missing_resource::<T>)?;
+        Ok(Res { value })
+    }
+}
+
+/// Mutable access to a resource. The system doesn't run if the resource is missing.
+pub struct ResMut<'w, T: 'static> {
+    value: &'w mut T,
+}
+
+impl<T: 'static> Deref for ResMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: 'static> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T: Send + 'static> SystemParam for ResMut<'_, T> {
+    type State = ();
+    type Item<'w, 's> = ResMut<'w, T>;
+
+    fn init_state() -> Self::State {}
+    fn access(access: &mut SystemAccess) {
+        access.resources.push(ResourceAccess::of::<T>(true));
+    }
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        let value = (*world).get_resource_mut_unchecked::<T>().ok_or_else(missing_resource::<T>)?;
+        Ok(ResMut { value })
+    }
+}
+
+// `Option<P>` is None instead of an error when P can't be fetched
+impl<P: SystemParam> SystemParam for Option<P> {
+    type State = P::State;
+    type Item<'w, 's> = Option<P::Item<'w, 's>>;
+
+    fn init_state() -> Self::State {
+        P::init_state()
+    }
+    fn access(access: &mut SystemAccess) {
+        P::access(access);
+    }
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        world: *mut World,
+        ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        Ok(P::get_param(state, world, ctx).ok())
+    }
+}
+
+// --- Events ---
+
+/// Reads `Events<T>` - see `Events::read` for which frame's events that yields.
+pub struct EventReader<'w, T: 'static> {
+    events: &'w Events<T>,
+}
+
+impl<T: 'static> EventReader<'_, T> {
+    pub fn read(&self) -> impl Iterator<Item = &T> {
+        self.events.read()
+    }
+}
+
+impl<T: Sync + 'static> SystemParam for EventReader<'_, T> {
+    type State = ();
+    type Item<'w, 's> = EventReader<'w, T>;
+
+    fn init_state() -> Self::State {}
+    fn access(access: &mut SystemAccess) {
+        access.resources.push(ResourceAccess::of::<Events<T>>(false));
+    }
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        let events = (*world).get_resource::<Events<T>>().ok_or_else(missing_event::<T>)?;
+        Ok(EventReader { events })
+    }
+}
+
+pub struct EventWriter<'w, T: 'static> {
+    events: &'w mut Events<T>,
+}
+
+impl<T: 'static> EventWriter<'_, T> {
+    pub fn send(&mut self, event: T) {
+        self.events.send(event);
+    }
+}
+
+impl<T: Send + 'static> SystemParam for EventWriter<'_, T> {
+    type State = ();
+    type Item<'w, 's> = EventWriter<'w, T>;
+
+    fn init_state() -> Self::State {}
+    fn access(access: &mut SystemAccess) {
+        access.resources.push(ResourceAccess::of::<Events<T>>(true));
+    }
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        let events = (*world).get_resource_mut_unchecked::<Events<T>>().ok_or_else(missing_event::<T>)?;
+        Ok(EventWriter { events })
+    }
+}
+
+/// Entities that lost their T last frame, by `remove_component` or despawn. See hooks.rs.
+pub struct RemovedComponents<'w, T: 'static> {
+    removed: Option<&'w Events<Entity>>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: 'static> RemovedComponents<'_, T> {
+    pub fn read(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.removed.into_iter().flat_map(|removed| removed.read().copied())
+    }
+}
+
+impl<T: 'static> SystemParam for RemovedComponents<'_, T> {
+    type State = ();
+    type Item<'w, 's> = RemovedComponents<'w, T>;
+
+    fn init_state() -> Self::State {}
+    // Nothing to declare: the removal log only changes while an exclusive system or the
+    // schedule's command sync points hold the whole world
+    fn access(_access: &mut SystemAccess) {}
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        let removed = (*world).removed_log(TypeId::of::<T>());
+        Ok(RemovedComponents { removed, _phantom: PhantomData })
+    }
+}
+
+// --- Queries ---
+
+/// Iterates entities matching `Q`, like `World::query_iter`. Takes `&mut self` to iterate because
+/// `Q` may hand out `&mut` components.
+pub struct Query<'w, Q: QueryData> {
+    world: *mut World,
+    _phantom: PhantomData<(&'w mut World, Q)>,
+}
+
+impl<Q: QueryData> Query<'_, Q> {
+    // Never through `&mut World` - systems with other params may be using the same world on
+    // other threads. Safety: the system's params were checked not to alias when it was added,
+    // and the executor never runs it alongside a conflicting system
+    pub fn iter(&mut self) -> QueryIter<'_, Q::Fetch<'_>> {
+        unsafe { World::query_iter_unchecked::<Q::Fetch<'_>>(self.world) }
+    }
+
+    pub fn get(&mut self, entity: Entity) -> Option<<Q::Fetch<'_> as Fetch<'_>>::Item> {
+        if !unsafe { (*self.world).is_alive(entity) } {
+            return None;
+        }
+        unsafe { World::query_unchecked::<Q::Fetch<'_>>(self.world, entity.id) }
+    }
+
+    /// The first match, for queries expected to match one entity (e.g. `With<Player>`).
+    pub fn single(&mut self) -> Option<<Q::Fetch<'_> as Fetch<'_>>::Item> {
+        self.iter().next()
+    }
+}
+
+impl<Q: QueryData> SystemParam for Query<'_, Q> {
+    type State = ();
+    type Item<'w, 's> = Query<'w, Q>;
+
+    fn init_state() -> Self::State {}
+    fn access(access: &mut SystemAccess) {
+        <Q::Fetch<'static> as Fetch<'static>>::access(&mut access.components);
+    }
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        Ok(Query { world, _phantom: PhantomData })
+    }
+}
+
+// --- System-local and context params ---
+
+/// Value owned by the system, kept between runs. Starts as `T::default()`.
+pub struct Local<'s, T: Default + 'static>(&'s mut T);
+
+impl<T: Default + 'static> Deref for Local<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.0
+    }
+}
+
+impl<T: Default + 'static> DerefMut for Local<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0
+    }
+}
+
+impl<T: Default + Send + 'static> SystemParam for Local<'_, T> {
+    type State = T;
+    type Item<'w, 's> = Local<'s, T>;
+
+    fn init_state() -> Self::State {
+        T::default()
+    }
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        _world: *mut World,
+        _ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        Ok(Local(state))
+    }
+}
+
+// The schedule's command queue - same one as `SystemContext::commands`
+impl SystemParam for &mut Commands {
+    type State = ();
+    type Item<'w, 's> = &'w mut Commands;
+
+    fn init_state() -> Self::State {}
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        _world: *mut World,
+        ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        Ok(&mut (*ctx).commands)
+    }
+}
+
+/// Seconds since the last frame (`SystemContext::delta_time`).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DeltaTime(pub f32);
+
+impl Deref for DeltaTime {
+    type Target = f32;
+    fn deref(&self) -> &f32 {
+        &self.0
+    }
+}
+
+impl SystemParam for DeltaTime {
+    type State = ();
+    type Item<'w, 's> = DeltaTime;
+
+    fn init_state() -> Self::State {}
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        _world: *mut World,
+        ctx: *mut SystemContext<'_>
+    ) -> anyhow::Result<Self::Item<'w, 's>> {
+        Ok(DeltaTime((*ctx).delta_time))
+    }
+}
+
+// --- Tuples ---
+
+// The whole parameter list of a function system is fetched as one tuple
+macro_rules! impl_system_param_tuple {
+    ($($param:ident),*) => {
+        impl<$($param: SystemParam),*> SystemParam for ($($param,)*) {
+            type State = ($($param::State,)*);
+            type Item<'w, 's> = ($($param::Item<'w, 's>,)*);
+
+            #[allow(clippy::unused_unit)]
+            fn init_state() -> Self::State {
+                ($($param::init_state(),)*)
+            }
+            #[allow(unused_variables)]
+            fn access(access: &mut SystemAccess) {
+                $($param::access(access);)*
+            }
+            #[allow(non_snake_case, unused_variables)]
+            unsafe fn get_param<'w, 's>(
+                state: &'s mut Self::State,
+                world: *mut World,
+                ctx: *mut SystemContext<'_>
+            ) -> anyhow::Result<Self::Item<'w, 's>> {
+                let ($($param,)*) = state;
+                Ok(($($param::get_param($param, world, ctx)?,)*))
+            }
+        }
+    }
+}
+
+impl_system_param_tuple!();
+impl_system_param_tuple!(A);
+impl_system_param_tuple!(A, B);
+impl_system_param_tuple!(A, B, C);
+impl_system_param_tuple!(A, B, C, D);
+impl_system_param_tuple!(A, B, C, D, E);
+impl_system_param_tuple!(A, B, C, D, E, F);
+impl_system_param_tuple!(A, B, C, D, E, F, G);
+impl_system_param_tuple!(A, B, C, D, E, F, G, H);
@@ -1,6 +1,10 @@
 use crate::engine::{
     ecs::{
-        components::{ camera::camera::Camera, transform::Transform },
+        components::{
+            camera::camera::Camera,
+            global_transform::GlobalTransform,
+            transform::Transform,
+        },
         resources::camera::ActiveCamera,
         system::SystemContext,
         world::World,
@@ -16,11 +20,16 @@ pub fn camera_update_system(world: &mut World, system_context: &mut SystemContex
     let active_camera_entity = active_camera.0;
 
     // We need to keep the cameras view projection and world position on the GPU in sync with
-    // its entity's Transform position
-    let Some(transform) = world.get_component::<Transform>(active_camera_entity) else {
-        return;
+    // its entity's world position - GlobalTransform if it's parented (e.g. a rig on the player)
+    let position = match world.get_component::<GlobalTransform>(active_camera_entity) {
+        Some(global) => global.position(),
+        None => {
+            let Some(transform) = world.get_component::<Transform>(active_camera_entity) else {
+                return;
+            };
+            transform.position
+        }
     };
-    let position = transform.position;
 
     let Some(camera) = world.get_component_mut::<Camera>(active_camera_entity) else {
         return;
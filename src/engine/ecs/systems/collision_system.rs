@@ -2,7 +2,11 @@ use cgmath::{ Quaternion, Vector3 };
 
 use crate::engine::{
     ecs::{
-        components::{ collider::{ Collider, ColliderShape }, transform::Transform },
+        components::{
+            collider::{ Collider, ColliderShape },
+            global_transform::GlobalTransform,
+            transform::Transform,
+        },
         entity::Entity,
         events::collision_event::CollisionEvent,
         system::SystemContext,
@@ -39,7 +43,12 @@ pub fn collision_system(world: &mut World, _: &mut SystemContext) {
 fn collect_colliders(world: &mut World) -> Vec<(Entity, Vector3<f32>, Collider)> {
     let mut out = Vec::new();
     for (entity_id, collider) in world.iter_component::<Collider>() {
-        if let Some(transform) = world.get_component_by_id::<Transform>(entity_id) {
+        // World space if the entity has been through transform propagation, local otherwise
+        let transform = world
+            .get_component_by_id::<GlobalTransform>(entity_id)
+            .map(|global| &global.0)
+            .or_else(|| world.get_component_by_id::<Transform>(entity_id));
+        if let Some(transform) = transform {
             if let Some(entity) = world.get_entity(entity_id) {
                 let (world_center, scaled) = resolve_collider(
                     collider,
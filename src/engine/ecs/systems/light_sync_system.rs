@@ -0,0 +1,335 @@
+use cgmath::{ InnerSpace, Vector3 };
+
+use crate::engine::{
+    ecs::{
+        components::{
+            camera::camera::Camera,
+            global_transform::GlobalTransform,
+            light::{ DirectionalLight, PointLight, SpotLight },
+            transform::Transform,
+        },
+        resources::{ camera::ActiveCamera, lighting::{ LightBuffer, Lighting, ShadowBuffers } },
+        system::SystemContext,
+        world::World,
+    },
+    light::{ GpuLight, LightsUniform, DIRECTIONAL_LIGHT, MAX_LIGHTS, POINT_LIGHT, SPOT_LIGHT },
+    shadow::{
+        cascade_view_projection,
+        spot_view_projection,
+        CameraFrustum,
+        ShadowUniform,
+        MAX_SHADOWED_SPOT_LIGHTS,
+        SHADOW_CASCADES,
+    },
+};
+
+/// Packs every light component into the shader's light array and uploads it, every frame like
+/// the camera - lights move with their entities. Also picks the shadow casting lights and
+/// uploads the view-projections their shadow maps are rendered and sampled with.
+pub fn light_sync_system(world: &mut World, system_context: &mut SystemContext) {
+    // Headless (e.g. tests) - there's no buffer to write
+    let (Some(queue), Some(LightBuffer(buffer))) = (system_context.queue, world.get_resource::<LightBuffer>()) else {
+        return;
+    };
+    let lights = collect_lights(world);
+    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[lights]));
+
+    let (shadows, active_maps) = collect_shadows(world, &lights);
+    let Some(shadow_buffers) = world.get_resource_mut::<ShadowBuffers>() else {
+        return;
+    };
+    queue.write_buffer(&shadow_buffers.uniform, 0, bytemuck::cast_slice(&[shadows]));
+    for &layer in &active_maps {
+        queue.write_buffer(&shadow_buffers.layers[layer], 0, bytemuck::cast_slice(&[shadows.view_projections[layer]]));
+    }
+    shadow_buffers.active_maps = active_maps;
+}
+
+// GlobalTransform if it's parented (e.g. a light on a laser), like render_sync
+fn world_transform(world: &World, entity_id: u32) -> Option<&Transform> {
+    world
+        .get_component_by_id::<GlobalTransform>(entity_id)
+        .map(|global| &global.0)
+        .or_else(|| world.get_component_by_id::<Transform>(entity_id))
+}
+
+// Lights point down their local -z
+fn direction(transform: &Transform) -> [f32; 3] {
+    (transform.rotation * Vector3::new(0.0, 0.0, -1.0)).normalize().into()
+}
+
+fn scaled(color: [f32; 3], intensity: f32) -> [f32; 3] {
+    [color[0] * intensity, color[1] * intensity, color[2] * intensity]
+}
+
+fn camera_position(world: &World) -> Vector3<f32> {
+    world
+        .get_resource::<ActiveCamera>()
+        .and_then(|camera| world_transform(world, camera.0.id))
+        .map_or(Vector3::new(0.0, 0.0, 0.0), |transform| transform.position)
+}
+
+// The cascades are fitted to this, so without an active camera there are none
+fn camera_frustum(world: &World) -> Option<CameraFrustum> {
+    let entity = world.get_resource::<ActiveCamera>()?.0;
+    let camera = world.get_component::<Camera>(entity)?;
+    Some(CameraFrustum {
+        position: world_transform(world, entity.id)?.position,
+        forward: camera.forward(),
+        fov_y: camera.projection.fov_y(),
+        aspect: camera.projection.aspect(),
+    })
+}
+
+// Directional lights come first and are always kept. Point and spot lights are kept nearest
+// the active camera first, since those are the ones you'd notice going missing.
+//
+// Shadow maps go to the first kept directional light with `shadows` (all the cascades), then
+// to the nearest kept spot lights with it until the spot layers run out.
+fn collect_lights(world: &World) -> LightsUniform {
+    let default_lighting = Lighting::default();
+    let lighting = world.get_resource::<Lighting>().unwrap_or(&default_lighting);
+    let camera_position = camera_position(world);
+
+    // (light, wants shadows)
+    let mut directional = Vec::new();
+    for (entity_id, light) in world.iter_component::<DirectionalLight>() {
+        let Some(transform) = world_transform(world, entity_id) else {
+            continue;
+        };
+        directional.push((
+            GpuLight {
+                kind: DIRECTIONAL_LIGHT,
+                direction: direction(transform),
+                color: scaled(light.color, light.intensity),
+                shadow_map: -1,
+                ..bytemuck::Zeroable::zeroed()
+            },
+            light.shadows,
+        ));
+    }
+
+    // (distance² to the camera, light, wants shadows)
+    let mut local: Vec<(f32, GpuLight, bool)> = Vec::new();
+    for (entity_id, light) in world.iter_component::<PointLight>() {
+        let Some(transform) = world_transform(world, entity_id) else {
+            continue;
+        };
+        local.push((
+            (transform.position - camera_position).magnitude2(),
+            GpuLight {
+                position: transform.position.into(),
+                kind: POINT_LIGHT,
+                range: light.range,
+                color: scaled(light.color, light.intensity),
+                shadow_map: -1,
+                ..bytemuck::Zeroable::zeroed()
+            },
+            false,
+        ));
+    }
+    for (entity_id, light) in world.iter_component::<SpotLight>() {
+        let Some(transform) = world_transform(world, entity_id) else {
+            continue;
+        };
+        local.push((
+            (transform.position - camera_position).magnitude2(),
+            GpuLight {
+                position: transform.position.into(),
+                kind: SPOT_LIGHT,
+                direction: direction(transform),
+                range: light.range,
+                color: scaled(light.color, light.intensity),
+                inner_cos: light.inner_angle.to_radians().cos(),
+                outer_cos: light.outer_angle.to_radians().cos(),
+                shadow_map: -1,
+                ..bytemuck::Zeroable::zeroed()
+            },
+            light.shadows,
+        ));
+    }
+    local.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut uniform = LightsUniform::empty();
+    uniform.ambient = lighting.ambient;
+    let lights = directional.into_iter().chain(local.into_iter().map(|(_, light, shadows)| (light, shadows)));
+    let mut cascades_free = camera_frustum(world).is_some();
+    let mut spot_maps = 0;
+    for (slot, (light, shadows)) in uniform.lights.iter_mut().zip(lights.take(lighting.max_lights.min(MAX_LIGHTS))) {
+        *slot = light;
+        if shadows && light.kind == DIRECTIONAL_LIGHT && cascades_free {
+            slot.shadow_map = 0;
+            cascades_free = false;
+        } else if shadows && light.kind == SPOT_LIGHT && spot_maps < MAX_SHADOWED_SPOT_LIGHTS {
+            slot.shadow_map = (SHADOW_CASCADES + spot_maps) as i32;
+            spot_maps += 1;
+        }
+        uniform.count += 1;
+    }
+    uniform
+}
+
+// The view-projection of every shadow map layer `collect_lights` handed out, and which
+// layers those are
+fn collect_shadows(world: &World, lights: &LightsUniform) -> (ShadowUniform, Vec<usize>) {
+    let default_lighting = Lighting::default();
+    let lighting = world.get_resource::<Lighting>().unwrap_or(&default_lighting);
+
+    let mut uniform = ShadowUniform::empty();
+    uniform.bias = lighting.shadow_bias;
+    let mut active_maps = Vec::new();
+    for light in lights.active() {
+        let Ok(first) = usize::try_from(light.shadow_map) else {
+            continue;
+        };
+        let direction = Vector3::from(light.direction);
+        if light.kind == DIRECTIONAL_LIGHT {
+            let Some(frustum) = camera_frustum(world) else {
+                continue;
+            };
+            let mut near = 0.0;
+            for (cascade, &far) in lighting.shadow_cascades.iter().enumerate() {
+                uniform.view_projections[first + cascade] = cascade_view_projection(&frustum, near, far, direction).into();
+                active_maps.push(first + cascade);
+                near = far;
+            }
+        } else {
+            let outer_angle = light.outer_cos.clamp(-1.0, 1.0).acos().to_degrees();
+            uniform.view_projections[first] = spot_view_projection(
+                Vector3::from(light.position),
+                direction,
+                outer_angle,
+                light.range
+            ).into();
+            active_maps.push(first);
+        }
+    }
+    (uniform, active_maps)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{ Deg, Quaternion, Rotation3 };
+
+    use super::*;
+
+    fn spawn_point_light(world: &mut World, x: f32) {
+        world
+            .spawn()
+            .with(Transform::new().with_position(x, 0.0, 0.0))
+            .with(PointLight::new([1.0, 0.5, 0.0], 2.0, 10.0))
+            .build();
+    }
+
+    #[test]
+    fn no_lights_leaves_the_scene_unlit() {
+        let world = World::new();
+        let uniform = collect_lights(&world);
+        assert_eq!(uniform.count, 0);
+        assert_eq!(uniform.ambient, Lighting::default().ambient);
+    }
+
+    #[test]
+    fn lights_take_position_and_direction_from_their_transform() {
+        let mut world = World::new();
+        spawn_point_light(&mut world, 3.0);
+        // Turned to point down -x
+        world
+            .spawn()
+            .with(Transform::new().with_rotation(Quaternion::from_angle_y(Deg(90.0))))
+            .with(DirectionalLight::new([1.0, 1.0, 1.0], 0.5))
+            .build();
+
+        let uniform = collect_lights(&world);
+        let [sun, point] = uniform.active() else {
+            panic!("expected two lights");
+        };
+        assert_eq!(sun.kind, DIRECTIONAL_LIGHT);
+        assert!((sun.direction[0] + 1.0).abs() < 1e-5 && sun.direction[2].abs() < 1e-5);
+        assert_eq!(sun.color, [0.5, 0.5, 0.5]);
+        assert_eq!(point.kind, POINT_LIGHT);
+        assert_eq!(point.position, [3.0, 0.0, 0.0]);
+        // Intensity is folded into the colour
+        assert_eq!(point.color, [2.0, 1.0, 0.0]);
+        assert_eq!(point.range, 10.0);
+    }
+
+    #[test]
+    fn spot_cones_are_sent_as_cosines() {
+        let mut world = World::new();
+        world.spawn().with(Transform::new()).with(SpotLight::new([1.0; 3], 1.0, 5.0, 0.0, 60.0)).build();
+
+        let spot = collect_lights(&world).lights[0];
+        assert_eq!(spot.kind, SPOT_LIGHT);
+        assert_eq!(spot.direction, [0.0, 0.0, -1.0]);
+        assert!((spot.inner_cos - 1.0).abs() < 1e-6);
+        assert!((spot.outer_cos - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn furthest_lights_are_dropped_past_the_maximum() {
+        let mut world = World::new();
+        world.add_resource(Lighting { max_lights: 2, ..Lighting::default() });
+        for x in [30.0, 10.0, 20.0] {
+            spawn_point_light(&mut world, x);
+        }
+        world.spawn().with(Transform::new()).with(DirectionalLight::new([1.0; 3], 1.0)).build();
+
+        let uniform = collect_lights(&world);
+        let kinds = uniform.active().iter().map(|light| light.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![DIRECTIONAL_LIGHT, POINT_LIGHT]);
+        assert_eq!(uniform.lights[1].position, [10.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn the_maximum_is_capped_at_the_shader_array_size() {
+        let mut world = World::new();
+        world.add_resource(Lighting { max_lights: 1000, ..Lighting::default() });
+        for x in 0..MAX_LIGHTS + 4 {
+            spawn_point_light(&mut world, x as f32);
+        }
+        assert_eq!(collect_lights(&world).count as usize, MAX_LIGHTS);
+    }
+
+    #[test]
+    fn lights_without_a_transform_are_skipped() {
+        let mut world = World::new();
+        world.spawn().with(PointLight::new([1.0; 3], 1.0, 1.0)).build();
+        assert_eq!(collect_lights(&world).count, 0);
+    }
+
+    #[test]
+    fn nearest_shadowed_spot_lights_get_the_spot_layers() {
+        let mut world = World::new();
+        for z in 1..MAX_SHADOWED_SPOT_LIGHTS + 2 {
+            world
+                .spawn()
+                .with(Transform::new().with_position(0.0, 0.0, z as f32))
+                .with(SpotLight::new([1.0; 3], 1.0, 5.0, 10.0, 20.0).with_shadows())
+                .build();
+        }
+        world.spawn().with(Transform::new()).with(SpotLight::new([1.0; 3], 1.0, 5.0, 10.0, 20.0)).build();
+
+        let lights = collect_lights(&world);
+        let maps = lights.active().iter().map(|light| light.shadow_map).collect::<Vec<_>>();
+        // The unshadowed one sorts first (it's nearest), and the furthest shadowed one misses out
+        let spot_layers = (SHADOW_CASCADES..SHADOW_CASCADES + MAX_SHADOWED_SPOT_LIGHTS).map(|layer| layer as i32);
+        assert_eq!(maps, [-1].into_iter().chain(spot_layers).chain([-1]).collect::<Vec<_>>());
+
+        let (shadows, active_maps) = collect_shadows(&world, &lights);
+        assert_eq!(active_maps, (SHADOW_CASCADES..SHADOW_CASCADES + MAX_SHADOWED_SPOT_LIGHTS).collect::<Vec<_>>());
+        assert_eq!(shadows.bias, Lighting::default().shadow_bias);
+    }
+
+    #[test]
+    fn directional_shadows_need_a_camera_and_point_lights_never_cast() {
+        let mut world = World::new();
+        world.spawn().with(Transform::new()).with(DirectionalLight::new([1.0; 3], 1.0).with_shadows()).build();
+        spawn_point_light(&mut world, 1.0);
+
+        // No active camera to fit the cascades to
+        let lights = collect_lights(&world);
+        assert!(lights.active().iter().all(|light| light.shadow_map == -1));
+        assert!(collect_shadows(&world, &lights).1.is_empty());
+    }
+}
@@ -3,3 +3,6 @@ pub mod velocity_system;
 pub mod camera_update_system;
 pub mod event_swap_system;
 pub mod collision_system;
+pub mod transform_propagate_system;
+pub mod previous_transform_system;
+pub mod light_sync_system;
@@ -0,0 +1,45 @@
+use crate::engine::ecs::{
+    components::{
+        global_transform::GlobalTransform,
+        previous_transform::PreviousTransform,
+        transform::Transform,
+        velocity::Velocity,
+    },
+    system::SystemContext,
+    world::World,
+};
+
+/// Runs at the start of every fixed step and records where each interpolated entity is before
+/// the step moves it. Anything with a Velocity gets a `PreviousTransform` on its first step.
+pub fn previous_transform_system(world: &mut World, _system_context: &mut SystemContext) {
+    let missing: Vec<u32> = world
+        .iter_component::<Velocity>()
+        .map(|(entity_id, _)| entity_id)
+        .filter(|&entity_id| !world.has_component_by_id::<PreviousTransform>(entity_id))
+        .collect();
+    for entity_id in missing {
+        if let Some(entity) = world.get_entity(entity_id) {
+            if let Some(transform) = current_transform(world, entity_id) {
+                world.add_component(entity, PreviousTransform(transform));
+            }
+        }
+    }
+
+    let updates: Vec<(u32, Transform)> = world
+        .iter_component::<PreviousTransform>()
+        .filter_map(|(entity_id, _)| current_transform(world, entity_id).map(|t| (entity_id, t)))
+        .collect();
+    for (entity_id, transform) in updates {
+        if let Some(previous) = world.get_component_mut_by_id::<PreviousTransform>(entity_id) {
+            previous.0 = transform;
+        }
+    }
+}
+
+// World space if the entity has been through transform propagation, local otherwise
+pub(crate) fn current_transform(world: &World, entity_id: u32) -> Option<Transform> {
+    world
+        .get_component_by_id::<GlobalTransform>(entity_id)
+        .map(|global| global.0)
+        .or_else(|| world.get_component_by_id::<Transform>(entity_id).copied())
+}
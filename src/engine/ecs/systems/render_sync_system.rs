@@ -1,8 +1,16 @@
-use std::collections::HashMap;
+use std::{ collections::HashMap, ops::Range };
 
 use crate::engine::{
+    assets::handle::AssetId,
     ecs::{
-        components::{ renderable::Renderable, transform::Transform },
+        components::{
+            global_transform::GlobalTransform,
+            previous_transform::PreviousTransform,
+            renderable::Renderable,
+            shadows::{ CastsShadows, ReceivesShadows },
+            transform::Transform,
+        },
+        resources::fixed_time::FixedTime,
         system::SystemContext,
         world::World,
     },
@@ -10,49 +18,115 @@ use crate::engine::{
 };
 
 pub fn render_sync_system(world: &mut World, system_context: &mut SystemContext) {
-    let queue = system_context.queue.unwrap();
-    let asset_server = system_context.asset_server.as_mut().unwrap();
+    // Headless (e.g. tests) - there are no GPU buffers to update
+    let (Some(queue), Some(asset_server)) = (
+        system_context.queue,
+        system_context.asset_server.as_deref_mut(),
+    ) else {
+        return;
+    };
 
     let groups = collect_instance_groups(world);
 
-    for (model_id, instances) in &groups {
-        asset_server.get_model_mut(*model_id).update_instances(queue, instances);
+    for (model_id, group) in groups {
+        // Still loading (or failed) - the entities show up once it's loaded
+        if let Some(model) = asset_server.get_model_mut(model_id) {
+            model.update_instances(queue, &group.instances, group.receivers, group.casters);
+        }
+    }
+}
+
+// A model's instances, ordered receive-only, receive + cast, cast-only, then neither, so the
+// receivers and the casters are each one range - one draw call apiece.
+#[derive(Default)]
+struct InstanceGroup {
+    instances: Vec<InstanceRaw>,
+    receivers: Range<u32>,
+    casters: Range<u32>,
+}
+
+impl InstanceGroup {
+    fn from_buckets(buckets: [Vec<InstanceRaw>; 4]) -> Self {
+        let [receive_only, both, cast_only, neither] = buckets;
+        let receivers_end = (receive_only.len() + both.len()) as u32;
+        let casters = (receive_only.len() as u32)..receivers_end + (cast_only.len() as u32);
+        let instances = [receive_only, both, cast_only, neither].concat();
+        Self { instances, receivers: 0..receivers_end, casters }
     }
 }
 
 // Groups InstanceRaw data by model_id for all entities with both Transform and Renderable.
+// Uses the propagated GlobalTransform when there is one, so parented entities render in world space.
+// Entities with a PreviousTransform are drawn part way between their last two fixed steps, by
+// FixedTime::alpha.
 //
 // PERFORMANCE NOTES (acceptable at current scale, revisit when profiler says so):
 //
 // 1. HashMap allocation - a new HashMap and Vec<InstanceRaw> per model group is heap-allocated
-//    every frame. Fix: keep a persistent HashMap<usize, Vec<InstanceRaw>> as a resource,
+//    every frame. Fix: keep a persistent HashMap<AssetId, Vec<InstanceRaw>> as a resource,
 //    call clear() each frame to reuse the allocation rather than dropping and recreating it.
 //
 // 2. Double iteration - we iterate Renderable to collect entity IDs, then look up Transform
 //    for each one (two sparse set reads per entity). This is O(n) but with a constant factor.
 //    An archetype-based ECS stores entities with the same component set contiguously, making
-//    this a single pass. That's the main tradeoff of sparse sets vs archetypes.
+//    this a single pass. That's the main tradeoff of sparse sets vs archetypes. Renderable and
+//    Transform can opt into table storage (`World::register_component_with_storage`) if this
+//    ever shows up in a profile - see benches/ecs_iteration.rs.
 //
 // 3. write_buffer every frame - instance data is uploaded to the GPU unconditionally, even
 //    for static models that haven't moved. Fix: add dirty: Vec<bool> + any_dirty: bool to
 //    SparseSet<T>, set on get_mut(), check in render_sync before uploading. Static buildings
 //    would then pay zero upload cost after initial placement.
-fn collect_instance_groups(world: &World) -> HashMap<usize, Vec<InstanceRaw>> {
-    let mut groups: HashMap<usize, Vec<InstanceRaw>> = HashMap::new();
+fn collect_instance_groups(world: &World) -> HashMap<AssetId, InstanceGroup> {
+    // [receive only, both, cast only, neither], per model
+    let mut buckets: HashMap<AssetId, [Vec<InstanceRaw>; 4]> = HashMap::new();
+    let alpha = world.get_resource::<FixedTime>().map_or(1.0, |fixed| fixed.alpha());
 
     for (entity_id, renderable) in world.iter_component::<Renderable>() {
-        if let Some(transform) = world.get_component_by_id::<Transform>(entity_id) {
-            groups.entry(renderable.model_id).or_default().push(transform.to_raw());
-        }
+        let transform = world
+            .get_component_by_id::<GlobalTransform>(entity_id)
+            .map(|global| &global.0)
+            .or_else(|| world.get_component_by_id::<Transform>(entity_id));
+        let Some(transform) = transform else {
+            continue;
+        };
+        let raw = match world.get_component_by_id::<PreviousTransform>(entity_id) {
+            Some(previous) => previous.lerp(transform, alpha).to_raw(),
+            None => transform.to_raw(),
+        };
+        let receives = world.get_component_by_id::<ReceivesShadows>(entity_id).is_some();
+        let casts = world.get_component_by_id::<CastsShadows>(entity_id).is_some();
+        let bucket = match (receives, casts) {
+            (true, false) => 0,
+            (true, true) => 1,
+            (false, true) => 2,
+            (false, false) => 3,
+        };
+        buckets.entry(renderable.model.id()).or_default()[bucket].push(raw);
     }
 
-    groups
+    buckets
+        .into_iter()
+        .map(|(model_id, buckets)| (model_id, InstanceGroup::from_buckets(buckets)))
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::engine::ecs::components::transform::Transform;
+    use crate::engine::{
+        assets::handle::{ DropQueue, Handle },
+        ecs::components::transform::Transform,
+    };
+
+    fn id(index: usize) -> AssetId {
+        AssetId { index, generation: 0 }
+    }
+
+    // Grouping only looks at ids, so there's no model behind these
+    fn model(index: usize) -> Handle<crate::engine::model::model::Model> {
+        Handle::new(id(index), &DropQueue::default())
+    }
 
     fn world_with_components() -> World {
         let mut world = World::new();
@@ -71,7 +145,7 @@ mod tests {
     fn entity_without_transform_is_excluded() {
         let mut world = world_with_components();
         let e = world.spawn_entity_only();
-        world.add_component(e, Renderable::new(0));
+        world.add_component(e, Renderable::new(model(0)));
         // No Transform added — should not appear in groups
         assert!(collect_instance_groups(&world).is_empty());
     }
@@ -90,11 +164,11 @@ mod tests {
         let mut world = world_with_components();
         let e = world.spawn_entity_only();
         world.add_component(e, Transform::new().with_position(1.0, 2.0, 3.0));
-        world.add_component(e, Renderable::new(0));
+        world.add_component(e, Renderable::new(model(0)));
 
         let groups = collect_instance_groups(&world);
         assert_eq!(groups.len(), 1);
-        assert_eq!(groups[&0].len(), 1);
+        assert_eq!(groups[&id(0)].instances.len(), 1);
     }
 
     #[test]
@@ -103,10 +177,10 @@ mod tests {
         for _ in 0..3 {
             let e = world.spawn_entity_only();
             world.add_component(e, Transform::new());
-            world.add_component(e, Renderable::new(0));
+            world.add_component(e, Renderable::new(model(0)));
         }
         let groups = collect_instance_groups(&world);
-        assert_eq!(groups[&0].len(), 3);
+        assert_eq!(groups[&id(0)].instances.len(), 3);
     }
 
     #[test]
@@ -115,13 +189,24 @@ mod tests {
         for model_id in [0, 1, 2] {
             let e = world.spawn_entity_only();
             world.add_component(e, Transform::new());
-            world.add_component(e, Renderable::new(0));
+            world.add_component(e, Renderable::new(model(model_id)));
         }
         let groups = collect_instance_groups(&world);
         assert_eq!(groups.len(), 3);
-        assert_eq!(groups[&0].len(), 1);
-        assert_eq!(groups[&1].len(), 1);
-        assert_eq!(groups[&2].len(), 1);
+        assert_eq!(groups[&id(0)].instances.len(), 1);
+        assert_eq!(groups[&id(1)].instances.len(), 1);
+        assert_eq!(groups[&id(2)].instances.len(), 1);
+    }
+
+    #[test]
+    fn global_transform_is_preferred_over_local() {
+        let mut world = world_with_components();
+        let e = world.spawn_entity_only();
+        world.add_component(e, Transform::new().with_position(1.0, 0.0, 0.0));
+        world.add_component(e, GlobalTransform(Transform::new().with_position(9.0, 0.0, 0.0)));
+        world.add_component(e, Renderable::new(model(0)));
+        let groups = collect_instance_groups(&world);
+        assert_eq!(groups[&id(0)].instances[0].model[3][0], 9.0);
     }
 
     #[test]
@@ -129,8 +214,50 @@ mod tests {
         let mut world = world_with_components();
         let e = world.spawn_entity_only();
         world.add_component(e, Transform::new());
-        world.add_component(e, Renderable::new(0));
+        world.add_component(e, Renderable::new(model(0)));
         world.despawn(e);
         assert!(collect_instance_groups(&world).is_empty());
     }
+
+    #[test]
+    fn previous_transform_is_blended_by_fixed_time_alpha() {
+        let mut world = world_with_components();
+        let mut fixed = FixedTime::from_hz(10.0);
+        fixed.accumulate(0.025);
+        world.add_resource(fixed);
+        let e = world.spawn_entity_only();
+        world.add_component(e, Transform::new().with_position(8.0, 0.0, 0.0));
+        world.add_component(e, PreviousTransform(Transform::new()));
+        world.add_component(e, Renderable::new(model(0)));
+        let groups = collect_instance_groups(&world);
+        assert!((groups[&id(0)].instances[0].model[3][0] - 2.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn shadow_receivers_and_casters_are_contiguous() {
+        let mut world = world_with_components();
+        let spawn = |world: &mut World, x: f32, receives: bool, casts: bool| {
+            let e = world.spawn_entity_only();
+            world.add_component(e, Transform::new().with_position(x, 0.0, 0.0));
+            world.add_component(e, Renderable::new(model(0)));
+            if receives {
+                world.add_component(e, ReceivesShadows);
+            }
+            if casts {
+                world.add_component(e, CastsShadows);
+            }
+        };
+        spawn(&mut world, 0.0, false, false);
+        spawn(&mut world, 1.0, false, true);
+        spawn(&mut world, 2.0, true, true);
+        spawn(&mut world, 3.0, true, false);
+        spawn(&mut world, 4.0, true, false);
+
+        let groups = collect_instance_groups(&world);
+        let group = &groups[&id(0)];
+        let xs = group.instances.iter().map(|raw| raw.model[3][0]).collect::<Vec<_>>();
+        assert_eq!(xs, vec![3.0, 4.0, 2.0, 1.0, 0.0]);
+        assert_eq!(group.receivers, 0..3);
+        assert_eq!(group.casters, 2..4);
+    }
 }
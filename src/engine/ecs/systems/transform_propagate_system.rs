@@ -0,0 +1,99 @@
+use crate::engine::ecs::{
+    components::{
+        global_transform::GlobalTransform,
+        hierarchy::{ Children, Parent },
+        transform::Transform,
+    },
+    system::SystemContext,
+    world::World,
+};
+
+/// Walks the hierarchy from every root and writes each entity's world-space
+/// `GlobalTransform`. Runs after velocity_system so collision and rendering see this
+/// frame's movement. Children without a `Transform` are skipped along with their subtree.
+pub fn transform_propagate_system(world: &mut World, _system_context: &mut SystemContext) {
+    propagate_transforms(world);
+}
+
+fn propagate_transforms(world: &mut World) {
+    let mut stack: Vec<(u32, GlobalTransform)> = world
+        .iter_component::<Transform>()
+        .filter(|(entity_id, _)| !world.has_component_by_id::<Parent>(*entity_id))
+        .map(|(entity_id, transform)| (entity_id, GlobalTransform(*transform)))
+        .collect();
+
+    let mut resolved: Vec<(u32, GlobalTransform)> = Vec::with_capacity(stack.len());
+    while let Some((entity_id, global)) = stack.pop() {
+        if let Some(children) = world.get_component_by_id::<Children>(entity_id) {
+            for child in &children.0 {
+                if let Some(local) = world.get_component_by_id::<Transform>(child.id) {
+                    stack.push((child.id, global.mul_transform(local)));
+                }
+            }
+        }
+        resolved.push((entity_id, global));
+    }
+
+    for (entity_id, global) in resolved {
+        if let Some(existing) = world.get_component_mut_by_id::<GlobalTransform>(entity_id) {
+            *existing = global;
+        } else if let Some(entity) = world.get_entity(entity_id) {
+            world.add_component(entity, global);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector3;
+
+    fn global_position(world: &World, entity: crate::engine::ecs::entity::Entity) -> Vector3<f32> {
+        world.get_component::<GlobalTransform>(entity).unwrap().position()
+    }
+
+    #[test]
+    fn root_global_matches_local() {
+        let mut world = World::new();
+        let e = world.spawn().with(Transform::new().with_position(1.0, 2.0, 3.0)).build();
+        propagate_transforms(&mut world);
+        assert_eq!(global_position(&world, e), Vector3::new(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn grandchild_accumulates_offsets() {
+        let mut world = World::new();
+        let root = world.spawn().with(Transform::new().with_position(10.0, 0.0, 0.0)).build();
+        let child = world.spawn().with(Transform::new().with_position(0.0, 1.0, 0.0)).build();
+        let grandchild = world.spawn().with(Transform::new().with_position(0.0, 0.0, 1.0)).build();
+        world.set_parent(child, root).unwrap();
+        world.set_parent(grandchild, child).unwrap();
+
+        propagate_transforms(&mut world);
+        assert_eq!(global_position(&world, child), Vector3::new(10.0, 1.0, 0.0));
+        assert_eq!(global_position(&world, grandchild), Vector3::new(10.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn moving_parent_moves_child_next_propagation() {
+        let mut world = World::new();
+        let root = world.spawn().with(Transform::new()).build();
+        let child = world.spawn().with(Transform::new().with_position(1.0, 0.0, 0.0)).build();
+        world.set_parent(child, root).unwrap();
+        propagate_transforms(&mut world);
+
+        world.get_component_mut::<Transform>(root).unwrap().position.z = 5.0;
+        propagate_transforms(&mut world);
+        assert_eq!(global_position(&world, child), Vector3::new(1.0, 0.0, 5.0));
+    }
+
+    #[test]
+    fn child_without_transform_is_skipped() {
+        let mut world = World::new();
+        let root = world.spawn().with(Transform::new()).build();
+        let child = world.spawn().build();
+        world.set_parent(child, root).unwrap();
+        propagate_transforms(&mut world);
+        assert!(world.get_component::<GlobalTransform>(child).is_none());
+    }
+}
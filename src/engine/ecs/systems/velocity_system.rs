@@ -1,18 +1,23 @@
 use crate::engine::ecs::{
     components::{ transform::Transform, velocity::Velocity },
-    system::SystemContext,
-    world::World,
+    system_param::{ DeltaTime, Query },
 };
 
-/// Applies each entity's Velocity to its Transform, then resets Velocity to zero.
-/// Systems write to Velocity additively each frame — releasing an input simply
-/// means no write happens, so the velocity stays at zero.
-pub fn velocity_system(world: &mut World, system_context: &mut SystemContext) {
-    let dt = system_context.delta_time;
-    for (transform, velocity) in world.query_iter::<(&mut Transform, &mut Velocity)>() {
+/// Applies each entity's Velocity to its Transform. Runs in the fixed stage, so `dt` is the
+/// fixed timestep and a frame may apply the same velocity several times (or not at all).
+pub fn velocity_system(mut query: Query<(&mut Transform, &Velocity)>, dt: DeltaTime) {
+    let dt = dt.0;
+    for (transform, velocity) in query.iter() {
         transform.position.x += velocity.x * dt;
         transform.position.y += velocity.y * dt;
         transform.position.z += velocity.z * dt;
+    }
+}
+
+/// Resets Velocity to zero at the end of the frame. Systems write to Velocity additively each
+/// frame — releasing an input simply means no write happens, so the velocity stays at zero.
+pub fn velocity_reset_system(mut query: Query<&mut Velocity>) {
+    for velocity in query.iter() {
         velocity.x = 0.0;
         velocity.y = 0.0;
         velocity.z = 0.0;
@@ -1,4 +1,4 @@
-use std::{ any::{ Any, TypeId }, collections::HashMap };
+use std::{ any::{ type_name, Any, TypeId }, cell::UnsafeCell, collections::HashMap };
 
 use cgmath::{ Deg, Vector3 };
 
@@ -12,46 +12,150 @@ use crate::engine::{
             },
             transform::Transform,
         },
+        archetype::{ StorageType, Tables },
         entity::{ Entity, EntityAllocator },
+        hooks::{ ComponentHook, ComponentHooks },
         resources::camera::ActiveCamera,
-        sparse_set::SparseSet,
+        sparse_set::{ ComponentTicks, SparseSet },
     },
     events::{ event_registry::EventRegistry, events::Events },
     input::input_state::InputState,
 };
 
-// Trait that lets World call remove() on a type-erased SparseSet without knowing T.
+/// Where a component type's values live. `SparseSet<T>` holds a single type; `Tables` holds
+/// every table component, so it implements this for all of them. World picks the backend in
+/// `storage`/`storage_mut` and every component accessor goes through those.
+pub(crate) trait ComponentStorage<T> {
+    fn get(&self, entity_id: u32) -> Option<&T>;
+    /// Marks the value as changed at `tick`
+    fn get_mut_tracked(&mut self, entity_id: u32, tick: u32) -> Option<&mut T>;
+    /// `get_mut_tracked` through a shared reference.
+    /// # Safety
+    /// Nothing else may access this entity's T, or its ticks, while the result is alive.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn get_mut_unchecked(&self, entity_id: u32, tick: u32) -> Option<&mut T>;
+    fn ticks(&self, entity_id: u32) -> Option<ComponentTicks>;
+    fn contains(&self, entity_id: u32) -> bool;
+    /// Inserts or overwrites, stamping the value with `tick`
+    fn insert(&mut self, entity_id: u32, value: T, tick: u32);
+    fn remove(&mut self, entity_id: u32);
+    fn iter(&self) -> Box<dyn Iterator<Item = (u32, &T)> + '_>;
+}
+
+impl<T: 'static> ComponentStorage<T> for SparseSet<T> {
+    fn get(&self, entity_id: u32) -> Option<&T> {
+        self.get(entity_id)
+    }
+    fn get_mut_tracked(&mut self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        self.get_mut_tracked(entity_id, tick)
+    }
+    unsafe fn get_mut_unchecked(&self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        self.get_mut_unchecked(entity_id, tick)
+    }
+    fn ticks(&self, entity_id: u32) -> Option<ComponentTicks> {
+        self.ticks(entity_id)
+    }
+    fn contains(&self, entity_id: u32) -> bool {
+        self.contains(entity_id)
+    }
+    fn insert(&mut self, entity_id: u32, value: T, tick: u32) {
+        self.insert_at_tick(entity_id, value, tick);
+    }
+    fn remove(&mut self, entity_id: u32) {
+        self.remove(entity_id);
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = (u32, &T)> + '_> {
+        Box::new(self.iter())
+    }
+}
+
+impl<T: 'static> ComponentStorage<T> for Tables {
+    fn get(&self, entity_id: u32) -> Option<&T> {
+        self.get(entity_id)
+    }
+    fn get_mut_tracked(&mut self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        self.get_mut_tracked(entity_id, tick)
+    }
+    unsafe fn get_mut_unchecked(&self, entity_id: u32, tick: u32) -> Option<&mut T> {
+        self.get_mut_unchecked(entity_id, tick)
+    }
+    fn ticks(&self, entity_id: u32) -> Option<ComponentTicks> {
+        self.ticks::<T>(entity_id)
+    }
+    fn contains(&self, entity_id: u32) -> bool {
+        self.contains(entity_id, TypeId::of::<T>())
+    }
+    fn insert(&mut self, entity_id: u32, value: T, tick: u32) {
+        self.insert(entity_id, value, tick);
+    }
+    fn remove(&mut self, entity_id: u32) {
+        self.remove(entity_id, TypeId::of::<T>());
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = (u32, &T)> + '_> {
+        Box::new(
+            self.archetypes()
+                .iter()
+                .filter_map(|archetype| {
+                    let column = archetype.column::<T>()?;
+                    Some(archetype.entities().iter().copied().zip(column.iter()))
+                })
+                .flatten()
+        )
+    }
+}
+
+// Lets World reach a SparseSet without knowing T, for despawn and for queries walking its ids.
 // as_any / as_any_mut allow downcasting back to SparseSet<T> when T is known.
-trait ComponentStorage {
+trait AnySparseSet {
     fn remove(&mut self, entity_id: u32);
+    fn contains(&self, entity_id: u32) -> bool;
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
-    fn entity_ids(&self) -> Vec<u32>;
+    fn dense_ids(&self) -> &[u32];
 }
 
-impl<T: 'static> ComponentStorage for SparseSet<T> {
+impl<T: 'static> AnySparseSet for SparseSet<T> {
     fn remove(&mut self, entity_id: u32) {
         self.remove(entity_id);
     }
+    fn contains(&self, entity_id: u32) -> bool {
+        self.contains(entity_id)
+    }
     fn as_any(&self) -> &dyn Any {
         self
     }
     fn as_any_mut(&mut self) -> &mut dyn Any {
         self
     }
-    fn entity_ids(&self) -> Vec<u32> {
-        self.iter()
-            .map(|(id, _)| id)
-            .collect()
+    fn dense_ids(&self) -> &[u32] {
+        self.entity_ids()
     }
 }
 
 pub struct World {
     pub entities: EntityAllocator,
     // Keyed by TypeId so each SparseSet<T> is stored and retrieved by its component type.
-    // Box<dyn ComponentStorage> erases the type while still exposing remove() for despawn.
-    components: HashMap<TypeId, Box<dyn ComponentStorage>>,
+    // Box<dyn AnySparseSet> erases the type while still exposing remove() for despawn.
+    components: HashMap<TypeId, Box<dyn AnySparseSet>>,
+    // Components registered with StorageType::Table live in archetype tables instead. Only
+    // `storage`/`storage_mut` look at `storage_types`, so callers never need to know which
+    // backend a type uses.
+    tables: Tables,
+    storage_types: HashMap<TypeId, StorageType>,
+    // Each is an `UnsafeCell<T>`, so a `ResMut` on one thread doesn't need `&mut World` while
+    // other systems read other resources (see `get_resource_mut_unchecked`)
     resources: HashMap<TypeId, Box<dyn Any>>,
+    // Change detection: components are stamped with `change_tick` when added or mutably
+    // accessed. `Changed<T>`/`Added<T>` match anything stamped at or after `last_change_tick`,
+    // i.e. during this frame or the previous one - so a system sees a change regardless of
+    // whether it runs before or after the system that made it (same idea as Events<T>).
+    change_tick: u32,
+    last_change_tick: u32,
+    // Per component type, see hooks.rs
+    hooks: HashMap<TypeId, ComponentHooks>,
+    // Entities that lost a component, per type. Read through `RemovedComponents<T>` and aged out
+    // once a frame by `swap_removed_components`, the same way `Events<T>` are.
+    removed: HashMap<TypeId, Events<Entity>>,
 }
 
 impl World {
@@ -63,7 +167,13 @@ impl World {
         Self {
             entities: EntityAllocator::default(),
             components: HashMap::new(),
+            tables: Tables::default(),
+            storage_types: HashMap::new(),
             resources: HashMap::new(),
+            change_tick: 0,
+            last_change_tick: 0,
+            hooks: HashMap::new(),
+            removed: HashMap::new(),
         }
     }
 
@@ -72,46 +182,163 @@ impl World {
         self.components.insert(type_id, Box::new(SparseSet::<T>::new()));
     }
 
+    /// Picks the storage backend for T. Must be called before any T is added -
+    /// existing components aren't migrated between backends.
+    pub fn register_component_with_storage<T: 'static>(&mut self, storage_type: StorageType) {
+        let type_id = TypeId::of::<T>();
+        assert!(
+            self.get_storage::<T>().is_none_or(|set| set.len() == 0),
+            "{} already has sparse set data - choose its storage before adding any",
+            type_name::<T>()
+        );
+        match storage_type {
+            StorageType::SparseSet => {
+                self.storage_types.remove(&type_id);
+                self.register_component::<T>();
+            }
+            StorageType::Table => {
+                self.components.remove(&type_id);
+                self.storage_types.insert(type_id, StorageType::Table);
+            }
+        }
+    }
+
+    pub fn storage_type<T: 'static>(&self) -> StorageType {
+        self.storage_types.get(&TypeId::of::<T>()).copied().unwrap_or_default()
+    }
+
+    fn is_table<T: 'static>(&self) -> bool {
+        self.storage_type::<T>() == StorageType::Table
+    }
+
     pub fn add_component<T: 'static>(&mut self, entity: Entity, value: T) {
         let type_id = TypeId::of::<T>();
-        self.components.entry(type_id).or_insert_with(|| Box::new(SparseSet::<T>::new()));
-        let storage = self.components.get_mut(&type_id).unwrap();
-        let set = storage.as_any_mut().downcast_mut::<SparseSet<T>>().unwrap();
-        set.insert(entity.id, value);
+        let hooks = self.hooks.get(&type_id).cloned();
+        let is_new = hooks.is_some() && !self.has_component_by_id::<T>(entity.id);
+
+        let tick = self.change_tick;
+        match self.storage_mut::<T>() {
+            Some(storage) => storage.insert(entity.id, value, tick),
+            // Sparse set types don't need registering
+            None => {
+                let mut set = SparseSet::<T>::new();
+                set.insert_at_tick(entity.id, value, tick);
+                self.components.insert(type_id, Box::new(set));
+            }
+        }
+
+        if let Some(hooks) = hooks {
+            if is_new {
+                hooks.on_add.iter().for_each(|hook| hook(self, entity));
+            }
+            hooks.on_insert.iter().for_each(|hook| hook(self, entity));
+        }
+    }
+
+    /// Runs `hook` whenever an entity gets a T it didn't have.
+    pub fn on_add<T: 'static>(&mut self, hook: ComponentHook) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().on_add.push(hook);
+    }
+
+    /// Runs `hook` after every T added, including overwrites.
+    pub fn on_insert<T: 'static>(&mut self, hook: ComponentHook) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().on_insert.push(hook);
+    }
+
+    /// Runs `hook` just before a T is removed, whether by `remove_component` or `despawn`.
+    pub fn on_remove<T: 'static>(&mut self, hook: ComponentHook) {
+        self.hooks.entry(TypeId::of::<T>()).or_default().on_remove.push(hook);
+    }
+
+    fn run_remove_hooks(&mut self, type_id: TypeId, entity: Entity) {
+        if let Some(hooks) = self.hooks.get(&type_id).map(|hooks| hooks.on_remove.clone()) {
+            hooks.iter().for_each(|hook| hook(self, entity));
+        }
+    }
+
+    fn record_removed(&mut self, type_id: TypeId, entity: Entity) {
+        self.removed.entry(type_id).or_default().send(entity);
+    }
+
+    /// Entities that lost their T last frame (removed or despawned).
+    pub fn removed<T: 'static>(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.removed_log(TypeId::of::<T>())
+            .into_iter()
+            .flat_map(|removed| removed.read().copied())
+    }
+
+    pub(crate) fn removed_log(&self, type_id: TypeId) -> Option<&Events<Entity>> {
+        self.removed.get(&type_id)
+    }
+
+    /// Called once per frame by `SystemSchedule::run_all`. Removals from this frame become
+    /// readable, and last frame's are dropped.
+    pub fn swap_removed_components(&mut self) {
+        for removed in self.removed.values_mut() {
+            removed.swap();
+        }
     }
 
     pub fn get_component<T: 'static>(&self, entity: Entity) -> Option<&T> {
-        let type_id = TypeId::of::<T>();
-        let storage = self.components.get(&type_id)?;
-        let set = storage.as_any().downcast_ref::<SparseSet<T>>().unwrap();
-        set.get(entity.id)
+        self.get_component_by_id(entity.id)
     }
 
     pub fn get_component_by_id<T: 'static>(&self, entity_id: u32) -> Option<&T> {
-        self.get_storage::<T>()?.get(entity_id)
+        self.storage::<T>()?.get(entity_id)
     }
 
     pub fn get_component_mut_by_id<T: 'static>(&mut self, entity_id: u32) -> Option<&mut T> {
-        let type_id = TypeId::of::<T>();
-        let storage = self.components.get_mut(&type_id)?;
-        let set = storage.as_any_mut().downcast_mut::<SparseSet<T>>().unwrap();
-        set.get_mut(entity_id)
+        let tick = self.change_tick;
+        self.storage_mut::<T>()?.get_mut_tracked(entity_id, tick)
+    }
+
+    /// `get_component_mut_by_id` through a shared reference, for queries run by parallel systems.
+    /// # Safety
+    /// Nothing else may access this entity's T while the result is alive - the executor ensures
+    /// that by never running systems with conflicting access together.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get_component_mut_unchecked<T: 'static>(&self, entity_id: u32) -> Option<&mut T> {
+        self.storage::<T>()?.get_mut_unchecked(entity_id, self.change_tick)
     }
 
     pub fn get_component_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
-        let type_id = TypeId::of::<T>();
-        let storage = self.components.get_mut(&type_id)?;
-        let set = storage.as_any_mut().downcast_mut::<SparseSet<T>>().unwrap();
-        set.get_mut(entity.id)
+        self.get_component_mut_by_id(entity.id)
+    }
+
+    pub fn has_component_by_id<T: 'static>(&self, entity_id: u32) -> bool {
+        self.storage::<T>().is_some_and(|storage| storage.contains(entity_id))
+    }
+
+    pub fn component_ticks_by_id<T: 'static>(&self, entity_id: u32) -> Option<ComponentTicks> {
+        self.storage::<T>()?.ticks(entity_id)
+    }
+
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+
+    pub fn last_change_tick(&self) -> u32 {
+        self.last_change_tick
+    }
+
+    /// Called once per frame by `SystemSchedule::run_all`, after every system has run.
+    /// Changes stamped before the previous frame stop matching `Changed<T>`/`Added<T>`.
+    pub fn advance_change_tick(&mut self) {
+        self.last_change_tick = self.change_tick;
+        self.change_tick = self.change_tick.wrapping_add(1);
     }
 
     pub fn remove_component<T: 'static>(&mut self, entity: Entity) {
-        let type_id = TypeId::of::<T>();
-        let Some(storage) = self.components.get_mut(&type_id) else {
+        if !self.is_alive(entity) || !self.has_component_by_id::<T>(entity.id) {
             return;
-        };
-        let set = storage.as_any_mut().downcast_mut::<SparseSet<T>>().unwrap();
-        set.remove(entity.id);
+        }
+        let type_id = TypeId::of::<T>();
+        self.run_remove_hooks(type_id, entity);
+
+        if let Some(storage) = self.storage_mut::<T>() {
+            storage.remove(entity.id);
+        }
+        self.record_removed(type_id, entity);
     }
 
     pub fn spawn_entity_only(&mut self) -> Entity {
@@ -128,41 +355,113 @@ impl World {
     }
 
     pub fn despawn(&mut self, entity: Entity) {
+        if !self.is_alive(entity) {
+            return;
+        }
+        // Remove hooks run first, while the entity and all its components are still there
+        let hooked: Vec<TypeId> = self
+            .component_types_of(entity.id)
+            .into_iter()
+            .filter(|type_id| self.hooks.get(type_id).is_some_and(|hooks| !hooks.on_remove.is_empty()))
+            .collect();
+        for type_id in hooked {
+            self.run_remove_hooks(type_id, entity);
+        }
+        // A hook may have despawned it already
+        if !self.is_alive(entity) {
+            return;
+        }
+
+        self.detach_from_hierarchy(entity);
+        for type_id in self.component_types_of(entity.id) {
+            self.record_removed(type_id, entity);
+        }
         self.entities.despawn(&entity);
         for storage in self.components.values_mut() {
             storage.remove(entity.id);
         }
+        self.tables.remove_entity(entity.id);
+    }
+
+    fn component_types_of(&self, entity_id: u32) -> Vec<TypeId> {
+        self.components
+            .iter()
+            .filter(|(_, storage)| storage.contains(entity_id))
+            .map(|(type_id, _)| *type_id)
+            .chain(self.tables.types_of(entity_id).iter().copied())
+            .collect()
     }
 
     pub fn add_resource<T: 'static>(&mut self, value: T) {
-        self.resources.insert(TypeId::of::<T>(), Box::new(value));
+        self.resources.insert(TypeId::of::<T>(), Box::new(UnsafeCell::new(value)));
     }
 
     pub fn get_resource<T: 'static>(&self) -> Option<&T> {
-        self.resources.get(&TypeId::of::<T>())?.downcast_ref::<T>()
+        let cell = self.resources.get(&TypeId::of::<T>())?.downcast_ref::<UnsafeCell<T>>()?;
+        // Safety: only `get_resource_mut_unchecked` writes through a shared reference, and its
+        // callers guarantee nothing else is reading T
+        Some(unsafe { &*cell.get() })
     }
 
     pub fn get_resource_mut<T: 'static>(&mut self) -> Option<&mut T> {
-        self.resources.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>()
+        let cell = self.resources.get_mut(&TypeId::of::<T>())?.downcast_mut::<UnsafeCell<T>>()?;
+        Some(cell.get_mut())
+    }
+
+    /// `get_resource_mut` through a shared reference, for `ResMut`/`EventWriter` params.
+    /// # Safety
+    /// Nothing else may access T while the result is alive.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn get_resource_mut_unchecked<T: 'static>(&self) -> Option<&mut T> {
+        let cell = self.resources.get(&TypeId::of::<T>())?.downcast_ref::<UnsafeCell<T>>()?;
+        Some(&mut *cell.get())
+    }
+
+    // The only place that looks at which backend T uses. None for sparse set types nothing has
+    // added or registered yet
+    fn storage<T: 'static>(&self) -> Option<&dyn ComponentStorage<T>> {
+        if self.is_table::<T>() {
+            return Some(&self.tables);
+        }
+        Some(self.get_storage::<T>()?)
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> Option<&mut dyn ComponentStorage<T>> {
+        if self.is_table::<T>() {
+            return Some(&mut self.tables);
+        }
+        let set = self.components.get_mut(&TypeId::of::<T>())?;
+        Some(set.as_any_mut().downcast_mut::<SparseSet<T>>()?)
     }
 
     fn get_storage<T: 'static>(&self) -> Option<&SparseSet<T>> {
         self.components.get(&TypeId::of::<T>())?.as_any().downcast_ref()
     }
 
-    pub(crate) fn entity_ids_for(&self, type_id: TypeId) -> Vec<u32> {
+    pub(crate) fn alive_entity_ids(&self) -> Vec<u32> {
+        self.entities.alive_ids()
+    }
+
+    /// The dense entity id array of a sparse set component, for iterating in place.
+    /// Empty for table components and unregistered types.
+    pub(crate) fn sparse_entity_ids(&self, type_id: TypeId) -> &[u32] {
         self.components
             .get(&type_id)
-            .map(|s| s.entity_ids())
+            .map(|s| s.dense_ids())
             .unwrap_or_default()
     }
 
+    pub(crate) fn is_table_type(&self, type_id: TypeId) -> bool {
+        self.storage_types.get(&type_id) == Some(&StorageType::Table)
+    }
+
+    pub(crate) fn tables(&self) -> &Tables {
+        &self.tables
+    }
+
     // Use when we only need the entity's ID itself, if you want comoponents user query_iter
     pub fn iter_component<T: 'static>(&self) -> impl Iterator<Item = (u32, &T)> {
-        self.get_storage::<T>()
-            .map(|s| s.iter())
-            .into_iter()
-            .flatten()
+        self.storage::<T>().into_iter().flat_map(|storage| storage.iter())
     }
 
     /// Finds entities with all provided components
@@ -484,4 +783,109 @@ mod tests {
         world.despawn(e);
         assert_eq!(world.iter_component::<Health>().count(), 0);
     }
+
+    // --- table storage ---
+
+    #[test]
+    fn table_components_round_trip_through_world_accessors() {
+        let mut world = World::new();
+        world.register_component_with_storage::<Health>(StorageType::Table);
+        let e = world.spawn().with(Health(10)).with(Speed(1.0)).build();
+        assert_eq!(world.storage_type::<Health>(), StorageType::Table);
+        assert!(world.has_component_by_id::<Health>(e.id));
+
+        world.get_component_mut::<Health>(e).unwrap().0 = 20;
+        assert_eq!(world.get_component::<Health>(e).unwrap().0, 20);
+        assert_eq!(world.iter_component::<Health>().count(), 1);
+
+        world.remove_component::<Health>(e);
+        assert!(world.get_component::<Health>(e).is_none());
+        assert_eq!(world.get_component::<Speed>(e).unwrap().0, 1.0);
+    }
+
+    #[test]
+    fn despawn_removes_table_components() {
+        let mut world = World::new();
+        world.register_component_with_storage::<Health>(StorageType::Table);
+        let a = world.spawn().with(Health(1)).build();
+        let b = world.spawn().with(Health(2)).build();
+        world.despawn(a);
+        assert!(world.get_component::<Health>(a).is_none());
+        assert_eq!(world.get_component::<Health>(b).unwrap().0, 2);
+        assert_eq!(world.iter_component::<Health>().count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "already has sparse set data")]
+    fn switching_storage_after_adding_panics() {
+        let mut world = World::new();
+        world.spawn().with(Health(1)).build();
+        world.register_component_with_storage::<Health>(StorageType::Table);
+    }
+
+    // --- hooks / removal detection ---
+
+    struct HookLog(Vec<(&'static str, u32)>);
+
+    fn log_hook(world: &mut World, event: &'static str, value: u32) {
+        world.get_resource_mut::<HookLog>().unwrap().0.push((event, value));
+    }
+
+    #[test]
+    fn on_add_fires_once_and_on_insert_every_time() {
+        let mut world = World::new();
+        world.add_resource(HookLog(Vec::new()));
+        world.on_add::<Health>(|world, e| {
+            let value = world.get_component::<Health>(e).unwrap().0;
+            log_hook(world, "add", value);
+        });
+        world.on_insert::<Health>(|world, e| {
+            let value = world.get_component::<Health>(e).unwrap().0;
+            log_hook(world, "insert", value);
+        });
+        let e = world.spawn().with(Health(1)).build();
+        world.add_component(e, Health(2));
+        assert_eq!(
+            world.get_resource::<HookLog>().unwrap().0,
+            vec![("add", 1), ("insert", 1), ("insert", 2)]
+        );
+    }
+
+    #[test]
+    fn on_remove_sees_the_component_on_remove_and_despawn() {
+        for storage in [StorageType::SparseSet, StorageType::Table] {
+            let mut world = World::new();
+            world.register_component_with_storage::<Health>(storage);
+            world.add_resource(HookLog(Vec::new()));
+            world.on_remove::<Health>(|world, e| {
+                let value = world.get_component::<Health>(e).unwrap().0;
+                log_hook(world, "remove", value);
+            });
+            let a = world.spawn().with(Health(1)).build();
+            let b = world.spawn().with(Health(2)).with(Speed(1.0)).build();
+            world.remove_component::<Health>(a);
+            world.remove_component::<Health>(a);
+            world.despawn(b);
+            assert_eq!(world.get_resource::<HookLog>().unwrap().0, vec![("remove", 1), ("remove", 2)]);
+        }
+    }
+
+    #[test]
+    fn removed_components_are_readable_for_one_frame() {
+        let mut world = World::new();
+        let a = world.spawn().with(Health(1)).build();
+        let b = world.spawn().with(Health(2)).with(Speed(1.0)).build();
+        world.spawn().with(Health(3)).build();
+        world.remove_component::<Health>(a);
+        world.despawn(b);
+        // Logged this frame, readable next frame
+        assert_eq!(world.removed::<Health>().count(), 0);
+
+        world.swap_removed_components();
+        assert_eq!(world.removed::<Health>().collect::<Vec<_>>(), vec![a, b]);
+        assert_eq!(world.removed::<Speed>().collect::<Vec<_>>(), vec![b]);
+
+        world.swap_removed_components();
+        assert_eq!(world.removed::<Health>().count(), 0);
+    }
 }
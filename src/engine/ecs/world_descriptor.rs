@@ -1,19 +1,63 @@
 use std::fmt;
 
 use anyhow::Result;
-use serde::de::{ self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor };
+use serde::{
+    de::{ self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor },
+    ser::{ SerializeMap, SerializeStruct, Serializer },
+    Deserialize,
+    Serialize,
+};
 
 use crate::engine::{
     assets::server::AssetServer,
     ecs::{
         component_registry::ComponentRegistry,
-        components::renderable::Renderable,
+        components::{
+            entity_name::EntityName,
+            hierarchy::{ Children, Parent },
+            renderable::Renderable,
+        },
         entity::Entity,
+        entity_ref::EntityNames,
+        prefab::{ EntityNode, Prefabs, PREFAB_KEY },
+        ron_node::RonNode,
         world::World,
+        world_validation::validate_world,
     },
 };
 
-const RENDERABLE_NAME: &str = "Renderable";
+pub(crate) const RENDERABLE_NAME: &str = "Renderable";
+// Reserved entity key holding a nested entity list, spawned as children of the entity
+pub(crate) const CHILDREN_KEY: &str = "children";
+
+// Reserved entity key naming the entity, so EntityRefs elsewhere in the file can point at it
+pub(crate) const ID_KEY: &str = "id";
+
+// Renderable is stored by model name, since model ids depend on load order
+#[derive(Deserialize, Serialize)]
+pub(crate) struct RenderableDescriptor {
+    pub(crate) model: String,
+}
+
+// What a load has spawned so far
+#[derive(Default)]
+struct LoadedEntities {
+    names: EntityNames,
+    // Top-level entities. Their descendants - including prefab children - are found through the
+    // hierarchy afterwards.
+    roots: Vec<Entity>,
+}
+
+impl LoadedEntities {
+    fn name(&mut self, world: &mut World, entity: Entity, name: String) -> Result<()> {
+        if self.names.contains_key(&name) {
+            anyhow::bail!("duplicate entity id: {}", name);
+        }
+        self.names.insert(name.clone(), entity);
+        world.add_component(entity, EntityName(name));
+        Ok(())
+    }
+}
 
 // --- Top level: deserializes the `( entities: [ ... ] )` wrapper struct ---
 
@@ -21,6 +65,7 @@ struct WorldDescriptorSeed<'a> {
     world: &'a mut World,
     registry: &'a ComponentRegistry,
     asset_server: &'a AssetServer,
+    loaded: &'a mut LoadedEntities,
 }
 
 impl<'de, 'a> DeserializeSeed<'de> for WorldDescriptorSeed<'a> {
@@ -52,6 +97,8 @@ impl<'de, 'a> Visitor<'de> for WorldDescriptorSeed<'a> {
                     world: self.world,
                     registry: self.registry,
                     asset_server: self.asset_server,
+                    loaded: self.loaded,
+                    parent: None,
                 })?;
             } else {
                 return Err(de::Error::unknown_field(&key, &["entities"]));
@@ -72,6 +119,9 @@ struct EntityListSeed<'a> {
     world: &'a mut World,
     registry: &'a ComponentRegistry,
     asset_server: &'a AssetServer,
+    loaded: &'a mut LoadedEntities,
+    // Set when this is an entity's `children` list
+    parent: Option<Entity>,
 }
 
 impl<'de, 'a> DeserializeSeed<'de> for EntityListSeed<'a> {
@@ -96,6 +146,8 @@ impl<'de, 'a> Visitor<'de> for EntityListSeed<'a> {
                     world: self.world,
                     registry: self.registry,
                     asset_server: self.asset_server,
+                    loaded: self.loaded,
+                    parent: self.parent,
                 })?
                 .is_some()
         {}
@@ -104,12 +156,14 @@ impl<'de, 'a> Visitor<'de> for EntityListSeed<'a> {
     }
 }
 
-// --- Single entity: deserializes `{ "Transform": (...), "Renderable": (...) }` ---
+// --- Single entity: deserializes `{ "Transform": (...), "children": [ ... ] }` ---
 
 struct EntitySeed<'a> {
     world: &'a mut World,
     registry: &'a ComponentRegistry,
     asset_server: &'a AssetServer,
+    loaded: &'a mut LoadedEntities,
+    parent: Option<Entity>,
 }
 
 impl<'de, 'a> DeserializeSeed<'de> for EntitySeed<'a> {
@@ -129,16 +183,43 @@ impl<'de, 'a> Visitor<'de> for EntitySeed<'a> {
 
     fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<(), M::Error> {
         let entity = self.world.spawn_entity_only();
+        if let Some(parent) = self.parent {
+            self.world.set_parent(entity, parent).map_err(de::Error::custom)?;
+        }
+
+        if self.parent.is_none() {
+            self.loaded.roots.push(entity);
+        }
 
+        let mut has_components = false;
         while let Some(component_name) = map.next_key::<String>()? {
-            if component_name == RENDERABLE_NAME {
-                #[derive(serde::Deserialize)]
-                struct RenderableDescriptor {
-                    model: String,
+            if component_name == ID_KEY {
+                let name: String = map.next_value()?;
+                self.loaded.name(self.world, entity, name).map_err(de::Error::custom)?;
+                continue;
+            }
+            if component_name == PREFAB_KEY {
+                if has_components {
+                    return Err(de::Error::custom("\"prefab\" must come before the components"));
                 }
+                return self.visit_prefab_instance(entity, map);
+            }
+            has_components = true;
+
+            if component_name == CHILDREN_KEY {
+                map.next_value_seed(EntityListSeed {
+                    world: self.world,
+                    registry: self.registry,
+                    asset_server: self.asset_server,
+                    loaded: self.loaded,
+                    parent: Some(entity),
+                })?;
+            } else if component_name == RENDERABLE_NAME {
                 let descriptor: RenderableDescriptor = map.next_value()?;
-                let model_id = self.asset_server.get_model_id(&descriptor.model);
-                self.world.add_component(entity, Renderable::new(model_id));
+                let model = self.asset_server
+                    .get_model_handle(&descriptor.model)
+                    .ok_or_else(|| de::Error::custom(format!("unknown model `{}`", descriptor.model)))?;
+                self.world.add_component(entity, Renderable::new(model));
             } else {
                 map.next_value_seed(ComponentSeed {
                     world: self.world,
@@ -153,6 +234,43 @@ impl<'de, 'a> Visitor<'de> for EntitySeed<'a> {
     }
 }
 
+impl EntitySeed<'_> {
+    // `{ "prefab": "name", ...overrides }`. The overrides are captured as raw RON so they can be
+    // merged field by field over the prefab before anything is deserialized.
+    fn visit_prefab_instance<'de, M: MapAccess<'de>>(
+        self,
+        entity: Entity,
+        mut map: M
+    ) -> Result<(), M::Error> {
+        let name: String = map.next_value()?;
+        let mut node = EntityNode { prefab: Some(name), ..Default::default() };
+        while let Some(key) = map.next_key::<String>()? {
+            if key == PREFAB_KEY {
+                return Err(de::Error::duplicate_field(PREFAB_KEY));
+            }
+            if key == ID_KEY {
+                let name: String = map.next_value()?;
+                self.loaded.name(self.world, entity, name).map_err(de::Error::custom)?;
+                continue;
+            }
+            let raw: Box<ron::value::RawValue> = map.next_value()?;
+            let value = RonNode::parse(raw.get_ron()).map_err(de::Error::custom)?;
+            node.insert(key, value).map_err(de::Error::custom)?;
+        }
+
+        let prefabs = self.world
+            .get_resource::<Prefabs>()
+            .cloned()
+            .ok_or_else(|| de::Error::custom("entity uses a prefab but there is no Prefabs resource"))?;
+        let asset_server = self.asset_server;
+        prefabs
+            .instantiate(self.world, entity, node, self.registry, &|model| {
+                asset_server.get_model_handle(model)
+            })
+            .map_err(|e| de::Error::custom(format!("{:#}", e)))
+    }
+}
+
 
 // --- Single component value: dispatches through the registry ---
 
@@ -178,16 +296,330 @@ impl<'de, 'a> DeserializeSeed<'de> for ComponentSeed<'a> {
     }
 }
 
-// --- Public API ---
-
+/// Spawns every entity in a world file. Returns the entities that were given an `"id"`, by id.
+/// The file is validated first; if anything is wrong nothing is spawned and the error is a
+/// `DiagnosticReport` listing every problem.
 pub fn load_world(
     ron_str: &str,
     world: &mut World,
     registry: &ComponentRegistry,
     asset_server: &AssetServer
-) -> Result<()> {
+) -> Result<EntityNames> {
+    spawn_world(ron_str, world, registry, asset_server).map(|(names, _)| names)
+}
+
+// load_world, also returning every entity it spawned (roots and all their descendants)
+pub(crate) fn spawn_world(
+    ron_str: &str,
+    world: &mut World,
+    registry: &ComponentRegistry,
+    asset_server: &AssetServer
+) -> Result<(EntityNames, Vec<Entity>)> {
+    let prefabs = world.get_resource::<Prefabs>().cloned();
+    let report = validate_world(ron_str, registry, asset_server, prefabs.as_ref());
+    if !report.is_empty() {
+        return Err(report.into());
+    }
+
+    let mut loaded = LoadedEntities::default();
     let mut deserializer = ron::de::Deserializer::from_str(ron_str)?;
-    let seed = WorldDescriptorSeed { world, registry, asset_server };
-    seed.deserialize(&mut deserializer)?;
-    Ok(())
+    let seed = WorldDescriptorSeed { world, registry, asset_server, loaded: &mut loaded };
+    if let Err(e) = seed.deserialize(&mut deserializer) {
+        // Don't leave half a file behind
+        for root in loaded.roots {
+            world.despawn_recursive(root);
+        }
+        return Err(e.into());
+    }
+
+    // Now every id is known, point the file's EntityRefs at live entities
+    let mut spawned = Vec::new();
+    for root in loaded.roots {
+        spawned.push(root);
+        spawned.extend(world.descendants(root));
+    }
+    registry.map_entities(world, &spawned, &loaded.names);
+    Ok((loaded.names, spawned))
+}
+
+// --- Saving: borrowed views of the world that serialize to the same format ---
+
+struct WorldView<'w> {
+    entities: Vec<EntityView<'w>>,
+}
+
+impl Serialize for WorldView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("WorldDescriptor", 1)?;
+        state.serialize_field("entities", &self.entities)?;
+        state.end()
+    }
+}
+
+struct EntityView<'w> {
+    id: Option<&'w str>,
+    components: Vec<(&'w str, &'w dyn erased_serde::Serialize)>,
+    renderable: Option<RenderableDescriptor>,
+    children: Vec<EntityView<'w>>,
+}
+
+impl Serialize for EntityView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(id) = self.id {
+            map.serialize_entry(ID_KEY, id)?;
+        }
+        for (name, value) in &self.components {
+            map.serialize_entry(name, value)?;
+        }
+        if let Some(renderable) = &self.renderable {
+            map.serialize_entry(RENDERABLE_NAME, renderable)?;
+        }
+        if !self.children.is_empty() {
+            map.serialize_entry(CHILDREN_KEY, &self.children)?;
+        }
+        map.end()
+    }
+}
+
+struct SaveContext<'w> {
+    world: &'w World,
+    registry: &'w ComponentRegistry,
+    asset_server: &'w AssetServer,
+}
+
+impl<'w> SaveContext<'w> {
+    // None if there's nothing to write for the entity or any of its children
+    fn entity_view(&self, entity_id: u32) -> Result<Option<EntityView<'w>>> {
+        let renderable = match self.world.get_component_by_id::<Renderable>(entity_id) {
+            Some(renderable) => {
+                let model = self.asset_server
+                    .get_model_name(renderable.model.id())
+                    .ok_or_else(|| anyhow::anyhow!("no model name for model {}", renderable.model.id()))?;
+                Some(RenderableDescriptor { model: model.to_string() })
+            }
+            None => None,
+        };
+
+        let mut children = Vec::new();
+        if let Some(Children(child_entities)) = self.world.get_component_by_id::<Children>(entity_id) {
+            for child in child_entities {
+                children.extend(self.entity_view(child.id)?);
+            }
+        }
+
+        let id = self.world
+            .get_component_by_id::<EntityName>(entity_id)
+            .map(|name| name.0.as_str());
+        let components = self.registry.serializable_components(self.world, entity_id);
+        if id.is_none() && components.is_empty() && renderable.is_none() && children.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(EntityView { id, components, renderable, children }))
+    }
+}
+
+// --- Public API ---
+
+/// Writes every registered component (plus `Renderable`, by model name, and the `"id"`) of every
+/// live entity in the format `load_world` reads. Children are nested under their parent.
+/// Entities with nothing to write - e.g. the camera - are left out.
+///
+/// Components are written in registration order and entities in id order, so saving a freshly
+/// loaded save reproduces it exactly.
+pub fn save_world(
+    world: &World,
+    registry: &ComponentRegistry,
+    asset_server: &AssetServer
+) -> Result<String> {
+    let context = SaveContext { world, registry, asset_server };
+    let mut entities = Vec::new();
+    for entity_id in world.alive_entity_ids() {
+        if world.has_component_by_id::<Parent>(entity_id) {
+            continue;
+        }
+        entities.extend(context.entity_view(entity_id)?);
+    }
+    let config = ron::ser::PrettyConfig::new().indentor("    ".to_string());
+    Ok(ron::ser::to_string_pretty(&WorldView { entities }, config)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{
+        assets::{ asset_storage::Assets, diagnostics::DiagnosticReport },
+        ecs::{
+            components::{ transform::Transform, velocity::Velocity },
+            entity_ref::{ EntityRef, MapEntities },
+        },
+        model::model::Model,
+    };
+
+    fn load(ron_str: &str) -> World {
+        let mut world = World::new();
+        load_world(ron_str, &mut world, &ComponentRegistry::new(), &AssetServer::new()).unwrap();
+        world
+    }
+
+    #[test]
+    fn loads_registered_components() {
+        let mut world = load(
+            r#"( entities: [ { "Velocity": (x: 1.0, y: 2.0, z: 3.0) } ] )"#
+        );
+        let velocity = world.query_iter::<&Velocity>().next().unwrap();
+        assert_eq!(velocity.y, 2.0);
+    }
+
+    #[test]
+    fn unknown_component_is_an_error() {
+        let mut world = World::new();
+        let result = load_world(
+            r#"( entities: [ { "Nope": () } ] )"#,
+            &mut world,
+            &ComponentRegistry::new(),
+            &AssetServer::new()
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn invalid_file_fails_with_a_report_and_spawns_nothing() {
+        let mut world = World::new();
+        let error = load_world(
+            r#"( entities: [ { "Velocity": (x: 1.0, y: 2.0, z: 3.0) }, { "Velocty": () } ] )"#,
+            &mut world,
+            &ComponentRegistry::new(),
+            &AssetServer::new()
+        ).unwrap_err();
+        let report = error.downcast_ref::<DiagnosticReport>().unwrap();
+        assert_eq!(report.diagnostics[0].suggestion.as_deref(), Some("Velocity"));
+        assert_eq!(world.query_iter::<&Velocity>().count(), 0);
+    }
+
+    #[test]
+    fn nested_children_are_parented() {
+        let world = load(
+            r#"(
+                entities: [
+                    {
+                        "Velocity": (x: 0.0, y: 0.0, z: 0.0),
+                        "children": [
+                            {
+                                "Transform": (
+                                    position: (x: 0.0, y: 1.0, z: 0.0),
+                                    rotation: (s: 1.0, v: (x: 0.0, y: 0.0, z: 0.0)),
+                                    scale: (x: 1.0, y: 1.0, z: 1.0),
+                                ),
+                                "children": [ { "Velocity": (x: 0.0, y: 0.0, z: 0.0) } ],
+                            },
+                        ],
+                    },
+                ]
+            )"#
+        );
+        let (root_id, _) = world.iter_component::<Children>().find(|(id, _)| {
+            !world.has_component_by_id::<Parent>(*id)
+        }).unwrap();
+        let root = world.get_entity(root_id).unwrap();
+        let descendants = world.descendants(root);
+        assert_eq!(descendants.len(), 2);
+        let (child_id, _) = world.iter_component::<Transform>().next().unwrap();
+        assert_eq!(world.get_component_by_id::<Parent>(child_id), Some(&Parent(root)));
+    }
+
+    #[test]
+    fn save_then_load_round_trips() {
+        let registry = ComponentRegistry::new();
+        let mut asset_server = AssetServer::new();
+        let _cube = asset_server.register_model_name("cube");
+        let _ship = asset_server.register_model_name("ship");
+
+        let mut world = World::new();
+        load_world(
+            r#"(
+                entities: [
+                    {
+                        "Velocity": (x: 1.0, y: 2.0, z: 3.0),
+                        "Renderable": (model: "ship"),
+                        "children": [ { "Renderable": (model: "cube") } ],
+                    },
+                    { "Velocity": (x: 0.0, y: 0.0, z: 0.0) },
+                ]
+            )"#,
+            &mut world,
+            &registry,
+            &asset_server
+        ).unwrap();
+        // nothing to save on this one
+        world.spawn().build();
+
+        let saved = save_world(&world, &registry, &asset_server).unwrap();
+        assert!(saved.contains("\"Renderable\": (\n"));
+        assert!(saved.contains("model: \"ship\""));
+
+        let mut reloaded = World::new();
+        load_world(&saved, &mut reloaded, &registry, &asset_server).unwrap();
+        assert_eq!(reloaded.alive_entity_ids().len(), 3);
+        assert_eq!(save_world(&reloaded, &registry, &asset_server).unwrap(), saved);
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Target {
+        entity: EntityRef,
+    }
+
+    impl MapEntities for Target {
+        fn map_entities(&mut self, names: &EntityNames) {
+            self.entity.map(names);
+        }
+    }
+
+    const TARGETING: &str = r#"(
+        entities: [
+            { "Target": (entity: "ship") },
+            { "id": "ship", "Velocity": (x: 0.0, y: 0.0, z: 0.0) },
+        ]
+    )"#;
+
+    #[test]
+    fn ids_are_returned_and_refs_mapped_after_loading() {
+        let mut registry = ComponentRegistry::new();
+        registry.register_mapped::<Target>("Target");
+        let mut world = World::new();
+        let names = load_world(TARGETING, &mut world, &registry, &AssetServer::new()).unwrap();
+
+        let ship = names["ship"];
+        assert!(world.has_component_by_id::<Velocity>(ship.id));
+        let (_, target) = world.iter_component::<Target>().next().unwrap();
+        assert_eq!(target.entity.get(), Some(ship));
+
+        // and they survive a save
+        let saved = save_world(&world, &registry, &AssetServer::new()).unwrap();
+        let mut reloaded = World::new();
+        let names = load_world(&saved, &mut reloaded, &registry, &AssetServer::new()).unwrap();
+        let (_, target) = reloaded.iter_component::<Target>().next().unwrap();
+        assert_eq!(target.entity.get(), Some(names["ship"]));
+    }
+
+    #[test]
+    fn duplicate_ids_are_an_error() {
+        let mut world = World::new();
+        let result = load_world(
+            r#"( entities: [ { "id": "a" }, { "id": "a" } ] )"#,
+            &mut world,
+            &ComponentRegistry::new(),
+            &AssetServer::new()
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn saving_an_unnamed_model_is_an_error() {
+        let unnamed = Assets::<Model>::new().reserve();
+        let mut world = World::new();
+        world.spawn().with(Renderable::new(unnamed)).build();
+        let result = save_world(&world, &ComponentRegistry::new(), &AssetServer::new());
+        assert!(result.is_err());
+    }
 }
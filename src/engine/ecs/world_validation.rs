@@ -0,0 +1,454 @@
+// Validation pass over a world file.
+//
+// `load_world` runs this first, so a bad file fails with every problem listed - unknown
+// components and models, type mismatches, bad enum variants, duplicate keys and ids - rather than
+// stopping at the first one (or panicking on a missing model). Each component value is
+// deserialized on its own into a scratch World, and ron's position inside that value is moved
+// back to the line and column in the file.
+
+use std::{ collections::HashSet, ops::Range };
+
+use crate::engine::{
+    assets::{
+        diagnostics::{ did_you_mean, DiagnosticReport, DiagnosticSink },
+        server::AssetServer,
+    },
+    ecs::{
+        component_registry::ComponentRegistry,
+        prefab::{ insert_component, EntityNode, Prefabs, PREFAB_KEY },
+        ron_node::{ entry_spans, item_spans, RonNode },
+        world::World,
+        world_descriptor::{ RenderableDescriptor, CHILDREN_KEY, ID_KEY, RENDERABLE_NAME },
+    },
+};
+
+/// Checks a world file without touching any world. An empty report means `load_world` will
+/// load it.
+pub fn validate_world(
+    ron_str: &str,
+    registry: &ComponentRegistry,
+    asset_server: &AssetServer,
+    prefabs: Option<&Prefabs>
+) -> DiagnosticReport {
+    let mut sink = DiagnosticSink::new("world file", ron_str);
+    // Nothing below makes sense if the file doesn't parse at all
+    if let Err(e) = ron::from_str::<ron::Value>(ron_str) {
+        sink.push_ron_error(0, e.span.start, e.code.to_string());
+        return sink.finish();
+    }
+
+    let mut validator = WorldValidator {
+        sink,
+        registry,
+        asset_server,
+        prefabs,
+        ids: HashSet::new(),
+        scratch: World::new(),
+    };
+    validator.validate_file();
+    validator.sink.finish()
+}
+
+struct WorldValidator<'a> {
+    sink: DiagnosticSink<'a>,
+    registry: &'a ComponentRegistry,
+    asset_server: &'a AssetServer,
+    prefabs: Option<&'a Prefabs>,
+    ids: HashSet<String>,
+    // Components are deserialized into this to check them
+    scratch: World,
+}
+
+// An override on a prefab instance, with where it was written
+struct Override {
+    name: String,
+    key: Range<usize>,
+    value: Range<usize>,
+}
+
+impl WorldValidator<'_> {
+    fn text(&self, range: &Range<usize>) -> &str {
+        &self.sink.source()[range.clone()]
+    }
+
+    // entry_spans/item_spans of a slice of the file, in file offsets
+    fn entries(&mut self, range: &Range<usize>) -> Option<Vec<(Range<usize>, Range<usize>)>> {
+        match entry_spans(self.text(range)) {
+            Ok(spans) => {
+                let shift = |span: Range<usize>| span.start + range.start..span.end + range.start;
+                Some(spans.into_iter().map(|(key, value)| (shift(key), shift(value))).collect())
+            }
+            Err(e) => {
+                self.sink.push(range.start, e.to_string(), None);
+                None
+            }
+        }
+    }
+
+    fn items(&mut self, range: &Range<usize>) -> Option<Vec<Range<usize>>> {
+        match item_spans(self.text(range)) {
+            Ok(spans) => {
+                Some(spans.into_iter().map(|span| span.start + range.start..span.end + range.start).collect())
+            }
+            Err(e) => {
+                self.sink.push(range.start, e.to_string(), None);
+                None
+            }
+        }
+    }
+
+    fn string_at(&mut self, range: &Range<usize>, what: &str) -> Option<String> {
+        match ron::from_str::<String>(self.text(range)) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.sink.push(range.start, format!("{} must be a string", what), None);
+                None
+            }
+        }
+    }
+
+    fn validate_file(&mut self) {
+        let whole = 0..self.sink.source().len();
+        let Some(entries) = self.entries(&whole) else {
+            return;
+        };
+        let mut found_entities = false;
+        for (key, value) in entries {
+            let field = self.text(&key).to_string();
+            if field != "entities" {
+                let suggestion = did_you_mean(&field, ["entities"]);
+                self.sink.push(key.start, format!("unknown field `{}`", field), suggestion);
+                continue;
+            }
+            if found_entities {
+                self.sink.push(key.start, "duplicate field `entities`", None);
+                continue;
+            }
+            found_entities = true;
+            for entity in self.items(&value).unwrap_or_default() {
+                self.validate_entity(&entity);
+            }
+        }
+        if !found_entities {
+            self.sink.push(0, "missing field `entities`", None);
+        }
+    }
+
+    fn validate_entity(&mut self, range: &Range<usize>) {
+        if !self.text(range).starts_with('{') {
+            self.sink.push(range.start, "expected an entity: a map of component name to data", None);
+            return;
+        }
+        let Some(entries) = self.entries(range) else {
+            return;
+        };
+
+        let mut seen = HashSet::new();
+        let mut has_components = false;
+        let mut prefab: Option<(String, usize)> = None;
+        let mut overrides = Vec::new();
+        for (key, value) in entries {
+            let Some(name) = self.string_at(&key, "an entity key") else {
+                continue;
+            };
+            if !seen.insert(name.clone()) {
+                self.sink.push(key.start, format!("duplicate key `{}`", name), None);
+                continue;
+            }
+
+            match name.as_str() {
+                ID_KEY => {
+                    if let Some(id) = self.string_at(&value, "\"id\"") {
+                        if !self.ids.insert(id.clone()) {
+                            self.sink.push(value.start, format!("duplicate entity id `{}`", id), None);
+                        }
+                    }
+                }
+                PREFAB_KEY => {
+                    if has_components {
+                        self.sink.push(key.start, "\"prefab\" must come before the components", None);
+                    }
+                    if let Some(prefab_name) = self.string_at(&value, "\"prefab\"") {
+                        prefab = self.check_prefab_exists(prefab_name, value.start);
+                    }
+                }
+                CHILDREN_KEY => {
+                    has_components = true;
+                    for child in self.items(&value).unwrap_or_default() {
+                        self.validate_entity(&child);
+                    }
+                }
+                _ => {
+                    has_components = true;
+                    if seen.contains(PREFAB_KEY) {
+                        if self.is_known_component(&name, &key) {
+                            overrides.push(Override { name, key, value });
+                        }
+                    } else {
+                        self.validate_component(&name, &key, &value);
+                    }
+                }
+            }
+        }
+
+        if let Some((prefab_name, offset)) = prefab {
+            self.validate_prefab_instance(&prefab_name, offset, overrides);
+        }
+    }
+
+    // The prefab name back if it can be checked further
+    fn check_prefab_exists(&mut self, name: String, offset: usize) -> Option<(String, usize)> {
+        let Some(prefabs) = self.prefabs else {
+            self.sink.push(offset, "entity uses a prefab but no prefabs are loaded", None);
+            return None;
+        };
+        if !prefabs.contains(&name) {
+            let suggestion = did_you_mean(&name, prefabs.names());
+            self.sink.push(offset, format!("unknown prefab `{}`", name), suggestion);
+            return None;
+        }
+        Some((name, offset))
+    }
+
+    fn is_known_component(&mut self, name: &str, key: &Range<usize>) -> bool {
+        if name == RENDERABLE_NAME || self.registry.get(name).is_some() {
+            return true;
+        }
+        let suggestion = did_you_mean(name, self.registry.names().chain([RENDERABLE_NAME]));
+        self.sink.push(key.start, format!("unknown component `{}`", name), suggestion);
+        false
+    }
+
+    fn validate_component(&mut self, name: &str, key: &Range<usize>, value: &Range<usize>) {
+        if name == RENDERABLE_NAME {
+            match ron::from_str::<RenderableDescriptor>(self.text(value)) {
+                Ok(descriptor) => self.check_model(&descriptor.model, value.start),
+                Err(e) => self.sink.push_ron_error(value.start, e.span.start, e.code.to_string()),
+            }
+            return;
+        }
+        if !self.is_known_component(name, key) {
+            return;
+        }
+
+        let source = self.sink.source();
+        let text = &source[value.clone()];
+        let deserialize_fn = self.registry.get(name).unwrap();
+        let entity = self.scratch.spawn_entity_only();
+        let mut deserializer = match ron::de::Deserializer::from_str(text) {
+            Ok(deserializer) => deserializer,
+            Err(e) => {
+                self.sink.push_ron_error(value.start, e.span.start, e.code.to_string());
+                return;
+            }
+        };
+        let result = {
+            let mut erased = <dyn erased_serde::Deserializer>::erase(&mut deserializer);
+            deserialize_fn(&mut self.scratch, entity, &mut erased)
+        };
+        let Err(e) = result else {
+            return;
+        };
+
+        let message = e.to_string();
+        // serde skips fields it doesn't know, so a misspelt field shows up as a missing one
+        if let Some((offset, typo, field)) = misspelt_field(&message, text) {
+            self.sink.push(
+                value.start + offset,
+                format!("unknown field `{}` in {}", typo, name),
+                Some(field)
+            );
+            return;
+        }
+        let span = deserializer.span_error(ron::Error::Message(message.clone())).span;
+        self.sink.push_ron_error(value.start, span.start, message);
+    }
+
+    fn check_model(&mut self, model: &str, offset: usize) {
+        if self.asset_server.get_model_handle(model).is_none() {
+            let suggestion = did_you_mean(model, self.asset_server.model_names());
+            self.sink.push(offset, format!("unknown model `{}`", model), suggestion);
+        }
+    }
+
+    // Overrides can only be checked once merged, so problems are reported at the override's key
+    // (or the "prefab" value, for ones that come from the prefab itself)
+    fn validate_prefab_instance(&mut self, prefab_name: &str, offset: usize, overrides: Vec<Override>) {
+        let prefabs = self.prefabs.unwrap();
+        let base_node = EntityNode { prefab: Some(prefab_name.to_string()), ..Default::default() };
+        let base = match prefabs.resolve(base_node.clone()) {
+            Ok(base) => base,
+            Err(e) => {
+                self.sink.push(offset, format!("{:#}", e), None);
+                return;
+            }
+        };
+
+        let mut node = base_node;
+        for Override { name, key, value } in &overrides {
+            let patch = match RonNode::parse(self.text(value)) {
+                Ok(patch) => patch,
+                Err(e) => {
+                    self.sink.push(value.start, e.to_string(), None);
+                    continue;
+                }
+            };
+            // A field the prefab doesn't have would be silently ignored
+            if let Some((_, base_value)) = base.components.iter().find(|(existing, _)| existing == name) {
+                for (field, suggestion) in unknown_fields(base_value, &patch) {
+                    self.sink.push(key.start, format!("unknown field `{}` in {}", field, name), suggestion);
+                }
+            }
+            node.components.push((name.clone(), patch));
+        }
+
+        let resolved = match prefabs.resolve(node) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                self.sink.push(offset, format!("{:#}", e), None);
+                return;
+            }
+        };
+        let asset_server = self.asset_server;
+        let entity = self.scratch.spawn_entity_only();
+        for (name, value) in &resolved.components {
+            let model = |model: &str| asset_server.get_model_handle(model);
+            let Err(e) = insert_component(&mut self.scratch, entity, name, value, self.registry, &model) else {
+                continue;
+            };
+            let at = overrides
+                .iter()
+                .find(|o| o.name == *name)
+                .map_or(offset, |o| o.key.start);
+            let message = format!("{:#} (in prefab `{}`)", e, prefab_name);
+            let suggestion = if name == RENDERABLE_NAME {
+                ron::from_str::<RenderableDescriptor>(&value.to_string())
+                    .ok()
+                    .and_then(|d| did_you_mean(&d.model, asset_server.model_names()))
+            } else {
+                None
+            };
+            self.sink.push(at, message, suggestion);
+        }
+    }
+}
+
+// For "missing field named `upper_limit`": a written field that's a near miss, as
+// (offset in `text`, written name, expected name)
+fn misspelt_field(message: &str, text: &str) -> Option<(usize, String, String)> {
+    if !message.contains("missing field") {
+        return None;
+    }
+    let missing = message.split('`').nth(1)?;
+    let mut offset = 0;
+    for token in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        let after = &text[offset + token.len()..];
+        let is_field = !token.is_empty() && after.trim_start().starts_with(':');
+        if is_field && did_you_mean(token, [missing]).is_some() {
+            return Some((offset, token.to_string(), missing.to_string()));
+        }
+        offset += token.len() + 1;
+    }
+    None
+}
+
+// Struct fields in `patch` that `base` doesn't have, with the closest one it does
+fn unknown_fields(base: &RonNode, patch: &RonNode) -> Vec<(String, Option<String>)> {
+    let (RonNode::Struct(_, base_fields), RonNode::Struct(_, patch_fields)) = (base, patch) else {
+        return Vec::new();
+    };
+    let mut unknown = Vec::new();
+    for (field, value) in patch_fields {
+        match base_fields.iter().find(|(existing, _)| existing == field) {
+            Some((_, base_value)) => unknown.extend(unknown_fields(base_value, value)),
+            None => {
+                let suggestion = did_you_mean(field, base_fields.iter().map(|(name, _)| name.as_str()));
+                unknown.push((field.clone(), suggestion));
+            }
+        }
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::game::components::hover_state::HoverState;
+
+    fn registry() -> ComponentRegistry {
+        let mut registry = ComponentRegistry::new();
+        registry.register::<HoverState>("HoverState");
+        registry
+    }
+
+    fn validate(ron_str: &str) -> DiagnosticReport {
+        let mut asset_server = AssetServer::new();
+        let _starfighter = asset_server.register_model_name("starfighter");
+        let mut prefabs = Prefabs::new(Arc::new(registry()));
+        prefabs.add("fighter", r#"{ "Velocity": (x: 0.0, y: 0.0, z: 0.0) }"#).unwrap();
+        validate_world(ron_str, &registry(), &asset_server, Some(&prefabs))
+    }
+
+    #[test]
+    fn valid_file_has_no_diagnostics() {
+        let report = validate(
+            r#"( entities: [ { "id": "a", "Renderable": (model: "starfighter"), "children": [ { "prefab": "fighter" } ] } ] )"#
+        );
+        assert!(report.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn collects_every_problem_with_positions_and_suggestions() {
+        let report = validate(
+            r#"(
+    entities: [
+        { "Transfrom": (), "Renderable": (model: "starfigher") },
+        { "HoverState": (direction: Dwn, upper_limit: 1.0, lower_limit: 0.0) },
+        { "Velocity": (x: "fast", y: 0.0, z: 0.0), "Velocity": (x: 0.0, y: 0.0, z: 0.0) },
+        { "HoverState": (direction: Up, uper_limit: 1.0, lower_limit: 0.0) },
+        { "prefab": "fihgter", "id": "a" },
+        { "id": "a" },
+    ]
+)"#
+        );
+        let found: Vec<(usize, usize, Option<&str>)> = report.diagnostics
+            .iter()
+            .map(|d| (d.line, d.column, d.suggestion.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (3, 11, Some("Transform")),
+                (3, 42, Some("starfighter")),
+                (4, 37, Some("Down")),
+                (5, 26, None),
+                (5, 52, None),
+                (6, 41, Some("upper_limit")),
+                (7, 21, Some("fighter")),
+                (8, 17, None)
+            ],
+            "{}",
+            report
+        );
+    }
+
+    #[test]
+    fn prefab_overrides_are_checked_after_merging() {
+        let report = validate(
+            r#"( entities: [ { "prefab": "fighter", "Velocity": (x: true, w: 1.0) } ] )"#
+        );
+        let messages: Vec<String> = report.diagnostics.iter().map(|d| d.to_string()).collect();
+        assert_eq!(messages.len(), 2, "{}", report);
+        assert!(messages[0].contains("unknown field `w` in Velocity"));
+        assert!(messages[1].contains("in prefab `fighter`"));
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_alone() {
+        let report = validate("( entities: [ { \"Velocity\": (x: 1.0 } ] )");
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.diagnostics[0].line, 1);
+    }
+}
@@ -2,9 +2,11 @@ use crate::engine::{
     assets::server::AssetServer,
     ecs::{
         component_registry::ComponentRegistry,
+        entity_ref::EntityNames,
         system::{ SystemContext, SystemSchedule },
         world::World,
     },
+    render_graph::graph::RenderGraph,
     state::context::GpuContext,
     ui::ui_registry::UIRegistry,
 };
@@ -14,11 +16,16 @@ pub trait GameSetup {
 
     /// Sets up the game's systems schedule for the ecs
     /// Systems here run after engine systems and before the rendering systems
+    /// Game states (`init_state`, `add_on_enter`/`add_on_exit`) are declared here too
     fn setup_ecs(&self, _schedule: &mut SystemSchedule) {}
 
     /// Any initial UI setup
     fn setup_ui(&self, _ui_registry: &mut UIRegistry) {}
 
+    /// Add render nodes to the engine's (see render_graph/nodes.rs). They're ordered by the
+    /// resources they read and write; the UI is always drawn last.
+    fn setup_render_graph(&self, _graph: &mut RenderGraph, _gpu_context: &GpuContext) {}
+
     /// Register game specific components for RON deserialisation
     fn register_components(&self, _component_registry: &mut ComponentRegistry) {}
 
@@ -27,11 +34,28 @@ pub trait GameSetup {
         None
     }
 
+    /// Path of the file `world_ron` comes from, watched with `--features hot-reload`. Changes
+    /// are patched into the world scene, keeping entities by their `"id"`.
+    fn world_ron_path(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Compile-time RON content for prefabs, as (prefab name, RON). Loaded before `world_ron`,
+    /// so world entities can use them.
+    fn prefabs_ron(&self) -> Vec<(&'static str, &'static str)> {
+        Vec::new()
+    }
+
     /// Compile time RON content for input bindings
     fn bindings_ron(&self) -> Option<&'static str> {
         None
     }
 
+    /// Path of the file `bindings_ron` comes from, watched with `--features hot-reload`.
+    fn bindings_ron_path(&self) -> Option<&'static str> {
+        None
+    }
+
     fn load_assets(
         &self,
         _gpu_context: &GpuContext,
@@ -41,5 +65,11 @@ pub trait GameSetup {
 
     /// One-time hook for game-specific setup that doesn't fit elsewhere
     /// (loading game-specific GPU assets, setting up game resources).
-    fn setup(&self, _world: &mut World, _system_context: &mut SystemContext) {}
+    /// `entities` holds the world file's entities that have an `"id"`, by id.
+    fn setup(
+        &self,
+        _world: &mut World,
+        _system_context: &mut SystemContext,
+        _entities: &EntityNames
+    ) {}
 }
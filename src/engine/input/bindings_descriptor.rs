@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
 use std::hash::Hash;
 
 use serde::Deserialize;
 use serde::de::DeserializeOwned;
 
+use crate::engine::assets::diagnostics::{ did_you_mean, DiagnosticReport, DiagnosticSink };
+use crate::engine::ecs::ron_node::entry_spans;
 use crate::engine::input::input_state::Binding;
 
 #[derive(Deserialize)]
@@ -11,3 +13,89 @@ use crate::engine::input::input_state::Binding;
 pub struct BindingsDescriptor<A: Hash + Eq> {
     pub bindings: HashMap<A, Vec<Binding>>,
 }
+
+/// Checks a bindings file entry by entry, so every unknown action or key is reported with its
+/// line and column. An empty report means it will deserialize as a `BindingsDescriptor<A>`.
+pub fn validate_bindings<A: DeserializeOwned + Hash + Eq>(ron_str: &str) -> DiagnosticReport {
+    let mut sink = DiagnosticSink::new("bindings file", ron_str);
+    if let Err(e) = ron::from_str::<ron::Value>(ron_str) {
+        sink.push_ron_error(0, e.span.start, e.code.to_string());
+        return sink.finish();
+    }
+    let entries = match entry_spans(ron_str) {
+        Ok(entries) => entries,
+        Err(e) => {
+            sink.push(0, e.to_string(), None);
+            return sink.finish();
+        }
+    };
+
+    let mut found_bindings = false;
+    for (key, value) in entries {
+        let field = &ron_str[key.clone()];
+        if field != "bindings" {
+            sink.push(key.start, format!("unknown field `{}`", field), did_you_mean(field, ["bindings"]));
+            continue;
+        }
+        found_bindings = true;
+        let actions = match entry_spans(&ron_str[value.clone()]) {
+            Ok(actions) => actions,
+            Err(e) => {
+                sink.push(value.start, e.to_string(), None);
+                continue;
+            }
+        };
+
+        let mut seen = HashSet::new();
+        for (action, keys) in actions {
+            let action_start = value.start + action.start;
+            let keys_start = value.start + keys.start;
+            let action_text = &ron_str[action_start..value.start + action.end];
+            match ron::from_str::<A>(action_text) {
+                Ok(_) if !seen.insert(action_text) => {
+                    sink.push(action_start, format!("duplicate action `{}`", action_text), None);
+                }
+                Ok(_) => {}
+                Err(e) => sink.push_ron_error(action_start, e.span.start, e.code.to_string()),
+            }
+            if let Err(e) = ron::from_str::<Vec<Binding>>(&ron_str[keys_start..value.start + keys.end]) {
+                sink.push_ron_error(keys_start, e.span.start, e.code.to_string());
+            }
+        }
+    }
+    if !found_bindings {
+        sink.push(0, "missing field `bindings`", None);
+    }
+    sink.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize, Hash, PartialEq, Eq)]
+    enum Action {
+        Jump,
+        Fire,
+    }
+
+    #[test]
+    fn reports_unknown_actions_and_keys() {
+        let report = validate_bindings::<Action>(
+            "(\n    bindings: {\n        Jmp: [(key: Space)],\n        Fire: [(key: KeyZZ)],\n        Fire: [(key: KeyX)],\n    },\n)"
+        );
+        let found: Vec<(usize, usize, Option<&str>)> = report.diagnostics
+            .iter()
+            .map(|d| (d.line, d.column, d.suggestion.as_deref()))
+            .collect();
+        assert_eq!(found, vec![(3, 9, Some("Jump")), (4, 22, Some("KeyZ")), (5, 9, None)], "{}", report);
+    }
+
+    #[test]
+    fn game_bindings_are_valid() {
+        let report = validate_bindings::<crate::game::input::actions::Action>(
+            include_str!("../../../assets/bindings.ron")
+        );
+        assert!(report.is_empty(), "{}", report);
+    }
+}
@@ -1,12 +1,137 @@
+// Lights as the shader sees them. `light_sync_system` packs the light components into a
+// `LightsUniform` each frame.
+//
+// Bind group 1 of the mesh pipeline holds the lights and the shadow maps sampled with them.
+
+use crate::engine::shadow::ShadowMaps;
+
+// A uniform array rather than a storage buffer, since WebGL2 has no storage buffers. Its
+// size is fixed in the shader, so keep `MAX_LIGHTS` in sync with shader.wgsl. 16 lights is
+// 1KB, well inside WebGL2's 16KB minimum uniform block size.
+pub const MAX_LIGHTS: usize = 16;
+
+// `GpuLight::kind` - keep in sync with shader.wgsl
+pub const DIRECTIONAL_LIGHT: u32 = 0;
+pub const POINT_LIGHT: u32 = 1;
+pub const SPOT_LIGHT: u32 = 2;
+
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct LightUniform {
-    // The paddings here are because WGSL shader structs have
-    // to be powers of 2.
-    // f32 array of length 3 would be 12 bytes, and the padding
-    // brings it up to 16 (2^4).
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuLight {
+    // Each row is one 16 byte WGSL chunk
     pub position: [f32; 3],
-    pub _padding: u32,
+    pub kind: u32,
+    // Normalized, the way the light points. Unused by point lights
+    pub direction: [f32; 3],
+    // Distance the light fades out by. Unused by directional lights
+    pub range: f32,
+    // Already multiplied by the intensity
     pub color: [f32; 3],
-    pub __padding: u32,
+    // Cosines of the spot cone's half angles - full brightness inside `inner`, none past `outer`
+    pub inner_cos: f32,
+    pub outer_cos: f32,
+    // First shadow map layer (see shadow.rs), or -1 if it doesn't cast shadows. Directional
+    // lights use `SHADOW_CASCADES` layers from here
+    pub shadow_map: i32,
+    pub _padding: [f32; 2],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightsUniform {
+    pub ambient: [f32; 3],
+    // How many of `lights` are in use. With none, the shader draws everything flat (unlit)
+    pub count: u32,
+    pub lights: [GpuLight; MAX_LIGHTS],
+}
+
+impl LightsUniform {
+    pub fn empty() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+
+    pub fn active(&self) -> &[GpuLight] {
+        &self.lights[..self.count as usize]
+    }
+}
+
+/// Group 1 of the mesh pipelines: the lights, the shadow view-projections, the shadow maps and
+/// their comparison sampler, then whether the instances drawn receive shadows.
+pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let uniform = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(
+        &(wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Bind Group Layout"),
+            entries: &[
+                uniform(0),
+                uniform(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+                uniform(4),
+            ],
+        })
+    )
+}
+
+/// The lighting bind group for instances that receive shadows and the one for those that
+/// don't, in that order.
+pub fn create_bind_groups(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    light_buffer: &wgpu::Buffer,
+    shadow_maps: &ShadowMaps
+) -> [wgpu::BindGroup; 2] {
+    [true, false].map(|receives_shadows| {
+        device.create_bind_group(
+            &(wgpu::BindGroupDescriptor {
+                label: Some("Light Bind Group"),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: light_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: shadow_maps.uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&shadow_maps.view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: wgpu::BindingResource::Sampler(&shadow_maps.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: shadow_maps.receiver_buffer(receives_shadows).as_entire_binding(),
+                    },
+                ],
+            })
+        )
+    })
 }
@@ -1,11 +1,14 @@
 pub mod app;
 pub mod light;
+pub mod shadow;
+pub mod post_process;
 pub mod model;
 pub mod resources;
 pub mod state;
 pub mod instance;
 mod texture;
 mod render_pipeline;
+pub mod render_graph;
 mod draw;
 mod fps_counter;
 mod color;
@@ -0,0 +1,430 @@
+// glTF 2.0 (.gltf/.glb) import
+//
+// Loading happens in two steps. `parse_gltf` turns the file into plain vectors (a
+// `GltfDocument`) without touching the GPU, so it can run on any thread and in tests. That's
+// then turned into `ModelData`, either flattened into one model with the node transforms baked
+// in (what `AssetServer::load_model` gives you) or one model per glTF mesh for spawning the
+// node hierarchy as entities (`AssetServer::load_gltf`), ready for `Model::upload`.
+
+use std::collections::HashMap;
+
+use anyhow::{ anyhow, bail, Context, Result };
+use base64::Engine;
+use cgmath::{ InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4 };
+
+use crate::engine::{
+    assets::handle::Handle,
+    ecs::components::transform::Transform,
+    model::{ material::{ MapKind, Material }, mesh_data::{ MeshData, ModelData } },
+    texture::Texture,
+};
+
+pub struct GltfDocument {
+    pub meshes: Vec<GltfMesh>,
+    pub materials: Vec<GltfMaterial>,
+    // Encoded image files (PNG/JPEG), indexed by the `GltfMaterial` texture fields
+    pub images: Vec<Vec<u8>>,
+    pub nodes: Vec<GltfNode>,
+    // Top level nodes of the default scene
+    pub roots: Vec<usize>,
+}
+
+pub struct GltfMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+pub struct GltfPrimitive {
+    pub positions: Vec<[f32; 3]>,
+    // Empty if the file has none - `MeshData::new` generates them
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+}
+
+pub struct GltfMaterial {
+    pub name: Option<String>,
+    // Linear RGBA
+    pub base_color: [f32; 4],
+    // Indices into `GltfDocument::images`
+    pub base_color_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+}
+
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub transform: Transform,
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// Buffer and image URIs the file needs fetched alongside it (everything but embedded
+/// `data:` URIs), relative to the file. Pass them to `parse_gltf` keyed by the URI.
+pub fn external_uris(bytes: &[u8]) -> Result<Vec<String>> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+    let buffers = gltf.buffers().filter_map(|buffer| match buffer.source() {
+        gltf::buffer::Source::Uri(uri) => Some(uri),
+        gltf::buffer::Source::Bin => None,
+    });
+    let images = gltf.images().filter_map(|image| match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(uri),
+        gltf::image::Source::View { .. } => None,
+    });
+    Ok(
+        buffers
+            .chain(images)
+            .filter(|uri| !uri.starts_with("data:"))
+            .map(str::to_string)
+            .collect()
+    )
+}
+
+/// Reads a .gltf or .glb file. `resources` holds the files named by `external_uris`.
+pub fn parse_gltf(bytes: &[u8], resources: &HashMap<String, Vec<u8>>) -> Result<GltfDocument> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+
+    let read_uri = |uri: &str| -> Result<Vec<u8>> {
+        match uri.strip_prefix("data:") {
+            Some(data) => {
+                let (_, encoded) = data
+                    .split_once(";base64,")
+                    .ok_or_else(|| anyhow!("only base64 data URIs are supported"))?;
+                Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?)
+            }
+            None =>
+                resources
+                    .get(uri)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("missing resource {}", uri)),
+        }
+    };
+
+    let mut buffers = Vec::new();
+    for buffer in gltf.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin =>
+                gltf.blob.clone().ok_or_else(|| anyhow!("buffer {} has no BIN chunk", buffer.index()))?,
+            gltf::buffer::Source::Uri(uri) => read_uri(uri).with_context(|| format!("buffer {}", buffer.index()))?,
+        };
+        if data.len() < buffer.length() {
+            bail!("buffer {} is {} bytes, expected {}", buffer.index(), data.len(), buffer.length());
+        }
+        buffers.push(data);
+    }
+
+    let mut images = Vec::new();
+    for image in gltf.images() {
+        let data = match image.source() {
+            gltf::image::Source::View { view, .. } => {
+                let start = view.offset();
+                buffers[view.buffer().index()][start..start + view.length()].to_vec()
+            }
+            gltf::image::Source::Uri { uri, .. } => read_uri(uri).with_context(|| format!("image {}", image.index()))?,
+        };
+        images.push(data);
+    }
+
+    let mut meshes = Vec::new();
+    for mesh in gltf.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                bail!("mesh {}: only triangle primitives are supported, got {:?}", mesh.index(), primitive.mode());
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let positions: Vec<[f32; 3]> = reader
+                .read_positions()
+                .ok_or_else(|| anyhow!("mesh {} has a primitive without positions", mesh.index()))?
+                .collect();
+            let indices = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            primitives.push(GltfPrimitive {
+                normals: reader.read_normals().map(Iterator::collect).unwrap_or_default(),
+                tex_coords: reader
+                    .read_tex_coords(0)
+                    .map(|tex_coords| tex_coords.into_f32().collect())
+                    .unwrap_or_default(),
+                positions,
+                indices,
+                material: primitive.material().index(),
+            });
+        }
+        meshes.push(GltfMesh { name: mesh.name().map(str::to_string), primitives });
+    }
+
+    let materials = gltf
+        .materials()
+        .map(|material| {
+            let pbr = material.pbr_metallic_roughness();
+            GltfMaterial {
+                name: material.name().map(str::to_string),
+                base_color: pbr.base_color_factor(),
+                base_color_texture: pbr.base_color_texture().map(|info| info.texture().source().index()),
+                normal_texture: material.normal_texture().map(|info| info.texture().source().index()),
+            }
+        })
+        .collect();
+
+    let nodes = gltf
+        .nodes()
+        .map(|node| {
+            let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+            GltfNode {
+                name: node.name().map(str::to_string),
+                transform: Transform {
+                    position: translation.into(),
+                    rotation: Quaternion::new(w, x, y, z),
+                    scale: scale.into(),
+                },
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node
+                    .children()
+                    .map(|child| child.index())
+                    .collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Without a scene, every node nobody lists as a child is a root
+    let roots = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None =>
+            (0..nodes.len())
+                .filter(|index| !nodes.iter().any(|node| node.children.contains(index)))
+                .collect(),
+    };
+
+    Ok(GltfDocument { meshes, materials, images, nodes, roots })
+}
+
+impl GltfDocument {
+    /// What image `index` is used as. Normal maps need uploading without the sRGB decode.
+    pub fn image_kind(&self, index: usize) -> MapKind {
+        if self.materials.iter().any(|material| material.normal_texture == Some(index)) {
+            MapKind::Normal
+        } else {
+            MapKind::Diffuse
+        }
+    }
+
+    /// Every node reachable from the roots with its model-space matrix, parents first.
+    pub fn world_matrices(&self) -> Vec<(usize, Matrix4<f32>)> {
+        let mut out = Vec::new();
+        let mut stack: Vec<(usize, Matrix4<f32>)> = self.roots
+            .iter()
+            .rev()
+            .map(|root| (*root, Matrix4::identity()))
+            .collect();
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let world = parent * local_matrix(&node.transform);
+            out.push((index, world));
+            for child in node.children.iter().rev() {
+                stack.push((*child, world));
+            }
+        }
+        out
+    }
+}
+
+// glTF applies scale, then rotation, then translation. Note that `Transform::to_raw` rotates
+// before scaling, so rotated nodes with non-uniform scale come out skewed when spawned as
+// entities - the flattened model is exact.
+fn local_matrix(transform: &Transform) -> Matrix4<f32> {
+    Matrix4::from_translation(transform.position) *
+        Matrix4::from(transform.rotation) *
+        Matrix4::from_nonuniform_scale(transform.scale.x, transform.scale.y, transform.scale.z)
+}
+
+// Positions and normals moved into model space by `matrix`
+fn bake(primitive: &GltfPrimitive, matrix: Matrix4<f32>) -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+    let positions = primitive.positions
+        .iter()
+        .map(|p| (matrix * Vector4::new(p[0], p[1], p[2], 1.0)).truncate().into())
+        .collect();
+    let linear = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+    let normal_matrix = linear.invert().unwrap_or(linear).transpose();
+    let normals = primitive.normals
+        .iter()
+        .map(|n| (normal_matrix * Vector3::from(*n)).normalize().into())
+        .collect();
+    (positions, normals)
+}
+
+// The factor is linear but `Material` colours are sRGB bytes
+fn material(
+    document: &GltfDocument,
+    index: Option<usize>,
+    textures: &[Option<Handle<Texture>>]
+) -> Material {
+    let Some(gltf_material) = index.and_then(|index| document.materials.get(index)) else {
+        return Material::new([255, 255, 255], 1.0);
+    };
+    let [r, g, b, a] = gltf_material.base_color;
+    let to_srgb = |c: f32| (c.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u32;
+    Material {
+        diffuse_color: [to_srgb(r), to_srgb(g), to_srgb(b)],
+        alpha: a,
+        diffuse_texture: gltf_material.base_color_texture
+            .and_then(|image| textures.get(image).cloned().flatten()),
+        normal_texture: gltf_material.normal_texture
+            .and_then(|image| textures.get(image).cloned().flatten()),
+    }
+}
+
+/// The whole default scene as one model, with every node's transform baked into its
+/// vertices. `textures` are the document's images uploaded in order (None where one didn't
+/// decode).
+pub fn flatten_gltf(document: &GltfDocument, textures: &[Option<Handle<Texture>>]) -> Result<ModelData> {
+    let mut meshes = Vec::new();
+    for (node_index, matrix) in document.world_matrices() {
+        let node = &document.nodes[node_index];
+        let Some(mesh_index) = node.mesh else {
+            continue;
+        };
+        let gltf_mesh = &document.meshes[mesh_index];
+        let label = node.name.as_deref().or(gltf_mesh.name.as_deref()).unwrap_or("gltf mesh");
+        for primitive in &gltf_mesh.primitives {
+            let (positions, normals) = bake(primitive, matrix);
+            meshes.push(
+                MeshData::new(
+                    label,
+                    positions,
+                    normals,
+                    primitive.tex_coords.clone(),
+                    primitive.indices.clone(),
+                    material(document, primitive.material, textures)
+                )
+            );
+        }
+    }
+    if meshes.is_empty() {
+        bail!("the scene has no meshes");
+    }
+
+    Ok(ModelData::new(meshes))
+}
+
+/// One model per glTF mesh, in the mesh's own space, for `GltfScene`.
+pub fn gltf_mesh_models(document: &GltfDocument, textures: &[Option<Handle<Texture>>]) -> Vec<ModelData> {
+    document.meshes
+        .iter()
+        .map(|gltf_mesh| {
+            let label = gltf_mesh.name.as_deref().unwrap_or("gltf mesh");
+            let meshes = gltf_mesh.primitives
+                .iter()
+                .map(|primitive| {
+                    MeshData::new(
+                        label,
+                        primitive.positions.clone(),
+                        primitive.normals.clone(),
+                        primitive.tex_coords.clone(),
+                        primitive.indices.clone(),
+                        material(document, primitive.material, textures)
+                    )
+                })
+                .collect();
+            ModelData::new(meshes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{ assert_relative_eq, Point3, Transform as _ };
+
+    use super::*;
+
+    const TWO_NODES: &[u8] = include_bytes!("fixtures/two_nodes.gltf");
+    const TRIANGLE: &[u8] = include_bytes!("fixtures/triangle.glb");
+
+    #[test]
+    fn embedded_gltf_parses_meshes_materials_and_nodes() {
+        let document = parse_gltf(TWO_NODES, &HashMap::new()).unwrap();
+
+        assert_eq!(document.meshes.len(), 2);
+        let body = &document.meshes[0].primitives[0];
+        assert_eq!(body.positions, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]);
+        assert_eq!(body.normals, vec![[0.0, 0.0, 1.0]; 3]);
+        assert_eq!(body.tex_coords.len(), 3);
+        assert_eq!(body.indices, vec![0, 1, 2]);
+        assert_eq!(body.material, Some(0));
+        // The wing only has positions
+        let wing = &document.meshes[1].primitives[0];
+        assert!(wing.normals.is_empty() && wing.tex_coords.is_empty());
+
+        let red = &document.materials[0];
+        assert_eq!(red.name.as_deref(), Some("red"));
+        assert_eq!(red.base_color, [1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(red.base_color_texture, Some(0));
+        assert!(document.images[0].starts_with(b"\x89PNG"));
+
+        assert_eq!(document.roots, vec![0]);
+        let wing_node = &document.nodes[1];
+        assert_eq!(wing_node.name.as_deref(), Some("Wing"));
+        assert_eq!(wing_node.transform.position, Vector3::new(1.0, 0.0, 0.0));
+        assert_eq!(wing_node.transform.scale, Vector3::new(2.0, 2.0, 2.0));
+        assert_eq!(document.nodes[0].children, vec![1]);
+    }
+
+    #[test]
+    fn glb_reads_its_binary_chunk() {
+        assert!(external_uris(TRIANGLE).unwrap().is_empty());
+        let document = parse_gltf(TRIANGLE, &HashMap::new()).unwrap();
+        assert_eq!(document.roots, vec![0]);
+        assert_eq!(document.nodes[0].name.as_deref(), Some("Triangle"));
+        let primitive = &document.meshes[0].primitives[0];
+        assert_eq!(primitive.positions.len(), 3);
+        assert_eq!(primitive.indices, vec![0, 1, 2]);
+        assert_eq!(primitive.material, None);
+    }
+
+    #[test]
+    fn child_matrices_include_their_parents() {
+        let document = parse_gltf(TWO_NODES, &HashMap::new()).unwrap();
+        let matrices = document.world_matrices();
+        assert_eq!(matrices.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![0, 1]);
+
+        // Body is moved up one; the wing is one along from that and doubled in size
+        let wing = matrices[1].1;
+        assert_relative_eq!(wing.transform_point(Point3::new(1.0, 0.0, 0.0)), Point3::new(3.0, 1.0, 0.0));
+
+        let (positions, normals) = bake(&document.meshes[0].primitives[0], matrices[0].1);
+        assert_eq!(positions[2], [0.0, 2.0, 0.0]);
+        assert_eq!(normals[0], [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn flattening_bakes_every_node_into_one_model() {
+        let document = parse_gltf(TWO_NODES, &HashMap::new()).unwrap();
+        let model = flatten_gltf(&document, &[None]).unwrap();
+        let labels = model.meshes.iter().map(|mesh| mesh.label.as_str()).collect::<Vec<_>>();
+        assert_eq!(labels, vec!["Body", "Wing"]);
+        // The wing's (1, 0, 0) lands at (3, 1, 0), and its missing normals are generated
+        assert_relative_eq!(model.bounds.max, Vector3::new(3.0, 3.0, 0.0));
+        assert_eq!(model.meshes[1].normals.len(), 3);
+        // Linear red at half alpha, no texture since the image didn't upload
+        let body = &model.meshes[0].material;
+        assert_eq!((body.diffuse_color, body.alpha), ([255, 0, 0], 0.5));
+        assert!(body.diffuse_texture.is_none());
+
+        let per_mesh = gltf_mesh_models(&document, &[None]);
+        assert_eq!(per_mesh.len(), 2);
+        assert_eq!(per_mesh[1].bounds.max, Vector3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn external_buffers_come_from_resources() {
+        let json = br#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "uri": "mesh.bin", "byteLength": 4 }]
+        }"#;
+        assert_eq!(external_uris(json).unwrap(), vec!["mesh.bin".to_string()]);
+        assert!(parse_gltf(json, &HashMap::new()).is_err());
+        let resources = HashMap::from([("mesh.bin".to_string(), vec![0; 4])]);
+        assert!(parse_gltf(json, &resources).unwrap().meshes.is_empty());
+    }
+}
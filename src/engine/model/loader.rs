@@ -1,10 +1,11 @@
-use std::io::{ BufReader, Cursor };
+use std::{ collections::HashMap, io::{ BufReader, Cursor } };
 
 use crate::engine::{
+    assets::{ asset_storage::Assets, handle::Handle },
     instance::Instance,
-    model::{ material::Material, model::{ Model, ModelBounds } },
-    resources::load_mesh_from_arrays,
+    model::{ material::{ MapKind, Material }, mesh_data::{ MeshData, ModelData }, model::Model },
     state::context::GpuContext,
+    texture::Texture,
 };
 
 pub fn load_model_from_obj_bytes(
@@ -13,7 +14,20 @@ pub fn load_model_from_obj_bytes(
     gpu_context: &GpuContext,
     initial_instances: Option<Vec<Instance>>,
     max_instances: usize
-) -> Model {
+) -> anyhow::Result<Model> {
+    // Nothing to resolve map names against, so in-memory models are untextured
+    let data = parse_obj(obj_bytes, mtl_bytes, &HashMap::new())?;
+    Ok(Model::upload(data, gpu_context, &Assets::new(), initial_instances, max_instances))
+}
+
+/// Reads an OBJ (and its material library, which may be empty) without touching the GPU.
+/// `textures` maps the file names in the library's `map_Kd`/`map_Bump` lines to the loaded
+/// maps; names missing from it are ignored.
+pub fn parse_obj(
+    obj_bytes: &[u8],
+    mtl_bytes: &[u8],
+    textures: &HashMap<String, Handle<Texture>>
+) -> anyhow::Result<ModelData> {
     let (raw_models, materials_result) = tobj
         ::load_obj_buf(
             &mut BufReader::new(Cursor::new(obj_bytes)),
@@ -23,71 +37,162 @@ pub fn load_model_from_obj_bytes(
                 ..Default::default()
             }),
             |_path| { tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mtl_bytes))) }
-        )
-        .expect("Failed to parse OBJ");
-
-    let mut all_vertices: Vec<[f32; 3]> = Vec::new();
+        )?;
 
     let meshes = raw_models
         .into_iter()
-        .map(|raw_model| {
-            let vertex_count = raw_model.mesh.positions.len() / 3;
-
-            let vertices: Vec<[f32; 3]> = (0..vertex_count)
-                .map(|i| {
-                    [
-                        raw_model.mesh.positions[i * 3],
-                        raw_model.mesh.positions[i * 3 + 1],
-                        raw_model.mesh.positions[i * 3 + 2],
-                    ]
-                })
-                .collect();
-
-            all_vertices.extend(vertices.iter().copied());
-
-            let normals: Vec<[f32; 3]> = if raw_model.mesh.normals.is_empty() {
-                vec![]
-            } else {
-                (0..vertex_count)
-                    .map(|i| {
-                        [
-                            raw_model.mesh.normals[i * 3],
-                            raw_model.mesh.normals[i * 3 + 1],
-                            raw_model.mesh.normals[i * 3 + 2],
-                        ]
-                    })
-                    .collect()
-            };
-
-            let material = match (&materials_result, raw_model.mesh.material_id) {
-                (Ok(materials), Some(material_id)) if material_id < materials.len() => {
-                    let diffuse = materials[material_id].diffuse;
-                    Material {
-                        diffuse_color: [
-                            (diffuse[0] * 255.0).round() as u32,
-                            (diffuse[1] * 255.0).round() as u32,
-                            (diffuse[2] * 255.0).round() as u32,
-                        ],
-                        alpha: materials[material_id].dissolve,
-                    }
-                }
-                _ => Material::new([255, 255, 255], 1.0),
-            };
-
-            load_mesh_from_arrays(
-                &raw_model.name,
-                vertices,
-                normals,
-                raw_model.mesh.indices.clone(),
-                gpu_context,
-                material,
-                initial_instances.clone(),
-                max_instances
-            )
+        .map(|raw_model| mesh_data_from_tobj(raw_model, &materials_result, textures))
+        .collect();
+
+    Ok(ModelData::new(meshes))
+}
+
+/// The diffuse and normal map file names a material library refers to, relative to it.
+pub fn mtl_texture_maps(mtl_bytes: &[u8]) -> Vec<(String, MapKind)> {
+    let Ok((materials, _)) = tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mtl_bytes))) else {
+        return vec![];
+    };
+    let mut maps = vec![];
+    for material in materials {
+        for (map, kind) in [(material.diffuse_texture, MapKind::Diffuse), (material.normal_texture, MapKind::Normal)] {
+            if !map.is_empty() && !maps.iter().any(|(name, _)| *name == map) {
+                maps.push((map, kind));
+            }
+        }
+    }
+    maps
+}
+
+pub(crate) fn mesh_data_from_tobj(
+    raw_model: tobj::Model,
+    materials_result: &Result<Vec<tobj::Material>, tobj::LoadError>,
+    textures: &HashMap<String, Handle<Texture>>
+) -> MeshData {
+    let vertex_count = raw_model.mesh.positions.len() / 3;
+
+    let vertices: Vec<[f32; 3]> = (0..vertex_count)
+        .map(|i| {
+            [
+                raw_model.mesh.positions[i * 3],
+                raw_model.mesh.positions[i * 3 + 1],
+                raw_model.mesh.positions[i * 3 + 2],
+            ]
         })
         .collect();
 
-    let bounds = ModelBounds::from_vertices(all_vertices);
+    let normals: Vec<[f32; 3]> = if raw_model.mesh.normals.is_empty() {
+        vec![]
+    } else {
+        (0..vertex_count)
+            .map(|i| {
+                [
+                    raw_model.mesh.normals[i * 3],
+                    raw_model.mesh.normals[i * 3 + 1],
+                    raw_model.mesh.normals[i * 3 + 2],
+                ]
+            })
+            .collect()
+    };
+
+    // OBJ's v axis points up, wgpu's down
+    let tex_coords: Vec<[f32; 2]> = if raw_model.mesh.texcoords.is_empty() {
+        vec![]
+    } else {
+        (0..vertex_count)
+            .map(|i| {
+                [raw_model.mesh.texcoords[i * 2], 1.0 - raw_model.mesh.texcoords[i * 2 + 1]]
+            })
+            .collect()
+    };
+
+    let material = match (materials_result, raw_model.mesh.material_id) {
+        (Ok(materials), Some(material_id)) if material_id < materials.len() => {
+            let raw_material = &materials[material_id];
+            let diffuse = raw_material.diffuse;
+            // tobj leaves the name empty when there's no map
+            let map = |name: &String| textures.get(name).cloned();
+            Material {
+                diffuse_color: [
+                    (diffuse[0] * 255.0).round() as u32,
+                    (diffuse[1] * 255.0).round() as u32,
+                    (diffuse[2] * 255.0).round() as u32,
+                ],
+                alpha: raw_material.dissolve,
+                diffuse_texture: map(&raw_material.diffuse_texture),
+                normal_texture: map(&raw_material.normal_texture),
+            }
+        }
+        _ => Material::new([255, 255, 255], 1.0),
+    };
+
+    MeshData::new(&raw_model.name, vertices, normals, tex_coords, raw_model.mesh.indices, material)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD_OBJ: &[u8] = b"mtllib quad.mtl
+o Quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+usemtl Green
+f 1/1 2/2 3/3 4/4
+";
+    const QUAD_MTL: &[u8] = b"newmtl Green
+Kd 0 1 0
+d 0.5
+";
+
+    #[test]
+    fn obj_parses_without_a_gpu() {
+        let model = parse_obj(QUAD_OBJ, QUAD_MTL, &HashMap::new()).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let quad = &model.meshes[0];
+        assert_eq!(quad.label, "Quad");
+        // Triangulated into two
+        assert_eq!(quad.indices.len(), 6);
+        assert_eq!(quad.positions.len(), 4);
+        // No normals in the file, so they're generated facing +z
+        assert!(quad.normals.iter().all(|normal| normal[2].abs() > 0.99));
+        // v flipped for wgpu
+        assert_eq!(quad.tex_coords[0], [0.0, 1.0]);
+        assert_eq!(quad.material.diffuse_color, [0, 255, 0]);
+        assert_eq!(quad.material.alpha, 0.5);
+        assert_eq!(model.bounds.max, cgmath::Vector3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn missing_material_library_falls_back_to_white() {
+        let model = parse_obj(QUAD_OBJ, b"", &HashMap::new()).unwrap();
+        assert_eq!(model.meshes[0].material.diffuse_color, [255, 255, 255]);
+    }
+
+    #[test]
+    fn material_maps_resolve_by_name() {
+        let mtl = b"newmtl Green
+Kd 0 1 0
+map_Kd green.png
+map_Bump green-normal.png
+";
+        assert_eq!(
+            mtl_texture_maps(mtl),
+            vec![("green.png".to_string(), MapKind::Diffuse), ("green-normal.png".to_string(), MapKind::Normal)]
+        );
 
-    Model { meshes, bounds }
+        let mut textures = Assets::<Texture>::new();
+        let diffuse = textures.reserve();
+        let maps = HashMap::from([("green.png".to_string(), diffuse.clone())]);
+        let model = parse_obj(QUAD_OBJ, mtl, &maps).unwrap();
+        let material = &model.meshes[0].material;
+        assert_eq!(material.diffuse_texture.as_ref().map(Handle::id), Some(diffuse.id()));
+        // Not fetched, so left out
+        assert!(material.normal_texture.is_none());
+    }
 }
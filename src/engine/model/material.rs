@@ -1,7 +1,30 @@
+use wgpu::util::DeviceExt;
+
+use crate::engine::{
+    assets::{ asset_storage::Assets, handle::Handle },
+    state::context::GpuContext,
+    texture::Texture,
+};
+
+// Set in `MaterialUniform::flags` - keep in sync with shader.wgsl
+const HAS_DIFFUSE_MAP: u32 = 1;
+const HAS_NORMAL_MAP: u32 = 2;
+
 #[derive(Debug)]
 pub struct Material {
     pub diffuse_color: [u32; 3],
     pub alpha: f32,
+    // Multiplied with `diffuse_color`
+    pub diffuse_texture: Option<Handle<Texture>>,
+    // Tangent space, loaded linear (`Texture::normal_map_from_bytes`)
+    pub normal_texture: Option<Handle<Texture>>,
+}
+
+/// What a texture is used for - normal maps need loading without the sRGB decode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapKind {
+    Diffuse,
+    Normal,
 }
 
 impl Material {
@@ -9,6 +32,197 @@ impl Material {
         Material {
             diffuse_color: color,
             alpha,
+            diffuse_texture: None,
+            normal_texture: None,
         }
     }
+
+    pub fn is_textured(&self) -> bool {
+        self.diffuse_texture.is_some() || self.normal_texture.is_some()
+    }
+
+    fn uniform(&self, textures: &Assets<Texture>) -> MaterialUniform {
+        // A map that hasn't loaded (or failed to) is left out
+        let has = |texture: &Option<Handle<Texture>>| {
+            texture.as_ref().is_some_and(|handle| textures.get(handle.id()).is_some())
+        };
+        let mut flags = 0;
+        if has(&self.diffuse_texture) {
+            flags |= HAS_DIFFUSE_MAP;
+        }
+        if has(&self.normal_texture) {
+            flags |= HAS_NORMAL_MAP;
+        }
+
+        // Convert from 0-255 sRGB to linear
+        MaterialUniform {
+            color: [
+                ((self.diffuse_color[0] as f32) / 255.0).powf(2.2),
+                ((self.diffuse_color[1] as f32) / 255.0).powf(2.2),
+                ((self.diffuse_color[2] as f32) / 255.0).powf(2.2),
+            ],
+            alpha: self.alpha,
+            flags,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+    // The paddings here are because WGSL uniform structs are laid out in 16 byte chunks.
+    // color + alpha fill the first, flags starts the second.
+    pub color: [f32; 3],
+    pub alpha: f32,
+    pub flags: u32,
+    _padding: [u32; 3],
+}
+
+/// Group 2 of the mesh pipelines: the material uniform, then the diffuse and normal maps, each
+/// followed by its sampler.
+pub fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    };
+    let sampler = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    };
+    device.create_bind_group_layout(
+        &(wgpu::BindGroupLayoutDescriptor {
+            label: Some("Material Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture(1),
+                sampler(2),
+                texture(3),
+                sampler(4),
+            ],
+        })
+    )
+}
+
+/// 1x1 textures bound in place of the maps a material doesn't have, so every material fits the
+/// one layout. Make one per upload and share it between the meshes.
+pub struct FallbackMaps {
+    diffuse: Texture,
+    normal: Texture,
+}
+
+impl FallbackMaps {
+    pub fn new(gpu_context: &GpuContext) -> Self {
+        let device = gpu_context.device;
+        let queue = gpu_context.queue;
+        Self {
+            diffuse: Texture::from_color(
+                device,
+                queue,
+                [255, 255, 255, 255],
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                "Fallback Diffuse Map"
+            ),
+            // Straight out of the surface
+            normal: Texture::from_color(
+                device,
+                queue,
+                [128, 128, 255, 255],
+                wgpu::TextureFormat::Rgba8Unorm,
+                "Fallback Normal Map"
+            ),
+        }
+    }
+}
+
+pub(crate) fn create_bind_group(
+    material: &Material,
+    textures: &Assets<Texture>,
+    fallback: &FallbackMaps,
+    gpu_context: &GpuContext
+) -> wgpu::BindGroup {
+    let device = gpu_context.device;
+    let uniform_buffer = device.create_buffer_init(
+        &(wgpu::util::BufferInitDescriptor {
+            label: Some("Material Buffer"),
+            contents: bytemuck::cast_slice(&[material.uniform(textures)]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })
+    );
+
+    let map = |texture: &Option<Handle<Texture>>, fallback| {
+        texture
+            .as_ref()
+            .and_then(|handle| textures.get(handle.id()))
+            .unwrap_or(fallback)
+    };
+    let diffuse = map(&material.diffuse_texture, &fallback.diffuse);
+    let normal = map(&material.normal_texture, &fallback.normal);
+
+    device.create_bind_group(
+        &(wgpu::BindGroupDescriptor {
+            label: Some("Material Bind Group"),
+            layout: &bind_group_layout(device),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&diffuse.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&diffuse.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&normal.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Sampler(&normal.sampler),
+                },
+            ],
+        })
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loaded_maps_set_their_flag() {
+        let mut textures = Assets::<Texture>::new();
+        let loading = textures.reserve();
+        let mut material = Material::new([255, 0, 0], 0.5);
+        assert_eq!(material.uniform(&textures).flags, 0);
+
+        material.diffuse_texture = Some(loading);
+        assert!(material.is_textured());
+        // Still loading, so it's drawn as if it had no map
+        let uniform = material.uniform(&textures);
+        assert_eq!(uniform.flags, 0);
+        assert_eq!(uniform.color, [1.0, 0.0, 0.0]);
+        assert_eq!(uniform.alpha, 0.5);
+    }
 }
@@ -0,0 +1,331 @@
+// Meshes on the CPU, before they're uploaded
+//
+// Everything that builds geometry (the OBJ and glTF loaders, terrain generation) produces
+// `MeshData`/`ModelData` first; `Mesh::upload`/`Model::upload` turn that into GPU buffers. None
+// of this needs a device, so it can be tested and used by tools without a GPU.
+
+use std::{ collections::HashSet, ops::{ Add, Sub } };
+
+use cgmath::{ vec3, InnerSpace, Vector3 };
+
+use crate::engine::model::{ material::Material, model::ModelBounds, vertex::ModelVertex };
+
+/// One mesh's geometry and material. Every vertex has a normal, UV, tangent and bitangent once
+/// built with `new`.
+#[derive(Debug)]
+pub struct MeshData {
+    pub label: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub tex_coords: Vec<[f32; 2]>,
+    pub tangents: Vec<[f32; 3]>,
+    pub bitangents: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+    pub material: Material,
+    pub bounds: ModelBounds,
+}
+
+impl MeshData {
+    /// Empty `normals` are generated from the triangles, and empty `tex_coords` filled with zeros.
+    pub fn new(
+        label: &str,
+        positions: Vec<[f32; 3]>,
+        normals: Vec<[f32; 3]>,
+        tex_coords: Vec<[f32; 2]>,
+        indices: Vec<u32>,
+        material: Material
+    ) -> Self {
+        let normals = if normals.is_empty() { calculate_normals(&positions, &indices) } else { normals };
+        let tex_coords = if tex_coords.is_empty() { vec![[0.0, 0.0]; positions.len()] } else { tex_coords };
+        let (tangents, bitangents) = calculate_tangents(&positions, &tex_coords, &indices);
+        let bounds = ModelBounds::from_vertices(positions.iter().copied());
+        Self {
+            label: label.to_string(),
+            positions,
+            normals,
+            tex_coords,
+            tangents,
+            bitangents,
+            indices,
+            material,
+            bounds,
+        }
+    }
+
+    /// Interleaved for the vertex buffer.
+    pub fn vertices(&self) -> Vec<ModelVertex> {
+        (0..self.positions.len())
+            .map(|i| ModelVertex {
+                position: self.positions[i],
+                tex_coords: self.tex_coords[i],
+                normal: self.normals[i],
+                tangent: self.tangents[i],
+                bitangent: self.bitangents[i],
+            })
+            .collect()
+    }
+
+    pub fn wireframe_indices(&self) -> Vec<u32> {
+        triangles_to_lines(&self.indices)
+    }
+}
+
+/// A model's meshes on the CPU. See `Model::upload`.
+#[derive(Debug)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub bounds: ModelBounds,
+}
+
+impl ModelData {
+    pub fn new(meshes: Vec<MeshData>) -> Self {
+        let bounds = ModelBounds::from_vertices(
+            meshes.iter().flat_map(|mesh| mesh.positions.iter().copied())
+        );
+        Self { meshes, bounds }
+    }
+}
+
+pub(crate) fn calculate_normals(
+    vertices: &[[f32; 3]],
+    triangle_indices: &[u32]
+) -> Vec<[f32; 3]> {
+    let triangle_count = triangle_indices.len() / 3;
+    let mut vertex_normals = vec![vec3(0.0, 0.0, 0.0); vertices.len()];
+
+    for i in 0..triangle_count {
+        let triangle_index = i * 3;
+
+        // Find out which vertices make our triangle
+        let vertex_index_a = triangle_indices[triangle_index] as usize;
+        let vertex_index_b = triangle_indices[triangle_index + 1] as usize;
+        let vertex_indec_c = triangle_indices[triangle_index + 2] as usize;
+
+        // Calculate the normal for the triangle
+        let triangle_normal = calculate_traingle_normals(
+            &vertices[vertex_index_a],
+            &vertices[vertex_index_b],
+            &vertices[vertex_indec_c]
+        );
+
+        // Add to already existing normals for each vertex
+        vertex_normals[vertex_index_a] = cgmath::Vector3::add(
+            vertex_normals[vertex_index_a],
+            triangle_normal
+        );
+        vertex_normals[vertex_index_b] = cgmath::Vector3::add(
+            vertex_normals[vertex_index_b],
+            triangle_normal
+        );
+        vertex_normals[vertex_indec_c] = cgmath::Vector3::add(
+            vertex_normals[vertex_indec_c],
+            triangle_normal
+        );
+    }
+
+    // Finally iterate through created normals, normalize the vectors, and convert to arrays
+    vertex_normals
+        .iter()
+        .map(|normal| { cgmath::Vector3::normalize(vec3(normal.x, normal.y, normal.z)).into() })
+        .collect::<Vec<_>>()
+}
+
+fn calculate_traingle_normals(a: &[f32; 3], b: &[f32; 3], c: &[f32; 3]) -> Vector3<f32> {
+    let ab = cgmath::Vector3::sub(vec3(a[0], a[1], a[2]), vec3(b[0], b[1], b[2]));
+    let ac = cgmath::Vector3::sub(vec3(a[0], a[1], a[2]), vec3(c[0], c[1], c[2]));
+    cgmath::Vector3::cross(ab, ac)
+}
+
+// Per vertex directions of +u and +v (as seen in the texture, so up is -v in wgpu's flipped
+// coordinates), averaged over the triangles sharing it. Zero where the UVs are degenerate, e.g.
+// untextured meshes - the shader only uses them with a normal map.
+pub(crate) fn calculate_tangents(
+    positions: &[[f32; 3]],
+    tex_coords: &[[f32; 2]],
+    indices: &[u32]
+) -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+    let mut tangents = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+    let mut bitangents = vec![Vector3::new(0.0, 0.0, 0.0); positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+        let delta_position_1 = Vector3::from(positions[b]) - Vector3::from(positions[a]);
+        let delta_position_2 = Vector3::from(positions[c]) - Vector3::from(positions[a]);
+        let delta_uv_1 = [tex_coords[b][0] - tex_coords[a][0], tex_coords[b][1] - tex_coords[a][1]];
+        let delta_uv_2 = [tex_coords[c][0] - tex_coords[a][0], tex_coords[c][1] - tex_coords[a][1]];
+
+        let determinant = delta_uv_1[0] * delta_uv_2[1] - delta_uv_1[1] * delta_uv_2[0];
+        if determinant.abs() < f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / determinant;
+        let tangent = (delta_position_1 * delta_uv_2[1] - delta_position_2 * delta_uv_1[1]) * r;
+        // Flipped, since v points down
+        let bitangent = (delta_position_2 * delta_uv_1[0] - delta_position_1 * delta_uv_2[0]) * -r;
+
+        for vertex in [a, b, c] {
+            tangents[vertex] += tangent;
+            bitangents[vertex] += bitangent;
+        }
+    }
+
+    let normalized = |vectors: Vec<Vector3<f32>>| {
+        vectors
+            .into_iter()
+            .map(|vector| if vector.magnitude2() > 0.0 { vector.normalize().into() } else { [0.0; 3] })
+            .collect()
+    };
+    (normalized(tangents), normalized(bitangents))
+}
+
+/// Converts triangle indices into unique edge pairs for wireframe rendering.
+pub fn triangles_to_lines(triangles: &[u32]) -> Vec<u32> {
+    let mut edges = HashSet::new();
+    let mut lines = vec![];
+
+    for tri in triangles.chunks_exact(3) {
+        let i0 = tri[0];
+        let i1 = tri[1];
+        let i2 = tri[2];
+
+        let edge_pairs = [
+            (i0, i1),
+            (i1, i2),
+            (i2, i0),
+        ];
+
+        for &(a, b) in &edge_pairs {
+            let edge = if a < b { (a, b) } else { (b, a) };
+            if edges.insert(edge) {
+                lines.push(edge.0);
+                lines.push(edge.1);
+            }
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // --- triangles_to_lines ---
+
+    #[test]
+    fn single_triangle_produces_three_edges() {
+        let lines = triangles_to_lines(&[0, 1, 2]);
+        assert_eq!(lines.len(), 6, "one triangle = 3 edges = 6 indices");
+    }
+
+    #[test]
+    fn shared_edge_is_not_duplicated() {
+        // Two triangles sharing edge (1,2): [0,1,2] and [2,1,3]
+        // Triangle 1 contributes edges: (0,1), (1,2), (0,2)
+        // Triangle 2 contributes edges: (1,2) [shared], (1,3), (2,3)
+        // Unique edges: (0,1), (1,2), (0,2), (1,3), (2,3) = 5 edges = 10 indices
+        let lines = triangles_to_lines(&[0, 1, 2, 2, 1, 3]);
+        assert_eq!(lines.len(), 10, "two triangles sharing one edge = 5 unique edges = 10 indices");
+    }
+
+    #[test]
+    fn empty_input_produces_no_lines() {
+        assert!(triangles_to_lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn all_edges_are_pairs() {
+        let lines = triangles_to_lines(&[0, 1, 2, 3, 4, 5]);
+        assert_eq!(lines.len() % 2, 0, "output must always have an even number of indices");
+    }
+
+    // --- calculate_normals ---
+
+    #[test]
+    fn flat_xz_triangle_has_vertical_normal() {
+        // Triangle flat on the XZ plane — normal should point along Y axis
+        let vertices = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]];
+        let indices = vec![0, 1, 2];
+        let normals = calculate_normals(&vertices, &indices);
+        assert_eq!(normals.len(), 3);
+        for n in &normals {
+            assert!(n[0].abs() < 1e-5, "X component should be ~0");
+            assert!(n[2].abs() < 1e-5, "Z component should be ~0");
+            assert!(n[1].abs() > 0.99, "Y component should be ~±1");
+        }
+    }
+
+    #[test]
+    fn normals_are_unit_length() {
+        let vertices = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.5, 1.0, 0.5]];
+        let indices = vec![0, 1, 2];
+        let normals = calculate_normals(&vertices, &indices);
+        for n in &normals {
+            let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            assert!((len - 1.0).abs() < 1e-5, "normal should be unit length, got {len}");
+        }
+    }
+
+    #[test]
+    fn normal_count_matches_vertex_count() {
+        let vertices = vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]];
+        let indices = vec![0, 1, 2, 1, 3, 2];
+        let normals = calculate_normals(&vertices, &indices);
+        assert_eq!(normals.len(), vertices.len());
+    }
+
+    // --- calculate_tangents ---
+
+    #[test]
+    fn tangents_follow_the_uv_axes() {
+        // u along +x, and texture-up (v decreasing) along +y
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let tex_coords = [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0]];
+        let (tangents, bitangents) = calculate_tangents(&positions, &tex_coords, &[0, 1, 2]);
+        assert_eq!(tangents, vec![[1.0, 0.0, 0.0]; 3]);
+        assert_eq!(bitangents, vec![[0.0, 1.0, 0.0]; 3]);
+    }
+
+    #[test]
+    fn untextured_meshes_get_zero_tangents() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+        let (tangents, _) = calculate_tangents(&positions, &[[0.0, 0.0]; 3], &[0, 1, 2]);
+        assert_eq!(tangents, vec![[0.0; 3]; 3]);
+    }
+
+    // --- MeshData ---
+
+    #[test]
+    fn missing_normals_and_uvs_are_filled_in() {
+        let mesh = MeshData::new(
+            "quad",
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+            vec![],
+            vec![],
+            vec![0, 2, 1],
+            Material::new([255, 255, 255], 1.0)
+        );
+        assert_eq!(mesh.normals.len(), 3);
+        assert_eq!(mesh.tex_coords, vec![[0.0, 0.0]; 3]);
+        assert_eq!(mesh.bounds.max, Vector3::new(1.0, 0.0, 1.0));
+        let vertices = mesh.vertices();
+        assert_eq!(vertices[1].position, [1.0, 0.0, 0.0]);
+        assert_eq!(vertices[1].normal, mesh.normals[1]);
+        assert_eq!(mesh.wireframe_indices().len(), 6);
+    }
+
+    #[test]
+    fn model_bounds_cover_every_mesh() {
+        let mesh = |label, position| MeshData::new(
+            label,
+            vec![position],
+            vec![[0.0, 1.0, 0.0]],
+            vec![],
+            vec![],
+            Material::new([255, 255, 255], 1.0)
+        );
+        let model = ModelData::new(vec![mesh("a", [-1.0, 0.0, 0.0]), mesh("b", [0.0, 2.0, 3.0])]);
+        assert_eq!(model.bounds.min, Vector3::new(-1.0, 0.0, 0.0));
+        assert_eq!(model.bounds.max, Vector3::new(0.0, 2.0, 3.0));
+    }
+}
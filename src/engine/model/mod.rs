@@ -1,6 +1,8 @@
 pub mod mesh;
+pub mod mesh_data;
 pub mod model;
 pub mod material;
 pub mod vertex;
 pub mod model_registry;
 pub mod loader;
+pub mod gltf_loader;
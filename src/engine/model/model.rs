@@ -2,9 +2,9 @@ use std::ops::Range;
 
 use cgmath::Vector3;
 
-use crate::engine::state::context::GpuContext;
+use crate::engine::{ assets::asset_storage::Assets, instance::Instance, state::context::GpuContext, texture::Texture };
 
-use super::mesh::Mesh;
+use super::{ material::FallbackMaps, mesh::Mesh, mesh_data::ModelData };
 
 #[derive(Clone, Copy, Debug)]
 pub struct ModelBounds {
@@ -48,6 +48,23 @@ pub struct Model {
 }
 
 impl Model {
+    /// Uploads every mesh in `data`, each with its own copy of `instances`. Material maps are
+    /// looked up in `textures` (the AssetServer's) - any not loaded yet are left out.
+    pub fn upload(
+        data: ModelData,
+        gpu_context: &GpuContext,
+        textures: &Assets<Texture>,
+        instances: Option<Vec<Instance>>,
+        max_instances: usize
+    ) -> Model {
+        let fallback = FallbackMaps::new(gpu_context);
+        let meshes = data.meshes
+            .into_iter()
+            .map(|mesh| Mesh::upload(mesh, gpu_context, textures, &fallback, instances.clone(), max_instances))
+            .collect();
+        Model { meshes, bounds: data.bounds }
+    }
+
     pub fn scale(&mut self, x: f32, y: f32, z: f32, gpu_context: &GpuContext) {
         for mesh in self.meshes.iter_mut() {
             mesh.scale(x, y, z, gpu_context);
@@ -72,11 +89,28 @@ impl Model {
         }
     }
 
+    pub fn gpu_bytes(&self) -> u64 {
+        self.meshes.iter().map(Mesh::gpu_bytes).sum()
+    }
+
+    /// See `Mesh::set_shadows`.
+    pub fn set_shadows(&mut self, receives: bool, casts: bool) {
+        for mesh in self.meshes.iter_mut() {
+            mesh.set_shadows(receives, casts);
+        }
+    }
+
     // Called by render_sync_system — applies the same instance transforms to all meshes.
     // All meshes in a model share transforms (e.g. fuselage and cockpit move together).
-    pub fn update_instances(&mut self, queue: &wgpu::Queue, instances: &[crate::engine::instance::InstanceRaw]) {
+    pub fn update_instances(
+        &mut self,
+        queue: &wgpu::Queue,
+        instances: &[crate::engine::instance::InstanceRaw],
+        shadow_receivers: Range<u32>,
+        shadow_casters: Range<u32>
+    ) {
         for mesh in self.meshes.iter_mut() {
-            mesh.update_instances(queue, instances);
+            mesh.update_instances(queue, instances, shadow_receivers.clone(), shadow_casters.clone());
         }
     }
 }
@@ -88,7 +122,7 @@ pub(in crate::engine) trait DrawModel<'a> {
         instances: Range<u32>,
         camera_bind_group: &'a wgpu::BindGroup,
         light_bind_group: &'a wgpu::BindGroup,
-        color_bind_group: &'a wgpu::BindGroup,
+        material_bind_group: &'a wgpu::BindGroup,
         use_line_index_buffer: bool
     );
 
@@ -98,7 +132,7 @@ pub(in crate::engine) trait DrawModel<'a> {
         model: &'a Model,
         camera_bind_group: &'a wgpu::BindGroup,
         light_bind_group: &'a wgpu::BindGroup,
-        color_bind_group: &'a wgpu::BindGroup
+        material_bind_group: &'a wgpu::BindGroup
     );
 
     fn draw_model_instanced(
@@ -107,7 +141,7 @@ pub(in crate::engine) trait DrawModel<'a> {
         instances: Range<u32>,
         camera_bind_group: &'a wgpu::BindGroup,
         light_bind_group: &'a wgpu::BindGroup,
-        color_bind_group: &'a wgpu::BindGroup,
+        material_bind_group: &'a wgpu::BindGroup,
         use_line_index_buffer: bool
     );
 }
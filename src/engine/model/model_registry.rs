@@ -1,39 +1,8 @@
-use crate::engine::model::model::Model;
+use crate::engine::{ assets::asset_storage::Assets, model::model::Model };
 
-pub struct ModelRegistry {
-    models: Vec<Model>,
-}
-
-impl ModelRegistry {
-    pub fn new() -> Self {
-        Self {
-            models: Vec::new(),
-        }
-    }
-
-    pub fn models(&self) -> &[Model] {
-        &self.models
-    }
-
-    // Does not suport remove/un-register for now
-    pub fn register(&mut self, model: Model) -> usize {
-        // Register the model, return the index as ID for ue in Renderable component
-        self.models.push(model);
-        self.models.len() - 1
-    }
-
-    pub fn get(&self, id: usize) -> Option<&Model> {
-        self.models.get(id)
-    }
-
-    pub fn get_mut(&mut self, id: usize) -> Option<&mut Model> {
-        self.models.get_mut(id)
-    }
-
-    pub fn len(&self) -> usize {
-        self.models.len()
-    }
-}
+// Models by AssetId - Renderable holds a strong handle into this. Slots can be reserved while a
+// model loads in the background, and are freed when the last handle drops.
+pub type ModelRegistry = Assets<Model>;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,390 @@
+// Post-processing: the scene is drawn into an HDR target, then taken to the surface by the
+// post-processing node (render_graph/nodes.rs), through the chain of effects in the
+// `PostProcessing` resource.
+//
+// Fullscreen passes with uniforms and filtered 2D textures only, so it all runs on WebGL2.
+// Rendering to a float target there needs EXT_color_buffer_float though - without it the
+// scene falls back to an 8 bit target, which still gets the effects but clips at 1.0.
+
+use crate::engine::{
+    ecs::resources::post_processing::{ Effect, Tonemapping },
+    render_pipeline::create_fullscreen_pipeline,
+};
+
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// sRGB, so dark colours keep their precision
+pub const FALLBACK_SCENE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+// Keep in sync with `render_graph::node::BLOOM_MIPS`
+pub const BLOOM_MIP_COUNT: usize = 6;
+
+/// How many enabled effects of the chain run - each gets a slot in the uniform buffer.
+pub const MAX_POST_EFFECTS: usize = 16;
+// Between those slots: wgpu's default `min_uniform_buffer_offset_alignment`
+pub const EFFECT_UNIFORM_STRIDE: u64 = 256;
+
+/// What the scene and the bloom mips are drawn in: `HDR_FORMAT` if the adapter can draw to,
+/// multisample, resolve, blend and filter it, otherwise `FALLBACK_SCENE_FORMAT`.
+pub fn scene_format(adapter: &wgpu::Adapter) -> wgpu::TextureFormat {
+    let features = adapter.get_texture_format_features(HDR_FORMAT);
+    let usages = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+    let flags =
+        wgpu::TextureFormatFeatureFlags::MULTISAMPLE_X4 |
+        wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE |
+        wgpu::TextureFormatFeatureFlags::BLENDABLE |
+        wgpu::TextureFormatFeatureFlags::FILTERABLE;
+    if features.allowed_usages.contains(usages) && features.flags.contains(flags) {
+        HDR_FORMAT
+    } else {
+        log::warn!("{:?} can't be rendered to, drawing the scene without HDR", HDR_FORMAT);
+        FALLBACK_SCENE_FORMAT
+    }
+}
+
+/// One chain entry's settings, laid out the way its entry point in post_process.wgsl reads
+/// them. The bloom entry's also serves bloom.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct EffectUniform {
+    pub params: [f32; 4],
+}
+
+impl EffectUniform {
+    // Out of range settings are clamped rather than trusted
+    pub fn new(effect: &Effect, grain_seed: f32) -> Self {
+        let params = match effect {
+            Effect::ChromaticAberration(strength) => [*strength, 0.0, 0.0, 0.0],
+            Effect::Bloom(bloom) => [bloom.filter_radius, bloom.intensity.clamp(0.0, 1.0), 0.0, 0.0],
+            Effect::Exposure(exposure) => [exposure.max(0.0), 0.0, 0.0, 0.0],
+            Effect::Tonemapping(_) => [0.0; 4],
+            Effect::Vignette(vignette) =>
+                [
+                    vignette.intensity.clamp(0.0, 1.0),
+                    vignette.radius,
+                    // smoothstep is undefined with equal edges
+                    vignette.smoothness.max(0.001),
+                    0.0,
+                ],
+            Effect::FilmGrain(intensity) => [intensity.max(0.0), grain_seed, 0.0, 0.0],
+        };
+        Self { params }
+    }
+}
+
+/// A pipeline per entry point of post_process.wgsl. All but `output` draw in the scene format.
+pub(crate) struct EffectPipelines {
+    pub(crate) chromatic_aberration: wgpu::RenderPipeline,
+    pub(crate) bloom: wgpu::RenderPipeline,
+    pub(crate) exposure: wgpu::RenderPipeline,
+    pub(crate) reinhard: wgpu::RenderPipeline,
+    pub(crate) aces: wgpu::RenderPipeline,
+    pub(crate) vignette: wgpu::RenderPipeline,
+    pub(crate) film_grain: wgpu::RenderPipeline,
+    // Into the surface, after the chain
+    pub(crate) output: wgpu::RenderPipeline,
+}
+
+impl EffectPipelines {
+    pub(crate) fn get(&self, effect: &Effect) -> &wgpu::RenderPipeline {
+        match effect {
+            Effect::ChromaticAberration(_) => &self.chromatic_aberration,
+            Effect::Bloom(_) => &self.bloom,
+            Effect::Exposure(_) => &self.exposure,
+            Effect::Tonemapping(Tonemapping::Reinhard) => &self.reinhard,
+            Effect::Tonemapping(Tonemapping::Aces) => &self.aces,
+            Effect::Vignette(_) => &self.vignette,
+            Effect::FilmGrain(_) => &self.film_grain,
+        }
+    }
+}
+
+/// The pipelines, sampler and uniform buffer for the chain's passes. The bind groups are made
+/// each frame, since the textures they sample are the render graph's and change with the
+/// surface size.
+pub struct PostProcess {
+    pub(crate) sampler: wgpu::Sampler,
+    // A slot of `EFFECT_UNIFORM_STRIDE` bytes per enabled chain entry, picked by dynamic offset
+    pub(crate) effect_buffer: wgpu::Buffer,
+    pub(crate) bloom_layout: wgpu::BindGroupLayout,
+    pub(crate) downsample_first_pipeline: wgpu::RenderPipeline,
+    pub(crate) downsample_pipeline: wgpu::RenderPipeline,
+    pub(crate) upsample_pipeline: wgpu::RenderPipeline,
+    pub(crate) effect_layout: wgpu::BindGroupLayout,
+    pub(crate) effect_pipelines: EffectPipelines,
+    // Kept so the pipelines can be rebuilt when their shaders are hot reloaded
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub(crate) bloom_pipeline_layout: wgpu::PipelineLayout,
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub(crate) effect_pipeline_layout: wgpu::PipelineLayout,
+}
+
+fn texture_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    }
+}
+
+fn sampler_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    }
+}
+
+// An `EffectUniform` slot of `effect_buffer`
+fn effect_uniform_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<EffectUniform>() as u64),
+        },
+        count: None,
+    }
+}
+
+/// The first downsample, the rest of the downsamples and the upsample, from bloom.wgsl.
+pub(crate) fn create_bloom_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    scene_format: wgpu::TextureFormat,
+    shader: &wgpu::ShaderModule
+) -> [wgpu::RenderPipeline; 3] {
+    let downsample_first = create_fullscreen_pipeline(device, layout, scene_format, None, shader, "fs_downsample_first");
+    let downsample = create_fullscreen_pipeline(device, layout, scene_format, None, shader, "fs_downsample");
+    // Added onto what the downsample left in the larger mip
+    let additive = wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    };
+    let upsample = create_fullscreen_pipeline(
+        device,
+        layout,
+        scene_format,
+        Some(wgpu::BlendState { color: additive, alpha: additive }),
+        shader,
+        "fs_upsample"
+    );
+    [downsample_first, downsample, upsample]
+}
+
+/// Every effect's pipeline from post_process.wgsl, and the output to the surface.
+pub(crate) fn create_effect_pipelines(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    scene_format: wgpu::TextureFormat,
+    surface_format: wgpu::TextureFormat,
+    shader: &wgpu::ShaderModule
+) -> EffectPipelines {
+    let effect = |entry_point| create_fullscreen_pipeline(device, layout, scene_format, None, shader, entry_point);
+    EffectPipelines {
+        chromatic_aberration: effect("fs_chromatic_aberration"),
+        bloom: effect("fs_bloom"),
+        exposure: effect("fs_exposure"),
+        reinhard: effect("fs_reinhard"),
+        aces: effect("fs_aces"),
+        vignette: effect("fs_vignette"),
+        film_grain: effect("fs_film_grain"),
+        output: create_fullscreen_pipeline(device, layout, surface_format, None, shader, "fs_output"),
+    }
+}
+
+impl PostProcess {
+    pub fn new(
+        device: &wgpu::Device,
+        scene_format: wgpu::TextureFormat,
+        surface_format: wgpu::TextureFormat
+    ) -> Self {
+        // Clamped, so the blur doesn't wrap around the screen's edges
+        let sampler = device.create_sampler(
+            &(wgpu::SamplerDescriptor {
+                label: Some("Post Process Sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            })
+        );
+
+        let effect_buffer = device.create_buffer(
+            &(wgpu::BufferDescriptor {
+                label: Some("Post Effect Buffer"),
+                size: (MAX_POST_EFFECTS as u64) * EFFECT_UNIFORM_STRIDE,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        );
+
+        // Bloom: the texture being filtered, its sampler, the bloom entry's settings
+        let bloom_layout = device.create_bind_group_layout(
+            &(wgpu::BindGroupLayoutDescriptor {
+                label: Some("Bloom Bind Group Layout"),
+                entries: &[texture_entry(0), sampler_entry(1), effect_uniform_entry(2)],
+            })
+        );
+        let bloom_pipeline_layout = device.create_pipeline_layout(
+            &(wgpu::PipelineLayoutDescriptor {
+                label: Some("Bloom Pipeline Layout"),
+                bind_group_layouts: &[&bloom_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let bloom_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../bloom.wgsl").into()),
+        });
+        let [downsample_first_pipeline, downsample_pipeline, upsample_pipeline] = create_bloom_pipelines(
+            device,
+            &bloom_pipeline_layout,
+            scene_format,
+            &bloom_shader
+        );
+
+        // Effects: the image so far, the finished bloom, their sampler, the entry's settings
+        let effect_layout = device.create_bind_group_layout(
+            &(wgpu::BindGroupLayoutDescriptor {
+                label: Some("Post Process Bind Group Layout"),
+                entries: &[texture_entry(0), texture_entry(1), sampler_entry(2), effect_uniform_entry(3)],
+            })
+        );
+        let effect_pipeline_layout = device.create_pipeline_layout(
+            &(wgpu::PipelineLayoutDescriptor {
+                label: Some("Post Process Pipeline Layout"),
+                bind_group_layouts: &[&effect_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        let effect_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Post Process Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../post_process.wgsl").into()),
+        });
+        let effect_pipelines = create_effect_pipelines(
+            device,
+            &effect_pipeline_layout,
+            scene_format,
+            surface_format,
+            &effect_shader
+        );
+
+        Self {
+            sampler,
+            effect_buffer,
+            bloom_layout,
+            downsample_first_pipeline,
+            downsample_pipeline,
+            upsample_pipeline,
+            effect_layout,
+            effect_pipelines,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            bloom_pipeline_layout,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            effect_pipeline_layout,
+        }
+    }
+
+    pub(crate) fn bloom_bind_group(&self, device: &wgpu::Device, source: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(
+            &(wgpu::BindGroupDescriptor {
+                label: Some("Bloom Bind Group"),
+                layout: &self.bloom_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self.effect_binding(),
+                    },
+                ],
+            })
+        )
+    }
+
+    pub(crate) fn effect_bind_group(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        bloom: &wgpu::TextureView
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(
+            &(wgpu::BindGroupDescriptor {
+                label: Some("Post Process Bind Group"),
+                layout: &self.effect_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(bloom),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::Sampler(&self.sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: self.effect_binding(),
+                    },
+                ],
+            })
+        )
+    }
+
+    // One slot - which one is the dynamic offset the pass is drawn with
+    fn effect_binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.effect_buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(std::mem::size_of::<EffectUniform>() as u64),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::ecs::resources::post_processing::{ Bloom, Vignette };
+
+    use super::*;
+
+    #[test]
+    fn uniform_matches_the_wgsl_layout() {
+        assert_eq!(std::mem::size_of::<EffectUniform>(), 16);
+        assert!(std::mem::size_of::<EffectUniform>() as u64 <= EFFECT_UNIFORM_STRIDE);
+    }
+
+    #[test]
+    fn uniform_clamps_out_of_range_settings() {
+        let bloom = EffectUniform::new(&Effect::Bloom(Bloom { intensity: 3.0, filter_radius: 0.005 }), 0.0);
+        assert_eq!(bloom.params[..2], [0.005, 1.0]);
+        assert_eq!(EffectUniform::new(&Effect::Exposure(-1.0), 0.0).params[0], 0.0);
+        let vignette = EffectUniform::new(
+            &Effect::Vignette(Vignette { intensity: -0.5, radius: 0.4, smoothness: 0.0 }),
+            0.0
+        );
+        assert_eq!(vignette.params[0], 0.0);
+        assert!(vignette.params[2] > 0.0);
+        assert_eq!(EffectUniform::new(&Effect::FilmGrain(-0.1), 7.0).params[..2], [0.0, 7.0]);
+        assert_eq!(EffectUniform::new(&Effect::ChromaticAberration(0.01), 0.0).params[0], 0.01);
+    }
+}
@@ -0,0 +1,402 @@
+// Ordering
+//
+// Nodes are ordered only by the resources they share:
+// - the nodes writing a resource run in the order they were added, and
+// - a node reading a resource without writing it runs after every node that writes it.
+// Nodes with nothing between them keep the order they were added in, like systems.
+//
+// So a node drawing into `scene_color` lands before `resolve` (which reads it) wherever it was
+// added, and one drawing over the surface lands between `post_process` and the UI, which the
+// engine adds last.
+
+use std::{ cmp::Reverse, collections::{ BinaryHeap, HashMap, HashSet } };
+
+use anyhow::anyhow;
+
+use crate::engine::{
+    ecs::world::World,
+    model::model::Model,
+    render_graph::{
+        node::{ NodeContext, RenderNode, SURFACE },
+        textures::{ TexturePool, TransientTexture },
+    },
+    state::context::{ EguiContext, RenderContext },
+};
+
+struct GraphNode {
+    node: Box<dyn RenderNode>,
+    enabled: bool,
+}
+
+pub struct RenderGraph {
+    nodes: Vec<GraphNode>,
+    textures: Vec<(&'static str, TransientTexture)>,
+    // Provided from outside the graph each frame, e.g. the surface
+    imports: Vec<&'static str>,
+    // Indices into `nodes`, in run order. Rebuilt by `build`
+    order: Vec<usize>,
+    dirty: bool,
+    pool: TexturePool,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            textures: Vec::new(),
+            imports: vec![SURFACE],
+            order: Vec::new(),
+            dirty: true,
+            pool: TexturePool::default(),
+        }
+    }
+
+    pub fn add_node(&mut self, node: impl RenderNode + 'static) {
+        self.nodes.push(GraphNode { node: Box::new(node), enabled: true });
+        self.dirty = true;
+    }
+
+    /// Declares a texture the graph creates at the surface's size, for nodes to use by `name`.
+    /// Adding one with the same name again replaces it.
+    pub fn add_texture(&mut self, name: &'static str, texture: TransientTexture) {
+        self.textures.retain(|(existing, _)| *existing != name);
+        self.textures.push((name, texture));
+        self.pool = TexturePool::default();
+        self.dirty = true;
+    }
+
+    /// Declares a resource that comes from outside the graph rather than from a node.
+    pub fn import(&mut self, name: &'static str) {
+        if !self.imports.contains(&name) {
+            self.imports.push(name);
+        }
+    }
+
+    /// Turns a node off (or back on) without changing the order. Returns false if there's no
+    /// node called `name`.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.nodes.iter_mut().find(|node| node.node.name() == name) {
+            Some(node) => {
+                node.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&TransientTexture> {
+        self.textures.iter().find(|(existing, _)| *existing == name).map(|(_, texture)| texture)
+    }
+
+    /// Orders the nodes. Called by `run` when nodes changed, but worth calling once after setup
+    /// so a bad graph fails at startup.
+    pub fn build(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let count = self.nodes.len();
+        let mut names = HashSet::new();
+        for node in &self.nodes {
+            if !names.insert(node.node.name()) {
+                return Err(anyhow!("two render graph nodes are called \"{}\"", node.node.name()));
+            }
+        }
+        self.warn_unknown_resources();
+
+        // successors[a] holds every b that must run after a
+        let mut successors: Vec<Vec<usize>> = vec![Vec::new(); count];
+        for resource in self.resources() {
+            let writers: Vec<usize> = (0..count)
+                .filter(|&i| self.nodes[i].node.writes().contains(&resource))
+                .collect();
+            for pair in writers.windows(2) {
+                successors[pair[0]].push(pair[1]);
+            }
+            let readers = (0..count).filter(|&i| {
+                self.nodes[i].node.reads().contains(&resource) && !writers.contains(&i)
+            });
+            for reader in readers {
+                for &writer in &writers {
+                    successors[writer].push(reader);
+                }
+            }
+        }
+        for next in successors.iter_mut() {
+            next.sort_unstable();
+            next.dedup();
+        }
+
+        // Kahn's algorithm, always taking the earliest-added ready node
+        let mut in_degree = vec![0; count];
+        for &j in successors.iter().flatten() {
+            in_degree[j] += 1;
+        }
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..count)
+            .filter(|&i| in_degree[i] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(count);
+        while let Some(Reverse(i)) = ready.pop() {
+            order.push(i);
+            for &j in &successors[i] {
+                in_degree[j] -= 1;
+                if in_degree[j] == 0 {
+                    ready.push(Reverse(j));
+                }
+            }
+        }
+        if order.len() < count {
+            let stuck = (0..count)
+                .filter(|&i| in_degree[i] > 0)
+                .map(|i| self.nodes[i].node.name())
+                .collect::<Vec<_>>();
+            return Err(anyhow!("render graph cycle between {}", stuck.join(", ")));
+        }
+
+        self.order = order;
+        self.dirty = false;
+        Ok(())
+    }
+
+    // Every resource any node names, in the order first named
+    fn resources(&self) -> Vec<&'static str> {
+        let mut resources = Vec::new();
+        for node in &self.nodes {
+            for &resource in node.node.reads().iter().chain(node.node.writes()) {
+                if !resources.contains(&resource) {
+                    resources.push(resource);
+                }
+            }
+        }
+        resources
+    }
+
+    // Reading something nothing provides is almost always a typo
+    fn warn_unknown_resources(&self) {
+        for node in &self.nodes {
+            for resource in node.node.reads() {
+                let provided =
+                    self.imports.contains(resource) ||
+                    self.texture(resource).is_some() ||
+                    self.nodes.iter().any(|other| other.node.writes().contains(resource));
+                if !provided {
+                    log::warn!("render node {} reads unknown resource \"{}\"", node.node.name(), resource);
+                }
+            }
+        }
+    }
+
+    /// Node names in run order. Only up to date after `build`.
+    pub fn order(&self) -> Vec<&'static str> {
+        self.order.iter().map(|&i| self.nodes[i].node.name()).collect()
+    }
+
+    /// Runs every enabled node, in order, into `encoder`.
+    ///
+    /// # Panics
+    /// If the graph needs rebuilding and has a cycle - see `build`.
+    pub(crate) fn run<'a>(
+        &mut self,
+        render: &'a RenderContext<'a>,
+        models: &'a [&'a Model],
+        world: &'a World,
+        encoder: &'a mut wgpu::CommandEncoder,
+        surface_view: &'a wgpu::TextureView,
+        egui: EguiContext<'a>
+    ) {
+        if let Err(e) = self.build() {
+            panic!("{}", e);
+        }
+        self.pool.prepare(render.device, &self.textures, render.surface_config.width, render.surface_config.height);
+
+        let mut views: HashMap<&'static str, &wgpu::TextureView> = self.pool.views().collect();
+        views.insert(SURFACE, surface_view);
+        let mut context = NodeContext { render, models, world, encoder, egui: Some(egui), views };
+        for &i in &self.order {
+            let node = &mut self.nodes[i];
+            if node.enabled {
+                node.node.run(&mut context);
+            }
+        }
+    }
+
+    /// The nodes in run order with what they read and write, then the transient textures. Only
+    /// up to date after `build`.
+    pub fn report(&self) -> String {
+        let list = |resources: &[&str]| resources.join(", ");
+        let mut report = String::from("render graph:\n");
+        for (position, &i) in self.order.iter().enumerate() {
+            let node = &self.nodes[i];
+            report.push_str(&format!("  {}: {}", position, node.node.name()));
+            if !node.enabled {
+                report.push_str(" (disabled)");
+            }
+            if !node.node.reads().is_empty() {
+                report.push_str(&format!(" reads [{}]", list(node.node.reads())));
+            }
+            if !node.node.writes().is_empty() {
+                report.push_str(&format!(" writes [{}]", list(node.node.writes())));
+            }
+            report.push('\n');
+        }
+        if !self.textures.is_empty() {
+            report.push_str("transient textures:\n");
+            for (name, texture) in &self.textures {
+                report.push_str(
+                    &format!(
+                        "  {}: {:?}, {} sample(s), {}x surface size\n",
+                        name,
+                        texture.format,
+                        texture.sample_count,
+                        texture.scale
+                    )
+                );
+            }
+        }
+        report
+    }
+
+    /// The graph in Graphviz DOT: nodes as numbered boxes in run order, resources as ellipses
+    /// (dashed unless the graph owns them), and an edge for every read and write. Only up to
+    /// date after `build`.
+    pub fn dot(&self) -> String {
+        let mut dot = String::from("digraph render_graph {\n    rankdir=LR;\n");
+        for (position, &i) in self.order.iter().enumerate() {
+            let node = &self.nodes[i];
+            let style = if node.enabled { "" } else { ", style=dashed" };
+            dot.push_str(
+                &format!(
+                    "    \"{}\" [shape=box, label=\"{}: {}\"{}];\n",
+                    node.node.name(),
+                    position,
+                    node.node.name(),
+                    style
+                )
+            );
+        }
+        for resource in self.resources() {
+            let style = if self.texture(resource).is_some() { "" } else { ", style=dashed" };
+            dot.push_str(&format!("    \"res:{}\" [shape=ellipse, label=\"{}\"{}];\n", resource, resource, style));
+        }
+        for &i in &self.order {
+            let node = &self.nodes[i].node;
+            for resource in node.reads() {
+                dot.push_str(&format!("    \"res:{}\" -> \"{}\";\n", resource, node.name()));
+            }
+            for resource in node.writes() {
+                dot.push_str(&format!("    \"{}\" -> \"res:{}\";\n", node.name(), resource));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestNode {
+        name: &'static str,
+        reads: Vec<&'static str>,
+        writes: Vec<&'static str>,
+    }
+
+    impl RenderNode for TestNode {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+        fn reads(&self) -> &[&'static str] {
+            &self.reads
+        }
+        fn writes(&self) -> &[&'static str] {
+            &self.writes
+        }
+        fn run(&mut self, _context: &mut NodeContext) {}
+    }
+
+    fn node(name: &'static str, reads: &[&'static str], writes: &[&'static str]) -> TestNode {
+        TestNode { name, reads: reads.to_vec(), writes: writes.to_vec() }
+    }
+
+    #[test]
+    fn readers_run_after_every_writer_wherever_they_were_added() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(node("resolve", &["color"], &["surface"]));
+        graph.add_node(node("clear", &[], &["color"]));
+        graph.add_node(node("opaque", &["shadows"], &["color"]));
+        graph.add_node(node("shadows", &[], &["shadows"]));
+        graph.build().unwrap();
+        assert_eq!(graph.order(), vec!["clear", "shadows", "opaque", "resolve"]);
+    }
+
+    #[test]
+    fn writers_and_unrelated_nodes_keep_the_order_they_were_added_in() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(node("a", &[], &["color"]));
+        graph.add_node(node("unrelated", &[], &[]));
+        graph.add_node(node("b", &[], &["color"]));
+        graph.add_node(node("c", &[], &["color"]));
+        graph.build().unwrap();
+        assert_eq!(graph.order(), vec!["a", "unrelated", "b", "c"]);
+    }
+
+    #[test]
+    fn cycles_are_reported_with_node_names() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(node("first", &["y"], &["x"]));
+        graph.add_node(node("second", &["x"], &["y"]));
+        graph.add_node(node("fine", &[], &[]));
+        let error = graph.build().unwrap_err().to_string();
+        assert!(error.contains("first") && error.contains("second"), "{}", error);
+        assert!(!error.contains("fine"), "{}", error);
+    }
+
+    #[test]
+    fn duplicate_names_are_an_error() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(node("opaque", &[], &[]));
+        graph.add_node(node("opaque", &[], &[]));
+        assert!(graph.build().is_err());
+    }
+
+    #[test]
+    fn adding_a_node_reorders_on_the_next_build() {
+        let mut graph = RenderGraph::new();
+        graph.add_node(node("resolve", &["color"], &["surface"]));
+        graph.build().unwrap();
+        graph.add_node(node("late_draw", &[], &["color"]));
+        graph.build().unwrap();
+        assert_eq!(graph.order(), vec!["late_draw", "resolve"]);
+    }
+
+    #[test]
+    fn dumps_show_order_accesses_and_disabled_nodes() {
+        let mut graph = RenderGraph::new();
+        graph.add_texture("color", TransientTexture::new(wgpu::TextureFormat::Rgba16Float).with_samples(4));
+        graph.add_node(node("draw", &[], &["color"]));
+        graph.add_node(node("resolve", &["color"], &["surface"]));
+        assert!(graph.set_enabled("draw", false));
+        assert!(!graph.set_enabled("missing", false));
+        graph.build().unwrap();
+
+        let report = graph.report();
+        assert!(report.contains("0: draw (disabled) writes [color]"), "{}", report);
+        assert!(report.contains("1: resolve reads [color] writes [surface]"), "{}", report);
+        assert!(report.contains("color: Rgba16Float, 4 sample(s)"), "{}", report);
+
+        let dot = graph.dot();
+        assert!(dot.starts_with("digraph render_graph {"), "{}", dot);
+        assert!(dot.contains("\"draw\" [shape=box, label=\"0: draw\", style=dashed];"), "{}", dot);
+        assert!(dot.contains("\"res:color\" -> \"resolve\";"), "{}", dot);
+        assert!(dot.contains("\"resolve\" -> \"res:surface\";"), "{}", dot);
+        // Not a graph owned texture
+        assert!(dot.contains("\"res:surface\" [shape=ellipse, label=\"surface\", style=dashed];"), "{}", dot);
+    }
+}
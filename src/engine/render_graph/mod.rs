@@ -0,0 +1,13 @@
+// Render graph
+//
+// A frame is a list of named nodes, each declaring the resources it reads and writes. The graph
+// orders them from those declarations, owns the transient textures they draw into (sized to the
+// surface), and runs them into one command encoder. See graph.rs for the ordering rules and
+// nodes.rs for the passes the engine adds.
+//
+// Games add their own nodes through `GameSetup::setup_render_graph`.
+
+pub mod graph;
+pub mod node;
+pub mod nodes;
+pub mod textures;
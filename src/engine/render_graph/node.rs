@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use crate::engine::{
+    ecs::world::World,
+    model::model::Model,
+    post_process::BLOOM_MIP_COUNT,
+    state::context::{ EguiContext, RenderContext },
+};
+
+// Resources the engine's nodes use. Games can read and write these from their own nodes too.
+
+/// The swapchain texture being drawn this frame.
+pub const SURFACE: &str = "surface";
+/// The multisampled colour target the scene is drawn into, resolved into `HDR` by `resolve`.
+pub const SCENE_COLOR: &str = "scene_color";
+/// The scene's multisampled depth buffer.
+pub const SCENE_DEPTH: &str = "scene_depth";
+/// The resolved scene, before post-processing. `post_process::HDR_FORMAT` where supported.
+pub const HDR: &str = "hdr";
+/// The bloom chain, each half the size of the last. The first holds the finished bloom.
+pub const BLOOM_MIPS: [&str; BLOOM_MIP_COUNT] = [
+    "bloom_mip0",
+    "bloom_mip1",
+    "bloom_mip2",
+    "bloom_mip3",
+    "bloom_mip4",
+    "bloom_mip5",
+];
+/// What the post-processing chain draws into, each effect reading the other's result.
+pub const POST_TARGETS: [&str; 2] = ["post_a", "post_b"];
+/// The shadow map array (`RenderContext::shadow_maps`) - not a texture the graph owns.
+pub const SHADOW_MAPS: &str = "shadow_maps";
+
+/// One step of a frame. `reads` and `writes` name the graph resources it uses, which is all
+/// the graph orders it by - see graph.rs.
+pub trait RenderNode {
+    fn name(&self) -> &'static str;
+
+    /// Resources whose contents this node uses. It runs after every node that writes them.
+    fn reads(&self) -> &[&'static str] {
+        &[]
+    }
+
+    /// Resources this node draws into or clears.
+    fn writes(&self) -> &[&'static str] {
+        &[]
+    }
+
+    fn run(&mut self, context: &mut NodeContext);
+}
+
+/// What a node gets to record its passes with.
+pub struct NodeContext<'a> {
+    pub render: &'a RenderContext<'a>,
+    pub models: &'a [&'a Model],
+    // Read only - e.g. settings resources like `PostProcessing`
+    pub world: &'a World,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    // Taken by the UI node
+    pub(crate) egui: Option<EguiContext<'a>>,
+    pub(crate) views: HashMap<&'static str, &'a wgpu::TextureView>,
+}
+
+impl<'a> NodeContext<'a> {
+    /// The view of a transient texture, or of the surface.
+    ///
+    /// # Panics
+    /// If `name` isn't one - the graph warns about undeclared resources when it's built.
+    pub fn view(&self, name: &str) -> &'a wgpu::TextureView {
+        self.views
+            .get(name)
+            .copied()
+            .unwrap_or_else(|| panic!("render graph has no texture \"{}\"", name))
+    }
+}
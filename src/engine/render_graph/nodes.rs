@@ -0,0 +1,469 @@
+// The engine's nodes, added by `RenderState` in this order:
+//
+//   shadows -> clear -> wireframes_behind -> opaque -> wireframes_on_top -> resolve
+//     -> post_process -> [game] -> ui
+
+use crate::engine::{
+    ecs::resources::post_processing::{ Effect, PostProcessing },
+    model::model::DrawModel,
+    post_process::{ EFFECT_UNIFORM_STRIDE, EffectUniform, MAX_POST_EFFECTS },
+    render_graph::node::{
+        BLOOM_MIPS,
+        HDR,
+        NodeContext,
+        POST_TARGETS,
+        RenderNode,
+        SCENE_COLOR,
+        SCENE_DEPTH,
+        SHADOW_MAPS,
+        SURFACE,
+    },
+};
+
+/// Renders the `CastsShadows` instances into each shadow map layer in use this frame.
+pub struct ShadowNode;
+
+impl RenderNode for ShadowNode {
+    fn name(&self) -> &'static str {
+        "shadows"
+    }
+
+    fn writes(&self) -> &[&'static str] {
+        &[SHADOW_MAPS]
+    }
+
+    // One depth-only pass per layer, drawing every caster from that layer's light
+    fn run(&mut self, context: &mut NodeContext) {
+        let shadow_maps = context.render.shadow_maps;
+        for &layer in context.render.shadow_layers {
+            let mut shadow_pass = context.encoder.begin_render_pass(
+                &(wgpu::RenderPassDescriptor {
+                    label: Some("Shadow Pass"),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &shadow_maps.layer_views[layer],
+                        depth_ops: Some(wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(1.0),
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: None,
+                })
+            );
+            shadow_pass.set_pipeline(&shadow_maps.pipeline);
+            shadow_pass.set_bind_group(0, &shadow_maps.layer_bind_groups[layer], &[]);
+            for model in context.models {
+                for mesh in &model.meshes {
+                    let Some(instance_buffer) = &mesh.instance_buffer else {
+                        continue;
+                    };
+                    let casters = mesh.shadow_casters.start..mesh.shadow_casters.end.min(mesh.instance_count);
+                    if casters.is_empty() {
+                        continue;
+                    }
+                    shadow_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                    shadow_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                    shadow_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    shadow_pass.draw_indexed(0..mesh.num_elements, 0, casters);
+                }
+            }
+        }
+    }
+}
+
+/// Clears the scene's colour and depth.
+pub struct ClearNode {
+    pub color: wgpu::Color,
+}
+
+impl RenderNode for ClearNode {
+    fn name(&self) -> &'static str {
+        "clear"
+    }
+
+    fn writes(&self) -> &[&'static str] {
+        &[SCENE_COLOR, SCENE_DEPTH]
+    }
+
+    fn run(&mut self, context: &mut NodeContext) {
+        let (color, depth) = (context.view(SCENE_COLOR), context.view(SCENE_DEPTH));
+        context.encoder.begin_render_pass(
+            &(wgpu::RenderPassDescriptor {
+                label: Some("Clear Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: color,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(self.color),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            })
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MeshPass {
+    /// Wireframes of the transparent meshes, seen through them
+    WireframesBehind,
+    Opaque,
+    /// The transparent meshes' wireframes again, over everything
+    WireframesOnTop,
+}
+
+/// Draws every model's meshes into the scene, for one of the three mesh passes. Skipped
+/// without an active camera.
+pub struct MeshNode(pub MeshPass);
+
+impl RenderNode for MeshNode {
+    fn name(&self) -> &'static str {
+        match self.0 {
+            MeshPass::WireframesBehind => "wireframes_behind",
+            MeshPass::Opaque => "opaque",
+            MeshPass::WireframesOnTop => "wireframes_on_top",
+        }
+    }
+
+    fn reads(&self) -> &[&'static str] {
+        match self.0 {
+            MeshPass::Opaque => &[SHADOW_MAPS],
+            _ => &[],
+        }
+    }
+
+    fn writes(&self) -> &[&'static str] {
+        &[SCENE_COLOR, SCENE_DEPTH]
+    }
+
+    fn run(&mut self, context: &mut NodeContext) {
+        let render = context.render;
+        let Some(camera_bind_group) = render.camera_bind_group else {
+            return;
+        };
+        let (color, depth) = (context.view(SCENE_COLOR), context.view(SCENE_DEPTH));
+        let mut render_pass = context.encoder.begin_render_pass(
+            &(wgpu::RenderPassDescriptor {
+                label: Some(self.name()),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: color,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            })
+        );
+
+        if self.0 == MeshPass::Opaque {
+            render_pass.set_pipeline(render.render_pipeline);
+            for model in context.models {
+                for mesh in &model.meshes {
+                    let Some(instance_buffer) = &mesh.instance_buffer else {
+                        continue;
+                    };
+                    render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                    // The shadow receivers come first - see render_sync_system
+                    let receivers_end = mesh.shadow_receivers.end.min(mesh.instance_count);
+                    if receivers_end > 0 {
+                        render_pass.draw_mesh_instanced(
+                            mesh,
+                            0..receivers_end,
+                            camera_bind_group,
+                            render.light_bind_group,
+                            &mesh.material_bind_group,
+                            false
+                        );
+                    }
+                    if receivers_end < mesh.instance_count {
+                        render_pass.draw_mesh_instanced(
+                            mesh,
+                            receivers_end..mesh.instance_count,
+                            camera_bind_group,
+                            render.light_bind_group_no_shadows,
+                            &mesh.material_bind_group,
+                            false
+                        );
+                    }
+                }
+            }
+            return;
+        }
+
+        // Only transparent meshes (alpha < 1.0) get wireframes
+        render_pass.set_pipeline(render.wireframe_render_pipeline);
+        for model in context.models {
+            for mesh in &model.meshes {
+                let Some(instance_buffer) = &mesh.instance_buffer else {
+                    continue;
+                };
+                if mesh._material.alpha >= 1.0 {
+                    continue;
+                }
+                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+                render_pass.draw_mesh_instanced(
+                    mesh,
+                    0..mesh.instance_count,
+                    camera_bind_group,
+                    render.light_bind_group_no_shadows,
+                    &mesh.material_bind_group,
+                    self.0 == MeshPass::WireframesBehind
+                );
+            }
+        }
+    }
+}
+
+/// Resolves the multisampled scene into `HDR`, for post-processing.
+pub struct ResolveNode;
+
+impl RenderNode for ResolveNode {
+    fn name(&self) -> &'static str {
+        "resolve"
+    }
+
+    fn reads(&self) -> &[&'static str] {
+        &[SCENE_COLOR]
+    }
+
+    fn writes(&self) -> &[&'static str] {
+        &[HDR]
+    }
+
+    // An empty pass - the resolve happens when it ends
+    fn run(&mut self, context: &mut NodeContext) {
+        let (color, hdr) = (context.view(SCENE_COLOR), context.view(HDR));
+        context.encoder.begin_render_pass(
+            &(wgpu::RenderPassDescriptor {
+                label: Some("Resolve Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: color,
+                        resolve_target: Some(hdr),
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                ..Default::default()
+            })
+        );
+    }
+}
+
+fn settings(context: &NodeContext) -> PostProcessing {
+    context.world.get_resource::<PostProcessing>().cloned().unwrap_or_default()
+}
+
+// A fullscreen pass over `target`, sampling the bind group's texture. `offset` picks the
+// chain entry's slot of `PostProcess::effect_buffer`
+fn fullscreen_pass(
+    context: &mut NodeContext,
+    label: &str,
+    target: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    offset: u32
+) {
+    let mut pass = context.encoder.begin_render_pass(
+        &(wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[
+                Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                }),
+            ],
+            ..Default::default()
+        })
+    );
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[offset]);
+    pass.draw(0..3, 0..1);
+}
+
+// Downsamples `source` through the bloom mips, each from the one before, then upsamples back
+// up them, adding each onto the next larger one. That leaves the finished bloom in the first
+fn bloom(context: &mut NodeContext, source: &wgpu::TextureView, offset: u32) {
+    let render = context.render;
+    let post_process = render.post_process;
+    let mut source = source;
+    for (i, &mip) in BLOOM_MIPS.iter().enumerate() {
+        let target = context.view(mip);
+        let pipeline = if i == 0 {
+            &post_process.downsample_first_pipeline
+        } else {
+            &post_process.downsample_pipeline
+        };
+        let bind_group = post_process.bloom_bind_group(render.device, source);
+        fullscreen_pass(
+            context,
+            "Bloom Downsample Pass",
+            target,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            pipeline,
+            &bind_group,
+            offset
+        );
+        source = target;
+    }
+    for i in (0..BLOOM_MIPS.len() - 1).rev() {
+        let (source, target) = (context.view(BLOOM_MIPS[i + 1]), context.view(BLOOM_MIPS[i]));
+        let bind_group = post_process.bloom_bind_group(render.device, source);
+        fullscreen_pass(
+            context,
+            "Bloom Upsample Pass",
+            target,
+            wgpu::LoadOp::Load,
+            &post_process.upsample_pipeline,
+            &bind_group,
+            offset
+        );
+    }
+}
+
+// Everything the chain can draw into
+const POST_PROCESS_WRITES: [&str; 9] = [
+    POST_TARGETS[0],
+    POST_TARGETS[1],
+    BLOOM_MIPS[0],
+    BLOOM_MIPS[1],
+    BLOOM_MIPS[2],
+    BLOOM_MIPS[3],
+    BLOOM_MIPS[4],
+    BLOOM_MIPS[5],
+    SURFACE,
+];
+
+/// Takes `HDR` to the surface through the `PostProcessing` resource's chain: a pass for each
+/// enabled effect, in order, back and forth between the `POST_TARGETS`, then one into the
+/// surface. With nothing enabled that last pass is all there is.
+#[derive(Default)]
+pub struct PostProcessNode {
+    // Moves the grain each frame
+    frame: u32,
+}
+
+impl RenderNode for PostProcessNode {
+    fn name(&self) -> &'static str {
+        "post_process"
+    }
+
+    fn reads(&self) -> &[&'static str] {
+        &[HDR]
+    }
+
+    fn writes(&self) -> &[&'static str] {
+        &POST_PROCESS_WRITES
+    }
+
+    fn run(&mut self, context: &mut NodeContext) {
+        let render = context.render;
+        let post_process = render.post_process;
+        // Kept small - the hash loses precision with large inputs
+        self.frame = (self.frame + 1) % 64;
+        let grain_seed = (self.frame as f32) * 13.7;
+
+        let settings = settings(context);
+        let enabled = settings.effects
+            .iter()
+            .filter(|entry| entry.enabled)
+            .take(MAX_POST_EFFECTS);
+        let mut source = context.view(HDR);
+        for (slot, (entry, &target)) in enabled.zip(POST_TARGETS.iter().cycle()).enumerate() {
+            // Each entry has its own slot, since every write lands before the passes run
+            let offset = (slot as u64) * EFFECT_UNIFORM_STRIDE;
+            let uniform = EffectUniform::new(&entry.effect, grain_seed);
+            render.queue.write_buffer(&post_process.effect_buffer, offset, bytemuck::cast_slice(&[uniform]));
+            let offset = offset as u32;
+
+            if let Effect::Bloom(_) = entry.effect {
+                bloom(context, source, offset);
+            }
+            let target = context.view(target);
+            let bind_group = post_process.effect_bind_group(render.device, source, context.view(BLOOM_MIPS[0]));
+            fullscreen_pass(
+                context,
+                entry.effect.name(),
+                target,
+                wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                post_process.effect_pipelines.get(&entry.effect),
+                &bind_group,
+                offset
+            );
+            source = target;
+        }
+
+        let bind_group = post_process.effect_bind_group(render.device, source, context.view(BLOOM_MIPS[0]));
+        let surface = context.view(SURFACE);
+        fullscreen_pass(
+            context,
+            "Post Process Output Pass",
+            surface,
+            wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            &post_process.effect_pipelines.output,
+            &bind_group,
+            0
+        );
+    }
+}
+
+/// Composites egui over the surface. Added after the game's nodes, so it's always on top.
+pub struct UiNode;
+
+impl RenderNode for UiNode {
+    fn name(&self) -> &'static str {
+        "ui"
+    }
+
+    fn writes(&self) -> &[&'static str] {
+        &[SURFACE]
+    }
+
+    fn run(&mut self, context: &mut NodeContext) {
+        let Some(egui) = context.egui.take() else {
+            return;
+        };
+        let surface = context.view(SURFACE);
+        egui.state.render(
+            context.render.device,
+            context.render.queue,
+            context.encoder,
+            surface,
+            egui.window,
+            egui.full_output
+        );
+    }
+}
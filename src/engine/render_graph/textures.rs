@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+/// A texture the graph owns and sizes to the surface, recreated when the surface is resized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransientTexture {
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    /// Of the surface's size, e.g. 0.5 for a half resolution bloom target
+    pub scale: f32,
+    // Render attachment is always added
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TransientTexture {
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            sample_count: 1,
+            scale: 1.0,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        }
+    }
+
+    pub fn with_samples(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sampled by a later node, e.g. a post-processing input.
+    pub fn sampled(mut self) -> Self {
+        self.usage |= wgpu::TextureUsages::TEXTURE_BINDING;
+        self
+    }
+
+    /// Its size for a surface of `width` x `height`, never below 1x1.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scaled = |length: u32| (((length as f32) * self.scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+}
+
+// The textures themselves, for the surface size they were made for
+#[derive(Default)]
+pub(crate) struct TexturePool {
+    surface_size: (u32, u32),
+    textures: HashMap<&'static str, (wgpu::Texture, wgpu::TextureView)>,
+}
+
+impl TexturePool {
+    // (Re)creates any texture missing or made for another surface size
+    pub(crate) fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        descriptors: &[(&'static str, TransientTexture)],
+        width: u32,
+        height: u32
+    ) {
+        if self.surface_size != (width, height) {
+            self.textures.clear();
+            self.surface_size = (width, height);
+        }
+        for (name, descriptor) in descriptors {
+            if self.textures.contains_key(name) {
+                continue;
+            }
+            let (width, height) = descriptor.size(width, height);
+            let texture = device.create_texture(
+                &(wgpu::TextureDescriptor {
+                    label: Some(name),
+                    size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                    mip_level_count: 1,
+                    sample_count: descriptor.sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: descriptor.format,
+                    usage: descriptor.usage | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                })
+            );
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            self.textures.insert(name, (texture, view));
+        }
+    }
+
+    pub(crate) fn views(&self) -> impl Iterator<Item = (&'static str, &wgpu::TextureView)> {
+        self.textures.iter().map(|(name, (_, view))| (*name, view))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_sizes_round_and_never_reach_zero() {
+        let half = TransientTexture::new(wgpu::TextureFormat::Rgba16Float).with_scale(0.5);
+        assert_eq!(half.size(1920, 1081), (960, 541));
+        assert_eq!(half.size(1, 1), (1, 1));
+        assert_eq!(TransientTexture::new(wgpu::TextureFormat::Rgba16Float).size(800, 600), (800, 600));
+    }
+}
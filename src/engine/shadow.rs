@@ -0,0 +1,362 @@
+// Shadow maps for directional and spot lights
+//
+// Every map is a layer of one depth texture array: the main directional light's cascades
+// first, then one per shadowed spot light. `light_sync_system` picks the lights and writes
+// each layer's view-projection; `RenderState` renders the `CastsShadows` instances into the
+// layers in use before the main pass, which samples them with 3x3 PCF.
+//
+// Sized for WebGL2 (`Limits::downlevel_webgl2_defaults`): 1024² is under its 2048 texture
+// limit, 7 layers under its 256, and the lighting group stays a uniform + texture + sampler.
+// Point lights would need cube maps and don't cast shadows.
+
+use cgmath::{ Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Rad, Transform, Vector3 };
+use wgpu::util::DeviceExt;
+
+use crate::engine::{
+    instance::InstanceRaw,
+    model::vertex::{ ModelVertex, Vertex },
+    render_pipeline::create_shadow_pipeline,
+};
+
+pub const SHADOW_MAP_SIZE: u32 = 1024;
+// Keep these three in sync with shader.wgsl
+pub const SHADOW_CASCADES: usize = 3;
+pub const MAX_SHADOWED_SPOT_LIGHTS: usize = 4;
+pub const MAX_SHADOW_MAPS: usize = SHADOW_CASCADES + MAX_SHADOWED_SPOT_LIGHTS;
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// How far behind a cascade (towards the sun) casters are still caught, e.g. canyon walls
+// outside the camera's view
+const CASTER_MARGIN: f32 = 50.0;
+const SPOT_NEAR: f32 = 0.1;
+
+// OpenGL's -1..1 clip depth to wgpu's 0..1. `Matrix4::new` takes columns, so the 0.5s are z's
+// scale and the translation column's offset. (The camera's `OPENGL_TO_WGPU_MATRIX` has the
+// second 0.5 in z's column instead, which scales w - fine for the view, wrong for a depth map.)
+#[rustfmt::skip]
+const GL_TO_WGPU_DEPTH: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct ShadowUniform {
+    pub view_projections: [[[f32; 4]; 4]; MAX_SHADOW_MAPS],
+    // Depth subtracted before comparing, against acne
+    pub bias: f32,
+    pub _padding: [f32; 3],
+}
+
+impl ShadowUniform {
+    pub fn empty() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+// Bound in place of the lights for instances without `ReceivesShadows`, which is all that
+// differs between the two lighting bind groups
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowReceiverUniform {
+    enabled: u32,
+    _padding: [u32; 3],
+}
+
+/// Where the active camera is looking - what the cascades are fitted around.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraFrustum {
+    pub position: Vector3<f32>,
+    pub forward: Vector3<f32>,
+    pub fov_y: Rad<f32>,
+    pub aspect: f32,
+}
+
+fn up_for(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() }
+}
+
+/// An orthographic view down `direction` covering the part of `frustum` between `near` and
+/// `far`. Fitted to a sphere and snapped to whole texels, so the cascade doesn't shimmer as the
+/// camera turns and moves.
+pub fn cascade_view_projection(
+    frustum: &CameraFrustum,
+    near: f32,
+    far: f32,
+    direction: Vector3<f32>
+) -> Matrix4<f32> {
+    let forward = frustum.forward.normalize();
+    let right = forward.cross(up_for(forward)).normalize();
+    let up = right.cross(forward);
+    let tan_y = (frustum.fov_y.0 * 0.5).tan();
+    let tan_x = tan_y * frustum.aspect;
+
+    let mut corners = Vec::with_capacity(8);
+    for distance in [near, far] {
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            corners.push(
+                frustum.position +
+                    forward * distance +
+                    right * (x * tan_x * distance) +
+                    up * (y * tan_y * distance)
+            );
+        }
+    }
+    let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner) / 8.0;
+    let radius = corners.iter().map(|corner| (corner - center).magnitude()).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    // A pure rotation, so light space positions can be snapped before building the projection
+    let direction = direction.normalize();
+    let rotation = Matrix4::look_to_rh(Point3::origin(), direction, up_for(direction));
+    let texel = (radius * 2.0) / (SHADOW_MAP_SIZE as f32);
+    let center = rotation.transform_point(Point3::from_vec(center));
+    let (x, y) = ((center.x / texel).floor() * texel, (center.y / texel).floor() * texel);
+
+    // Looking down -z, so the near plane is the one furthest along +z
+    let projection = cgmath::ortho(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -center.z - radius - CASTER_MARGIN,
+        -center.z + radius
+    );
+    GL_TO_WGPU_DEPTH * projection * rotation
+}
+
+/// A perspective view from a spot light, wide enough for its outer cone.
+pub fn spot_view_projection(
+    position: Vector3<f32>,
+    direction: Vector3<f32>,
+    outer_angle: f32,
+    range: f32
+) -> Matrix4<f32> {
+    let direction = direction.normalize();
+    let view = Matrix4::look_to_rh(Point3::from_vec(position), direction, up_for(direction));
+    let fov = Deg((outer_angle * 2.0).clamp(1.0, 170.0));
+    GL_TO_WGPU_DEPTH * cgmath::perspective(fov, 1.0, SPOT_NEAR, range.max(SPOT_NEAR * 2.0)) * view
+}
+
+/// The shadow map texture array, the depth-only pipeline that fills it, and a bind group per
+/// layer holding that layer's view-projection.
+pub struct ShadowMaps {
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub uniform_buffer: wgpu::Buffer,
+    pub(crate) layer_views: Vec<wgpu::TextureView>,
+    pub(crate) layer_buffers: Vec<wgpu::Buffer>,
+    pub(crate) layer_bind_groups: Vec<wgpu::BindGroup>,
+    pub(crate) pipeline: wgpu::RenderPipeline,
+    // Kept so the pipeline can be rebuilt when shadow.wgsl is hot reloaded
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub(crate) pipeline_layout: wgpu::PipelineLayout,
+    receiver_buffers: [wgpu::Buffer; 2],
+}
+
+impl ShadowMaps {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture = device.create_texture(
+            &(wgpu::TextureDescriptor {
+                label: Some("Shadow Maps"),
+                size: wgpu::Extent3d {
+                    width: SHADOW_MAP_SIZE,
+                    height: SHADOW_MAP_SIZE,
+                    depth_or_array_layers: MAX_SHADOW_MAPS as u32,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: SHADOW_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+        );
+        let view = texture.create_view(
+            &(wgpu::TextureViewDescriptor {
+                label: Some("Shadow Maps View"),
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        );
+        let layer_views = (0..MAX_SHADOW_MAPS as u32)
+            .map(|layer| {
+                texture.create_view(
+                    &(wgpu::TextureViewDescriptor {
+                        label: Some("Shadow Map Layer"),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_array_layer: layer,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    })
+                )
+            })
+            .collect();
+
+        // Linear comparison gives a little hardware filtering on top of the PCF taps
+        let sampler = device.create_sampler(
+            &(wgpu::SamplerDescriptor {
+                label: Some("Shadow Sampler"),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                ..Default::default()
+            })
+        );
+
+        let uniform_buffer = device.create_buffer_init(
+            &(wgpu::util::BufferInitDescriptor {
+                label: Some("Shadow Buffer"),
+                contents: bytemuck::cast_slice(&[ShadowUniform::empty()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        );
+        let receiver_buffers = [0, 1].map(|enabled| {
+            device.create_buffer_init(
+                &(wgpu::util::BufferInitDescriptor {
+                    label: Some("Shadow Receiver Buffer"),
+                    contents: bytemuck::cast_slice(&[ShadowReceiverUniform { enabled, _padding: [0; 3] }]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                })
+            )
+        });
+
+        // The shadow pass's only bind group: the layer's view-projection, in place of a camera
+        let layer_bind_group_layout = device.create_bind_group_layout(
+            &(wgpu::BindGroupLayoutDescriptor {
+                label: Some("Shadow Layer Bind Group Layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        );
+        let layer_buffers: Vec<wgpu::Buffer> = (0..MAX_SHADOW_MAPS)
+            .map(|_| {
+                device.create_buffer(
+                    &(wgpu::BufferDescriptor {
+                        label: Some("Shadow Layer Buffer"),
+                        size: std::mem::size_of::<[[f32; 4]; 4]>() as u64,
+                        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    })
+                )
+            })
+            .collect();
+        let layer_bind_groups = layer_buffers
+            .iter()
+            .map(|buffer| {
+                device.create_bind_group(
+                    &(wgpu::BindGroupDescriptor {
+                        label: Some("Shadow Layer Bind Group"),
+                        layout: &layer_bind_group_layout,
+                        entries: &[
+                            wgpu::BindGroupEntry {
+                                binding: 0,
+                                resource: buffer.as_entire_binding(),
+                            },
+                        ],
+                    })
+                )
+            })
+            .collect();
+
+        let pipeline_layout = device.create_pipeline_layout(
+            &(wgpu::PipelineLayoutDescriptor {
+                label: Some("Shadow Pipeline Layout"),
+                bind_group_layouts: &[&layer_bind_group_layout],
+                push_constant_ranges: &[],
+            })
+        );
+        // Same vertex and instance buffers as the main pass
+        let pipeline = create_shadow_pipeline(
+            device,
+            &pipeline_layout,
+            SHADOW_FORMAT,
+            &[ModelVertex::desc(), InstanceRaw::desc()],
+            wgpu::ShaderModuleDescriptor {
+                label: Some("Shadow Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("../shadow.wgsl").into()),
+            }
+        );
+
+        Self {
+            view,
+            sampler,
+            uniform_buffer,
+            layer_views,
+            layer_buffers,
+            layer_bind_groups,
+            pipeline,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            pipeline_layout,
+            receiver_buffers,
+        }
+    }
+
+    /// The receiver flag buffer for the lighting bind group - see `light::create_bind_groups`.
+    pub(crate) fn receiver_buffer(&self, receives_shadows: bool) -> &wgpu::Buffer {
+        &self.receiver_buffers[receives_shadows as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+
+    use super::*;
+
+    fn project(matrix: Matrix4<f32>, point: Vector3<f32>) -> Vector3<f32> {
+        let clip = matrix * Vector4::new(point.x, point.y, point.z, 1.0);
+        clip.truncate() / clip.w
+    }
+
+    fn in_map(ndc: Vector3<f32>) -> bool {
+        ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0 && (0.0..=1.0).contains(&ndc.z)
+    }
+
+    #[test]
+    fn cascades_cover_their_slice_of_the_view() {
+        let frustum = CameraFrustum {
+            position: Vector3::new(5.0, 2.0, 0.0),
+            forward: Vector3::new(0.0, 0.0, 1.0),
+            fov_y: Deg(45.0).into(),
+            aspect: 16.0 / 9.0,
+        };
+        let sun = Vector3::new(0.3, -1.0, 0.5);
+        let matrix = cascade_view_projection(&frustum, 10.0, 30.0, sun);
+
+        // Straight ahead in the slice, and its far corner
+        assert!(in_map(project(matrix, Vector3::new(5.0, 2.0, 20.0))));
+        let tan_y = (22.5f32).to_radians().tan();
+        let corner = Vector3::new(5.0 + 30.0 * tan_y * 16.0 / 9.0, 2.0 + 30.0 * tan_y, 30.0);
+        assert!(in_map(project(matrix, corner)));
+        // Well past it
+        assert!(!in_map(project(matrix, Vector3::new(5.0, 2.0, 80.0))));
+        // Something between the slice and the sun is still a caster
+        assert!(in_map(project(matrix, Vector3::new(5.0, 2.0, 20.0) - sun.normalize() * 30.0)));
+    }
+
+    #[test]
+    fn spot_maps_look_down_the_light() {
+        let matrix = spot_view_projection(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, -1.0, 0.0), 30.0, 10.0);
+        let below = project(matrix, Vector3::new(0.0, 0.0, 0.0));
+        assert!(below.x.abs() < 1e-5 && below.y.abs() < 1e-5 && in_map(below));
+        // Out of range
+        assert!(!in_map(project(matrix, Vector3::new(0.0, -10.0, 0.0))));
+    }
+}
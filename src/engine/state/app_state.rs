@@ -1,17 +1,35 @@
+use std::hash::Hash;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use std::path::PathBuf;
 use std::sync::Arc;
+use serde::de::DeserializeOwned;
 use web_time::Instant;
 use winit::event::{ ElementState };
 use winit::keyboard::{ KeyCode };
 use winit::window::{ Window };
 
 use crate::engine::assets::server::AssetServer;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::engine::assets::hot_reload::{
+    AssetWatcher, WatchedAsset, WatchedShader, BLOOM_SHADER_PATH, POST_PROCESS_SHADER_PATH, SHADER_PATH,
+    SHADOW_SHADER_PATH, WIREFRAME_SHADER_PATH,
+};
+use crate::engine::ecs::commands::Commands;
 use crate::engine::ecs::component_registry::ComponentRegistry;
 use crate::engine::ecs::components::camera::camera::{ Camera, SurfaceDimensions };
+use crate::engine::ecs::entity_ref::EntityNames;
+use crate::engine::ecs::prefab::Prefabs;
 use crate::engine::ecs::events::collision_event::CollisionEvent;
 use crate::engine::ecs::resources::camera::ActiveCamera;
-use crate::engine::ecs::world_descriptor::load_world;
+use crate::engine::ecs::resources::debug::RenderGraphDump;
+use crate::engine::ecs::resources::fixed_time::FixedTime;
+use crate::engine::ecs::resources::lighting::{ LightBuffer, Lighting, ShadowBuffers };
+use crate::engine::ecs::resources::post_processing::PostProcessing;
+use crate::engine::ecs::scene::{ apply_scene_requests, load_scene, Scenes };
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::engine::ecs::scene::patch_scene;
 use crate::engine::events::event_registry::EventRegistry;
-use crate::engine::input::bindings_descriptor::BindingsDescriptor;
+use crate::engine::input::bindings_descriptor::{ validate_bindings, BindingsDescriptor };
 use crate::engine::input::input_state::InputState;
 use crate::engine::ecs::system::{ SystemContext, SystemSchedule };
 use crate::engine::ecs::world::World;
@@ -27,6 +45,13 @@ use crate::game::input::bindings::Bindings;
 
 const MINIMUM_DELTA_TIME: f32 = 0.1;
 
+// Watched files, plus how to reload the bindings (needs the game's Action type)
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+struct HotReload {
+    watcher: AssetWatcher,
+    load_bindings: fn(&mut World, &str),
+}
+
 pub struct AppState {
     pub instance: wgpu::Instance,
     engine_state: Option<EngineState>,
@@ -40,6 +65,8 @@ pub struct AppState {
     system_schedule: Option<SystemSchedule>,
     pub egui_state: Option<EguiState>,
     ui_registry: Option<UIRegistry>,
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    hot_reload: Option<HotReload>,
 }
 
 impl AppState {
@@ -59,6 +86,8 @@ impl AppState {
             system_schedule: None,
             egui_state: None,
             ui_registry: None,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            hot_reload: None,
         }
     }
 
@@ -72,18 +101,23 @@ impl AppState {
     /// Setup pipeline (in order):
     /// 1. Allocate the `World`, `AssetServer`, `SystemSchedule`, and `UIRegistry`.
     /// 2. Install window/engine_state/render_state on `self`.
-    /// 3. Run `game_setup.load_assets` to register GPU models with the AssetServer.
+    /// 3. Run `game_setup.load_assets` to register GPU models with the AssetServer (models
+    ///    started with `AssetServer::load_model` keep loading in the background).
     /// 4. Run `game_setup.register_components` to populate the component registry
     ///    with game-specific components (engine components are auto-registered).
-    /// 5. Run `game_setup.setup_ecs` and `setup_ui` to register systems and panels.
-    /// 6. Add engine-managed resources (input, fps, surface dims, event registry).
+    /// 5. Run `game_setup.setup_ecs`, `setup_ui` and `setup_render_graph` to register systems,
+    ///    panels and render nodes, then sort the schedule and the render graph (panics on a
+    ///    cycle in either).
+    /// 6. Add engine-managed resources (input, fps, fixed timestep, lighting, surface dims, event
+    ///    registry, render graph dump).
     /// 7. Register engine events on the event registry.
     /// 8. Load the bindings RON (if any) so input is usable from this point on.
-    /// 9. Load the world's RON file (if any) to spawn declared entities.
-    /// 10. Run `game_setup.setup` for game-specific entity/resource setup —
+    /// 9. Load the game's prefabs into the `Prefabs` resource.
+    /// 10. Load the world's RON file (if any) as the first scene, adding the `Scenes` resource.
+    /// 11. Run `game_setup.setup` for game-specific entity/resource setup —
     ///     runs last so it can query/modify entities loaded from the world RON.
-    /// 11. Construct egui state.
-    /// 12. Move locals into `self`.
+    /// 12. Construct egui state.
+    /// 13. Move locals into `self`.
     ///
     /// Generic over `G: GameSetup` so the associated `Action` type is known
     /// at the type system level — needed for typed bindings deserialization.
@@ -104,8 +138,19 @@ impl AppState {
         self.engine_state = Some(engine_state);
         self.render_state = Some(render_state);
 
+        // Handed to light_sync_system in step 6
+        let light_buffer = self.engine_state.as_ref().unwrap().light_buffer.clone();
+        let shadow_buffers = {
+            let shadow_maps = &self.engine_state.as_ref().unwrap().shadow_maps;
+            ShadowBuffers {
+                uniform: shadow_maps.uniform_buffer.clone(),
+                layers: shadow_maps.layer_buffers.clone(),
+                active_maps: Vec::new(),
+            }
+        };
+
         // Step 3: asset loading
-        let render_context = self.engine_state.as_mut().unwrap().render_context(None);
+        let render_context = self.engine_state.as_mut().unwrap().render_context(None, &[]);
         let gpu_context = GpuContext {
             device: render_context.device,
             queue: render_context.queue,
@@ -115,37 +160,78 @@ impl AppState {
         // Step 4: register game-specific components (engine ones auto-registered)
         let mut component_registry = ComponentRegistry::new();
         game_setup.register_components(&mut component_registry);
+        // Shared with the Prefabs resource from step 9
+        let component_registry = Arc::new(component_registry);
 
-        // Step 5: register systems and UI panels
+        // Step 5: register systems, UI panels and render nodes
         game_setup.setup_ecs(&mut system_schedule);
+        // Fail on an ordering cycle now rather than on the first frame
+        if let Err(e) = system_schedule.build() {
+            panic!("invalid system schedule: {}", e);
+        }
+        log::debug!("system batches:\n{}", system_schedule.batch_report());
         game_setup.setup_ui(&mut ui_registry);
+        let render_state = self.render_state.as_mut().unwrap();
+        game_setup.setup_render_graph(render_state.graph_mut(), &gpu_context);
+        // Like the schedule, a cycle fails here rather than on the first frame
+        if let Err(e) = render_state.finish_graph() {
+            panic!("invalid render graph: {}", e);
+        }
+        let render_graph_dump = RenderGraphDump {
+            text: render_state.graph().report(),
+            dot: render_state.graph().dot(),
+        };
+        log::debug!("{}", render_graph_dump.text);
 
         // Step 6: engine-managed resources
         world.add_resource(InputState::default());
         world.add_resource(FpsCounter::new());
+        world.add_resource(FixedTime::default());
+        world.add_resource(Lighting::default());
+        world.add_resource(PostProcessing::default());
+        world.add_resource(LightBuffer(light_buffer));
+        world.add_resource(shadow_buffers);
         world.add_resource(camera_bind_group_layout);
         world.add_resource(SurfaceDimensions { width: 1920.0, height: 1080.0 });
         world.add_resource(EventRegistry::new());
+        world.add_resource(render_graph_dump);
 
         // Step 7: engine events
         world.register_event::<CollisionEvent>();
 
         // Step 8: bindings — input usable from here on
         if let Some(ron) = game_setup.bindings_ron() {
-            if let Ok(descriptor) = ron::from_str::<BindingsDescriptor<G::Action>>(ron) {
-                world.add_resource(Bindings::<G::Action>::from_descriptor(descriptor));
+            load_bindings::<G::Action>(&mut world, ron);
+        }
+
+        // Step 9: prefabs, before the world content that uses them
+        let mut prefabs = Prefabs::new(component_registry.clone());
+        for (name, ron) in game_setup.prefabs_ron() {
+            if let Err(e) = prefabs.add(name, ron) {
+                log::error!("Failed to load prefab {}: {:?}", name, e);
             }
         }
+        prefabs.sync_models(&asset_server);
+        world.add_resource(prefabs);
 
-        // Step 9: declarative world content from RON
+        // Step 10: declarative world content from RON, loaded as the first scene
+        world.add_resource(Scenes::new(component_registry.clone()));
+        let mut world_entities = EntityNames::new();
         if let Some(ron) = game_setup.world_ron() {
-            if let Err(e) = load_world(ron, &mut world, &component_registry, &asset_server) {
-                log::error!("Failed to load world: {:?}", e);
+            match load_scene(&mut world, "world", ron, &asset_server) {
+                Ok(scene) => {
+                    world_entities = world
+                        .get_resource::<Scenes>()
+                        .and_then(|scenes| scenes.entities(scene))
+                        .cloned()
+                        .unwrap_or_default();
+                }
+                Err(e) => log::error!("Failed to load world: {:#}", e),
             }
         }
 
-        // Step 10: programmatic game setup. Runs last so it can query/modify
-        // entities that were loaded from RON in step 9. Block scope keeps the
+        // Step 11: programmatic game setup. Runs last so it can query/modify
+        // entities that were loaded from RON in step 10. Block scope keeps the
         // &mut asset_server borrow short-lived.
         {
             let mut ecs_system_context = SystemContext::new(
@@ -153,11 +239,17 @@ impl AppState {
                 render_context.device,
                 render_context.queue,
                 &mut asset_server,
+                Commands::new(&world),
             );
-            game_setup.setup(&mut world, &mut ecs_system_context);
+            game_setup.setup(&mut world, &mut ecs_system_context, &world_entities);
+            ecs_system_context.commands.apply(&mut world);
+        }
+        // setup may have registered more models
+        if let Some(prefabs) = world.get_resource_mut::<Prefabs>() {
+            prefabs.sync_models(&asset_server);
         }
 
-        // Step 11: egui state
+        // Step 12: egui state
         let engine_state = self.engine_state.as_ref().unwrap();
         let egui_state = EguiState::new(
             &engine_state.device,
@@ -165,7 +257,12 @@ impl AppState {
             self.window.as_ref().unwrap(),
         );
 
-        // Step 12: commit locals to self
+        #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+        {
+            self.hot_reload = watch_assets(&game_setup);
+        }
+
+        // Step 13: commit locals to self
         self.world = Some(world);
         self.asset_server = Some(asset_server);
         self.system_schedule = Some(system_schedule);
@@ -208,6 +305,9 @@ impl AppState {
 
     /// Update runs once per-frame
     fn update(&mut self) {
+        #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+        self.apply_hot_reload();
+
         // Update FPS counter (lives in World now)
         if let Some(world) = self.world.as_mut() {
             if let Some(fps_counter) = world.get_resource_mut::<FpsCounter>() {
@@ -232,14 +332,19 @@ impl AppState {
 
             let world = self.world.as_mut().unwrap();
             let asset_server = self.asset_server.as_mut().unwrap();
+            // Upload any models/textures that finished loading in the background
+            asset_server.update_loads(&GpuContext { device, queue });
 
             let mut system_context = SystemContext::new(
                 self.delta_time,
                 device,
                 queue,
-                asset_server
+                asset_server,
+                Commands::new(world)
             );
             self.system_schedule.as_mut().unwrap().run_all(world, &mut system_context);
+            // Scene loads/unloads queued by this frame's systems
+            apply_scene_requests(world, asset_server);
         }
     }
 
@@ -270,7 +375,8 @@ impl AppState {
 
         let engine_state = self.engine_state.as_ref().unwrap();
         let render_state = self.render_state.as_mut().unwrap();
-        let ecs_models = self.asset_server.as_ref().unwrap().models();
+        // Models still loading aren't drawn
+        let ecs_models = self.asset_server.as_ref().unwrap().models().collect::<Vec<_>>();
 
         let world = self.world.as_ref().unwrap();
 
@@ -278,10 +384,14 @@ impl AppState {
             .get_resource::<ActiveCamera>()
             .and_then(|ac| world.get_component::<Camera>(ac.0))
             .map(|camera| &camera.render_pass_data.bind_group);
+        let shadow_layers = world
+            .get_resource::<ShadowBuffers>()
+            .map_or(&[][..], |shadows| &shadows.active_maps);
 
         render_state.handle_redraw(
-            engine_state.render_context(camera_bind_group),
-            ecs_models,
+            engine_state.render_context(camera_bind_group, shadow_layers),
+            &ecs_models,
+            world,
             EguiContext { state: egui_state, full_output, window: &window }
         );
 
@@ -301,4 +411,71 @@ impl AppState {
             self.show_fps = !self.show_fps;
         }
     }
+
+    // Applies whatever changed on disk since last frame. Failures keep the old version.
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    fn apply_hot_reload(&mut self) {
+        let Some(hot_reload) = self.hot_reload.as_ref() else {
+            return;
+        };
+        let (Some(world), Some(asset_server), Some(engine_state)) = (
+            self.world.as_mut(),
+            self.asset_server.as_ref(),
+            self.engine_state.as_mut(),
+        ) else {
+            return;
+        };
+
+        for (asset, contents) in hot_reload.watcher.changed() {
+            let result = match asset {
+                WatchedAsset::World => {
+                    match world.get_resource::<Scenes>().and_then(|scenes| scenes.find("world")) {
+                        Some(scene) => patch_scene(world, scene, &contents, asset_server),
+                        None => load_scene(world, "world", &contents, asset_server).map(|_| ()),
+                    }
+                }
+                WatchedAsset::Bindings => {
+                    (hot_reload.load_bindings)(world, &contents);
+                    Ok(())
+                }
+                WatchedAsset::Shader(shader) => engine_state.rebuild_pipeline(&contents, shader),
+            };
+            match result {
+                Ok(()) => log::info!("hot reload: reloaded {:?}", asset),
+                Err(e) => log::error!("hot reload: keeping the old {:?}: {:#}", asset, e),
+            }
+        }
+    }
+}
+
+// Validates then installs the bindings; a bad file is logged and leaves the old ones in place
+fn load_bindings<A: DeserializeOwned + Hash + Eq + 'static>(world: &mut World, ron: &str) {
+    let report = validate_bindings::<A>(ron);
+    if !report.is_empty() {
+        log::error!("Failed to load bindings: {}", report);
+        return;
+    }
+    if let Ok(descriptor) = ron::from_str::<BindingsDescriptor<A>>(ron) {
+        world.add_resource(Bindings::<A>::from_descriptor(descriptor));
+    }
+}
+
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+fn watch_assets<G: GameSetup>(game_setup: &G) -> Option<HotReload> {
+    let mut files = vec![
+        (PathBuf::from(SHADER_PATH), WatchedAsset::Shader(WatchedShader::Main)),
+        (PathBuf::from(WIREFRAME_SHADER_PATH), WatchedAsset::Shader(WatchedShader::Wireframe)),
+        (PathBuf::from(POST_PROCESS_SHADER_PATH), WatchedAsset::Shader(WatchedShader::PostProcess)),
+        (PathBuf::from(BLOOM_SHADER_PATH), WatchedAsset::Shader(WatchedShader::Bloom)),
+        (PathBuf::from(SHADOW_SHADER_PATH), WatchedAsset::Shader(WatchedShader::Shadow)),
+    ];
+    files.extend(game_setup.world_ron_path().map(|path| (PathBuf::from(path), WatchedAsset::World)));
+    files.extend(game_setup.bindings_ron_path().map(|path| (PathBuf::from(path), WatchedAsset::Bindings)));
+    match AssetWatcher::new(files) {
+        Ok(watcher) => Some(HotReload { watcher, load_bindings: load_bindings::<G::Action> }),
+        Err(e) => {
+            log::error!("hot reload: couldn't start watching: {:#}", e);
+            None
+        }
+    }
 }
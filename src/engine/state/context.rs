@@ -1,6 +1,6 @@
 use winit::window::Window;
 
-use crate::engine::ui::egui_state::EguiState;
+use crate::engine::{ post_process::PostProcess, shadow::ShadowMaps, ui::egui_state::EguiState };
 
 pub struct GpuContext<'a> {
     pub device: &'a wgpu::Device,
@@ -15,10 +15,13 @@ pub struct RenderContext<'a> {
     pub depth_texture_view: &'a wgpu::TextureView,
     pub camera_bind_group: Option<&'a wgpu::BindGroup>,
     pub light_bind_group: &'a wgpu::BindGroup,
+    pub light_bind_group_no_shadows: &'a wgpu::BindGroup,
+    pub shadow_maps: &'a ShadowMaps,
+    // The shadow map layers in use this frame, from `ShadowBuffers`
+    pub shadow_layers: &'a [usize],
+    pub post_process: &'a PostProcess,
     pub render_pipeline: &'a wgpu::RenderPipeline,
     pub wireframe_render_pipeline: &'a wgpu::RenderPipeline,
-    pub msaa_texture_view: &'a wgpu::TextureView,
-    pub msaa_depth_texture_view: &'a wgpu::TextureView,
 }
 
 pub struct EguiContext<'a> {
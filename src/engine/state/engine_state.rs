@@ -5,28 +5,40 @@ use winit::window::Window;
 use crate::engine::{
     ecs::components::camera::camera::Camera,
     instance::InstanceRaw,
-    light::LightUniform,
-    model::vertex::{ ModelVertex, Vertex },
+    light::{ self, LightsUniform },
+    model::{ material, vertex::{ ModelVertex, Vertex } },
+    post_process::{ self, PostProcess },
     render_pipeline::{ create_render_pipeline, create_wireframe_render_pipeline },
+    shadow::ShadowMaps,
     state::context::{ RenderContext },
     texture::{ self, Texture },
 };
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::engine::{
+    assets::hot_reload::WatchedShader,
+    render_pipeline::create_shadow_pipeline,
+    shadow::SHADOW_FORMAT,
+};
 
 pub struct EngineState {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface_config: wgpu::SurfaceConfiguration,
     pub surface: wgpu::Surface<'static>,
+    // What the scene is drawn in before post-processing - HDR where the adapter allows
+    pub scene_format: wgpu::TextureFormat,
     pub render_pipeline: wgpu::RenderPipeline,
+    // Kept so the pipelines can be rebuilt when the shaders are hot reloaded
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    render_pipeline_layout: wgpu::PipelineLayout,
     pub depth_texture: Texture,
-    pub light_uniform: LightUniform,
     pub light_buffer: wgpu::Buffer,
+    // For instances with `ReceivesShadows`, and for the rest
     pub light_bind_group: wgpu::BindGroup,
+    pub light_bind_group_no_shadows: wgpu::BindGroup,
+    pub shadow_maps: ShadowMaps,
+    pub post_process: PostProcess,
     pub wireframe_render_pipeline: wgpu::RenderPipeline,
-    pub msaa_texture: wgpu::Texture,
-    pub msaa_texture_view: wgpu::TextureView,
-    pub msaa_depth_texture: wgpu::Texture,
-    pub msaa_depth_texture_view: wgpu::TextureView,
 }
 
 impl EngineState {
@@ -94,6 +106,8 @@ impl EngineState {
         };
 
         surface.configure(&device, &surface_config);
+        let scene_format = post_process::scene_format(&adapter);
+        let post_process = PostProcess::new(&device, scene_format, surface_config.format);
 
         // Depth texture //
         let depth_texture = Texture::create_depth_texture(
@@ -102,70 +116,26 @@ impl EngineState {
             "depth_texture"
         );
 
-        // Global Lighting Setup //
-        let light_uniform = LightUniform {
-            position: [2.0, 2.0, 2.0],
-            _padding: 0,
-            color: [0.443, 0.941, 0.922],
-            __padding: 0,
-        };
-
+        // Lighting Setup //
+        // Filled in each frame by light_sync_system from the light components
         let light_buffer = device.create_buffer_init(
             &(wgpu::util::BufferInitDescriptor {
                 label: Some("Light Buffer"),
-                contents: bytemuck::cast_slice(&[light_uniform]),
+                contents: bytemuck::cast_slice(&[LightsUniform::empty()]),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             })
         );
 
-        let light_bind_group_layout = device.create_bind_group_layout(
-            &(wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-            })
-        );
-
-        let light_bind_group = device.create_bind_group(
-            &(wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &light_bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: light_buffer.as_entire_binding(),
-                    },
-                ],
-            })
+        let shadow_maps = ShadowMaps::new(&device);
+        let light_bind_group_layout = light::bind_group_layout(&device);
+        let [light_bind_group, light_bind_group_no_shadows] = light::create_bind_groups(
+            &device,
+            &light_bind_group_layout,
+            &light_buffer,
+            &shadow_maps
         );
 
-        let color_bind_group_layout = device.create_bind_group_layout(
-            &(wgpu::BindGroupLayoutDescriptor {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    },
-                ],
-            })
-        );
+        let material_bind_group_layout = material::bind_group_layout(&device);
 
         // Render Pipeline Definition //
         let camera_bind_group_layout = Camera::create_bind_group_layout(&device);
@@ -175,7 +145,7 @@ impl EngineState {
                 bind_group_layouts: &[
                     &camera_bind_group_layout,
                     &light_bind_group_layout,
-                    &color_bind_group_layout,
+                    &material_bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             })
@@ -190,7 +160,7 @@ impl EngineState {
         let render_pipeline: wgpu::RenderPipeline = create_render_pipeline(
             &device,
             &render_pipeline_layout,
-            surface_config.format,
+            scene_format,
             Some(texture::Texture::DEPTH_FORMAT),
             &[ModelVertex::desc(), InstanceRaw::desc()],
             shader
@@ -203,68 +173,29 @@ impl EngineState {
         let wireframe_render_pipeline: wgpu::RenderPipeline = create_wireframe_render_pipeline(
             &device,
             &render_pipeline_layout,
-            surface_config.format,
+            scene_format,
             Some(texture::Texture::DEPTH_FORMAT),
             &[ModelVertex::desc(), InstanceRaw::desc()],
             wireframe_shader
         );
 
-        // MSAA setup //
-        let msaa_texture = device.create_texture(
-            &(wgpu::TextureDescriptor {
-                label: Some("MSAA Framebuffer"),
-                size: wgpu::Extent3d {
-                    width: surface_config.width,
-                    height: surface_config.height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 4,
-                dimension: wgpu::TextureDimension::D2,
-                format: surface_config.format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[], // Add an empty slice for view_formats
-            })
-        );
-        let msaa_texture_view = msaa_texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let depth_format = wgpu::TextureFormat::Depth32Float;
-
-        let msaa_depth_texture = device.create_texture(
-            &(wgpu::TextureDescriptor {
-                label: Some("MSAA Depth Texture"),
-                size: wgpu::Extent3d {
-                    width: surface_config.width,
-                    height: surface_config.height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 4, // match MSAA color
-                dimension: wgpu::TextureDimension::D2,
-                format: depth_format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[],
-            })
-        );
-        let msaa_depth_texture_view = msaa_depth_texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
-
         Ok((
             Self {
                 device,
                 queue,
                 surface,
                 surface_config,
+                scene_format,
                 render_pipeline,
+                #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+                render_pipeline_layout,
                 depth_texture,
-                light_uniform,
                 light_buffer,
                 light_bind_group,
+                light_bind_group_no_shadows,
+                shadow_maps,
+                post_process,
                 wireframe_render_pipeline,
-                msaa_texture,
-                msaa_texture_view,
-                msaa_depth_texture,
-                msaa_depth_texture_view,
             },
             camera_bind_group_layout,
         ))
@@ -275,56 +206,89 @@ impl EngineState {
         self.surface_config.width = width;
         self.surface_config.height = height;
         self.surface.configure(&self.device, &self.surface_config);
+        // The scene and post-processing targets belong to the render graph, which resizes them on
+        // its next run
+    }
 
-        // Recreate MSAA color texture and view, we need to do this
-        // since the mssaa texture will error if we try to draw it to
-        // our resized surface without first changing their width and height
-        self.msaa_texture = self.device.create_texture(
-            &(wgpu::TextureDescriptor {
-                label: Some("MSAA Framebuffer"),
-                size: wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 4,
-                dimension: wgpu::TextureDimension::D2,
-                format: self.surface_config.format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[],
-            })
-        );
-        self.msaa_texture_view = self.msaa_texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
+    /// Rebuilds the pipelines that use `shader` from new WGSL. On a compile or validation error
+    /// the current ones stay in use.
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub fn rebuild_pipeline(&mut self, wgsl: &str, shader: WatchedShader) -> std::result::Result<(), Error> {
+        let descriptor = wgpu::ShaderModuleDescriptor {
+            label: Some("Reloaded Shader"),
+            source: wgpu::ShaderSource::Wgsl(wgsl.into()),
+        };
+        let device = &self.device;
+        let model_buffers = [ModelVertex::desc(), InstanceRaw::desc()];
 
-        // Recreate MSAA depth texture and view
-        let depth_format = wgpu::TextureFormat::Depth32Float;
-        self.msaa_depth_texture = self.device.create_texture(
-            &(wgpu::TextureDescriptor {
-                label: Some("MSAA Depth Texture"),
-                size: wgpu::Extent3d {
-                    width,
-                    height,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 4,
-                dimension: wgpu::TextureDimension::D2,
-                format: depth_format,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                view_formats: &[],
-            })
-        );
-        self.msaa_depth_texture_view = self.msaa_depth_texture.create_view(
-            &wgpu::TextureViewDescriptor::default()
-        );
+        // Catch the errors instead of letting wgpu's default handler panic. Everything is built
+        // before anything is replaced, so a bad shader leaves the old pipelines alone
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        match shader {
+            WatchedShader::Main | WatchedShader::Wireframe => {
+                let build = match shader {
+                    WatchedShader::Wireframe => create_wireframe_render_pipeline,
+                    _ => create_render_pipeline,
+                };
+                let pipeline = build(
+                    device,
+                    &self.render_pipeline_layout,
+                    self.scene_format,
+                    Some(texture::Texture::DEPTH_FORMAT),
+                    &model_buffers,
+                    descriptor
+                );
+                pop_validation_error(device)?;
+                if shader == WatchedShader::Wireframe {
+                    self.wireframe_render_pipeline = pipeline;
+                } else {
+                    self.render_pipeline = pipeline;
+                }
+            }
+            WatchedShader::PostProcess => {
+                let module = device.create_shader_module(descriptor);
+                let pipelines = post_process::create_effect_pipelines(
+                    device,
+                    &self.post_process.effect_pipeline_layout,
+                    self.scene_format,
+                    self.surface_config.format,
+                    &module
+                );
+                pop_validation_error(device)?;
+                self.post_process.effect_pipelines = pipelines;
+            }
+            WatchedShader::Bloom => {
+                let module = device.create_shader_module(descriptor);
+                let [downsample_first, downsample, upsample] = post_process::create_bloom_pipelines(
+                    device,
+                    &self.post_process.bloom_pipeline_layout,
+                    self.scene_format,
+                    &module
+                );
+                pop_validation_error(device)?;
+                self.post_process.downsample_first_pipeline = downsample_first;
+                self.post_process.downsample_pipeline = downsample;
+                self.post_process.upsample_pipeline = upsample;
+            }
+            WatchedShader::Shadow => {
+                let pipeline = create_shadow_pipeline(
+                    device,
+                    &self.shadow_maps.pipeline_layout,
+                    SHADOW_FORMAT,
+                    &model_buffers,
+                    descriptor
+                );
+                pop_validation_error(device)?;
+                self.shadow_maps.pipeline = pipeline;
+            }
+        }
+        Ok(())
     }
 
     pub(crate) fn render_context<'a>(
         &'a self,
-        camera_bind_group: Option<&'a wgpu::BindGroup>
+        camera_bind_group: Option<&'a wgpu::BindGroup>,
+        shadow_layers: &'a [usize]
     ) -> RenderContext<'a> {
         RenderContext {
             device: &self.device,
@@ -334,10 +298,20 @@ impl EngineState {
             depth_texture_view: &self.depth_texture.view,
             camera_bind_group: camera_bind_group,
             light_bind_group: &self.light_bind_group,
+            light_bind_group_no_shadows: &self.light_bind_group_no_shadows,
+            shadow_maps: &self.shadow_maps,
+            shadow_layers,
+            post_process: &self.post_process,
             render_pipeline: &self.render_pipeline,
             wireframe_render_pipeline: &self.wireframe_render_pipeline,
-            msaa_texture_view: &self.msaa_texture_view,
-            msaa_depth_texture_view: &self.msaa_depth_texture_view,
         }
     }
 }
+
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+fn pop_validation_error(device: &wgpu::Device) -> std::result::Result<(), Error> {
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(anyhow::anyhow!("{}", error)),
+        None => Ok(()),
+    }
+}
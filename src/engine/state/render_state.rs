@@ -1,31 +1,87 @@
-use crate::engine::state::context::{ EguiContext, RenderContext };
-use crate::engine::{ model::model::Model };
-use crate::engine::model::model::DrawModel;
+use crate::engine::{
+    ecs::world::World,
+    model::model::Model,
+    render_graph::{
+        graph::RenderGraph,
+        node::{ BLOOM_MIPS, HDR, POST_TARGETS, SCENE_COLOR, SCENE_DEPTH },
+        nodes::{
+            ClearNode,
+            MeshNode,
+            MeshPass,
+            PostProcessNode,
+            ResolveNode,
+            ShadowNode,
+            UiNode,
+        },
+        textures::TransientTexture,
+    },
+    state::context::{ EguiContext, RenderContext },
+    texture::Texture,
+};
+
+// Matches the mesh pipelines (render_pipeline.rs)
+const MSAA_SAMPLES: u32 = 4;
 
 pub struct RenderState {
-    clear_color: wgpu::Color,
-    // text_brush: TextBrush<FontVec>,
+    graph: RenderGraph,
 }
 
 impl RenderState {
-    pub fn new() -> Self {
-        RenderState {
-            clear_color: wgpu::Color {
+    /// `scene_format` is `EngineState::scene_format`, which the mesh pipelines draw in.
+    pub fn new(scene_format: wgpu::TextureFormat) -> Self {
+        let mut graph = RenderGraph::new();
+        graph.add_texture(SCENE_COLOR, TransientTexture::new(scene_format).with_samples(MSAA_SAMPLES));
+        graph.add_texture(SCENE_DEPTH, TransientTexture::new(Texture::DEPTH_FORMAT).with_samples(MSAA_SAMPLES));
+        graph.add_texture(HDR, TransientTexture::new(scene_format).sampled());
+        for target in POST_TARGETS {
+            graph.add_texture(target, TransientTexture::new(scene_format).sampled());
+        }
+        // Half the surface's size, then a quarter, ...
+        let mut scale = 1.0;
+        for mip in BLOOM_MIPS {
+            scale *= 0.5;
+            graph.add_texture(mip, TransientTexture::new(scene_format).with_scale(scale).sampled());
+        }
+
+        graph.add_node(ShadowNode);
+        graph.add_node(ClearNode {
+            color: wgpu::Color {
                 r: 0.081,
                 g: 0.084,
                 b: 0.14,
                 a: 1.0,
             },
-        }
+        });
+        graph.add_node(MeshNode(MeshPass::WireframesBehind));
+        graph.add_node(MeshNode(MeshPass::Opaque));
+        graph.add_node(MeshNode(MeshPass::WireframesOnTop));
+        graph.add_node(ResolveNode);
+        graph.add_node(PostProcessNode::default());
+        RenderState { graph }
+    }
+
+    /// For `GameSetup::setup_render_graph` to add nodes to.
+    pub fn graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.graph
+    }
+
+    pub fn graph(&self) -> &RenderGraph {
+        &self.graph
+    }
+
+    /// Adds the UI on top of whatever the game added, then orders the graph.
+    pub(crate) fn finish_graph(&mut self) -> anyhow::Result<()> {
+        self.graph.add_node(UiNode);
+        self.graph.build()
     }
 
     pub fn handle_redraw(
         &mut self,
         render_context: RenderContext,
-        ecs_models: &[Model],
+        ecs_models: &[&Model],
+        world: &World,
         egui_context: EguiContext
     ) {
-        // Mesh Rendering //
         let surface_texture = render_context.surface
             .get_current_texture()
             .expect("Failed to acquire next swap chain texture");
@@ -38,143 +94,30 @@ impl RenderState {
             &(wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") })
         );
 
-        {
-            // Clear pass
-            command_encoder.begin_render_pass(
-                &(wgpu::RenderPassDescriptor {
-                    color_attachments: &[
-                        Some(wgpu::RenderPassColorAttachment {
-                            view: &render_context.msaa_texture_view,
-                            resolve_target: Some(&surface_view),
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Clear(self.clear_color),
-                                store: wgpu::StoreOp::Store,
-                            },
-                        }),
-                    ],
-                    depth_stencil_attachment: None,
-                    ..Default::default()
-                })
-            );
-        }
-
-        // We use a guard scope here because render_pass holds a borrow of
-        // the command encoder, so we need to drop it before calling encoder.finish()
-        if let Some(camera_bind_group) = render_context.camera_bind_group {
-            // Render pass init
-            let mut render_pass: wgpu::RenderPass<'_> = command_encoder.begin_render_pass(
-                &(wgpu::RenderPassDescriptor {
-                    label: Some("Render Pass"),
-                    color_attachments: &[
-                        Some(wgpu::RenderPassColorAttachment {
-                            view: &render_context.msaa_texture_view, // render into MSAA texture
-                            resolve_target: Some(&surface_view), // resolve to swap chain
-                            ops: wgpu::Operations {
-                                load: wgpu::LoadOp::Load,
-                                store: wgpu::StoreOp::Store,
-                            },
-                        }),
-                    ],
-                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &render_context.msaa_depth_texture_view,
-                        depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(1.0),
-                            store: wgpu::StoreOp::Store,
-                        }),
-                        stencil_ops: None,
-                    }),
-                    occlusion_query_set: None,
-                    timestamp_writes: None,
-                })
-            );
-
-            // Render pass
-            // 1. Render wireframes that should be behind transparent objects
-            render_pass.set_pipeline(render_context.wireframe_render_pipeline);
-            for model in ecs_models {
-                for mesh in &model.meshes {
-                    // Only render if this mesh is transparent (has alpha < 1.0)
-                    if mesh._material.alpha < 1.0 {
-                        match &mesh.instance_buffer {
-                            Some(instance_buffer) => {
-                                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-                                render_pass.draw_mesh_instanced(
-                                    mesh,
-                                    0..mesh.instance_count,
-                                    camera_bind_group,
-                                    render_context.light_bind_group,
-                                    &mesh.color_bind_group,
-                                    true
-                                );
-                            }
-                            None => {
-                                continue;
-                            }
-                        }
-                    }
-                }
-            }
-
-            // 2. Opaque geometry
-            render_pass.set_pipeline(render_context.render_pipeline);
-            for model in ecs_models {
-                for mesh in &model.meshes {
-                    match &mesh.instance_buffer {
-                        Some(instance_buffer) => {
-                            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-                            render_pass.draw_mesh_instanced(
-                                // Changed this line
-                                mesh, // Pass mesh instead of model
-                                0..mesh.instance_count,
-                                camera_bind_group,
-                                render_context.light_bind_group,
-                                &mesh.color_bind_group,
-                                false
-                            );
-                        }
-                        None => {
-                            continue;
-                        }
-                    }
-                }
-            }
-            // 3. Render wireframes on top
-            render_pass.set_pipeline(render_context.wireframe_render_pipeline);
-            for model in ecs_models {
-                for mesh in &model.meshes {
-                    if mesh._material.alpha < 1.0 {
-                        match &mesh.instance_buffer {
-                            Some(instance_buffer) => {
-                                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
-                                render_pass.draw_mesh_instanced(
-                                    mesh,
-                                    0..mesh.instance_count,
-                                    camera_bind_group,
-                                    render_context.light_bind_group,
-                                    &mesh.color_bind_group,
-                                    false
-                                );
-                            }
-                            None => {
-                                continue;
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        // egui pass — composites the UI on top of the 3D scene
-        egui_context.state.render(
-            render_context.device,
-            render_context.queue,
-            &mut command_encoder,
-            &surface_view,
-            egui_context.window,
-            egui_context.full_output
-        );
+        self.graph.run(&render_context, ecs_models, world, &mut command_encoder, &surface_view, egui_context);
 
         render_context.queue.submit(Some(command_encoder.finish()));
         surface_texture.present();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn post_processing_runs_between_the_resolve_and_the_ui() {
+        let mut render_state = RenderState::new(wgpu::TextureFormat::Rgba16Float);
+        render_state.finish_graph().unwrap();
+        assert_eq!(render_state.graph().order(), vec![
+            "shadows",
+            "clear",
+            "wireframes_behind",
+            "opaque",
+            "wireframes_on_top",
+            "resolve",
+            "post_process",
+            "ui",
+        ]);
+    }
+}
@@ -20,14 +20,63 @@ impl Texture {
         Self::from_image(device, queue, &loaded_image, Some(label))
     }
 
+    /// Normal maps hold directions, not colours, so they skip the sRGB decode `from_bytes` does.
+    pub fn normal_map_from_bytes(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+        label: &str
+    ) -> Result<Self, ImageError> {
+        let loaded_image = image::load_from_memory(bytes)?;
+        Ok(
+            Self::from_rgba(
+                device,
+                queue,
+                &loaded_image.to_rgba8(),
+                loaded_image.dimensions(),
+                wgpu::TextureFormat::Rgba8Unorm,
+                Some(label)
+            )
+        )
+    }
+
+    /// A 1x1 texture, e.g. to stand in for a map a material doesn't have.
+    pub fn from_color(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        rgba: [u8; 4],
+        format: wgpu::TextureFormat,
+        label: &str
+    ) -> Self {
+        Self::from_rgba(device, queue, &rgba, (1, 1), format, Some(label))
+    }
+
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         dynamic_image: &image::DynamicImage,
         label: Option<&str>
     ) -> Result<Self, ImageError> {
-        let image_as_rgba = dynamic_image.to_rgba8();
-        let dimensions = dynamic_image.dimensions();
+        Ok(
+            Self::from_rgba(
+                device,
+                queue,
+                &dynamic_image.to_rgba8(),
+                dynamic_image.dimensions(),
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                label
+            )
+        )
+    }
+
+    fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image_as_rgba: &[u8],
+        dimensions: (u32, u32),
+        format: wgpu::TextureFormat,
+        label: Option<&str>
+    ) -> Self {
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
@@ -42,7 +91,7 @@ impl Texture {
                 mip_level_count: 1,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                format,
                 usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
                 view_formats: &[],
             })
@@ -55,7 +104,7 @@ impl Texture {
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
             },
-            &image_as_rgba,
+            image_as_rgba,
             wgpu::TexelCopyBufferLayout {
                 offset: 0,
                 bytes_per_row: Some(4 * dimensions.0),
@@ -67,9 +116,10 @@ impl Texture {
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(
             &(wgpu::SamplerDescriptor {
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                // Model UVs are allowed to tile
+                address_mode_u: wgpu::AddressMode::Repeat,
+                address_mode_v: wgpu::AddressMode::Repeat,
+                address_mode_w: wgpu::AddressMode::Repeat,
                 mag_filter: wgpu::FilterMode::Linear,
                 min_filter: wgpu::FilterMode::Nearest,
                 mipmap_filter: wgpu::FilterMode::Nearest,
@@ -77,11 +127,18 @@ impl Texture {
             })
         );
 
-        Ok(Self {
+        Self {
             texture,
             view,
             sampler,
-        })
+        }
+    }
+
+    /// Bytes of texel data on the GPU (mip level 0 only - nothing here generates mips yet).
+    pub fn gpu_bytes(&self) -> u64 {
+        let size = self.texture.size();
+        let texel_bytes = self.texture.format().block_copy_size(None).unwrap_or(0);
+        (size.width as u64) * (size.height as u64) * (size.depth_or_array_layers as u64) * (texel_bytes as u64)
     }
 
     pub fn create_depth_texture(
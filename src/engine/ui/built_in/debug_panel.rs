@@ -1,7 +1,13 @@
 use egui::Color32;
 
 use crate::{
-    engine::{ ecs::{ resources::debug::ShowDebugPanel, world::World }, fps_counter::FpsCounter },
+    engine::{
+        ecs::{
+            resources::{ debug::ShowDebugPanel, post_processing::{ Effect, PostProcessing, Tonemapping } },
+            world::World,
+        },
+        fps_counter::FpsCounter,
+    },
     game::input::{ actions::Action, world_ext::InputWorldExt },
 };
 
@@ -29,6 +35,7 @@ pub fn debug_panel(context: &egui::Context, world: &mut World) {
         .map(|f| f.get_fps())
         .unwrap_or(0.0);
     let n_entities = world.live_entity_count();
+    let post_processing = world.get_resource_mut::<PostProcessing>();
 
     egui::Window
         ::new("Debug")
@@ -43,5 +50,51 @@ pub fn debug_panel(context: &egui::Context, world: &mut World) {
             ui.label(
                 egui::RichText::new(format!("Entities: {}", n_entities)).color(Color32::WHITE)
             );
+            if let Some(settings) = post_processing {
+                ui.collapsing("Post-processing", |ui| post_processing_controls(ui, settings));
+            }
         });
 }
+
+// Edits the resource in place - the node picks it up next frame. A checkbox per entry of the
+// chain, in its order, with that effect's settings under it
+fn post_processing_controls(ui: &mut egui::Ui, settings: &mut PostProcessing) {
+    for (i, entry) in settings.effects.iter_mut().enumerate() {
+        ui.push_id(i, |ui| {
+            ui.checkbox(&mut entry.enabled, entry.effect.name());
+            ui.add_enabled_ui(entry.enabled, |ui| effect_controls(ui, &mut entry.effect));
+        });
+    }
+}
+
+fn effect_controls(ui: &mut egui::Ui, effect: &mut Effect) {
+    match effect {
+        Effect::ChromaticAberration(strength) => {
+            ui.add(egui::Slider::new(strength, 0.0..=0.02).text("Strength"));
+        }
+        Effect::Bloom(bloom) => {
+            ui.add(egui::Slider::new(&mut bloom.intensity, 0.0..=0.5).text("Intensity"));
+            ui.add(egui::Slider::new(&mut bloom.filter_radius, 0.001..=0.02).text("Radius"));
+        }
+        Effect::Exposure(exposure) => {
+            ui.add(egui::Slider::new(exposure, 0.0..=4.0).text("Exposure"));
+        }
+        Effect::Tonemapping(tonemapping) => {
+            egui::ComboBox
+                ::from_label("Curve")
+                .selected_text(format!("{:?}", tonemapping))
+                .show_ui(ui, |ui| {
+                    for curve in [Tonemapping::Reinhard, Tonemapping::Aces] {
+                        ui.selectable_value(tonemapping, curve, format!("{:?}", curve));
+                    }
+                });
+        }
+        Effect::Vignette(vignette) => {
+            ui.add(egui::Slider::new(&mut vignette.intensity, 0.0..=1.0).text("Intensity"));
+            ui.add(egui::Slider::new(&mut vignette.radius, 0.0..=1.0).text("Radius"));
+        }
+        Effect::FilmGrain(intensity) => {
+            ui.add(egui::Slider::new(intensity, 0.0..=0.2).text("Intensity"));
+        }
+    }
+}
@@ -16,16 +16,17 @@ use crate::{
             STARFIGHTER_MODEL_OBJ,
             STARFIGHTER_PLAYER_MTL,
         },
+        resources::model_resources::GameModels,
     },
 };
 
 pub fn load_and_register_world_models(
     gpu_context: &GpuContext,
     asset_server: &mut AssetServer,
-    _world: &mut World
+    world: &mut World
 ) {
     // Player
-    load_obj(
+    let starfighter = load_obj(
         "starfighter",
         STARFIGHTER_MODEL_OBJ,
         STARFIGHTER_PLAYER_MTL,
@@ -36,7 +37,7 @@ pub fn load_and_register_world_models(
     );
 
     // Enemy
-    load_obj(
+    let starfighter_enemy = load_obj(
         "starfighter_enemy",
         STARFIGHTER_MODEL_OBJ,
         STARFIGHTER_ENEMY_MTL,
@@ -46,8 +47,10 @@ pub fn load_and_register_world_models(
         asset_server
     );
     // Cube
-    load_obj("cube", CUBE_PREFAB_OBJ, CUBE_PREFAB_MTL, &gpu_context, None, 64, asset_server);
+    let cube = load_obj("cube", CUBE_PREFAB_OBJ, CUBE_PREFAB_MTL, &gpu_context, None, 64, asset_server);
 
     // Laser
-    load_obj("laser", LASER_MODEL_OBJ, LASER_MODEL_MTL, gpu_context, None, 1024, asset_server);
+    let laser = load_obj("laser", LASER_MODEL_OBJ, LASER_MODEL_MTL, gpu_context, None, 1024, asset_server);
+
+    world.add_resource(GameModels { starfighter, starfighter_enemy, cube, laser });
 }
@@ -5,8 +5,16 @@ use crate::{
         assets::server::AssetServer,
         ecs::{
             component_registry::ComponentRegistry,
-            resources::debug::{ ShowColliderDebug, ShowDebugPanel },
+            components::transform::Transform,
+            condition::{ in_state, on_event },
+            resources::{
+                debug::{ ShowColliderDebug, ShowDebugPanel },
+                lighting::Lighting,
+                post_processing::{ Bloom, Effect, PostEffect, PostProcessing, Tonemapping, Vignette },
+            },
             system::{ SystemContext, SystemSchedule },
+            entity_ref::EntityNames,
+            system_config::{ IntoSystemConfig, SystemSet },
             world::World,
         },
         game_setup::GameSetup,
@@ -21,26 +29,31 @@ use crate::{
         input::actions::Action,
         resources::{
             enemy_resources::EnemySpawnManager,
+            game_state::{ GameState, PauseState },
             laser_resources::LaserManager,
-            move_player::MovePlayer,
-            terrain_resources::{ TerrainGeneration, TerrainModelIds },
+            terrain_resources::{ TerrainGeneration, TerrainModels },
         },
         systems::{
             camera_control_system::camera_control_system,
             collider_debug_system::collider_debug_system,
             collision_log_system::collision_log_system,
             enemy_spawn_system::enemy_spawn_system,
+            game_over_system::game_over_system,
+            game_state_system::game_state_system,
             hover_system::hover_system,
             laser_log_system::laser_log_system,
             laser_system::laser_system,
             player_system::player_system,
+            reset_run_system::reset_run_system,
             terrain_system::terrain_system,
         },
+        ui::state_panel::state_panel,
     },
 };
 
 const TERRAIN_WIDTH: u32 = 50;
 const TERRAIN_LENGTH: u32 = 150;
+const CAMERA_OFFSET: Vector3<f32> = Vector3::new(0.0, 0.75, -2.0);
 
 pub struct CanyonRunnerWorld;
 
@@ -48,18 +61,33 @@ impl GameSetup for CanyonRunnerWorld {
     type Action = Action;
 
     fn setup_ecs(&self, schedule: &mut SystemSchedule) {
+        schedule.init_state(GameState::Menu);
+        schedule.init_state(PauseState::Running);
+        schedule.configure_set(
+            SystemSet::new("gameplay")
+                .after("game_state_system")
+                .run_if(in_state(GameState::Playing))
+                .run_if(in_state(PauseState::Running))
+        );
+        schedule.add_on_exit(GameState::GameOver, reset_run_system);
+
         schedule.add_game_system(camera_control_system);
         schedule.add_game_system(hover_system);
-        schedule.add_game_system(player_system);
+        schedule.add_game_system(game_state_system);
+        schedule.add_game_system(player_system.label("gameplay"));
         schedule.add_game_system(terrain_system);
-        schedule.add_game_system(laser_system);
-        schedule.add_game_system(laser_log_system);
-        schedule.add_game_system(enemy_spawn_system);
+        schedule.add_game_system(laser_system.label("gameplay"));
+        schedule.add_game_system(
+            laser_log_system.after("laser_system").run_if(on_event::<LaserFiredEvent>())
+        );
+        schedule.add_game_system(enemy_spawn_system.label("gameplay"));
+        schedule.add_game_system(game_over_system.label("gameplay"));
         schedule.add_game_system(collider_debug_system);
     }
 
     fn setup_ui(&self, ui_registry: &mut crate::engine::ui::ui_registry::UIRegistry) {
         ui_registry.add(debug_panel);
+        ui_registry.add(state_panel);
     }
 
     fn register_components(&self, registry: &mut ComponentRegistry) {
@@ -72,10 +100,26 @@ impl GameSetup for CanyonRunnerWorld {
         Some(include_str!("../../assets/worlds/canyon_runner.ron"))
     }
 
+    fn world_ron_path(&self) -> Option<&'static str> {
+        Some(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/worlds/canyon_runner.ron"))
+    }
+
+    fn prefabs_ron(&self) -> Vec<(&'static str, &'static str)> {
+        vec![
+            ("fighter", include_str!("../../assets/prefabs/fighter.ron")),
+            ("player_fighter", include_str!("../../assets/prefabs/player_fighter.ron")),
+            ("enemy_fighter", include_str!("../../assets/prefabs/enemy_fighter.ron")),
+        ]
+    }
+
     fn bindings_ron(&self) -> Option<&'static str> {
         Some(include_str!("../../assets/bindings.ron"))
     }
 
+    fn bindings_ron_path(&self) -> Option<&'static str> {
+        Some(concat!(env!("CARGO_MANIFEST_DIR"), "/assets/bindings.ron"))
+    }
+
     fn load_assets(
         &self,
         gpu_context: &GpuContext,
@@ -85,16 +129,34 @@ impl GameSetup for CanyonRunnerWorld {
         load_and_register_world_models(&gpu_context, asset_server, world);
     }
 
-    fn setup(&self, world: &mut World, system_context: &mut SystemContext) {
+    fn setup(&self, world: &mut World, system_context: &mut SystemContext, entities: &EntityNames) {
         let gpu = GpuContext {
             device: system_context.device.unwrap(),
             queue: system_context.queue.unwrap(),
         };
 
-        world.create_active_camera(gpu.device, Vector3::new(24.5, -0.25, 1.0));
+        // Camera starts just behind and above the player
+        let player_position = entities
+            .get("player")
+            .and_then(|player| world.get_component::<Transform>(*player))
+            .map_or(Vector3::new(24.5, -1.0, 3.0), |transform| transform.position);
+        world.create_active_camera(gpu.device, player_position + CAMERA_OFFSET);
         world.add_resource(FreeCameraEnabled(false));
         world.add_resource(ShowDebugPanel(false));
         world.add_resource(ShowColliderDebug(false));
+        // Bright enough that the shaded sides keep their colour
+        world.add_resource(Lighting { ambient: [0.4, 0.4, 0.45], ..Lighting::default() });
+        // Glowing neon wireframes, and a bit of a lens look
+        world.add_resource(PostProcessing {
+            effects: vec![
+                PostEffect::on(Effect::ChromaticAberration(0.004)),
+                PostEffect::on(Effect::Bloom(Bloom { intensity: 0.08, ..Bloom::default() })),
+                PostEffect::off(Effect::Exposure(1.0)),
+                PostEffect::on(Effect::Tonemapping(Tonemapping::Aces)),
+                PostEffect::on(Effect::Vignette(Vignette { intensity: 0.35, ..Vignette::default() })),
+                PostEffect::on(Effect::FilmGrain(0.03))
+            ],
+        });
         world.register_event::<LaserFiredEvent>();
 
         let asset_server: &mut AssetServer = system_context.asset_server.as_mut().unwrap();
@@ -102,18 +164,18 @@ impl GameSetup for CanyonRunnerWorld {
         // Laser setup
         world.add_resource(LaserManager::new());
 
-        // Player setup
-        world.add_resource(MovePlayer(true));
-
         // Enemy setup
+        world.on_add::<Enemy>(|world, _| {
+            if let Some(manager) = world.get_resource_mut::<EnemySpawnManager>() {
+                manager.n_enemies_spawned += 1;
+            }
+        });
         world.add_resource(EnemySpawnManager {
             n_enemies_spawned: 0,
             z_gap_between_spanws: 100.0,
             last_z_pos_spawned_at: 0.0,
             canyon_center_x: 24.5,
             enemy_spawn_elevation: -1.0,
-            enemy_spawn_scale: Vector3 { x: 0.3, y: 0.3, z: 0.3 },
-            enemy_entities: Vec::new(),
         });
         // Terrain setup
         let mut terrain_generation = TerrainGeneration {
@@ -125,14 +187,14 @@ impl GameSetup for CanyonRunnerWorld {
         };
 
         let [terrain_a, terrain_b, terrain_c] = get_initial_terrain(&mut terrain_generation, &gpu);
-        let terrain_model_ids = [
-            asset_server.register_model("terrain_a", terrain_a),
-            asset_server.register_model("terrain_b", terrain_b),
-            asset_server.register_model("terrain_c", terrain_c),
+        let terrain_models = [
+            asset_server.add_model(terrain_a),
+            asset_server.add_model(terrain_b),
+            asset_server.add_model(terrain_c),
         ];
 
         world.add_resource(terrain_generation);
-        world.add_resource(TerrainModelIds(terrain_model_ids));
+        world.add_resource(TerrainModels(terrain_models));
     }
 }
 
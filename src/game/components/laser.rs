@@ -2,7 +2,7 @@ use web_time::Instant;
 
 pub const DEFAULT_TRAVEL_SPEED: f32 = 30.0;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Laser {
     pub initial_z: f32,
     pub fired_at: Instant,
@@ -31,4 +31,5 @@ pub fn animate_hover(transform: &Transform, velocity: &mut Velocity, hover_state
 
 pub fn load_model(gpu_context: &GpuContext) -> Model {
     load_model_from_obj_bytes(STARFIGHTER_MODEL_OBJ, STARFIGHTER_PLAYER_MTL, gpu_context, None, 1)
+        .expect("Failed to parse starfighter OBJ")
 }
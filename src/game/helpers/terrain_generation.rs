@@ -4,8 +4,8 @@ use rand::{ Rng, SeedableRng, rng };
 
 use crate::{
     engine::{
-        model::{ material::Material, mesh, model::{ Model, ModelBounds }, vertex::ModelVertex },
-        resources,
+        assets::asset_storage::Assets,
+        model::{ material::Material, mesh_data::{ MeshData, ModelData }, model::Model },
         state::context::GpuContext,
     },
     game::{ helpers::procedural_generation, resources::terrain_resources::TerrainGeneration },
@@ -126,8 +126,16 @@ fn generate_canyon_mesh(
     }
 }
 
-/// Creates a `Model` object from the generated terrain data.
+/// Creates a `Model` object from the generated terrain data. Terrain is untextured.
 pub fn create_model_from_data(data: TerrainMeshData, gpu_context: &GpuContext) -> Model {
+    let mut model = Model::upload(terrain_model_data(data), gpu_context, &Assets::new(), None, 1);
+    // The canyon walls shade the floor
+    model.set_shadows(true, true);
+    model
+}
+
+/// The terrain and canyon floor meshes, before they're uploaded.
+pub fn terrain_model_data(data: TerrainMeshData) -> ModelData {
     let canyon_color = if RAINBOW_ROAD {
         let mut thread_rng = rng();
         VIBRANT_COLORS[thread_rng.random_range(0..VIBRANT_COLORS.len())]
@@ -135,35 +143,26 @@ pub fn create_model_from_data(data: TerrainMeshData, gpu_context: &GpuContext) -
         [236, 95, 255]
     };
 
-    let bounds = ModelBounds::from_vertices(
-        data.terrain_vertices.iter().chain(data.canyon_vertices.iter()).copied()
-    );
-
-    Model {
-        meshes: vec![
-            resources::load_mesh_from_arrays(
+    ModelData::new(
+        vec![
+            MeshData::new(
                 "terrain landscape",
                 data.terrain_vertices,
                 vec![],
+                vec![],
                 data.terrain_triangles,
-                gpu_context,
-                Material::new([60, 66, 98], 0.5),
-                None,
-                1
+                Material::new([60, 66, 98], 0.5)
             ),
-            resources::load_mesh_from_arrays(
+            MeshData::new(
                 "terrain canyon floor",
                 data.canyon_vertices,
                 vec![],
+                vec![],
                 data.canyon_triangles,
-                gpu_context,
-                Material::new(canyon_color, 1.0),
-                None,
-                1
+                Material::new(canyon_color, 1.0)
             )
-        ],
-        bounds,
-    }
+        ]
+    )
 }
 
 /// Generates new terrain mesh data for a chunk based on the player's position.
@@ -188,58 +187,3 @@ pub fn terrain_update(
         None
     }
 }
-
-pub fn replace_terrain_model_buffers(
-    terrain_mesh_data: TerrainMeshData,
-    old_terrain_model: &mut Model,
-    gpu_context: &GpuContext
-) {
-    let (terrain_mesh, canyon_mesh) = old_terrain_model.meshes.split_at_mut(1);
-    let terrain_mesh = &mut terrain_mesh[0];
-    let canyon_mesh = &mut canyon_mesh[0];
-
-    let terrain_normals = mesh::calculate_normals(
-        &terrain_mesh_data.terrain_vertices,
-        &terrain_mesh_data.terrain_triangles
-    );
-
-    let canyon_normals = mesh::calculate_normals(
-        &terrain_mesh_data.canyon_vertices,
-        &terrain_mesh_data.canyon_triangles
-    );
-
-    let terrain_vertices = (0..terrain_mesh_data.terrain_vertices.len())
-        .map(|i| {
-            ModelVertex {
-                position: [
-                    terrain_mesh_data.terrain_vertices[i][0],
-                    terrain_mesh_data.terrain_vertices[i][1],
-                    terrain_mesh_data.terrain_vertices[i][2],
-                ],
-                tex_coords: [0.0, 0.0],
-                normal: terrain_normals[i],
-            }
-        })
-        .collect::<Vec<_>>();
-
-    let canyon_vertices = (0..terrain_mesh_data.canyon_vertices.len())
-        .map(|i| {
-            ModelVertex {
-                position: [
-                    terrain_mesh_data.canyon_vertices[i][0],
-                    terrain_mesh_data.canyon_vertices[i][1],
-                    terrain_mesh_data.canyon_vertices[i][2],
-                ],
-                tex_coords: [0.0, 0.0],
-                normal: canyon_normals[i],
-            }
-        })
-        .collect::<Vec<_>>();
-
-    terrain_mesh.update_buffers(
-        gpu_context,
-        &terrain_vertices,
-        &terrain_mesh_data.terrain_triangles
-    );
-    canyon_mesh.update_buffers(gpu_context, &canyon_vertices, &terrain_mesh_data.canyon_triangles);
-}
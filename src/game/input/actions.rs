@@ -15,6 +15,7 @@ pub enum Action {
     MoveUp,
     MoveDown,
     Pause,
+    Confirm,
     //Builtins
     ToggleDebugPanel,
     ToggleColliderDebug,
@@ -7,3 +7,4 @@ pub mod helpers;
 pub mod assets;
 pub mod input;
 pub mod events;
+pub mod ui;
@@ -0,0 +1,16 @@
+// Menu -> Playing -> GameOver -> Menu. Lives in the world as `State<GameState>`, see
+// engine/ecs/state.rs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    Menu,
+    Playing,
+    GameOver,
+}
+
+// Toggled while Playing. A state of its own so pausing doesn't leave Playing, which would
+// despawn everything `StateScoped(GameState::Playing)`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PauseState {
+    Running,
+    Paused,
+}
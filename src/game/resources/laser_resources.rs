@@ -1,11 +1,9 @@
-use crate::engine::ecs::entity::Entity;
 use web_time::Instant;
 
 const MAX_TRAVEL_DISTANCE: f32 = 50.0;
 const FIRE_COOLDOWN_SECONDS: f32 = 0.75;
 
 pub struct LaserManager {
-    pub alive_lasers: Vec<Entity>,
     pub last_fired_time: Instant,
     pub fire_cooldown_seconds: f32,
     pub max_travel_distance: f32,
@@ -15,7 +13,6 @@ impl LaserManager {
     pub fn new() -> Self {
         Self {
             last_fired_time: Instant::now(),
-            alive_lasers: Vec::new(),
             fire_cooldown_seconds: FIRE_COOLDOWN_SECONDS,
             max_travel_distance: MAX_TRAVEL_DISTANCE,
         }
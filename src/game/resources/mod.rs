@@ -1,4 +1,5 @@
 pub mod laser_resources;
 pub mod terrain_resources;
-pub mod move_player;
+pub mod game_state;
 pub mod enemy_resources;
+pub mod model_resources;
@@ -0,0 +1,10 @@
+use crate::engine::{ assets::handle::Handle, model::model::Model };
+
+// Keeps the game's built-in models loaded for the whole run, so systems and the world RON can
+// keep finding them by name
+pub struct GameModels {
+    pub starfighter: Handle<Model>,
+    pub starfighter_enemy: Handle<Model>,
+    pub cube: Handle<Model>,
+    pub laser: Handle<Model>,
+}
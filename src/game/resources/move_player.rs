@@ -1 +0,0 @@
-pub struct MovePlayer(pub bool);
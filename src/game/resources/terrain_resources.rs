@@ -1,4 +1,7 @@
-pub struct TerrainModelIds(pub [usize; 3]);
+use crate::engine::{ assets::handle::Handle, model::model::Model };
+
+// The three live terrain chunks. Replacing a handle unloads the chunk it pointed to
+pub struct TerrainModels(pub [Handle<Model>; 3]);
 
 pub struct TerrainGeneration {
     pub terrain_width: u32,
@@ -28,10 +28,9 @@ pub fn collider_debug_system(world: &mut World, system_context: &mut SystemConte
         .map(|r| r.0)
         .unwrap_or(false);
 
-    let stale_visual_ids: Vec<u32> = world.get_entities_with::<DebugVisual>();
-    for entity_id in stale_visual_ids {
+    for (entity_id, _) in world.iter_component::<DebugVisual>() {
         if let Some(entity) = world.get_entity(entity_id) {
-            world.despawn(entity);
+            system_context.commands.despawn(entity);
         }
     }
 
@@ -39,49 +38,38 @@ pub fn collider_debug_system(world: &mut World, system_context: &mut SystemConte
         return;
     }
 
-    let cube_model_id = system_context.asset_server.as_deref().unwrap().get_model_id("cube");
-
-    let collider_snapshots: Vec<(u32, ColliderShape)> = world
-        .iter_component::<Collider>()
-        .map(|(entity_id, collider)| (entity_id, collider.shape.clone()))
-        .collect();
+    let Some(cube_model) = system_context.asset_server.as_deref().unwrap().get_model_handle("cube") else {
+        return;
+    };
 
-    let visuals: Vec<(Vector3<f32>, Vector3<f32>)> = collider_snapshots
-        .into_iter()
-        .filter_map(|(entity_id, shape)| {
-            let transform = world.get_component_by_id::<Transform>(entity_id)?;
-            let (offset, half_extents) = match shape {
-                ColliderShape::AABB { offset, half_extents } =>
-                    (
-                        offset,
-                        Vector3::new(
-                            half_extents.x * transform.scale.x,
-                            half_extents.y * transform.scale.y,
-                            half_extents.z * transform.scale.z
-                        ),
+    for (collider, transform) in world.query_iter::<(&Collider, &Transform)>() {
+        let (offset, half_extents) = match collider.shape {
+            ColliderShape::AABB { offset, half_extents } =>
+                (
+                    offset,
+                    Vector3::new(
+                        half_extents.x * transform.scale.x,
+                        half_extents.y * transform.scale.y,
+                        half_extents.z * transform.scale.z
                     ),
-                ColliderShape::Sphere { offset, radius } => {
-                    let r =
-                        radius * transform.scale.x.max(transform.scale.y).max(transform.scale.z);
-                    (offset, Vector3::new(r, r, r))
-                }
-            };
-            let scaled_offset = Vector3::new(
-                offset.x * transform.scale.x,
-                offset.y * transform.scale.y,
-                offset.z * transform.scale.z
-            );
-            let world_offset = transform.rotation * scaled_offset;
-            Some((transform.position + world_offset, half_extents))
-        })
-        .collect();
-
-    for (position, half_extents) in visuals {
-        world
+                ),
+            ColliderShape::Sphere { offset, radius } => {
+                let r =
+                    radius * transform.scale.x.max(transform.scale.y).max(transform.scale.z);
+                (offset, Vector3::new(r, r, r))
+            }
+        };
+        let scaled_offset = Vector3::new(
+            offset.x * transform.scale.x,
+            offset.y * transform.scale.y,
+            offset.z * transform.scale.z
+        );
+        let world_offset = transform.rotation * scaled_offset;
+        system_context.commands
             .spawn()
-            .with(Renderable::new(cube_model_id))
+            .with(Renderable::new(cube_model.clone()))
             .with(Transform {
-                position,
+                position: transform.position + world_offset,
                 rotation: Quaternion::one(),
                 scale: half_extents,
             })
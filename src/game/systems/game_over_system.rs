@@ -0,0 +1,23 @@
+use crate::{
+    engine::ecs::{
+        events::collision_event::CollisionEvent,
+        state::State,
+        system_param::{ EventReader, Query, ResMut },
+    },
+    game::{ components::{ enemy::Enemy, player::Player }, resources::game_state::GameState },
+};
+
+pub fn game_over_system(
+    collisions: EventReader<CollisionEvent>,
+    mut players: Query<&Player>,
+    mut enemies: Query<&Enemy>,
+    mut state: ResMut<State<GameState>>
+) {
+    let player_hit = collisions.read().any(|event| {
+        (players.get(event.a).is_some() && enemies.get(event.b).is_some()) ||
+            (players.get(event.b).is_some() && enemies.get(event.a).is_some())
+    });
+    if player_hit {
+        state.set(GameState::GameOver);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{
+    engine::ecs::{ state::State, system::SystemContext, world::World },
+    game::{
+        input::{ actions::Action, world_ext::InputWorldExt },
+        resources::game_state::{ GameState, PauseState },
+    },
+};
+
+// Input-driven transitions. Losing is handled by game_over_system
+pub fn game_state_system(world: &mut World, _system_context: &mut SystemContext) {
+    let input = world.input_state();
+    let key_bindings = world.key_bindings();
+    let confirm = key_bindings.is_action_just_pressed(&Action::Confirm, &input);
+    let pause = key_bindings.is_action_just_pressed(&Action::Pause, &input);
+
+    let Some(state) = world.get_resource_mut::<State<GameState>>() else {
+        return;
+    };
+    let playing = state.get() == GameState::Playing;
+    match state.get() {
+        GameState::Menu if confirm => state.set(GameState::Playing),
+        GameState::GameOver if confirm => state.set(GameState::Menu),
+        _ => {}
+    }
+
+    if !(playing && pause) {
+        return;
+    }
+    if let Some(pause_state) = world.get_resource_mut::<State<PauseState>>() {
+        match pause_state.get() {
+            PauseState::Running => pause_state.set(PauseState::Paused),
+            PauseState::Paused => pause_state.set(PauseState::Running),
+        }
+    }
+}
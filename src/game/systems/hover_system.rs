@@ -1,16 +1,13 @@
 use crate::{
     engine::ecs::{
         components::{ transform::Transform, velocity::Velocity },
-        system::SystemContext,
-        world::World,
+        system_param::Query,
     },
     game::{ components::hover_state::HoverState, helpers::starfighter::animate_hover },
 };
 
-pub fn hover_system(world: &mut World, _system_context: &mut SystemContext) {
-    for (transform, velocity, hover_state) in world.query_iter::<
-        (&Transform, &mut Velocity, &mut HoverState)
-    >() {
+pub fn hover_system(mut query: Query<(&Transform, &mut Velocity, &mut HoverState)>) {
+    for (transform, velocity, hover_state) in query.iter() {
         animate_hover(transform, velocity, hover_state);
     }
 }
@@ -1,12 +1,10 @@
 use crate::{
-    engine::{ ecs::{ system::SystemContext, world::World }, events::events::Events },
+    engine::ecs::system_param::EventReader,
     game::events::laser_fired_event::LaserFiredEvent,
 };
 
-pub fn laser_log_system(world: &mut World, _ctx: &mut SystemContext) {
-    if let Some(events) = world.get_resource::<Events<LaserFiredEvent>>() {
-        for event in events.read() {
-            println!("Laser fired at {:?}", event.origin);
-        }
+pub fn laser_log_system(lasers_fired: EventReader<LaserFiredEvent>) {
+    for event in lasers_fired.read() {
+        println!("Laser fired at {:?}", event.origin);
     }
 }
@@ -1,5 +1,3 @@
-use std::collections::HashSet;
-
 use web_time::Instant;
 use cgmath::{ One, Quaternion, Vector3 };
 
@@ -7,8 +5,15 @@ use crate::{
     engine::{
         assets::server::AssetServer,
         ecs::{
-            components::{ renderable::Renderable, transform::Transform, velocity::Velocity },
-            entity::Entity,
+            commands::Commands,
+            components::{
+                light::PointLight,
+                renderable::Renderable,
+                transform::Transform,
+                velocity::Velocity,
+            },
+            query::With,
+            state::StateScoped,
             system::SystemContext,
             world::World,
         },
@@ -16,7 +21,7 @@ use crate::{
     game::{
         components::{ laser::{ DEFAULT_TRAVEL_SPEED, Laser }, player::Player },
         input::{ actions::Action, world_ext::InputWorldExt },
-        resources::laser_resources::LaserManager,
+        resources::{ game_state::GameState, laser_resources::LaserManager },
     },
 };
 
@@ -25,7 +30,7 @@ pub fn laser_system(world: &mut World, system_context: &mut SystemContext) {
     let key_bindings = world.key_bindings();
 
     let player_position = world
-        .query_iter::<(&Player, &Transform)>()
+        .query_iter::<(With<Player>, &Transform)>()
         .next()
         .map(|(_, transform)| transform.position);
 
@@ -34,91 +39,78 @@ pub fn laser_system(world: &mut World, system_context: &mut SystemContext) {
         log::info!("tried to fire!");
         let now = Instant::now();
 
-        let is_allowed_to_fire = {
-            let Some(laser_manager) = world.get_resource::<LaserManager>() else {
-                return;
-            };
-            laser_manager.is_allowed_to_fire(now)
+        let Some(laser_manager) = world.get_resource_mut::<LaserManager>() else {
+            return;
         };
+        let is_allowed_to_fire = laser_manager.is_allowed_to_fire(now);
         log::info!("is allowed to fire: {:?}", is_allowed_to_fire);
-        let laser_entity: Option<Entity> = if is_allowed_to_fire {
-            Some(
-                spawn_laser(
-                    world,
-                    system_context.asset_server.as_deref().unwrap(),
-                    player_position.unwrap(),
-                    Vector3 { x: 10.0, y: 10.0, z: 10.0 },
-                    now
-                )
-            )
-        } else {
-            None
-        };
 
-        // New laser beam created
-        if let Some(laser) = laser_entity {
-            let Some(laser_manager) = world.get_resource_mut::<LaserManager>() else {
-                return;
-            };
-            laser_manager.alive_lasers.push(laser);
+        // New laser beam created - its components land at the next sync point
+        if is_allowed_to_fire {
+            spawn_laser(
+                &mut system_context.commands,
+                system_context.asset_server.as_deref().unwrap(),
+                player_position.unwrap(),
+                Vector3 { x: 10.0, y: 10.0, z: 10.0 },
+                now
+            );
             laser_manager.last_fired_time = now;
         }
     }
 
-    let (alive_lasers, max_travel_distance) = {
-        let laser_manager = world.get_resource::<LaserManager>().unwrap();
-        (laser_manager.alive_lasers.clone(), laser_manager.max_travel_distance)
-    };
+    let max_travel_distance = world.get_resource::<LaserManager>().unwrap().max_travel_distance;
 
     // Move/despawn beams
-    let mut to_despawn: Vec<Entity> = Vec::new();
-
-    for laser_entity in alive_lasers {
-        if
-            let Some((laser, transform, velocity)) = world.query::<
-                (&mut Laser, &mut Transform, &mut Velocity)
-            >(laser_entity.id)
-        {
-            let distance_travelled = (transform.position.z - laser.initial_z).abs();
-            if distance_travelled > max_travel_distance {
-                to_despawn.push(laser_entity);
-            } else {
-                velocity.z = laser.travel_speed;
+    let lasers: Vec<(u32, Laser)> = world
+        .iter_component::<Laser>()
+        .map(|(entity_id, laser)| (entity_id, *laser))
+        .collect();
+    for (entity_id, laser) in lasers {
+        let Some(transform) = world.get_component_by_id::<Transform>(entity_id) else {
+            continue;
+        };
+        if (transform.position.z - laser.initial_z).abs() > max_travel_distance {
+            if let Some(entity) = world.get_entity(entity_id) {
+                system_context.commands.despawn(entity);
             }
+        } else if let Some(velocity) = world.get_component_mut_by_id::<Velocity>(entity_id) {
+            velocity.z = laser.travel_speed;
         }
     }
-
-    let despawn_ids: HashSet<u32> = to_despawn
-        .iter()
-        .map(|entity| entity.id)
-        .collect();
-    let laser_manager = world.get_resource_mut::<LaserManager>().unwrap();
-    laser_manager.alive_lasers.retain(|entity| !despawn_ids.contains(&entity.id));
-
-    for entity in to_despawn {
-        world.despawn(entity);
-    }
 }
 
+const LASER_LIGHT_INTENSITY: f32 = 6.0;
+const LASER_LIGHT_RANGE: f32 = 8.0;
+
 fn spawn_laser(
-    world: &mut World,
+    commands: &mut Commands,
     asset_server: &AssetServer,
     position: Vector3<f32>,
     scale: Vector3<f32>,
     fired_at: Instant
-) -> Entity {
+) {
+    let Some(laser_model) = asset_server.get_model_handle("laser") else {
+        log::warn!("No laser model registered, not firing");
+        return;
+    };
+    let Some(collider) = asset_server.get_collider_aabb(&laser_model) else {
+        log::warn!("Laser model isn't loaded, not firing");
+        return;
+    };
     log::info!("Spawning laser at z: {:?}", position);
-    let laser_model_id = asset_server.get_model_id("laser");
-    world
+    commands
         .spawn()
-        .with(Renderable::new(laser_model_id))
-        .with(asset_server.get_collider_aabb("laser"))
+        .with(Renderable::new(laser_model))
+        .with(collider)
         .with(Transform {
             position,
             scale,
             rotation: Quaternion::one(),
         })
         .with(Velocity { x: 0.0, y: 0.0, z: 0.0 })
+        // Same cyan as the laser model, lighting up the canyon walls as it passes
+        .with(PointLight::new([0.78, 1.0, 1.0], LASER_LIGHT_INTENSITY, LASER_LIGHT_RANGE))
         .with(Laser { initial_z: position.z, fired_at, travel_speed: DEFAULT_TRAVEL_SPEED })
-        .build()
+        .with(StateScoped(GameState::Playing))
+        .build();
 }
@@ -1,5 +1,8 @@
 pub mod camera_control_system;
 pub mod player_system;
+pub mod game_state_system;
+pub mod game_over_system;
+pub mod reset_run_system;
 pub mod hover_system;
 pub mod terrain_system;
 pub mod laser_system;
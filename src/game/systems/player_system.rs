@@ -4,6 +4,7 @@ use crate::{
     engine::{
         ecs::{
             components::{ transform::Transform, velocity::Velocity },
+            query::With,
             resources::camera::ActiveCamera,
             system::SystemContext,
             world::World,
@@ -12,7 +13,6 @@ use crate::{
     game::{
         components::player::Player,
         input::{ actions::Action, world_ext::InputWorldExt },
-        resources::move_player::MovePlayer,
     },
 };
 
@@ -22,25 +22,14 @@ const STRAFE_SPEED: f32 = 4.0;
 const X_MIN: f32 = 23.5;
 const X_MAX: f32 = 25.5;
 
+// Only runs while playing - see the gameplay set in canyon_runner_world
 pub fn player_system(world: &mut World, system_context: &mut SystemContext) {
     let input = world.input_state();
     let key_bindings = world.key_bindings();
 
-    let move_player = {
-        let resource = world.get_resource_mut::<MovePlayer>().unwrap();
-        if key_bindings.is_action_just_pressed(&Action::Pause, &input) {
-            resource.0 = !resource.0;
-        }
-        resource.0
-    };
-
-    if !move_player {
-        return;
-    }
-
     if
-        let Some((_player, transform, velocity)) = world
-            .query_iter::<(&Player, &Transform, &mut Velocity)>()
+        let Some((_, transform, velocity)) = world
+            .query_iter::<(With<Player>, &Transform, &mut Velocity)>()
             .next()
     {
         velocity.z += Z_MOVEMENT_SPEED;
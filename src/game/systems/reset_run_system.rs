@@ -0,0 +1,21 @@
+use crate::{
+    engine::ecs::{
+        components::transform::Transform,
+        query::With,
+        system::SystemContext,
+        world::World,
+    },
+    game::{ components::player::Player, resources::enemy_resources::EnemySpawnManager },
+};
+
+// Runs when leaving GameOver. Enemies and lasers are `StateScoped(GameState::Playing)`, so they're
+// already gone - this restarts enemy spacing from where the player is now.
+pub fn reset_run_system(world: &mut World, _system_context: &mut SystemContext) {
+    let player_z = world
+        .query_iter::<(With<Player>, &Transform)>()
+        .next()
+        .map(|(_, transform)| transform.position.z);
+    if let (Some(player_z), Some(manager)) = (player_z, world.get_resource_mut::<EnemySpawnManager>()) {
+        manager.last_z_pos_spawned_at = player_z;
+    }
+}
@@ -9,8 +9,8 @@ use crate::{
         state::context::GpuContext,
     },
     game::{
-        helpers::terrain_generation::{ replace_terrain_model_buffers, terrain_update },
-        resources::terrain_resources::{ TerrainGeneration, TerrainModelIds },
+        helpers::terrain_generation::{ create_model_from_data, terrain_update },
+        resources::terrain_resources::{ TerrainGeneration, TerrainModels },
     },
 };
 
@@ -27,8 +27,6 @@ pub fn terrain_system(world: &mut World, system_context: &mut SystemContext) {
     };
 
     if let Some(new_terrain_mesh_data) = terrain_result {
-        let terrain_model_ids: [usize; 3] = world.get_resource::<TerrainModelIds>().unwrap().0;
-
         let oldest_index = {
             let terrain_generation = world.get_resource_mut::<TerrainGeneration>().unwrap();
             let index = terrain_generation.oldest_terrain_index as usize;
@@ -37,18 +35,16 @@ pub fn terrain_system(world: &mut World, system_context: &mut SystemContext) {
             index
         };
 
-        let model_to_replace = system_context.asset_server
-            .as_mut()
-            .unwrap()
-            .get_model_mut(terrain_model_ids[oldest_index]);
-
-        replace_terrain_model_buffers(
+        let new_terrain = create_model_from_data(
             new_terrain_mesh_data,
-            model_to_replace,
             &(GpuContext {
                 device: system_context.device.unwrap(),
                 queue: system_context.queue.unwrap(),
             })
         );
+        let handle = system_context.asset_server.as_mut().unwrap().add_model(new_terrain);
+
+        // Dropping the oldest chunk's handle unloads it (and its buffers) next frame
+        world.get_resource_mut::<TerrainModels>().unwrap().0[oldest_index] = handle;
     }
 }
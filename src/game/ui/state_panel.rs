@@ -0,0 +1,32 @@
+use egui::Color32;
+
+use crate::{
+    engine::ecs::{ state::State, world::World },
+    game::resources::game_state::{ GameState, PauseState },
+};
+
+// Title, pause and game over text. Nothing is drawn while playing
+pub fn state_panel(context: &egui::Context, world: &mut World) {
+    let Some(state) = world.get_resource::<State<GameState>>().map(|s| s.get()) else {
+        return;
+    };
+    let paused = world.get_resource::<State<PauseState>>().is_some_and(|s| s.get() == PauseState::Paused);
+    let (title, hint) = match state {
+        GameState::Menu => ("CANYON RUNNER", "Press Enter to start"),
+        GameState::Playing if paused => ("PAUSED", "Press Ctrl+P to resume"),
+        GameState::GameOver => ("GAME OVER", "Press Enter to continue"),
+        GameState::Playing => {
+            return;
+        }
+    };
+
+    egui::Area
+        ::new(egui::Id::new("state_panel"))
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(context, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(egui::RichText::new(title).size(48.0).color(Color32::WHITE));
+                ui.label(egui::RichText::new(hint).size(18.0).color(Color32::WHITE));
+            });
+        });
+}
@@ -0,0 +1,112 @@
+// The post-processing chain: one entry point per effect, each a fullscreen pass from the last
+// one's result into the next, then `fs_output` takes the result to the surface. Which ones
+// run, and in what order, is up to the `PostProcessing` resource.
+
+// What each effect reads from `params` - written by `EffectUniform::new` in post_process.rs
+struct Effect {
+    params: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+// The finished bloom, only sampled by `fs_bloom`
+@group(0) @binding(1)
+var bloom_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var post_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> effect: Effect;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the screen, no vertex buffer
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source_texture, post_sampler, uv, 0.0).rgb;
+}
+
+// Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Cheap hash noise in [0, 1)
+fn hash(p: vec2<f32>) -> f32 {
+    let q = fract(p * vec2<f32>(123.34, 456.21));
+    let r = q + dot(q, q + 45.32);
+    return fract(r.x * r.y);
+}
+
+// x: strength. Red and blue pulled apart, more towards the edges
+@fragment
+fn fs_chromatic_aberration(in: VertexOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - 0.5) * effect.params.x;
+    let color = vec3<f32>(
+        sample_source(in.uv + offset).r,
+        sample_source(in.uv).g,
+        sample_source(in.uv - offset).b
+    );
+    return vec4<f32>(color, 1.0);
+}
+
+// x: filter radius, for bloom.wgsl. y: intensity
+@fragment
+fn fs_bloom(in: VertexOutput) -> @location(0) vec4<f32> {
+    let bloom = textureSampleLevel(bloom_texture, post_sampler, in.uv, 0.0).rgb;
+    return vec4<f32>(mix(sample_source(in.uv), bloom, effect.params.y), 1.0);
+}
+
+// x: exposure
+@fragment
+fn fs_exposure(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sample_source(in.uv) * effect.params.x, 1.0);
+}
+
+@fragment
+fn fs_reinhard(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = max(sample_source(in.uv), vec3<f32>(0.0));
+    return vec4<f32>(color / (1.0 + color), 1.0);
+}
+
+@fragment
+fn fs_aces(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(aces(max(sample_source(in.uv), vec3<f32>(0.0))), 1.0);
+}
+
+// x: intensity, y: radius, z: smoothness
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    // 0 at the centre, 1 at the corners
+    let distance_from_centre = length(in.uv - 0.5) * 1.41421356;
+    let falloff = smoothstep(effect.params.y, effect.params.y + effect.params.z, distance_from_centre);
+    return vec4<f32>(sample_source(in.uv) * (1.0 - effect.params.x * falloff), 1.0);
+}
+
+// x: intensity, y: a seed that changes every frame, so the grain moves
+@fragment
+fn fs_film_grain(in: VertexOutput) -> @location(0) vec4<f32> {
+    let noise = hash(in.clip_position.xy + effect.params.y) - 0.5;
+    return vec4<f32>(sample_source(in.uv) + noise * effect.params.x, 1.0);
+}
+
+// Always last, into the surface. Anything over 1.0 the chain didn't tonemap is clipped there
+@fragment
+fn fs_output(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(max(sample_source(in.uv), vec3<f32>(0.0)), 1.0);
+}
@@ -22,6 +22,9 @@ struct InstanceInput {
 struct VerexInput {
     @location(0) position: vec3<f32>,
     @location(1) normal: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
 }
 
 struct VertexOutput {
@@ -29,14 +32,57 @@ struct VertexOutput {
     @location(0) world_normal: vec3<f32>,
     @location(1) world_position: vec3<f32>,
     @location(2) camera_distance: f32,
+    @location(3) tex_coords: vec2<f32>,
+    @location(4) world_tangent: vec3<f32>,
+    @location(5) world_bitangent: vec3<f32>,
 }
 
+// Lights - see light.rs, packed by light_sync_system
+const MAX_LIGHTS: u32 = 16u;
+const DIRECTIONAL_LIGHT: u32 = 0u;
+const POINT_LIGHT: u32 = 1u;
+const SPOT_LIGHT: u32 = 2u;
+
 struct Light {
     position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    range: f32,
     color: vec3<f32>,
+    inner_cos: f32,
+    outer_cos: f32,
+    // First shadow map layer, or -1
+    shadow_map: i32,
+}
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    lights: array<Light, MAX_LIGHTS>,
 }
 @group(1) @binding(0)
-var<uniform> light: Light;
+var<uniform> lights: Lights;
+
+// Shadow maps - see shadow.rs
+const SHADOW_CASCADES: i32 = 3;
+const MAX_SHADOW_MAPS: u32 = 7u;
+const SHADOW_MAP_TEXEL: f32 = 1.0 / 1024.0;
+
+struct Shadows {
+    view_projections: array<mat4x4<f32>, MAX_SHADOW_MAPS>,
+    bias: f32,
+}
+@group(1) @binding(1)
+var<uniform> shadows: Shadows;
+@group(1) @binding(2)
+var shadow_texture: texture_depth_2d_array;
+@group(1) @binding(3)
+var shadow_sampler: sampler_comparison;
+// Zero for instances without ReceivesShadows
+struct ShadowReceiver {
+    enabled: u32,
+}
+@group(1) @binding(4)
+var<uniform> shadow_receiver: ShadowReceiver;
 
 @vertex
 fn vs_main(
@@ -58,6 +104,9 @@ fn vs_main(
     var out: VertexOutput;
     // transform the normal(model.normal) to match the transformation of the instance(instance_normal_matrix)
     out.world_normal = instance_normal_matrix * model.normal;
+    out.world_tangent = instance_normal_matrix * model.tangent;
+    out.world_bitangent = instance_normal_matrix * model.bitangent;
+    out.tex_coords = model.tex_coords;
     // transform the vertices(model.position) to match the transformation of the instance(instance_model_matrix)
     var world_position: vec4<f32> = instance_model_matrix * vec4<f32>(model.position, 1.0);
     out.world_position = world_position.xyz;
@@ -68,29 +117,121 @@ fn vs_main(
     return out;
 }
 
-// Flat material
+// Material - see model/material.rs
+const HAS_DIFFUSE_MAP: u32 = 1u;
+const HAS_NORMAL_MAP: u32 = 2u;
+
 struct Material {
     color: vec3<f32>,
     alpha: f32,
+    flags: u32,
 }
 @group(2) @binding(0)
 var<uniform> material: Material;
+// Materials without a map get a 1x1 white / flat normal stand-in
+@group(2) @binding(1)
+var diffuse_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var diffuse_sampler: sampler;
+@group(2) @binding(3)
+var normal_texture: texture_2d<f32>;
+@group(2) @binding(4)
+var normal_sampler: sampler;
+
+fn light_contribution(light: Light, normal: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    if (light.kind == DIRECTIONAL_LIGHT) {
+        return light.color * max(dot(normal, -light.direction), 0.0);
+    }
+
+    let to_light = light.position - world_position;
+    let distance = length(to_light);
+    let light_direction = to_light / max(distance, 0.0001);
+    // Inverse square, smoothly reaching zero at the light's range
+    let falloff = saturate(1.0 - pow(distance / light.range, 4.0));
+    var attenuation = falloff * falloff / (distance * distance + 1.0);
+    if (light.kind == SPOT_LIGHT) {
+        attenuation *= smoothstep(light.outer_cos, light.inner_cos, dot(-light_direction, light.direction));
+    }
+    return light.color * max(dot(normal, light_direction), 0.0) * attenuation;
+}
+
+// Texture coordinates (xy) and depth (z) in shadow map `layer`, plus clip w - negative
+// behind a spot light
+fn shadow_position(layer: i32, world_position: vec3<f32>) -> vec4<f32> {
+    let clip = shadows.view_projections[layer] * vec4<f32>(world_position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    return vec4<f32>(ndc.xy * vec2<f32>(0.5, -0.5) + 0.5, ndc.z, clip.w);
+}
+
+fn in_shadow_map(position: vec4<f32>) -> bool {
+    return position.w > 0.0 && all(position.xy >= vec2<f32>(0.0)) && all(position.xy <= vec2<f32>(1.0)) && position.z <= 1.0;
+}
+
+// 3x3 PCF - the fraction of the taps that are lit
+fn sample_shadow(layer: i32, position: vec3<f32>) -> f32 {
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * SHADOW_MAP_TEXEL;
+            lit += textureSampleCompareLevel(shadow_texture, shadow_sampler, position.xy + offset, layer, position.z - shadows.bias);
+        }
+    }
+    return lit / 9.0;
+}
+
+// 1 where nothing is between the fragment and the light, down to 0 in full shadow
+fn shadow_factor(light: Light, world_position: vec3<f32>) -> f32 {
+    if (shadow_receiver.enabled == 0u || light.shadow_map < 0) {
+        return 1.0;
+    }
+    if (light.kind == DIRECTIONAL_LIGHT) {
+        // The nearest cascade that covers the fragment. Past the last one it's unshadowed
+        for (var cascade = 0; cascade < SHADOW_CASCADES; cascade++) {
+            let position = shadow_position(light.shadow_map + cascade, world_position);
+            if (in_shadow_map(position)) {
+                return sample_shadow(light.shadow_map + cascade, position.xyz);
+            }
+        }
+        return 1.0;
+    }
+    // Outside a spot light's map is outside its cone too, so unlit regardless
+    let position = shadow_position(light.shadow_map, world_position);
+    if (!in_shadow_map(position)) {
+        return 1.0;
+    }
+    return sample_shadow(light.shadow_map, position.xyz);
+}
 
 // Fragment Shader
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    // We're not doing lighting at the moment, keeping here
-    // for reference later
-    // let ambient_light_strength = 0.1;
-    // let ambient_light_color = ambient_light_strength;
+    // Sampled up front - textureSample has to be in uniform control flow
+    let diffuse_sample = textureSample(diffuse_texture, diffuse_sampler, in.tex_coords);
+    let normal_sample = textureSample(normal_texture, normal_sampler, in.tex_coords);
 
-    // let light_direction = normalize(light.position - in.world_position);
+    // With no lights in the scene everything is drawn flat, the way the game has always looked
+    var result = material.color;
+    var alpha = material.alpha;
+    if ((material.flags & HAS_DIFFUSE_MAP) != 0u) {
+        result *= diffuse_sample.rgb;
+        alpha *= diffuse_sample.a;
+    }
 
-    // let diffuse_strength = max(dot(in.world_normal, light_direction), 0.0);
-    // let diffuse_color = light.color * diffuse_strength;
+    if (lights.count > 0u) {
+        var normal = normalize(in.world_normal);
+        if ((material.flags & HAS_NORMAL_MAP) != 0u) {
+            // Tangent space to world space
+            let tbn = mat3x3<f32>(normalize(in.world_tangent), normalize(in.world_bitangent), normal);
+            normal = normalize(tbn * (normal_sample.xyz * 2.0 - 1.0));
+        }
 
-    let result = material.color;
-    let alpha = material.alpha;
+        var lighting = lights.ambient;
+        for (var i = 0u; i < min(lights.count, MAX_LIGHTS); i++) {
+            let light = lights.lights[i];
+            lighting += light_contribution(light, normal, in.world_position) * shadow_factor(light, in.world_position);
+        }
+        result *= lighting;
+    }
 
     let fade_start = 75.0;
     let fade_end = 125.0;
@@ -0,0 +1,32 @@
+// Depth-only pass into one shadow map layer - see shadow.rs
+
+struct ShadowLayer {
+    view_projection: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> layer: ShadowLayer;
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+}
+
+struct VerexInput {
+    @location(0) position: vec3<f32>,
+}
+
+@vertex
+fn vs_main(
+    model: VerexInput,
+    instance: InstanceInput,
+) -> @builtin(position) vec4<f32> {
+    let instance_model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return layer.view_projection * instance_model_matrix * vec4<f32>(model.position, 1.0);
+}
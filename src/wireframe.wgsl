@@ -66,6 +66,8 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
     let fade_factor = 1.0 - smoothstep(fade_start, fade_end, in.camera_distance);
     
-    // pink line with opacity fade
-    return vec4<f32>(0.93, 0.11, 1.0, fade_factor);
+    // pink line with opacity fade. Brighter than 1.0, so it blooms when the scene is HDR (and
+    // the tonemapper brings it back down)
+    let glow = 2.0;
+    return vec4<f32>(vec3<f32>(0.93, 0.11, 1.0) * glow, fade_factor);
 }
\ No newline at end of file